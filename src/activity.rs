@@ -0,0 +1,402 @@
+//! Activities (comments and change history) of list entries, from the Zenkit activities api
+use crate::{rest::RestClient, Error};
+use serde::Serialize;
+use serde_json::Value;
+use std::result::Result;
+use zenkit::types::ID;
+
+/// Number of activities requested per page
+const PAGE_SIZE: usize = 100;
+
+/// Activity filter: comments only
+pub(crate) const FILTER_COMMENTS: u32 = 2;
+
+/// Fetch all activities of a list entry matching the filter, following pages with `lastId`.
+/// Activities are returned newest first.
+pub(crate) async fn get_entry_activities(
+    rest: &RestClient,
+    list_id: ID,
+    entry_id: ID,
+    filter: u32,
+) -> Result<Vec<Value>, Error> {
+    let path = format!("lists/{}/entries/{}/activities", list_id, entry_id);
+    get_activities(rest, &path, Some(filter), None, None).await
+}
+
+/// Fetch activities from the endpoint `path` (workspace, list, or entry activities),
+/// newest first. Activities are fetched in pages, until `limit` activities
+/// have been fetched (or all activities, if `limit` is None).
+/// If `before` is set, only activities with lower ids (older activities) are returned.
+pub(crate) async fn get_activities(
+    rest: &RestClient,
+    path: &str,
+    filter: Option<u32>,
+    limit: Option<usize>,
+    before: Option<u64>,
+) -> Result<Vec<Value>, Error> {
+    let mut all = Vec::new();
+    let mut last_id: Option<u64> = before;
+    loop {
+        let page_size = match limit {
+            Some(limit) => PAGE_SIZE.min(limit - all.len()),
+            None => PAGE_SIZE,
+        };
+        let mut page_path = format!("{}?limit={}", path, page_size);
+        if let Some(filter) = filter {
+            page_path.push_str(&format!("&filter={}", filter));
+        }
+        if let Some(id) = last_id {
+            page_path.push_str(&format!("&lastId={}", id));
+        }
+        let page = activities_page(rest.get(&page_path).await?);
+        let count = page.len();
+        last_id = page
+            .last()
+            .and_then(|a| a.get("id"))
+            .and_then(|id| id.as_u64());
+        all.extend(page);
+        if count < page_size || last_id.is_none() || Some(all.len()) == limit {
+            break;
+        }
+    }
+    Ok(all)
+}
+
+/// Fetch the current user's notifications, newest first, optionally only unread ones
+pub(crate) async fn get_notifications(
+    rest: &RestClient,
+    unread_only: bool,
+    limit: Option<usize>,
+) -> Result<Vec<Value>, Error> {
+    let mut all = Vec::new();
+    let mut last_id: Option<u64> = None;
+    loop {
+        let mut path = format!("users/me/notifications?limit={}", PAGE_SIZE);
+        if unread_only {
+            path.push_str("&isRead=false");
+        }
+        if let Some(id) = last_id {
+            path.push_str(&format!("&lastId={}", id));
+        }
+        let page = match rest.get(&path).await? {
+            Value::Object(mut obj) => match obj.remove("notifications") {
+                Some(Value::Array(notifications)) => notifications,
+                _ => Vec::new(),
+            },
+            Value::Array(notifications) => notifications,
+            _ => Vec::new(),
+        };
+        let count = page.len();
+        last_id = page
+            .last()
+            .and_then(|n| n.get("id"))
+            .and_then(|id| id.as_u64());
+        all.extend(page);
+        if let Some(limit) = limit {
+            if all.len() >= limit {
+                all.truncate(limit);
+                break;
+            }
+        }
+        if count < PAGE_SIZE || last_id.is_none() {
+            break;
+        }
+    }
+    Ok(all)
+}
+
+pub(crate) async fn mark_notification_read(rest: &RestClient, id: ID) -> Result<(), Error> {
+    let body = serde_json::json!({ "isRead": true });
+    rest.put(&format!("users/me/notifications/{}", id), &body)
+        .await?;
+    Ok(())
+}
+
+/// Activities endpoints return `{ "activities": [ ... ], ... }`
+fn activities_page(resp: Value) -> Vec<Value> {
+    match resp {
+        Value::Object(mut obj) => match obj.remove("activities") {
+            Some(Value::Array(activities)) => activities,
+            _ => Vec::new(),
+        },
+        Value::Array(activities) => activities,
+        _ => Vec::new(),
+    }
+}
+
+/// Summary of an activity, for display
+#[derive(Debug, Serialize)]
+pub(crate) struct ActivitySummary {
+    pub id: u64,
+    pub created_at: String,
+    pub author: String,
+    /// activity type code
+    #[serde(rename = "type")]
+    pub activity_type: u64,
+    /// item display string, for item activities
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub item: String,
+    /// field name, for field changes
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub field: String,
+    pub message: String,
+}
+
+impl ActivitySummary {
+    pub(crate) fn from_activity(activity: &Value) -> Self {
+        let str_field = |key: &str| {
+            activity
+                .get(key)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+        let author = activity
+            .get("userDisplayname")
+            .or_else(|| activity.get("user").and_then(|u| u.get("displayname")))
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .to_string();
+        ActivitySummary {
+            id: activity
+                .get("id")
+                .and_then(|v| v.as_u64())
+                .unwrap_or_default(),
+            created_at: str_field("created_at"),
+            author,
+            activity_type: activity
+                .get("type")
+                .and_then(|v| v.as_u64())
+                .unwrap_or_default(),
+            item: str_field("listEntryDisplayString"),
+            field: str_field("elementName"),
+            message: str_field("message"),
+        }
+    }
+}
+
+/// A change to one field of an item
+#[derive(Debug, Serialize)]
+pub(crate) struct FieldChange {
+    pub activity_id: u64,
+    pub created_at: String,
+    pub author: String,
+    pub field: String,
+    pub old_value: String,
+    pub new_value: String,
+    /// id of the user who made the change
+    #[serde(skip)]
+    pub user_id: Option<u64>,
+    /// the old value as recorded by Zenkit
+    #[serde(skip)]
+    pub old_raw: Value,
+}
+
+impl FieldChange {
+    /// The field change recorded in the activity, or None if the activity
+    /// isn't a field change (e.g., a comment). The old and new values are
+    /// read from the activity's `changedData` (or `elementData`), as recorded by Zenkit.
+    pub(crate) fn from_activity(activity: &Value) -> Option<Self> {
+        if activity.get("filter").and_then(|v| v.as_u64()) == Some(FILTER_COMMENTS as u64) {
+            return None;
+        }
+        let summary = ActivitySummary::from_activity(activity);
+        if summary.field.is_empty() {
+            return None;
+        }
+        let data = activity
+            .get("changedData")
+            .or_else(|| activity.get("elementData"));
+        let value = |keys: &[&str]| data.and_then(|d| keys.iter().find_map(|k| d.get(*k)));
+        let old = value(&["oldValue", "old"]);
+        let user_id = activity
+            .get("userId")
+            .or_else(|| activity.get("user").and_then(|u| u.get("id")))
+            .and_then(|v| v.as_u64());
+        Some(FieldChange {
+            activity_id: summary.id,
+            created_at: summary.created_at,
+            author: summary.author,
+            field: summary.field,
+            old_value: old.map(display_change_value).unwrap_or_default(),
+            new_value: value(&["newValue", "new", "value"])
+                .map(display_change_value)
+                .unwrap_or_default(),
+            user_id,
+            old_raw: old.cloned().unwrap_or(Value::Null),
+        })
+    }
+}
+
+/// Display string of a value in a change record. Objects (such as choices and persons)
+/// are shown by name, and arrays as comma-separated lists.
+fn display_change_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(values) => values
+            .iter()
+            .map(display_change_value)
+            .collect::<Vec<_>>()
+            .join(", "),
+        Value::Object(obj) => ["name", "displayname", "displayString", "fileName"]
+            .iter()
+            .find_map(|k| obj.get(*k).and_then(|v| v.as_str()))
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| value.to_string()),
+        v => v.to_string(),
+    }
+}
+
+/// A notification, and a summary of the activity that triggered it
+#[derive(Debug, Serialize)]
+pub(crate) struct Notification {
+    pub id: u64,
+    pub is_read: bool,
+    pub activity: ActivitySummary,
+}
+
+impl Notification {
+    pub(crate) fn from_json(notification: &Value) -> Self {
+        Notification {
+            id: notification
+                .get("id")
+                .and_then(|v| v.as_u64())
+                .unwrap_or_default(),
+            is_read: notification
+                .get("isRead")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false),
+            activity: ActivitySummary::from_activity(
+                notification.get("activity").unwrap_or(notification),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn activities_from_page() {
+        let page = json!({ "activities": [{ "id": 2 }, { "id": 1 }], "count": 2 });
+        assert_eq!(
+            activities_page(page),
+            vec![json!({ "id": 2 }), json!({ "id": 1 })]
+        );
+        assert_eq!(
+            activities_page(json!([{ "id": 3 }])),
+            vec![json!({ "id": 3 })]
+        );
+        assert!(activities_page(json!({ "error": "not found" })).is_empty());
+        assert!(activities_page(Value::Null).is_empty());
+    }
+
+    #[test]
+    fn comment_summary() {
+        let comment = ActivitySummary::from_activity(&json!({
+            "id": 12,
+            "created_at": "2021-03-01T10:00:00.000Z",
+            "userDisplayname": "Ann",
+            "message": "Looks good",
+        }));
+        assert_eq!(comment.id, 12);
+        assert_eq!(comment.created_at, "2021-03-01T10:00:00.000Z");
+        assert_eq!(comment.author, "Ann");
+        assert_eq!(comment.message, "Looks good");
+        let comment = ActivitySummary::from_activity(&json!({
+            "id": 13,
+            "user": { "displayname": "Bob" },
+        }));
+        assert_eq!(comment.author, "Bob");
+        assert_eq!(comment.message, "");
+    }
+
+    #[test]
+    fn activity_summary_json() {
+        let activity = ActivitySummary::from_activity(&json!({
+            "id": 20,
+            "created_at": "2021-03-01T10:00:00.000Z",
+            "userDisplayname": "Ann",
+            "type": 4,
+            "listEntryDisplayString": "Write tests",
+            "elementName": "Status",
+            "message": "",
+        }));
+        assert_eq!(
+            serde_json::to_value(&activity).unwrap(),
+            json!({
+                "id": 20,
+                "created_at": "2021-03-01T10:00:00.000Z",
+                "author": "Ann",
+                "type": 4,
+                "item": "Write tests",
+                "field": "Status",
+                "message": "",
+            })
+        );
+        // item and field are omitted for workspace and list activities
+        let activity = ActivitySummary::from_activity(&json!({ "id": 21, "type": 0 }));
+        let json = serde_json::to_value(&activity).unwrap();
+        assert!(json.get("item").is_none() && json.get("field").is_none());
+    }
+
+    #[test]
+    fn notification_from_json() {
+        let notification = Notification::from_json(&json!({
+            "id": 5,
+            "isRead": true,
+            "activity": { "id": 20, "userDisplayname": "Ann", "message": "Hi" },
+        }));
+        assert_eq!(notification.id, 5);
+        assert!(notification.is_read);
+        assert_eq!(notification.activity.id, 20);
+        assert_eq!(notification.activity.author, "Ann");
+        assert_eq!(notification.activity.message, "Hi");
+        // without an embedded activity, the notification's own fields are used
+        let notification = Notification::from_json(&json!({ "id": 6, "message": "Hello" }));
+        assert!(!notification.is_read);
+        assert_eq!(notification.activity.message, "Hello");
+    }
+
+    #[test]
+    fn field_change_from_activity() {
+        let change = FieldChange::from_activity(&json!({
+            "id": 30,
+            "created_at": "2021-03-01T10:00:00.000Z",
+            "userDisplayname": "Ann",
+            "userId": 7,
+            "elementName": "Tags",
+            "changedData": {
+                "oldValue": [{ "id": 1, "name": "red" }],
+                "newValue": [{ "id": 1, "name": "red" }, { "id": 2, "name": "blue" }],
+            },
+        }))
+        .unwrap();
+        assert_eq!(change.activity_id, 30);
+        assert_eq!(change.field, "Tags");
+        assert_eq!(change.old_value, "red");
+        assert_eq!(change.new_value, "red, blue");
+        assert_eq!(change.user_id, Some(7));
+        assert_eq!(change.old_raw, json!([{ "id": 1, "name": "red" }]));
+
+        let change = FieldChange::from_activity(&json!({
+            "id": 31,
+            "elementName": "Points",
+            "elementData": { "old": null, "new": 3 },
+        }))
+        .unwrap();
+        assert_eq!(
+            (change.old_value.as_str(), change.new_value.as_str()),
+            ("", "3")
+        );
+        assert_eq!(change.old_raw, Value::Null);
+
+        // comments and activities without a field aren't field changes
+        let comment = json!({ "id": 32, "filter": 2, "elementName": "Notes", "message": "Hi" });
+        assert!(FieldChange::from_activity(&comment).is_none());
+        assert!(FieldChange::from_activity(&json!({ "id": 33, "message": "Created" })).is_none());
+    }
+}
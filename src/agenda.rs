@@ -0,0 +1,131 @@
+//! Agenda of items by date
+use crate::{
+    backup::get_all_items,
+    cache,
+    dates::{local_date, parse_day},
+    fuzzy,
+    rest::RestClient,
+    values::{raw_value, ValueFormatter},
+    AgendaOpt, Error,
+};
+use chrono::{Local, NaiveDate};
+use std::result::Result;
+use zenkit::types::{Element, ElementCategoryId, Entry, ZKObjectID, ID};
+
+/// Print the items with a date in the date field between `--from` and `--to` (inclusive),
+/// grouped by day. Items dated before today are marked overdue, and with `--overdue`,
+/// overdue items before `--from` are included. With `--mine`, only items with the
+/// authenticated user in a persons field are shown.
+pub(crate) async fn print_agenda(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &AgendaOpt,
+) -> Result<(), Error> {
+    let from = parse_day(&opt.from).map_err(Error::Message)?;
+    let to = parse_day(&opt.to).map_err(Error::Message)?;
+    let today = Local::today().naive_local();
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let field = fuzzy::get_field(&list_info, &opt.field)?;
+    if field.element_category != ElementCategoryId::Date {
+        return Err(Error::Invalid(format!(
+            "Field '{}' is not a date field",
+            field.name
+        )));
+    }
+    let mine: Option<(Vec<&Element>, ValueFormatter, String)> = if opt.mine {
+        let user = rest.get("users/me").await?;
+        let uuid = user
+            .get("uuid")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| Error::Message("Authenticated user has no uuid".into()))?
+            .to_string();
+        let persons = list_info
+            .fields()
+            .iter()
+            .filter(|f| f.element_category == ElementCategoryId::Persons)
+            .collect();
+        Some((persons, ValueFormatter::new(ws_id).await?, uuid))
+    } else {
+        None
+    };
+
+    let items = get_all_items(&list_info.list().uuid, false).await?;
+    let overdue_before = if opt.overdue { Some(today) } else { None };
+    let mut agenda = dated_items(&items, field, from, to, overdue_before);
+    if let Some((ref persons, ref formatter, ref uuid)) = mine {
+        agenda.retain(|(_, item)| persons.iter().any(|f| formatter.has_value(item, f, uuid)));
+    }
+
+    let mut day = None;
+    for (date, item) in agenda.iter() {
+        if day != Some(*date) {
+            println!("{}", date.format("%Y-%m-%d %a"));
+            day = Some(*date);
+        }
+        println!(
+            "  {}\t{}{}",
+            item.get_id(),
+            item.display_string,
+            if *date < today { "\t(overdue)" } else { "" }
+        );
+    }
+    Ok(())
+}
+
+/// Items with a date in the field from `from` to `to` (inclusive), sorted by date.
+/// With `overdue_before`, items dated before it are included too.
+fn dated_items<'i>(
+    items: &'i [Entry],
+    field: &Element,
+    from: NaiveDate,
+    to: NaiveDate,
+    overdue_before: Option<NaiveDate>,
+) -> Vec<(NaiveDate, &'i Entry)> {
+    let mut dated: Vec<(NaiveDate, &Entry)> = items
+        .iter()
+        .filter_map(|item| {
+            let date = local_date(raw_value(item, field)?.as_str()?)?;
+            Some((date, item))
+        })
+        .filter(|(date, _)| {
+            (*date >= from || overdue_before.map(|d| *date < d).unwrap_or(false)) && *date <= to
+        })
+        .collect();
+    dated.sort_by_key(|(date, _)| *date);
+    dated
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{field, item};
+    use serde_json::json;
+
+    #[test]
+    fn items_by_date() {
+        let due = field(4, "Due", 4);
+        let items = vec![
+            item(1, "later", json!({"field-4_date": "2021-03-20"})),
+            item(2, "next", json!({"field-4_date": "2021-03-05"})),
+            item(3, "late", json!({"field-4_date": "2021-02-20"})),
+            item(4, "undated", json!({"field-4_date": null})),
+            item(5, "soon", json!({"field-4_date": "2021-03-03"})),
+            item(6, "last", json!({"field-4_date": "2021-03-17"})),
+        ];
+        let day = |d| NaiveDate::from_ymd(2021, 3, d);
+        let ids = |dated: Vec<(NaiveDate, &Entry)>| -> Vec<ID> {
+            dated.iter().map(|(_, item)| item.get_id()).collect()
+        };
+        // from and to are inclusive
+        assert_eq!(
+            ids(dated_items(&items, &due, day(3), day(17), None)),
+            vec![5, 2, 6]
+        );
+        // with overdue items before today
+        assert_eq!(
+            ids(dated_items(&items, &due, day(4), day(17), Some(day(3)))),
+            vec![3, 2, 6]
+        );
+    }
+}
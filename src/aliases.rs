@@ -0,0 +1,76 @@
+//! Local names for lists and fields, from the config file's `[aliases]` section:
+//!
+//! ```toml
+//! [aliases]
+//! bugs = "Engineering Bug Tracker 2024"
+//! sev = "Severity"
+//! ```
+//!
+//! An alias may name a list or field by its uuid, so that scripts using the alias
+//! keep working after the list or field is renamed in the web app.
+use crate::Error;
+use config::Config;
+use std::{collections::HashMap, result::Result, sync::Mutex};
+
+static ALIASES: Mutex<Option<HashMap<String, String>>> = Mutex::new(None);
+
+/// Load the aliases from the config file's `[aliases]` section
+pub(crate) fn configure(settings: &Config) -> Result<(), Error> {
+    let aliases = match settings.get::<HashMap<String, String>>("aliases") {
+        Ok(aliases) => aliases,
+        Err(config::ConfigError::NotFound(_)) => HashMap::new(),
+        Err(e) => return Err(Error::Message(format!("aliases: {}", e))),
+    };
+    if let Ok(mut a) = ALIASES.lock() {
+        *a = Some(aliases);
+    }
+    Ok(())
+}
+
+/// The list or field name, id, or uuid that the alias stands for (ignoring case),
+/// or the name itself if it isn't an alias
+pub(crate) fn resolve(name: &str) -> String {
+    ALIASES
+        .lock()
+        .ok()
+        .and_then(|a| {
+            a.as_ref()?
+                .iter()
+                .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+                .map(|(_, target)| target.clone())
+        })
+        .unwrap_or_else(|| name.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(toml: &str) -> Config {
+        let mut settings = Config::default();
+        settings
+            .merge(config::File::from_str(toml, config::FileFormat::Toml))
+            .unwrap();
+        settings
+    }
+
+    #[test]
+    fn resolve_aliases() {
+        assert!(configure(&settings("aliases = 5")).is_err());
+        configure(&settings("[zenkit]\ntoken = \"0\"")).unwrap();
+        assert_eq!(resolve("sev"), "sev");
+
+        configure(&settings(
+            r#"
+            [aliases]
+            sev = "Severity"
+            bugs = "Engineering Bug Tracker 2024"
+            "#,
+        ))
+        .unwrap();
+        assert_eq!(resolve("sev"), "Severity");
+        // ignoring case
+        assert_eq!(resolve("BUGS"), "Engineering Bug Tracker 2024");
+        assert_eq!(resolve("Severity"), "Severity");
+    }
+}
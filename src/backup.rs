@@ -1,75 +1,1138 @@
-use crate::{BackupOpt, Error};
-use std::result::Result;
+use crate::{
+    activity::{get_entry_activities, FILTER_COMMENTS},
+    aliases, cache, dryrun, offline, progress,
+    rest::RestClient,
+    retry::retry,
+    s3::S3Client,
+    BackupOpt, Error, ListInfo,
+};
+use futures::{
+    future::try_join_all,
+    stream::{self, StreamExt},
+};
+use indicatif::ProgressBar;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{Seek, SeekFrom, Write},
+    path::Path,
+    process::Command,
+    result::Result,
+    sync::Mutex,
+};
 use tokio::fs;
-use zenkit::types::{Entry, GetEntriesRequest, ID};
+use zenkit::types::{
+    Element, ElementCategoryId, Entry, GetEntriesRequest, List, Workspace, ZKObjectID, ID,
+};
+
+/// Backup lists in the workspace (or only `opt.list`, if set) to the output folder.
+/// Lists are backed up concurrently, at most `opt.jobs` at a time.
+/// Progress is shown for the workspace (lists backed up) and for each list being backed up.
+/// If `upload` is set, each list's files are uploaded when the list is complete.
+pub(crate) async fn backup_workspace(
+    rest: &RestClient,
+    ws: &Workspace,
+    opt: &BackupOpt,
+    tstamp: u64,
+    checkpoint: &Checkpoint,
+    upload: Option<&S3Client>,
+) -> Result<BackupSummary, Error> {
+    let lists: Vec<&List> = match opt.list {
+        Some(ref lname) => vec![cache::find_list(&ws.lists, &aliases::resolve(lname))
+            .ok_or_else(|| Error::NotFound(format!("List '{}' not found", lname)))?],
+        // backup all lists
+        None => ws.lists.iter().collect(),
+    };
+    let jobs = tokio::sync::Semaphore::new(opt.jobs.max(1));
+    let bars = progress::multi();
+    let lists_done = bars.add(progress::bar(lists.len(), &ws.name));
+    lists_done.set_message("lists");
+    let (jobs, bars, lists_done) = (&jobs, &bars, &lists_done);
+    let lists: Vec<BackupItem> = try_join_all(lists.into_iter().map(|list| async move {
+        let _permit = jobs
+            .acquire()
+            .await
+            .map_err(|e| Error::Message(e.to_string()))?;
+        let items_done = bars.add(progress::counter(&list.name));
+        items_done.set_message("items");
+        let item = backup_list(rest, list, opt, &items_done, checkpoint, upload).await;
+        items_done.finish_and_clear();
+        bars.remove(&items_done);
+        lists_done.inc(1);
+        item
+    }))
+    .await?;
+    lists_done.finish_and_clear();
+    Ok(BackupSummary {
+        workspace: ws.name.clone(),
+        uuid: ws.uuid.clone(),
+        tstamp,
+        since: opt.since,
+        lists,
+    })
+}
+
+/// Write summary_<tstamp>.json to the folder, pretty-printed if `pretty` is set,
+/// or upload it if `upload` is set. Returns the summary json.
+pub(crate) async fn write_summary<T: serde::Serialize>(
+    dir: &str,
+    tstamp: u64,
+    summary: &T,
+    pretty: bool,
+    upload: Option<&S3Client>,
+) -> Result<Vec<u8>, Error> {
+    let summary_fname = format!("{}/summary_{}.json", dir, tstamp);
+    let summary_data = if pretty {
+        serde_json::to_vec_pretty(summary)
+    } else {
+        serde_json::to_vec(summary)
+    }
+    .map_err(|e| Error::Message(format!("Error generating summary: {}", e)))?;
+    save(upload, &summary_fname, summary_data.clone()).await?;
+    Ok(summary_data)
+}
+
+/// Object with a copy of the most recent summary uploaded to the destination,
+/// used to find the last backup for `--incremental`
+pub(crate) const LATEST_SUMMARY: &str = "latest_summary.json";
+
+/// Timestamp of the most recent backup of the folder `dir` uploaded to the destination
+pub(crate) async fn last_uploaded_tstamp(
+    upload: &S3Client,
+    dir: &str,
+) -> Result<Option<u64>, Error> {
+    let summary: Value = match upload.get(&format!("{}/{}", dir, LATEST_SUMMARY)).await? {
+        Some(data) => serde_json::from_slice(&data)?,
+        None => return Ok(None),
+    };
+    Ok(summary.get("tstamp").and_then(|v| v.as_u64()))
+}
+
+/// Write the file to the output folder, or upload it instead if `upload` is set
+async fn save(upload: Option<&S3Client>, path: &str, data: Vec<u8>) -> Result<(), Error> {
+    match upload {
+        Some(upload) => upload.put(path, data).await,
+        None => Ok(fs::write(path, data).await?),
+    }
+}
 
 /// Backup a list in json to three files in the output directory, named
 ///     <uuid>_list.json, <uuid>_fields.json, and <uuid>_items.json
+/// If `opt.with_comments` is set, comments are saved to <uuid>_comments.json,
+/// as a map of item uuid to the item's comments.
+/// If `opt.with_files` is set, attachments are downloaded (see `backup_files`).
+/// If `opt.since` is set, only items modified after that time are saved,
+/// and the items file is named <uuid>_items_since_<since>.json.
+/// The entries api has no filter on `updated_at`, so all items are still fetched
+/// from the server, and filtered here.
+/// Deleted items are not detected by incremental backups.
+/// Items and comments are written to their files as each batch is received,
+/// so memory use doesn't grow with the size of the list. `progress` counts the items received.
+/// Once the list's items are counted, up to `opt.page_jobs` batches are fetched
+/// concurrently, and written in order.
+/// The list's progress is saved to the checkpoint after each batch, with the
+/// files manifest so far. A list completed in the checkpoint is skipped, and a
+/// partially saved list is continued from the last batch saved.
+/// If `opt.git` is set, the files are then rewritten for stable diffs (see `normalize_json`).
+/// If `upload` is set, the files are uploaded instead of kept in the output folder.
+/// The items, comments, and files manifest are still written there as they're received,
+/// so the checkpoint works the same, and are uploaded and removed when the list is complete.
 // The data written is not exactly what was received from the server:
 //   It's been unserialized and then re-serialized. If there are
 //   missing fields in the (Element or List) struct definitions,
 //   they may be omitted from the json. (Entry has a catch-all 'fields' field,
 //   so it's not likely to be suceptible to this risk). Also, from manual review,
 //   it appears that all business (user-defined) fields are included in the definitions.
-pub(crate) async fn backup_list<'ws>(
-    ws_id: ID,
-    list_id: &str,
+pub(crate) async fn backup_list(
+    rest: &RestClient,
+    list: &List,
     opt: &BackupOpt,
-    //output_dir: &str,
+    progress: &ProgressBar,
+    checkpoint: &Checkpoint,
+    upload: Option<&S3Client>,
 ) -> Result<BackupItem, Error> {
+    // the fields are fetched from the api, not the cache, so they match the items
     let api = zenkit::get_api()?;
-    let list_info = api.get_list_info(ws_id, list_id).await.map_err(|e| {
-        crate::Error::Message(format!("Error loading list {}: {}", list_id, e.to_string()))
-    })?;
+    let fields = retry("get_list_elements", || api.get_list_elements(list.id)).await?;
+    let list_info = ListInfo::new(list.clone(), fields);
+    let list_uuid = &list_info.list().uuid;
+    progress.set_prefix(list_info.list().name.clone());
+    let checkpoint_key = format!("{}/{}", &opt.output, list_uuid);
+    let mut state = checkpoint.get(&checkpoint_key)?;
+    let backup_item = |state: &ListCheckpoint| BackupItem {
+        name: list_info.list().name.clone(),
+        uuid: list_uuid.clone(),
+        items: state.count,
+    };
+    if state.done {
+        return Ok(backup_item(&state));
+    }
 
-    let list_fname = format!("{}/{}_list.json", &opt.output, list_info.list().uuid);
-    let list_data = serde_json::to_string(list_info.list())?;
-    fs::write(list_fname, &list_data).await?;
+    let list_fname = format!("{}/{}_list.json", &opt.output, list_uuid);
+    let list_data = serde_json::to_vec(list_info.list())?;
+    save(upload, &list_fname, list_data).await?;
 
-    let fields_fname = format!("{}/{}_fields.json", &opt.output, &list_info.list().uuid);
-    let fields_data = serde_json::to_string(list_info.fields())?;
-    fs::write(fields_fname, &fields_data).await?;
+    let fields_fname = format!("{}/{}_fields.json", &opt.output, list_uuid);
+    let fields_data = serde_json::to_vec(list_info.fields())?;
+    save(upload, &fields_fname, fields_data).await?;
 
-    let items_fname = format!("{}/{}_items.json", &opt.output, &list_info.list().uuid);
-    let mut all_items: Vec<Entry> = Vec::new();
-    let max_items = 500usize; // items per iteeration
-    let mut start_index = 0usize;
+    let items_fname = match opt.since {
+        Some(since) => format!("{}/{}_items_since_{}.json", &opt.output, list_uuid, since),
+        None => format!("{}/{}_items.json", &opt.output, list_uuid),
+    };
+    let comments_fname = format!("{}/{}_comments.json", &opt.output, list_uuid);
+    let mut items_file = if state.offset > 0 {
+        JsonWriter::resume(&items_fname, JsonKind::Array, state.bytes, state.count)?
+    } else {
+        JsonWriter::create(&items_fname, JsonKind::Array)?
+    };
+    let mut comments_file = if !opt.with_comments {
+        None
+    } else if state.comments_bytes > 0 {
+        Some(JsonWriter::resume(
+            &comments_fname,
+            JsonKind::Object,
+            state.comments_bytes,
+            state.comments_count,
+        )?)
+    } else {
+        Some(JsonWriter::create(&comments_fname, JsonKind::Object)?)
+    };
+    progress.inc(state.offset as u64);
+    let manifest_fname = format!("{}/{}_files.json", &opt.output, list_uuid);
+    let mut manifest = if opt.with_files {
+        read_manifest(&manifest_fname).await?
+    } else {
+        BTreeMap::new()
+    };
+
+    let mut next_offset = state.offset;
+    // pages of the counted items are fetched concurrently, and handled in order
+    let total = if opt.page_jobs > 1 && !dryrun::enabled() {
+        count_items(rest, list_info.get_id(), opt.include_archived).await
+    } else {
+        None
+    };
+    let pages: Vec<usize> = match total {
+        Some(total) => (next_offset..total).step_by(BATCH_SIZE).collect(),
+        None => Vec::new(),
+    };
+    let mut prefetched = stream::iter(pages.into_iter().map(|start| async move {
+        get_items_batch(list_uuid, opt.include_archived, start)
+            .await
+            .map(|items| (start, items))
+    }))
+    .buffered(opt.page_jobs.max(1));
     loop {
-        // get the items and build the index
-        let mut batch_items: Vec<Entry> = api
-            .get_list_entries(
-                list_id,
-                &GetEntriesRequest {
-                    limit: max_items,
-                    skip: start_index,
-                    allow_deprecated: opt.include_archived,
-                    ..Default::default()
-                },
+        let mut batch_items = match prefetched.next().await {
+            // the pages were requested at fixed offsets, so a short page
+            // doesn't move the pages after it
+            Some(batch) => {
+                let (start, items) = batch?;
+                next_offset = start + BATCH_SIZE;
+                items
+            }
+            // then until the end of the list, including items added since it was counted
+            None => {
+                let items = get_items_batch(list_uuid, opt.include_archived, next_offset).await?;
+                if items.is_empty() {
+                    break;
+                }
+                next_offset += items.len();
+                items
+            }
+        };
+        if batch_items.is_empty() {
+            continue;
+        }
+        progress.inc(batch_items.len() as u64);
+        if let Some(since) = opt.since {
+            batch_items.retain(|item| item.updated_at.timestamp_millis() as u64 > since);
+        }
+        for item in batch_items.iter() {
+            items_file.write(item)?;
+        }
+        if let Some(comments_file) = comments_file.as_mut() {
+            for item in batch_items.iter().filter(|item| item.comment_count > 0) {
+                let item_comments =
+                    get_entry_activities(rest, list_info.get_id(), item.get_id(), FILTER_COMMENTS)
+                        .await?;
+                comments_file.write_entry(&item.uuid, &item_comments)?;
+            }
+        }
+        if opt.with_files {
+            backup_files(
+                rest,
+                list_uuid,
+                list_info.fields(),
+                &batch_items,
+                opt,
+                &mut manifest,
+                upload,
             )
-            .await
-            .map_err(|e| {
-                eprintln!(
-                    "Error getting items from list {} (start={})",
-                    list_id, start_index
-                );
-                e
-            })?;
+            .await?;
+        }
+        if opt.with_files {
+            fs::write(&manifest_fname, serde_json::to_string_pretty(&manifest)?).await?;
+        }
+        state.offset = next_offset;
+        state.bytes = items_file.position()?;
+        state.count = items_file.count;
+        if let Some(comments_file) = comments_file.as_mut() {
+            state.comments_bytes = comments_file.position()?;
+            state.comments_count = comments_file.count;
+        }
+        checkpoint.update(&checkpoint_key, &state)?;
+    }
+    state.count = items_file.count;
+    items_file.finish()?;
+    if let Some(upload) = upload {
+        upload.upload_file(Path::new(&items_fname)).await?;
+    }
+    if let Some(comments_file) = comments_file {
+        comments_file.finish()?;
+        if let Some(upload) = upload {
+            upload.upload_file(Path::new(&comments_fname)).await?;
+        }
+    }
+    if opt.with_files {
+        save(
+            upload,
+            &manifest_fname,
+            serde_json::to_vec_pretty(&manifest)?,
+        )
+        .await?;
+    }
+    state.done = true;
+    checkpoint.update(&checkpoint_key, &state)?;
+    if upload.is_some() {
+        // uploaded, so only the checkpoint is kept in the output folder
+        for path in [&items_fname, &comments_fname, &manifest_fname].iter() {
+            match fs::remove_file(path).await {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+    }
+    if opt.git {
+        for path in [&list_fname, &fields_fname, &items_fname].iter() {
+            normalize_json(path)?;
+        }
+        if opt.with_comments {
+            normalize_json(&comments_fname)?;
+        }
+    }
+    Ok(backup_item(&state))
+}
+
+/// Number of items requested per batch
+const BATCH_SIZE: usize = 500;
+
+/// Fetch all items of the list, in batches
+pub(crate) async fn get_all_items(
+    list_id: &str,
+    include_archived: bool,
+) -> Result<Vec<Entry>, Error> {
+    get_items_range(list_id, include_archived, 0, None).await
+}
+
+/// Fetch items of the list, in batches, skipping the first `skip` items,
+/// and stopping after `limit` items (if not None) or at the end of the list.
+pub(crate) async fn get_items_range(
+    list_id: &str,
+    include_archived: bool,
+    skip: usize,
+    limit: Option<usize>,
+) -> Result<Vec<Entry>, Error> {
+    let mut all_items: Vec<Entry> = Vec::new();
+    for_each_items_batch(list_id, include_archived, skip, limit, |mut batch| {
+        all_items.append(&mut batch);
+        Ok(())
+    })
+    .await?;
+    Ok(all_items)
+}
+
+/// Fetch items of the list as in `get_items_range`, calling `f` with each batch
+/// as it is received. Returns the number of items.
+/// In offline mode, the items are read from the backup, and `f` is called once.
+pub(crate) async fn for_each_items_batch<F>(
+    list_id: &str,
+    include_archived: bool,
+    skip: usize,
+    limit: Option<usize>,
+    mut f: F,
+) -> Result<usize, Error>
+where
+    F: FnMut(Vec<Entry>) -> Result<(), Error>,
+{
+    if let Some(dir) = offline::backup_dir() {
+        let items: Vec<Entry> = offline::get_items(&dir, list_id, include_archived)?
+            .into_iter()
+            .skip(skip)
+            .take(limit.unwrap_or(usize::MAX))
+            .collect();
+        let count = items.len();
+        if count > 0 {
+            f(items)?;
+        }
+        return Ok(count);
+    }
+    let mut count = 0;
+    while let Some(batch_size) = next_batch_size(limit, count) {
+        let batch_items =
+            get_items_page(list_id, include_archived, skip + count, batch_size).await?;
         if batch_items.is_empty() {
             break;
         }
-        start_index += batch_items.len();
-        all_items.append(&mut batch_items);
+        count += batch_items.len();
+        f(batch_items)?;
     }
-    let items_data = serde_json::to_string(&all_items)?;
-    fs::write(items_fname, &items_data).await?;
-    Ok(BackupItem {
-        name: list_info.list().name.clone(),
-        uuid: list_info.list().uuid.clone(),
+    Ok(count)
+}
+
+/// Number of items to request after `count` items were fetched, or None
+/// if `limit` items have been fetched
+fn next_batch_size(limit: Option<usize>, count: usize) -> Option<usize> {
+    match limit {
+        Some(limit) if limit <= count => None,
+        Some(limit) => Some((limit - count).min(BATCH_SIZE)),
+        None => Some(BATCH_SIZE),
+    }
+}
+
+/// Number of items in the list, or None if it couldn't be determined.
+/// (The count request is a POST, so it's skipped in dry-run mode.)
+async fn count_items(rest: &RestClient, list_id: ID, include_archived: bool) -> Option<usize> {
+    let body = serde_json::json!({
+        "limit": 1,
+        "skip": 0,
+        "allowDeprecated": include_archived,
+    });
+    let resp = rest
+        .post(&format!("lists/{}/entries/filter/list", list_id), &body)
+        .await
+        .ok()?;
+    let counts = resp.get("countData")?;
+    counts
+        .get("filteredTotal")
+        .or_else(|| counts.get("total"))
+        .and_then(|v| v.as_u64())
+        .map(|n| n as usize)
+}
+
+/// Fetch one batch of up to BATCH_SIZE items, starting at `start_index`.
+/// Returns an empty Vec after the last item.
+async fn get_items_batch(
+    list_id: &str,
+    include_archived: bool,
+    start_index: usize,
+) -> Result<Vec<Entry>, Error> {
+    get_items_page(list_id, include_archived, start_index, BATCH_SIZE).await
+}
+
+/// Fetch up to `limit` items, starting at `start_index`
+async fn get_items_page(
+    list_id: &str,
+    include_archived: bool,
+    start_index: usize,
+    limit: usize,
+) -> Result<Vec<Entry>, Error> {
+    let api = zenkit::get_api()?;
+    let request = GetEntriesRequest {
+        limit,
+        skip: start_index,
+        allow_deprecated: include_archived,
+        ..Default::default()
+    };
+    match retry("get_list_entries", || {
+        api.get_list_entries(list_id, &request)
     })
+    .await
+    {
+        Ok(items) => Ok(items),
+        Err(e) => {
+            eprintln!(
+                "Error getting items from list {} (start={})",
+                list_id, start_index
+            );
+            Err(e)
+        }
+    }
+}
+
+/// Json value written by a `JsonWriter`
+#[derive(Clone, Copy, Debug)]
+enum JsonKind {
+    Array,
+    Object,
+}
+
+/// Writes a json array or object to a file, one element (or entry) at a time
+struct JsonWriter {
+    out: std::io::BufWriter<std::fs::File>,
+    kind: JsonKind,
+    count: usize,
+}
+
+impl JsonWriter {
+    fn create(path: &str, kind: JsonKind) -> Result<Self, Error> {
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        out.write_all(match kind {
+            JsonKind::Array => b"[",
+            JsonKind::Object => b"{",
+        })?;
+        Ok(JsonWriter {
+            out,
+            kind,
+            count: 0,
+        })
+    }
+
+    /// Continue writing a value that was interrupted: the file is truncated to `bytes`,
+    /// the length after `count` elements were written.
+    fn resume(path: &str, kind: JsonKind, bytes: u64, count: usize) -> Result<Self, Error> {
+        let mut file = std::fs::OpenOptions::new().write(true).open(path)?;
+        file.set_len(bytes)?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(JsonWriter {
+            out: std::io::BufWriter::new(file),
+            kind,
+            count,
+        })
+    }
+
+    /// Length of the file, after writing buffered data
+    fn position(&mut self) -> Result<u64, Error> {
+        self.out.flush()?;
+        Ok(self.out.get_ref().metadata()?.len())
+    }
+
+    /// Append an element to an array
+    fn write<T: serde::Serialize>(&mut self, value: &T) -> Result<(), Error> {
+        if self.count > 0 {
+            self.out.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut self.out, value)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Append an entry to an object
+    fn write_entry<T: serde::Serialize>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        self.write(&key)?;
+        self.out.write_all(b":")?;
+        serde_json::to_writer(&mut self.out, value)?;
+        Ok(())
+    }
+
+    /// Close the array or object
+    fn finish(mut self) -> Result<(), Error> {
+        self.out.write_all(match self.kind {
+            JsonKind::Array => b"]",
+            JsonKind::Object => b"}",
+        })?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Name of the checkpoint file, in the backup output folder
+pub(crate) const CHECKPOINT_FILE: &str = "backup_checkpoint.json";
+
+/// Progress of a backup, saved to the output folder after each batch of items
+/// so that a failed backup can be continued with `--resume`.
+/// The checkpoint is removed when the backup completes.
+pub(crate) struct Checkpoint {
+    path: String,
+    data: Mutex<CheckpointData>,
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+struct CheckpointData {
+    /// start time of the backup, used in summary and archive file names
+    tstamp: u64,
+    since: Option<u64>,
+    /// "<output folder>/<list uuid>" -> list progress
+    lists: BTreeMap<String, ListCheckpoint>,
+}
+
+#[derive(Debug, Default, Clone, serde::Serialize, serde::Deserialize)]
+struct ListCheckpoint {
+    /// true if the list's backup is complete
+    done: bool,
+    /// offset of the next batch of items to fetch
+    offset: usize,
+    /// length of the items file, and number of items in it
+    bytes: u64,
+    count: usize,
+    /// length of the comments file, and number of items with comments in it
+    #[serde(default)]
+    comments_bytes: u64,
+    #[serde(default)]
+    comments_count: usize,
+}
+
+impl Checkpoint {
+    /// Start a checkpoint for a new backup, saved in the folder
+    pub(crate) fn create(dir: &str, tstamp: u64, since: Option<u64>) -> Result<Self, Error> {
+        let checkpoint = Checkpoint {
+            path: format!("{}/{}", dir, CHECKPOINT_FILE),
+            data: Mutex::new(CheckpointData {
+                tstamp,
+                since,
+                lists: BTreeMap::new(),
+            }),
+        };
+        {
+            let data = checkpoint.lock()?;
+            checkpoint.save(&data)?;
+        }
+        Ok(checkpoint)
+    }
+
+    /// Load the checkpoint of an unfinished backup in the folder
+    pub(crate) fn load(dir: &str) -> Result<Self, Error> {
+        let path = format!("{}/{}", dir, CHECKPOINT_FILE);
+        let data = std::fs::read(&path)
+            .map_err(|_| Error::NotFound(format!("No unfinished backup to resume in {}", dir)))?;
+        Ok(Checkpoint {
+            path,
+            data: Mutex::new(
+                serde_json::from_slice(&data).map_err(|e| {
+                    Error::Invalid(format!("Invalid checkpoint file {}: {}", path, e))
+                })?,
+            ),
+        })
+    }
+
+    /// Start time of the backup
+    pub(crate) fn tstamp(&self) -> Result<u64, Error> {
+        Ok(self.lock()?.tstamp)
+    }
+
+    /// The `--since` time of the backup, if it's incremental
+    pub(crate) fn since(&self) -> Result<Option<u64>, Error> {
+        Ok(self.lock()?.since)
+    }
+
+    /// Delete the checkpoint file, after the backup is complete
+    pub(crate) fn remove(self) -> Result<(), Error> {
+        std::fs::remove_file(&self.path)?;
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<ListCheckpoint, Error> {
+        Ok(self.lock()?.lists.get(key).cloned().unwrap_or_default())
+    }
+
+    fn update(&self, key: &str, state: &ListCheckpoint) -> Result<(), Error> {
+        let mut data = self.lock()?;
+        data.lists.insert(key.to_string(), state.clone());
+        self.save(&data)
+    }
+
+    fn lock(&self) -> Result<std::sync::MutexGuard<'_, CheckpointData>, Error> {
+        self.data
+            .lock()
+            .map_err(|_| Error::Message("Backup checkpoint lock failed".into()))
+    }
+
+    /// Write the checkpoint to a temporary file, then rename it, so an
+    /// interrupted write doesn't leave a truncated checkpoint
+    fn save(&self, data: &CheckpointData) -> Result<(), Error> {
+        let tmp = format!("{}.tmp", &self.path);
+        std::fs::write(&tmp, serde_json::to_string(data)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(())
+    }
+}
+
+/// Rewrite a json file so that backups of unchanged data are identical: pretty-printed,
+/// with object keys sorted, and if it's an array of objects with ids (items or fields),
+/// the objects sorted by id.
+fn normalize_json(path: &str) -> Result<(), Error> {
+    let mut value = sort_keys(serde_json::from_slice(&std::fs::read(path)?)?);
+    if let Value::Array(ref mut values) = value {
+        values.sort_by_key(|v| v.get("id").and_then(|id| id.as_u64()));
+    }
+    std::fs::write(path, serde_json::to_string_pretty(&value)? + "\n")?;
+    Ok(())
+}
+
+/// The value with the keys of all objects in sorted order
+fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries: Vec<(String, Value)> =
+                map.into_iter().map(|(k, v)| (k, sort_keys(v))).collect();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(entries.into_iter().collect())
+        }
+        Value::Array(values) => Value::Array(values.into_iter().map(sort_keys).collect()),
+        value => value,
+    }
+}
+
+/// Commit all changes in the folder to its git repository, creating the repository
+/// if there isn't one. Returns false if there were no changes to commit.
+/// The checkpoint and downloaded attachments are ignored (in .gitignore);
+/// the files manifests, with their checksums, are committed.
+/// If git has no user name or email configured, the commit is made by zenkit-cli.
+pub(crate) fn git_commit(dir: &str, message: &str) -> Result<bool, Error> {
+    let git = |args: &[&str]| -> Result<std::process::Output, Error> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .map_err(|e| Error::Message(format!("Error running git: {}", e)))?;
+        if !output.status.success() {
+            return Err(Error::Message(format!(
+                "git {} failed: {}",
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        Ok(output)
+    };
+    if !Path::new(dir).join(".git").exists() {
+        git(&["init", "-q"])?;
+    }
+    let ignore = Path::new(dir).join(".gitignore");
+    if !ignore.exists() {
+        std::fs::write(&ignore, format!("/{}\n/files/\n", CHECKPOINT_FILE))?;
+    }
+    git(&["add", "-A"])?;
+    if git(&["status", "--porcelain"])?.stdout.is_empty() {
+        return Ok(false);
+    }
+    let mut args = Vec::new();
+    if git(&["config", "user.name"]).is_err() {
+        args.extend(&["-c", "user.name=zenkit-cli"]);
+    }
+    if git(&["config", "user.email"]).is_err() {
+        args.extend(&["-c", "user.email=zenkit-cli@localhost"]);
+    }
+    args.extend(&["commit", "-q", "-m", message]);
+    git(&args)?;
+    Ok(true)
+}
+
+/// File manifest: path (relative to output folder) -> file info
+type FileManifest = BTreeMap<String, FileManifestEntry>;
+
+/// Read the files manifest <list-uuid>_files.json, if present
+async fn read_manifest(path: &str) -> Result<FileManifest, Error> {
+    match fs::read(path).await {
+        Ok(data) => serde_json::from_slice(&data)
+            .map_err(|e| Error::Invalid(format!("Invalid files manifest {}: {}", path, e))),
+        Err(_) => Ok(BTreeMap::new()),
+    }
+}
+
+/// Download attachments of the list's file fields into <output>/files/<item-uuid>/,
+/// or upload them there if `upload` is set.
+/// File paths and sha256 checksums are recorded in the manifest.
+/// Files already present with a matching checksum are not downloaded again.
+async fn backup_files(
+    rest: &RestClient,
+    list_uuid: &str,
+    fields: &[Element],
+    items: &[Entry],
+    opt: &BackupOpt,
+    manifest: &mut FileManifest,
+    upload: Option<&S3Client>,
+) -> Result<(), Error> {
+    let file_fields: Vec<&Element> = fields
+        .iter()
+        .filter(|f| f.element_category == ElementCategoryId::Files)
+        .collect();
+    for item in items.iter() {
+        // file names used in the item's folder
+        let mut names = HashSet::new();
+        for field in file_fields.iter() {
+            let files = match item
+                .fields
+                .get(&format!("{}_files_sort", field.uuid))
+                .and_then(|v| v.as_array())
+            {
+                Some(files) => files,
+                None => continue,
+            };
+            for file in files.iter() {
+                let (file_id, file_uuid, file_name) = match (
+                    file.get("id").and_then(|v| v.as_u64()),
+                    file.get("uuid").and_then(|v| v.as_str()),
+                    file.get("fileName").and_then(|v| v.as_str()),
+                ) {
+                    (Some(id), Some(uuid), Some(name)) => (id, uuid, name),
+                    _ => continue,
+                };
+                // files with the same name are prefixed with their id
+                let mut file_name = safe_file_name(file_name);
+                if !names.insert(file_name.clone()) {
+                    file_name = format!("{}_{}", file_id, file_name);
+                    names.insert(file_name.clone());
+                }
+                let rel_path = format!("files/{}/{}", item.uuid, file_name);
+                let path = format!("{}/{}", &opt.output, rel_path);
+                if let Some(prev) = manifest.get(&rel_path) {
+                    if prev.file_uuid == file_uuid {
+                        if let Ok(data) = fs::read(&path).await {
+                            if sha256_hex(&data) == prev.sha256 {
+                                continue;
+                            }
+                        }
+                    }
+                }
+                let data = rest
+                    .get_bytes(&format!("lists/{}/files/{}", list_uuid, file_id))
+                    .await?;
+                let entry = FileManifestEntry {
+                    file_uuid: file_uuid.to_string(),
+                    item_uuid: item.uuid.clone(),
+                    size: data.len() as u64,
+                    sha256: sha256_hex(&data),
+                };
+                if upload.is_none() {
+                    fs::create_dir_all(format!("{}/files/{}", &opt.output, item.uuid)).await?;
+                }
+                save(upload, &path, data.to_vec()).await?;
+                manifest.insert(rel_path, entry);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The attachment's name, usable as a file name in a folder: path separators and
+/// control characters are replaced with '_', and leading dots are removed, so that
+/// the file can't be written outside the folder or be hidden
+pub(crate) fn safe_file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    let name = name.trim().trim_start_matches('.');
+    if name.is_empty() {
+        "file".to_string()
+    } else {
+        name.to_string()
+    }
+}
+
+pub(crate) fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub(crate) struct FileManifestEntry {
+    pub file_uuid: String,
+    pub item_uuid: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// Returns the timestamp of the most recent backup summary (summary_<tstamp>.json)
+/// or backup archive (backup_<tstamp>.<ext>) in the folder, or None if there are none.
+pub(crate) fn last_backup_tstamp(dir: &str) -> Result<Option<u64>, Error> {
+    let mut last = None;
+    for entry in std::fs::read_dir(dir)? {
+        let tstamp = entry?.file_name().to_str().and_then(backup_tstamp);
+        last = last.max(tstamp);
+    }
+    Ok(last)
+}
+
+/// Timestamp of a backup summary (summary_<tstamp>.json) or archive (backup_<tstamp>.<ext>)
+/// from its file name
+pub(crate) fn backup_tstamp(name: &str) -> Option<u64> {
+    name.strip_prefix("summary_")
+        .or_else(|| name.strip_prefix("backup_"))
+        .and_then(|name| name.split('.').next())
+        .and_then(|ts| ts.parse::<u64>().ok())
+}
+
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct BackupSummary {
+    workspace: String,
+    uuid: String,
+    tstamp: u64,
+    /// for incremental backups, the lower bound on item modification time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    since: Option<u64>,
+    lists: Vec<BackupItem>,
+}
+
+/// Summary of a backup of all workspaces (`--all-workspaces`).
+/// Each workspace folder also contains its own summary.
+#[derive(Debug, serde::Serialize)]
+pub(crate) struct AccountSummary {
+    pub tstamp: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub since: Option<u64>,
+    pub workspaces: Vec<BackupSummary>,
 }
 
 #[derive(Debug, serde::Serialize)]
 pub(crate) struct BackupItem {
     name: String,
     uuid: String,
+    /// number of items written to the items file
+    items: usize,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum ArchiveFormat {
+    TarGz,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub(crate) fn extension(&self) -> &'static str {
+        match self {
+            ArchiveFormat::TarGz => "tar.gz",
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+}
+
+impl std::str::FromStr for ArchiveFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tar.gz" | "tgz" => Ok(ArchiveFormat::TarGz),
+            "zip" => Ok(ArchiveFormat::Zip),
+            _ => Err("Invalid archive format: should be tar.gz or zip"),
+        }
+    }
+}
+
+/// Pack all files in the folder `dir`, including subfolders, into a compressed archive.
+/// Paths in the archive are relative to `dir`.
+pub(crate) fn create_archive(dir: &str, archive: &str, format: ArchiveFormat) -> Result<(), Error> {
+    let file = std::fs::File::create(archive)?;
+    match format {
+        ArchiveFormat::TarGz => {
+            let gz = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+            let mut tar = tar::Builder::new(gz);
+            tar.append_dir_all(".", dir)?;
+            tar.into_inner()?.finish()?;
+        }
+        ArchiveFormat::Zip => {
+            let mut zip = zip::ZipWriter::new(file);
+            add_dir_to_zip(&mut zip, Path::new(dir), "")?;
+            zip.finish()?;
+        }
+    }
+    Ok(())
+}
+
+fn add_dir_to_zip(
+    zip: &mut zip::ZipWriter<std::fs::File>,
+    dir: &Path,
+    prefix: &str,
+) -> Result<(), Error> {
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{}{}", prefix, entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            zip.add_directory(name.as_str(), options)?;
+            add_dir_to_zip(zip, &entry.path(), &format!("{}/", name))?;
+        } else {
+            zip.start_file(name.as_str(), options)?;
+            zip.write_all(&std::fs::read(entry.path())?)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn attachment_file_names() {
+        assert_eq!(safe_file_name("report.pdf"), "report.pdf");
+        assert_eq!(safe_file_name("../../etc/passwd"), "_.._etc_passwd");
+        assert_eq!(safe_file_name("a\\b\nc.txt"), "a_b_c.txt");
+        assert_eq!(safe_file_name(".hidden"), "hidden");
+        assert_eq!(safe_file_name(" .. "), "file");
+    }
+
+    #[test]
+    fn sha256_known_answer() {
+        assert_eq!(
+            sha256_hex(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn archive_format_names() {
+        for (name, ext) in [("tar.gz", "tar.gz"), ("tgz", "tar.gz"), ("zip", "zip")].iter() {
+            assert_eq!(name.parse::<ArchiveFormat>().unwrap().extension(), *ext);
+        }
+        assert!("rar".parse::<ArchiveFormat>().is_err());
+    }
+
+    #[test]
+    fn zip_archive_paths() {
+        let dir = std::env::temp_dir().join(format!("zk_archive_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("ws-1")).unwrap();
+        std::fs::write(dir.join("summary_1.json"), "{}").unwrap();
+        std::fs::write(dir.join("ws-1").join("list-1_items.json"), "[]").unwrap();
+        let archive = format!("{}.zip", dir.display());
+        create_archive(dir.to_str().unwrap(), &archive, ArchiveFormat::Zip).unwrap();
+
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&archive).unwrap()).unwrap();
+        let mut names: Vec<String> = zip.file_names().map(String::from).collect();
+        names.sort();
+        assert_eq!(
+            names,
+            vec!["summary_1.json", "ws-1/", "ws-1/list-1_items.json"]
+        );
+        let mut items = String::new();
+        std::io::Read::read_to_string(
+            &mut zip.by_name("ws-1/list-1_items.json").unwrap(),
+            &mut items,
+        )
+        .unwrap();
+        assert_eq!(items, "[]");
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_file(&archive).unwrap();
+    }
+
+    #[test]
+    fn json_array_writer() {
+        let path = std::env::temp_dir().join(format!("zk_array_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        JsonWriter::create(path, JsonKind::Array)
+            .unwrap()
+            .finish()
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "[]");
+
+        let mut writer = JsonWriter::create(path, JsonKind::Array).unwrap();
+        for value in [serde_json::json!({"id": 1}), "two".into(), 3.into()].iter() {
+            writer.write(value).unwrap();
+        }
+        writer.finish().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            r#"[{"id":1},"two",3]"#
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn git_commits() {
+        let dir = std::env::temp_dir().join(format!("zk_git_{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("files/item-1")).unwrap();
+        std::fs::write(dir.join("list-1_items.json"), "[]").unwrap();
+        std::fs::write(dir.join(CHECKPOINT_FILE), "{}").unwrap();
+        std::fs::write(dir.join("files/item-1/a.pdf"), "pdf").unwrap();
+        let dir_name = dir.to_str().unwrap();
+        assert!(git_commit(dir_name, "first").unwrap());
+        assert!(!git_commit(dir_name, "no changes").unwrap());
+
+        let files = Command::new("git")
+            .arg("-C")
+            .arg(&dir)
+            .arg("ls-files")
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&files.stdout),
+            ".gitignore\nlist-1_items.json\n"
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn json_object_writer() {
+        let path = std::env::temp_dir().join(format!("zk_object_{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        JsonWriter::create(path, JsonKind::Object)
+            .unwrap()
+            .finish()
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(path).unwrap(), "{}");
+
+        let mut writer = JsonWriter::create(path, JsonKind::Object).unwrap();
+        writer.write_entry("item-1", &vec!["a", "b"]).unwrap();
+        let (bytes, count) = (writer.position().unwrap(), writer.count);
+        writer.write_entry("item-2", &vec!["c"]).unwrap();
+        drop(writer);
+
+        // resuming drops the entries written after the checkpoint
+        let mut writer = JsonWriter::resume(path, JsonKind::Object, bytes, count).unwrap();
+        writer.write_entry("item-3", &Vec::<&str>::new()).unwrap();
+        writer.finish().unwrap();
+        let data: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        assert_eq!(
+            data,
+            serde_json::json!({"item-1": ["a", "b"], "item-3": []})
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn account_summary() {
+        let dir = std::env::temp_dir().join(format!("zk_summary_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let workspace = BackupSummary {
+            workspace: "Home".into(),
+            uuid: "ws-1".into(),
+            tstamp: 1_614_556_800_000,
+            since: None,
+            lists: Vec::new(),
+        };
+        let summary = AccountSummary {
+            tstamp: 1_614_556_800_000,
+            since: Some(1_614_470_400_000),
+            workspaces: vec![workspace],
+        };
+        let data = write_summary(dir.to_str().unwrap(), summary.tstamp, &summary, false, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(dir.join("summary_1614556800000.json")).unwrap(),
+            data
+        );
+        assert_eq!(
+            serde_json::from_slice::<Value>(&data).unwrap(),
+            serde_json::json!({
+                "tstamp": 1_614_556_800_000u64,
+                "since": 1_614_470_400_000u64,
+                "workspaces": [{
+                    "workspace": "Home",
+                    "uuid": "ws-1",
+                    "tstamp": 1_614_556_800_000u64,
+                    "lists": [],
+                }],
+            })
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn batch_sizes() {
+        assert_eq!(next_batch_size(None, 0), Some(BATCH_SIZE));
+        assert_eq!(next_batch_size(None, 5000), Some(BATCH_SIZE));
+        assert_eq!(next_batch_size(Some(20), 0), Some(20));
+        assert_eq!(next_batch_size(Some(1200), 1000), Some(200));
+        assert_eq!(next_batch_size(Some(2000), 1000), Some(BATCH_SIZE));
+        assert_eq!(next_batch_size(Some(20), 20), None);
+        assert_eq!(next_batch_size(Some(0), 0), None);
+    }
 }
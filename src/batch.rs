@@ -0,0 +1,209 @@
+//! Run a file of commands in one process (`zk run --file`), sharing the api client
+//! and caches, instead of starting `zk` for each command
+use crate::{
+    dryrun,
+    rest::RestClient,
+    run_cmd,
+    shell::{expand, list_arg, parse_command, split_words},
+    txn, Error, RunOpt, Sub,
+};
+use std::{io, result::Result, time::Instant};
+
+/// A command from the file
+struct Command {
+    /// line number in the file
+    line: usize,
+    text: String,
+    cmd: Sub,
+}
+
+/// Run the commands in the file, in order. Lines are commands as entered in `zk shell`,
+/// including `use LIST`. Blank lines and lines starting with `#` are skipped, and
+/// `exit` or `quit` ends the file. All commands are parsed before any are run.
+/// After a command fails, the rest are skipped, unless `--continue` is used.
+/// With `--transactional`, the changes of the commands before the failed one are
+/// reverted, and the file may only contain commands whose changes can be reverted.
+/// A summary is printed to stderr at the end.
+pub(crate) async fn run_file(opt: &RunOpt, ws_name: &str, rest: &RestClient) -> Result<(), Error> {
+    let text = if opt.file == "-" {
+        let mut data = String::new();
+        io::Read::read_to_string(&mut io::stdin(), &mut data)?;
+        data
+    } else {
+        std::fs::read_to_string(&opt.file)
+            .map_err(|e| Error::Message(format!("Reading {}: {}", opt.file, e)))?
+    };
+    let commands = parse_commands(&opt.file, &text)?;
+    if opt.transactional {
+        let unsupported: Vec<String> = commands
+            .iter()
+            .filter(|command| !txn::revertible(&command.cmd))
+            .map(|command| format!("line {}: {}", command.line, command.text))
+            .collect();
+        if !unsupported.is_empty() {
+            return Err(Error::Invalid(format!(
+                "Changes of these commands can't be reverted, so they can't be used with --transactional. None were run:\n{}",
+                unsupported.join("\n")
+            )));
+        }
+    }
+
+    let start = Instant::now();
+    let total = commands.len();
+    let mut ran = 0;
+    let mut failed: Vec<(usize, String, String)> = Vec::new();
+    let mut first_error: Option<Error> = None;
+    if opt.transactional && !dryrun::enabled() {
+        txn::begin();
+    }
+    for command in commands {
+        ran += 1;
+        if let Err(e) = run_cmd(command.cmd, ws_name, rest).await {
+            eprintln!("Error (line {}): {}", command.line, e.summary());
+            failed.push((command.line, command.text, e.summary()));
+            first_error.get_or_insert(e);
+            if !opt.continue_on_error {
+                break;
+            }
+        }
+    }
+    if txn::active() {
+        if first_error.is_some() {
+            if let Err(e) = txn::rollback(rest).await {
+                eprintln!("Error: {}", e.summary());
+            }
+        } else {
+            txn::commit();
+        }
+    }
+    eprintln!(
+        "Ran {} of {} commands in {:.1}s: {} succeeded, {} failed",
+        ran,
+        total,
+        start.elapsed().as_secs_f64(),
+        ran - failed.len(),
+        failed.len()
+    );
+    for (line, text, message) in failed.iter() {
+        eprintln!("  line {}: {}: {}", line, text, message);
+    }
+    match first_error {
+        // the exit status is that of the failed command
+        Some(e) if !opt.continue_on_error => Err(e),
+        Some(_) => Err(Error::Message(format!(
+            "{} of {} commands failed",
+            failed.len(),
+            total
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Parse the commands in the file. Returns an error listing the lines that aren't
+/// valid commands, if any.
+fn parse_commands(file: &str, text: &str) -> Result<Vec<Command>, Error> {
+    let mut commands = Vec::new();
+    let mut errors = Vec::new();
+    let mut current: Option<String> = None;
+    for (n, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let words = match split_words(line) {
+            Ok(words) => words,
+            Err(e) => {
+                errors.push(format!("line {}: {}", n + 1, e.summary()));
+                continue;
+            }
+        };
+        match words[0].as_str() {
+            "exit" | "quit" => break,
+            "use" => {
+                current = words.get(1).cloned();
+                continue;
+            }
+            _ => {}
+        }
+        let args = expand(words, current.as_deref());
+        match parse_command(&args) {
+            Ok(Sub::Shell) | Ok(Sub::Run(_)) => errors.push(format!(
+                "line {}: {} can't be used in a command file",
+                n + 1,
+                args[0]
+            )),
+            Ok(cmd) => {
+                // the last list used becomes the current list, as in the shell
+                if let Some(list) = list_arg(&args) {
+                    current = Some(list.to_string());
+                }
+                commands.push(Command {
+                    line: n + 1,
+                    text: line.to_string(),
+                    cmd,
+                })
+            }
+            Err(e) => {
+                let message = e.to_string();
+                errors.push(format!(
+                    "line {}: {}",
+                    n + 1,
+                    message.lines().next().unwrap_or_default()
+                ))
+            }
+        }
+    }
+    if !errors.is_empty() {
+        return Err(Error::Invalid(format!(
+            "Invalid commands in {}, none were run:\n{}",
+            file,
+            errors.join("\n")
+        )));
+    }
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_file() {
+        let text = "# update tasks\n\
+                    use Tasks\n\
+                    \n\
+                    set 1 Notes 'two words'\n\
+                    items Other\n\
+                    set 2 Status Done\n\
+                    exit\n\
+                    not a command\n";
+        let commands = parse_commands("tasks.zk", text).unwrap();
+        let lines: Vec<usize> = commands.iter().map(|c| c.line).collect();
+        assert_eq!(lines, vec![4, 5, 6]);
+        assert_eq!(commands[0].text, "set 1 Notes 'two words'");
+        assert!(matches!(
+            &commands[0].cmd,
+            Sub::Set(opt) if opt.list == "Tasks" && opt.item == 1
+                && opt.value.as_deref() == Some("two words")
+        ));
+        assert!(matches!(&commands[1].cmd, Sub::Items(opt) if opt.list == "Other"));
+        // the last list used is the current list
+        assert!(matches!(&commands[2].cmd, Sub::Set(opt) if opt.list == "Other"));
+    }
+
+    #[test]
+    fn invalid_command_file() {
+        let text = "items Tasks\nfrobnicate\nset 1 'Notes\nshell\n";
+        match parse_commands("tasks.zk", text) {
+            Err(Error::Invalid(msg)) => {
+                let lines: Vec<&str> = msg.lines().collect();
+                assert_eq!(lines[0], "Invalid commands in tasks.zk, none were run:");
+                assert!(lines[1].starts_with("line 2: "));
+                assert_eq!(lines[2], "line 3: Unterminated quote");
+                assert_eq!(lines[3], "line 4: shell can't be used in a command file");
+                assert_eq!(lines.len(), 4);
+            }
+            _ => panic!("expected invalid commands"),
+        }
+    }
+}
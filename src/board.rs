@@ -0,0 +1,152 @@
+//! Board view of a list: items in columns by category
+use crate::{backup::get_all_items, cache, fuzzy, values::ValueFormatter, BoardOpt, Error};
+use std::result::Result;
+use zenkit::types::{ElementCategoryId, Entry, ZKObjectID, ID};
+
+/// Heading of the column of items with no category
+const NO_VALUE: &str = "(none)";
+/// Terminal width used if it can't be determined
+const DEFAULT_WIDTH: usize = 120;
+/// Minimum width of a column
+const MIN_COLUMN_WIDTH: usize = 12;
+/// Space between columns
+const GAP: &str = " | ";
+
+/// Print the list's items side by side in columns, one column per choice of the
+/// categories field. Item titles longer than the column are truncated.
+/// If there are too many columns for the width, the board is printed in several rows of columns.
+pub(crate) async fn print_board(ws_id: ID, opt: &BoardOpt) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let field = fuzzy::get_field(&list_info, &opt.by)?;
+    if field.element_category != ElementCategoryId::Categories {
+        return Err(Error::Invalid(format!(
+            "Field '{}' is not a categories field",
+            field.name
+        )));
+    }
+    let items = get_all_items(&list_info.list().uuid, false).await?;
+    let formatter = ValueFormatter::new(ws_id).await?;
+
+    let mut columns: Vec<(String, Vec<&Entry>)> = field
+        .element_data
+        .predefined_categories
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .map(|c| (c.name.clone(), Vec::new()))
+        .collect();
+    let mut uncategorized = Vec::new();
+    for item in items.iter() {
+        let values = formatter.display_values(item, field);
+        if values.is_empty() {
+            uncategorized.push(item);
+        }
+        for value in values {
+            match columns.iter_mut().find(|(name, _)| *name == value) {
+                Some((_, cards)) => cards.push(item),
+                None => columns.push((value, vec![item])),
+            }
+        }
+    }
+    if !uncategorized.is_empty() {
+        columns.push((NO_VALUE.to_string(), uncategorized));
+    }
+    if opt.hide_empty {
+        columns.retain(|(_, cards)| !cards.is_empty());
+    }
+    if columns.is_empty() {
+        return Ok(());
+    }
+
+    let (per_row, column_width) = layout(opt.width.unwrap_or_else(terminal_width), columns.len());
+    for (n, row) in columns.chunks(per_row).enumerate() {
+        if n > 0 {
+            println!();
+        }
+        print_row(
+            row.iter()
+                .map(|(name, cards)| format!("{} ({})", name, cards.len())),
+            column_width,
+        );
+        print_row(row.iter().map(|_| "-".repeat(column_width)), column_width);
+        let height = row.iter().map(|(_, cards)| cards.len()).max().unwrap_or(0);
+        for i in 0..height {
+            print_row(
+                row.iter().map(|(_, cards)| {
+                    cards
+                        .get(i)
+                        .map(|item| format!("{} {}", item.get_id(), item.display_string))
+                        .unwrap_or_default()
+                }),
+                column_width,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Number of columns per row (as many as fit the width), and the width of each column
+fn layout(width: usize, columns: usize) -> (usize, usize) {
+    let per_row = ((width + GAP.len()) / (MIN_COLUMN_WIDTH + GAP.len())).clamp(1, columns);
+    (per_row, (width + GAP.len()) / per_row - GAP.len())
+}
+
+/// Print one line of the board: cells truncated or padded to the column width
+fn print_row<I: Iterator<Item = String>>(cells: I, width: usize) {
+    let line = cells
+        .map(|cell| fit(&cell, width))
+        .collect::<Vec<_>>()
+        .join(GAP);
+    println!("{}", line.trim_end());
+}
+
+/// Text on one line, truncated (with "...") or padded with spaces to the width
+fn fit(text: &str, width: usize) -> String {
+    let chars: Vec<char> = text
+        .chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .collect();
+    if chars.len() > width {
+        let mut s: String = chars[..width.saturating_sub(3)].iter().collect();
+        s.push_str("...");
+        s
+    } else {
+        let mut s: String = chars.iter().collect();
+        s.push_str(&" ".repeat(width - chars.len()));
+        s
+    }
+}
+
+/// Terminal width from the COLUMNS environment variable, or DEFAULT_WIDTH
+fn terminal_width() -> usize {
+    std::env::var("COLUMNS")
+        .ok()
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(DEFAULT_WIDTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_per_row() {
+        // 3 columns of 38 and 2 gaps of 3 in 120
+        assert_eq!(layout(120, 3), (3, 38));
+        // at most 8 columns of 12 fit in 120
+        assert_eq!(layout(120, 10), (8, 12));
+        // at least one column per row, however narrow
+        assert_eq!(layout(10, 4), (1, 10));
+    }
+
+    #[test]
+    fn fit_to_width() {
+        assert_eq!(fit("Write tests", 14), "Write tests   ");
+        assert_eq!(fit("Write tests", 11), "Write tests");
+        assert_eq!(fit("Write tests", 8), "Write...");
+        // on one line
+        assert_eq!(fit("two\nlines", 9), "two lines");
+        // by characters, not bytes
+        assert_eq!(fit("Überprüfung", 7), "Über...");
+    }
+}
@@ -0,0 +1,181 @@
+//! Update many items at once
+use crate::{
+    backup::get_all_items,
+    cache, dryrun, fuzzy, parse_setval, progress,
+    rest::RestClient,
+    txn,
+    values::{resolve_values, ValueFormatter},
+    ArchiveOpt, BulkSetOpt, Error, ListInfo,
+};
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use std::result::Result;
+use zenkit::types::{Element, Entry, ZKObjectID, ID};
+
+/// Set a field on every item of the list matching all the filters.
+/// With `--dry-run`, the update request for each matching item is printed instead.
+/// With `--transactional`, or in a transaction, the first failed update stops the
+/// command; with `--transactional`, the items already updated are then reverted.
+pub(crate) async fn bulk_set(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &BulkSetOpt,
+) -> Result<BulkSummary, Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    // resolve the field name and value before fetching items
+    let mut values = vec![(
+        opt.field.clone(),
+        parse_setval(opt.value.clone()),
+        opt.action.update_action(),
+    )];
+    resolve_values(rest, ws_id, &list_info, &mut values).await?;
+    let items = matching_items(ws_id, &list_info, &opt.filter).await?;
+
+    let mut summary = BulkSummary::default();
+    // in a `run --transactional` batch, the batch is reverted instead
+    let own_txn = opt.transactional && !dryrun::enabled() && !txn::active();
+    if own_txn {
+        txn::begin();
+    }
+    for item in items.iter() {
+        if dryrun::enabled() {
+            dryrun::print_item_request(&list_info, Some(item.get_id()), &values).await?;
+            summary.updated += 1;
+            continue;
+        }
+        txn::record_update(ws_id, &list_info, item, &values)?;
+        match list_info.update_item(item.get_id(), &values).await {
+            Ok(_) => summary.updated += 1,
+            Err(e) => {
+                eprintln!("Error updating item {}: {}", item.id, e.summary());
+                summary.failed.push(item.get_id());
+                if txn::active() {
+                    if own_txn {
+                        if let Err(e) = txn::rollback(rest).await {
+                            eprintln!("Error: {}", e.summary());
+                        }
+                    }
+                    return Err(Error::Message(format!(
+                        "Failed to update item {}: {}",
+                        item.id,
+                        e.summary()
+                    )));
+                }
+            }
+        }
+    }
+    if own_txn {
+        txn::commit();
+    }
+    Ok(summary)
+}
+
+/// Archive (deprecate) every item of the list matching all the filters, and, with
+/// `--older-than`, not updated in that time. Items are archived in batches of
+/// `--batch-size`, with progress shown after each batch.
+/// With `--dry-run`, the archive request for each matching item is printed instead.
+pub(crate) async fn bulk_archive(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &ArchiveOpt,
+) -> Result<BulkSummary, Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let items = matching_items(ws_id, &list_info, &opt.filter).await?;
+    let cutoff = opt
+        .older_than
+        .map(chrono::Duration::from_std)
+        .transpose()
+        .map_err(|e| Error::Invalid(format!("Invalid --older-than: {}", e)))?
+        .map(|age| Utc::now() - age);
+    let items = updated_before(items, cutoff);
+
+    let mut summary = BulkSummary::default();
+    let done = progress::bar(items.len(), "Archiving");
+    for batch in items.chunks(opt.batch_size.max(1)) {
+        for item in batch.iter() {
+            match deprecate_item(rest, list_info.get_id(), item.get_id()).await {
+                Ok(()) => summary.updated += 1,
+                Err(e) => {
+                    eprintln!("Error archiving item {}: {}", item.id, e.summary());
+                    summary.failed.push(item.get_id());
+                }
+            }
+        }
+        done.inc(batch.len() as u64);
+        done.set_message(format!("items ({} failed)", summary.failed.len()));
+    }
+    done.finish_and_clear();
+    Ok(summary)
+}
+
+/// Items not updated since the cutoff, or all items if there's no cutoff
+fn updated_before(items: Vec<Entry>, cutoff: Option<DateTime<Utc>>) -> Vec<Entry> {
+    items
+        .into_iter()
+        .filter(|item| cutoff.map(|c| *item.updated_at < c).unwrap_or(true))
+        .collect()
+}
+
+/// Archive (deprecate) an item
+pub(crate) async fn deprecate_item(
+    rest: &RestClient,
+    list_id: ID,
+    item_id: ID,
+) -> Result<(), Error> {
+    let body = json!({ "deprecated_at": Utc::now().to_rfc3339() });
+    rest.put(&format!("lists/{}/entries/{}", list_id, item_id), &body)
+        .await?;
+    Ok(())
+}
+
+/// Active items of the list with all the values in `filters` (Field=Value)
+pub(crate) async fn matching_items(
+    ws_id: ID,
+    list_info: &ListInfo,
+    filters: &[(String, String)],
+) -> Result<Vec<Entry>, Error> {
+    let filters: Vec<(&Element, &str)> = filters
+        .iter()
+        .map(|(name, value)| Ok((fuzzy::get_field(list_info, name)?, value.as_str())))
+        .collect::<Result<_, Error>>()?;
+    let formatter = ValueFormatter::new(ws_id).await?;
+    Ok(get_all_items(&list_info.list().uuid, false)
+        .await?
+        .into_iter()
+        .filter(|item| {
+            filters
+                .iter()
+                .all(|(field, value)| formatter.has_value(item, field, value))
+        })
+        .collect())
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct BulkSummary {
+    /// number of items updated or archived (or matched, for a dry run)
+    pub updated: usize,
+    /// ids of items that couldn't be updated or archived
+    pub failed: Vec<ID>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::item;
+    use chrono::TimeZone;
+
+    #[test]
+    fn items_updated_before() {
+        let items = || {
+            vec![
+                item(1, "old", json!({"updated_at": "2021-01-01T00:00:00Z"})),
+                item(2, "new", json!({"updated_at": "2021-03-02T00:00:00Z"})),
+            ]
+        };
+        let ids = |items: Vec<Entry>| items.iter().map(|i| i.get_id()).collect::<Vec<_>>();
+        let cutoff = Utc.ymd(2021, 3, 1).and_hms(0, 0, 0);
+        assert_eq!(ids(updated_before(items(), Some(cutoff))), vec![1]);
+        assert_eq!(ids(updated_before(items(), None)), vec![1, 2]);
+    }
+}
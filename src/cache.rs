@@ -0,0 +1,276 @@
+//! On-disk cache of workspace, list, and field metadata
+use crate::{aliases, backup::sha256_hex, fuzzy, offline, retry::retry, Error, ListInfo};
+use chrono::Utc;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    result::Result,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tracing::debug;
+use zenkit::types::{List, Workspace, ID};
+
+/// File with the workspaces and their lists
+const WORKSPACES_FILE: &str = "workspaces.json";
+
+static SETTINGS: Mutex<Option<CacheSettings>> = Mutex::new(None);
+
+#[derive(Debug, Clone)]
+pub(crate) struct CacheSettings {
+    /// Cache folder for the account (api token and endpoint)
+    pub dir: PathBuf,
+    /// Maximum age of cached metadata
+    pub ttl: Duration,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedWorkspaces {
+    /// unix time
+    fetched_at: i64,
+    workspaces: Vec<Workspace>,
+}
+
+/// Fields of a workspace's lists, by list uuid
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct CachedFields {
+    lists: HashMap<String, ListFields>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ListFields {
+    /// unix time
+    fetched_at: i64,
+    /// the list's fields (Vec<Element>)
+    fields: serde_json::Value,
+}
+
+/// Enable the cache, in a folder for the account under `base`, or disable it (None).
+pub(crate) fn configure(base: Option<PathBuf>, ttl: Duration, endpoint: &str, token: &str) {
+    let settings = base.map(|base| CacheSettings {
+        // don't store the token
+        dir: base.join(&sha256_hex(format!("{} {}", endpoint, token).as_bytes())[..16]),
+        ttl,
+    });
+    if let Ok(mut s) = SETTINGS.lock() {
+        *s = settings;
+    }
+}
+
+/// Default cache folder: `$XDG_CACHE_HOME/zenkit-cli`, or `~/.cache/zenkit-cli`
+pub(crate) fn default_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+        .map(|dir| dir.join("zenkit-cli"))
+}
+
+fn settings() -> Option<CacheSettings> {
+    SETTINGS.lock().ok().and_then(|s| s.clone())
+}
+
+/// Remove all cached metadata for the account
+pub(crate) fn clear() -> Result<(), Error> {
+    if let Some(settings) = settings() {
+        if settings.dir.exists() {
+            std::fs::remove_dir_all(&settings.dir).map_err(|e| {
+                Error::Message(format!("Removing cache {}: {}", settings.dir.display(), e))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// The workspace with the name, id, or uuid, from the cache if it's fresh
+pub(crate) async fn get_workspace(name: &str) -> Result<Arc<Workspace>, Error> {
+    let api = zenkit::get_api()?;
+    let settings = match settings() {
+        Some(settings) => settings,
+        None => return retry("get_workspace", || api.get_workspace(name)).await,
+    };
+    if let Some(ws) = cached_workspaces(&settings).and_then(|all| find_workspace(all, name)) {
+        return Ok(Arc::new(ws));
+    }
+    let all = retry("get_all_workspaces_and_lists", || {
+        api.get_all_workspaces_and_lists()
+    })
+    .await?;
+    let workspaces: Vec<Workspace> = all.iter().map(|ws| ws.as_ref().clone()).collect();
+    save(
+        &settings,
+        WORKSPACES_FILE,
+        &CachedWorkspaces {
+            fetched_at: Utc::now().timestamp(),
+            workspaces: workspaces.clone(),
+        },
+    );
+    match find_workspace(workspaces, name) {
+        Some(ws) => Ok(Arc::new(ws)),
+        // for the api's error message
+        None => Ok(retry("get_workspace", || api.get_workspace(name)).await?),
+    }
+}
+
+/// The list (name, id, or uuid) and its fields, from the cache if they're fresh.
+/// In offline mode, they're read from the backup.
+/// The name may be an alias from the config file. If the list isn't found, the error
+/// suggests lists with similar names, or with `--fuzzy`, the closest one is used.
+pub(crate) async fn get_list_info(ws_id: ID, list: &str) -> Result<ListInfo, Error> {
+    let list = &aliases::resolve(list);
+    match lookup_list_info(ws_id, list).await {
+        Ok(list_info) => Ok(list_info),
+        Err(e) => {
+            let names = match list_names(ws_id).await {
+                // not a missing list
+                Ok(names) if !names.iter().any(|n| n == list) => names,
+                _ => return Err(e),
+            };
+            let names: Vec<&str> = names.iter().map(|n| n.as_str()).collect();
+            match fuzzy::closest_match("List", list, &names)? {
+                Some(best) => lookup_list_info(ws_id, &best).await,
+                None => Err(e),
+            }
+        }
+    }
+}
+
+/// Names of the workspace's lists
+async fn list_names(ws_id: ID) -> Result<Vec<String>, Error> {
+    if let Some(dir) = offline::backup_dir() {
+        return Ok(offline::get_lists(&dir)?
+            .into_iter()
+            .map(|l| l.name)
+            .collect());
+    }
+    let cached = settings()
+        .and_then(|settings| cached_workspaces(&settings))
+        .and_then(|all| all.into_iter().find(|ws| ws.id == ws_id));
+    let lists = match cached {
+        Some(ws) => ws.lists,
+        None => {
+            let api = zenkit::get_api()?;
+            retry("get_all_workspaces_and_lists", || {
+                api.get_all_workspaces_and_lists()
+            })
+            .await?
+            .iter()
+            .find(|ws| ws.id == ws_id)
+            .map(|ws| ws.lists.clone())
+            .unwrap_or_default()
+        }
+    };
+    Ok(lists.into_iter().map(|l| l.name).collect())
+}
+
+async fn lookup_list_info(ws_id: ID, list: &str) -> Result<ListInfo, Error> {
+    if let Some(dir) = offline::backup_dir() {
+        return offline::get_list_info(&dir, list);
+    }
+    let api = zenkit::get_api()?;
+    let settings = match settings() {
+        Some(settings) => settings,
+        None => return fetch_list_info(ws_id, list).await,
+    };
+    let ws = cached_workspaces(&settings).and_then(|all| all.into_iter().find(|ws| ws.id == ws_id));
+    let found = ws
+        .as_ref()
+        .and_then(|ws| find_list(&ws.lists, list))
+        .cloned();
+    let (ws, found) = match (ws, found) {
+        (Some(ws), Some(found)) => (ws, found),
+        (ws, _) => {
+            let list_info = fetch_list_info(ws_id, list).await?;
+            if ws.is_some() {
+                // a list created since the workspace was cached
+                remove(&settings, WORKSPACES_FILE);
+            }
+            return Ok(list_info);
+        }
+    };
+    let file = format!("{}.json", ws.uuid);
+    let mut cached: CachedFields = load(&settings, &file).unwrap_or_default();
+    if let Some(entry) = cached.lists.remove(&found.uuid) {
+        if is_fresh(&settings, entry.fetched_at) {
+            if let Ok(fields) = serde_json::from_value(entry.fields) {
+                return Ok(ListInfo::new(found, fields));
+            }
+        }
+    }
+    let fields = retry("get_list_elements", || api.get_list_elements(found.id)).await?;
+    cached.lists.insert(
+        found.uuid.clone(),
+        ListFields {
+            fetched_at: Utc::now().timestamp(),
+            fields: serde_json::to_value(&fields)?,
+        },
+    );
+    save(&settings, &file, &cached);
+    Ok(ListInfo::new(found, fields))
+}
+
+/// The list and its fields, from the api
+async fn fetch_list_info(ws_id: ID, list: &str) -> Result<ListInfo, Error> {
+    let api = zenkit::get_api()?;
+    let found = retry("get_all_workspaces_and_lists", || {
+        api.get_all_workspaces_and_lists()
+    })
+    .await?
+    .iter()
+    .find(|ws| ws.id == ws_id)
+    .and_then(|ws| find_list(&ws.lists, list).cloned())
+    .ok_or_else(|| Error::NotFound(format!("List '{}' not found", list)))?;
+    let fields = retry("get_list_elements", || api.get_list_elements(found.id)).await?;
+    Ok(ListInfo::new(found, fields))
+}
+
+fn cached_workspaces(settings: &CacheSettings) -> Option<Vec<Workspace>> {
+    let cached: CachedWorkspaces = load(settings, WORKSPACES_FILE)?;
+    if is_fresh(settings, cached.fetched_at) {
+        Some(cached.workspaces)
+    } else {
+        None
+    }
+}
+
+fn find_workspace(workspaces: Vec<Workspace>, name: &str) -> Option<Workspace> {
+    workspaces
+        .into_iter()
+        .find(|ws| ws.name == name || ws.uuid == name || ws.id.to_string() == name)
+}
+
+/// The list with the name, id, or uuid
+pub(crate) fn find_list<'l>(lists: &'l [List], name: &str) -> Option<&'l List> {
+    lists
+        .iter()
+        .find(|l| l.name == name || l.uuid == name || l.id.to_string() == name)
+}
+
+fn is_fresh(settings: &CacheSettings, fetched_at: i64) -> bool {
+    let age = Utc::now().timestamp() - fetched_at;
+    age >= 0 && (age as u64) < settings.ttl.as_secs()
+}
+
+/// Read a cache file. Missing and invalid files are treated as not cached.
+fn load<T: DeserializeOwned>(settings: &CacheSettings, file: &str) -> Option<T> {
+    let data = std::fs::read(settings.dir.join(file)).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+/// Write a cache file. Errors are logged, and otherwise ignored: the cache is only
+/// an optimization.
+fn save<T: Serialize>(settings: &CacheSettings, file: &str, value: &T) {
+    let path = settings.dir.join(file);
+    let result = std::fs::create_dir_all(&settings.dir).and_then(|_| {
+        let data = serde_json::to_vec(value).map_err(std::io::Error::from)?;
+        std::fs::write(&path, data)
+    });
+    if let Err(e) = result {
+        debug!(path = %path.display(), error = %e, "cache write failed");
+    }
+}
+
+fn remove(settings: &CacheSettings, file: &str) {
+    let _ = std::fs::remove_file(settings.dir.join(file));
+}
@@ -0,0 +1,180 @@
+//! Item checklists
+use crate::{cache, rest::RestClient, ChecklistOpt, Error};
+use serde_json::{json, Value};
+use std::result::Result;
+use zenkit::types::ID;
+
+/// Print an item's checklist, after applying any changes in the options:
+/// items are added, removed, checked, unchecked, or toggled, in that order.
+/// Checklist items are matched by text or uuid.
+pub(crate) async fn update_checklist(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &ChecklistOpt,
+) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    // checklists aren't included in the zenkit crate's Entry
+    let path = format!("lists/{}/entries/{}", list_info.get_id(), opt.item);
+    let entry = rest.get(&path).await?;
+    let mut checklists: Vec<Value> = entry
+        .get("checklists")
+        .and_then(|v| v.as_array())
+        .cloned()
+        .unwrap_or_default();
+
+    let changed = !(opt.add.is_empty()
+        && opt.remove.is_empty()
+        && opt.check.is_empty()
+        && opt.uncheck.is_empty()
+        && opt.toggle.is_empty());
+    let pos = match find_checklist(&checklists, opt.checklist.as_deref())? {
+        Some(pos) => pos,
+        None if !opt.add.is_empty() => {
+            let name = opt.checklist.as_deref().unwrap_or("Checklist");
+            checklists.push(json!({ "name": name, "items": [] }));
+            checklists.len() - 1
+        }
+        None => {
+            return Err(Error::NotFound(match opt.checklist {
+                Some(ref name) => format!("Item {} has no checklist '{}'", opt.item, name),
+                None => format!("Item {} has no checklist", opt.item),
+            }))
+        }
+    };
+    {
+        let invalid = || Error::Invalid(format!("Invalid checklist in item {}", opt.item));
+        let items = checklists[pos]
+            .as_object_mut()
+            .ok_or_else(invalid)?
+            .entry("items")
+            .or_insert(Value::Null);
+        if items.is_null() {
+            *items = json!([]);
+        }
+        let items = items.as_array_mut().ok_or_else(invalid)?;
+        for text in opt.add.iter() {
+            items.push(json!({ "text": text, "checked": false }));
+        }
+        for text in opt.remove.iter() {
+            let i = find_item(items, text)?;
+            items.remove(i);
+        }
+        for text in opt.check.iter() {
+            let i = find_item(items, text)?;
+            items[i]["checked"] = true.into();
+        }
+        for text in opt.uncheck.iter() {
+            let i = find_item(items, text)?;
+            items[i]["checked"] = false.into();
+        }
+        for text in opt.toggle.iter() {
+            let i = find_item(items, text)?;
+            let checked = is_checked(&items[i]);
+            items[i]["checked"] = (!checked).into();
+        }
+    }
+    if changed {
+        rest.put(&path, &json!({ "checklists": checklists }))
+            .await?;
+    }
+    print_checklist(&checklists[pos]);
+    Ok(())
+}
+
+/// Index of the checklist with the name or uuid, or the only checklist if `name` is None
+fn find_checklist(checklists: &[Value], name: Option<&str>) -> Result<Option<usize>, Error> {
+    match name {
+        Some(name) => Ok(checklists.iter().position(|c| {
+            c.get("name").and_then(|v| v.as_str()) == Some(name)
+                || c.get("uuid").and_then(|v| v.as_str()) == Some(name)
+        })),
+        None if checklists.len() > 1 => Err(Error::Invalid(format!(
+            "Item has {} checklists ({}). Use --field to select one",
+            checklists.len(),
+            checklists
+                .iter()
+                .filter_map(|c| c.get("name").and_then(|v| v.as_str()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+        None if checklists.is_empty() => Ok(None),
+        None => Ok(Some(0)),
+    }
+}
+
+/// Index of the checklist item with the text or uuid
+fn find_item(items: &[Value], text: &str) -> Result<usize, Error> {
+    items
+        .iter()
+        .position(|item| {
+            item.get("text").and_then(|v| v.as_str()) == Some(text)
+                || item.get("uuid").and_then(|v| v.as_str()) == Some(text)
+        })
+        .ok_or_else(|| Error::NotFound(format!("Checklist item '{}' not found", text)))
+}
+
+fn is_checked(item: &Value) -> bool {
+    item.get("checked")
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false)
+}
+
+/// Print the checklist name, then one line per item: "[x] text" or "[ ] text"
+fn print_checklist(checklist: &Value) {
+    if let Some(name) = checklist.get("name").and_then(|v| v.as_str()) {
+        println!("{}", name);
+    }
+    for item in checklist
+        .get("items")
+        .and_then(|v| v.as_array())
+        .map(|v| v.as_slice())
+        .unwrap_or_default()
+    {
+        println!(
+            "[{}] {}",
+            if is_checked(item) { "x" } else { " " },
+            item.get("text")
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn find_checklists() {
+        let todo = json!({ "uuid": "c-1", "name": "Todo", "items": [] });
+        let release = json!({ "uuid": "c-2", "name": "Release", "items": [] });
+        assert_eq!(find_checklist(&[], None).unwrap(), None);
+        assert_eq!(find_checklist(&[todo.clone()], None).unwrap(), Some(0));
+        let both = [todo, release];
+        assert!(matches!(
+            find_checklist(&both, None),
+            Err(Error::Invalid(_))
+        ));
+        assert_eq!(find_checklist(&both, Some("Release")).unwrap(), Some(1));
+        assert_eq!(find_checklist(&both, Some("c-1")).unwrap(), Some(0));
+        assert_eq!(find_checklist(&both, Some("Other")).unwrap(), None);
+    }
+
+    #[test]
+    fn find_checklist_items() {
+        let items = [
+            json!({ "uuid": "i-1", "text": "Write tests", "checked": true }),
+            json!({ "uuid": "i-2", "text": "Release" }),
+        ];
+        assert_eq!(find_item(&items, "Release").unwrap(), 1);
+        assert_eq!(find_item(&items, "i-1").unwrap(), 0);
+        assert!(matches!(
+            find_item(&items, "Deploy"),
+            Err(Error::NotFound(_))
+        ));
+        assert!(is_checked(&items[0]));
+        assert!(!is_checked(&items[1]));
+    }
+}
@@ -0,0 +1,274 @@
+//! Add, rename, and delete the choices of category fields
+use crate::{
+    backup::get_all_items, cache, confirm, fuzzy, rest::RestClient, values::value_key,
+    AddChoiceOpt, DeleteChoiceOpt, Error, ListInfo, RenameChoiceOpt,
+};
+use serde_json::{json, Value};
+use std::result::Result;
+use zenkit::types::{Element, ElementCategoryId, PredefinedCategory, ID};
+
+/// Color names accepted by `--color`, and their hex values
+const COLORS: &[(&str, &str)] = &[
+    ("red", "#e74c3c"),
+    ("orange", "#e67e22"),
+    ("yellow", "#f1c40f"),
+    ("green", "#2ecc71"),
+    ("teal", "#1abc9c"),
+    ("blue", "#3498db"),
+    ("purple", "#9b59b6"),
+    ("pink", "#e84393"),
+    ("brown", "#a0522d"),
+    ("gray", "#95a5a6"),
+];
+
+/// Add a choice to the field. Prints the new choice's id and name.
+pub(crate) async fn add_choice(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &AddChoiceOpt,
+) -> Result<(), Error> {
+    let list_info = get_list_info(ws_id, &opt.list).await?;
+    let field = category_field(&list_info, &opt.field)?;
+    let existing = field_choices(field);
+    if existing.iter().any(|c| c.name == opt.name) {
+        return Err(Error::Invalid(format!(
+            "Field '{}' already has a choice '{}'",
+            field.name, opt.name
+        )));
+    }
+    let color = opt.color.as_deref().map(parse_color).transpose()?;
+    let mut choices = choice_values(existing);
+    choices.push(json!({ "name": opt.name, "colorHex": color }));
+    let resp = update_choices(rest, list_info.get_id(), field, choices).await?;
+    if let Some(choice) = resp
+        .pointer("/elementData/predefinedCategories")
+        .and_then(|v| v.as_array())
+        .and_then(|choices| {
+            choices
+                .iter()
+                .find(|c| c.get("name").and_then(|v| v.as_str()) == Some(opt.name.as_str()))
+        })
+    {
+        println!(
+            "{}\t{}",
+            choice.get("id").cloned().unwrap_or_default(),
+            opt.name
+        );
+    }
+    Ok(())
+}
+
+/// Rename a choice, and optionally change its color. Items keep the choice.
+pub(crate) async fn rename_choice(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &RenameChoiceOpt,
+) -> Result<(), Error> {
+    let list_info = get_list_info(ws_id, &opt.list).await?;
+    let field = category_field(&list_info, &opt.field)?;
+    let existing = field_choices(field);
+    let choice = find_choice(field, existing, &opt.name)?;
+    if existing
+        .iter()
+        .any(|c| c.name == opt.to && c.id != choice.id)
+    {
+        return Err(Error::Invalid(format!(
+            "Field '{}' already has a choice '{}'",
+            field.name, opt.to
+        )));
+    }
+    let color = opt.color.as_deref().map(parse_color).transpose()?;
+    let choices = existing
+        .iter()
+        .map(|c| {
+            if c.id == choice.id {
+                json!({
+                    "id": c.id,
+                    "name": opt.to,
+                    "colorHex": color.clone().unwrap_or_else(|| c.color.clone()),
+                })
+            } else {
+                choice_value(c)
+            }
+        })
+        .collect();
+    update_choices(rest, list_info.get_id(), field, choices).await?;
+    Ok(())
+}
+
+/// Delete a choice from the field. Reports the number of (active) items with the
+/// choice, and unless `--yes` is used, asks for confirmation.
+/// Returns false if deletion was cancelled.
+pub(crate) async fn delete_choice(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &DeleteChoiceOpt,
+) -> Result<bool, Error> {
+    let list_info = get_list_info(ws_id, &opt.list).await?;
+    let field = category_field(&list_info, &opt.field)?;
+    let existing = field_choices(field);
+    let choice = find_choice(field, existing, &opt.name)?;
+
+    let key = value_key(field).unwrap_or_default();
+    let used = get_all_items(&list_info.list().uuid, false)
+        .await?
+        .iter()
+        .filter(|item| {
+            item.fields
+                .get(&key)
+                .and_then(|v| v.as_array())
+                .map(|ids| ids.iter().any(|id| id_matches(id, choice.id)))
+                .unwrap_or(false)
+        })
+        .count();
+    println!("{} item(s) use choice '{}'", used, choice.name);
+    if !opt.yes {
+        let prompt = format!(
+            "Delete choice '{}' from field '{}'?",
+            choice.name, field.name
+        );
+        if !confirm(&prompt)? {
+            return Ok(false);
+        }
+    }
+    let choices = existing
+        .iter()
+        .filter(|c| c.id != choice.id)
+        .map(choice_value)
+        .collect();
+    update_choices(rest, list_info.get_id(), field, choices).await?;
+    Ok(true)
+}
+
+/// Replace the field's choices. Choices with an id are kept (and updated),
+/// choices without one are created, and existing choices that aren't included are removed.
+/// Returns the updated field.
+pub(crate) async fn update_choices(
+    rest: &RestClient,
+    list_id: ID,
+    field: &Element,
+    choices: Vec<Value>,
+) -> Result<Value, Error> {
+    let body = json!({ "elementData": { "predefinedCategories": choices } });
+    rest.put(&format!("lists/{}/elements/{}", list_id, field.id), &body)
+        .await
+}
+
+/// A choice, as sent in `update_choices`
+pub(crate) fn choice_value(choice: &PredefinedCategory) -> Value {
+    json!({ "id": choice.id, "name": choice.name, "colorHex": choice.color })
+}
+
+fn choice_values(choices: &[PredefinedCategory]) -> Vec<Value> {
+    choices.iter().map(choice_value).collect()
+}
+
+async fn get_list_info(ws_id: ID, list: &str) -> Result<ListInfo, Error> {
+    cache::get_list_info(ws_id, list).await
+}
+
+/// The field, which must be a category field
+fn category_field<'l>(list_info: &'l ListInfo, name: &str) -> Result<&'l Element, Error> {
+    let field = fuzzy::get_field(list_info, name)?;
+    if field.element_category != ElementCategoryId::Categories {
+        return Err(Error::Invalid(format!(
+            "Field '{}' is not a choice field",
+            field.name
+        )));
+    }
+    Ok(field)
+}
+
+/// The choices of the field (none, if it isn't a category field)
+pub(crate) fn field_choices(field: &Element) -> &[PredefinedCategory] {
+    field
+        .element_data
+        .predefined_categories
+        .as_deref()
+        .unwrap_or_default()
+}
+
+/// The choice with the name or id
+fn find_choice<'c>(
+    field: &Element,
+    choices: &'c [PredefinedCategory],
+    name: &str,
+) -> Result<&'c PredefinedCategory, Error> {
+    choices
+        .iter()
+        .find(|c| c.name == name)
+        .or_else(|| choices.iter().find(|c| c.id.to_string() == name))
+        .ok_or_else(|| Error::NotFound(format!("Field '{}' has no choice '{}'", field.name, name)))
+}
+
+/// True if a value of a category field is the choice id
+fn id_matches(value: &Value, id: ID) -> bool {
+    match value {
+        Value::Number(n) => n.as_u64() == Some(id),
+        Value::String(s) => s == &id.to_string(),
+        _ => false,
+    }
+}
+
+/// A color name (see COLORS) or hex value (#rrggbb) as a hex value
+fn parse_color(color: &str) -> Result<String, Error> {
+    if let Some((_, hex)) = COLORS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(color))
+    {
+        return Ok(hex.to_string());
+    }
+    let hex = color.trim_start_matches('#');
+    if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(format!("#{}", hex.to_lowercase()));
+    }
+    let names: Vec<&str> = COLORS.iter().map(|(name, _)| *name).collect();
+    Err(Error::Invalid(format!(
+        "Invalid color '{}': should be #rrggbb or one of {}",
+        color,
+        names.join(", ")
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{choice_field, field};
+
+    #[test]
+    fn choices_by_name_or_id() {
+        let stage = choice_field(3, "Stage", &[(1, "Todo"), (2, "Done"), (3, "1")]);
+        let choices = field_choices(&stage);
+        assert_eq!(find_choice(&stage, choices, "Done").unwrap().id, 2);
+        assert_eq!(find_choice(&stage, choices, "2").unwrap().name, "Done");
+        // names before ids
+        assert_eq!(find_choice(&stage, choices, "1").unwrap().id, 3);
+        assert!(matches!(
+            find_choice(&stage, choices, "Blocked"),
+            Err(Error::NotFound(_))
+        ));
+        assert_eq!(
+            choice_value(&choices[1]),
+            json!({ "id": 2, "name": "Done", "colorHex": "#ffffff" })
+        );
+        assert!(field_choices(&field(4, "Notes", 1)).is_empty());
+    }
+
+    #[test]
+    fn choice_ids() {
+        assert!(id_matches(&json!(2), 2));
+        assert!(id_matches(&json!("2"), 2));
+        assert!(!id_matches(&json!(3), 2));
+        assert!(!id_matches(&json!({ "id": 2 }), 2));
+    }
+
+    #[test]
+    fn colors() {
+        assert_eq!(parse_color("Red").unwrap(), "#e74c3c");
+        assert_eq!(parse_color("#00FF7f").unwrap(), "#00ff7f");
+        assert_eq!(parse_color("00ff7f").unwrap(), "#00ff7f");
+        assert!(parse_color("#0f0").is_err());
+        assert!(parse_color("magenta").is_err());
+    }
+}
@@ -0,0 +1,477 @@
+//! Copy items within a list, and move or sync items between lists
+use crate::{
+    activity::{get_entry_activities, ActivitySummary, FILTER_COMMENTS},
+    backup::get_all_items,
+    bulk::{deprecate_item, matching_items},
+    cache, clear_value, dryrun, fuzzy,
+    items::Column,
+    rest::RestClient,
+    restore::entry_values,
+    retry::retry,
+    schema::map_values,
+    values::{value_key, ValueFormatter},
+    CopyItemOpt, Error, MoveItemOpt, SyncListsOpt,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    result::Result,
+};
+use zenkit::types::{Element, ElementCategoryId, Entry, FieldVal, UpdateAction, ZKObjectID, ID};
+
+/// Create a copy of the item in the destination list, with the values of fields
+/// that have the same name (or are mapped with `--map`) and type, then optionally
+/// delete or deprecate the source item. Values that couldn't be copied are listed
+/// on stderr. Returns the new item, or None in dry-run mode.
+pub(crate) async fn move_item(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &MoveItemOpt,
+) -> Result<Option<Entry>, Error> {
+    let source = cache::get_list_info(ws_id, &opt.from).await?;
+    let dest = cache::get_list_info(ws_id, &opt.to).await?;
+    if source.get_id() == dest.get_id() {
+        return Err(Error::Invalid(
+            "Source and destination are the same list".into(),
+        ));
+    }
+    for (from, to) in opt.map.iter() {
+        if !source.fields().iter().any(|f| &f.name == from) {
+            return Err(Error::NotFound(format!(
+                "No field '{}' in list '{}'",
+                from,
+                source.list().name
+            )));
+        }
+        if !dest.fields().iter().any(|f| &f.name == to) {
+            return Err(Error::NotFound(format!(
+                "No field '{}' in list '{}'",
+                to,
+                dest.list().name
+            )));
+        }
+    }
+    let renames: HashMap<&str, &str> = opt
+        .map
+        .iter()
+        .map(|(from, to)| (from.as_str(), to.as_str()))
+        .collect();
+    let item = retry("get_item", || source.get_item(&opt.item)).await?;
+    let (values, unmapped) = map_values(
+        entry_values(&item, source.fields(), UpdateAction::Null),
+        source.fields(),
+        dest.fields(),
+        &renames,
+    );
+    for message in unmapped.iter() {
+        eprintln!("Not copied: {}", message);
+    }
+
+    let new_item = if dryrun::enabled() {
+        dryrun::print_item_request(&dest, None, &values).await?;
+        None
+    } else {
+        Some(dest.create_item(&values).await?)
+    };
+
+    if opt.delete {
+        rest.delete(&format!(
+            "lists/{}/entries/{}",
+            source.get_id(),
+            item.get_id()
+        ))
+        .await?;
+        if !dryrun::enabled() {
+            eprintln!("Deleted {}\t{}", item.get_id(), item.display_string);
+        }
+    } else if opt.deprecate {
+        deprecate_item(rest, source.get_id(), item.get_id()).await?;
+        if !dryrun::enabled() {
+            eprintln!("Archived {}\t{}", item.get_id(), item.display_string);
+        }
+    }
+    Ok(new_item)
+}
+
+/// Create a copy of the item in the same list, with all its field values (except
+/// formulas and files), adding the suffix to the title. With `--with-comments`,
+/// the item's comments are added to the copy, oldest first.
+/// Returns the new item, or None in dry-run mode, where only the request to create
+/// the item is printed.
+pub(crate) async fn copy_item(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &CopyItemOpt,
+) -> Result<Option<Entry>, Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let item = retry("get_item", || list_info.get_item(&opt.item)).await?;
+    let mut values = entry_values(&item, list_info.fields(), UpdateAction::Null);
+    if !opt.title_suffix.is_empty()
+        && !add_title_suffix(
+            &mut values,
+            list_info.fields(),
+            &item.display_string,
+            &opt.title_suffix,
+        )
+    {
+        eprintln!("Title field not found: the copy has the same title");
+    }
+    if dryrun::enabled() {
+        dryrun::print_item_request(&list_info, None, &values).await?;
+        return Ok(None);
+    }
+    let new_item = list_info.create_item(&values).await?;
+
+    if opt.with_comments {
+        let mut comments: Vec<ActivitySummary> =
+            get_entry_activities(rest, list_info.get_id(), item.get_id(), FILTER_COMMENTS)
+                .await?
+                .iter()
+                .map(ActivitySummary::from_activity)
+                .collect();
+        // activities are returned newest first
+        comments.reverse();
+        let new_id = new_item.get_id().to_string();
+        for comment in comments.iter() {
+            list_info
+                .add_item_comment(&new_id, &comment.message)
+                .await?;
+        }
+    }
+    Ok(Some(new_item))
+}
+
+/// Append the suffix to the title, the value of the text field that is the item's
+/// display string. Returns false if there's no such value.
+fn add_title_suffix(
+    values: &mut [(String, FieldVal, UpdateAction)],
+    fields: &[Element],
+    title: &str,
+    suffix: &str,
+) -> bool {
+    let title_field = fields.iter().find(|f| {
+        f.element_category == ElementCategoryId::Text
+            && values.iter().any(|(uuid, value, _)| {
+                uuid == &f.uuid && matches!(value, FieldVal::Str(s) if s == title)
+            })
+    });
+    match title_field {
+        Some(field) => {
+            for (uuid, value, _) in values.iter_mut() {
+                if uuid == &field.uuid {
+                    *value = FieldVal::Str(format!("{}{}", title, suffix));
+                }
+            }
+            true
+        }
+        None => false,
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct SyncListsSummary {
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    /// items that couldn't be synced, and why
+    pub failed: Vec<String>,
+}
+
+/// Copy the matching items (with all `--filter` values) of each source list to the
+/// destination list, or update the destination item with the same key. The key is a
+/// source column (a field, or the item's uuid, id, or name) whose value is stored in a
+/// destination field (`--key "Source=Dest"`, or `--key Field` for the same name).
+/// Values are mapped as for `move-item`: to the field with the same name and type, or
+/// the field named with `--map`. Existing items are only updated if a mapped value
+/// changed, and values cleared in the source are cleared in the destination.
+/// Values that couldn't be mapped are listed once on stderr.
+pub(crate) async fn sync_lists(ws_id: ID, opt: &SyncListsOpt) -> Result<SyncListsSummary, Error> {
+    let dest = cache::get_list_info(ws_id, &opt.to).await?;
+    let (source_key, dest_key) = match opt.key.find('=') {
+        Some(pos) => (&opt.key[..pos], &opt.key[pos + 1..]),
+        None => (opt.key.as_str(), opt.key.as_str()),
+    };
+    let dest_key = fuzzy::get_field(&dest, dest_key)?;
+    let formatter = ValueFormatter::new(ws_id).await?;
+
+    // destination items by key value
+    let dest_items = get_all_items(&dest.list().uuid, false).await?;
+    let mut by_key: HashMap<String, &Entry> = HashMap::new();
+    for item in dest_items.iter() {
+        if let Some(key) = formatter.display(item, dest_key) {
+            by_key.entry(key).or_insert(item);
+        }
+    }
+
+    let mut summary = SyncListsSummary::default();
+    let mut not_copied = HashSet::new();
+    for from in opt.from.iter() {
+        let source = cache::get_list_info(ws_id, from).await?;
+        if source.get_id() == dest.get_id() {
+            return Err(Error::Invalid(format!(
+                "Source list '{}' is the destination list",
+                from
+            )));
+        }
+        let key_column = Column::parse(&source, source_key)?;
+        let renames: HashMap<&str, &str> = opt
+            .map
+            .iter()
+            .filter(|(name, _)| source.fields().iter().any(|f| &f.name == name))
+            .map(|(from, to)| (from.as_str(), to.as_str()))
+            .collect();
+        // destination fields with a value from the source (same name or renamed, same type)
+        let mapped_fields: Vec<&Element> = source
+            .fields()
+            .iter()
+            .filter(|f| value_key(f).is_some())
+            .filter_map(|f| {
+                let name = renames.get(f.name.as_str()).copied().unwrap_or(&f.name);
+                dest.fields()
+                    .iter()
+                    .find(|d| d.name == name && d.element_category == f.element_category)
+            })
+            .collect();
+
+        for item in matching_items(ws_id, &source, &opt.filter).await?.iter() {
+            let key = key_column.value(item, Some(&formatter));
+            if key.is_empty() {
+                summary.failed.push(format!(
+                    "{} '{}': no {} value",
+                    source.list().name,
+                    item.display_string,
+                    key_column.name()
+                ));
+                continue;
+            }
+            let (mut values, unmapped) = map_values(
+                entry_values(item, source.fields(), UpdateAction::Null),
+                source.fields(),
+                dest.fields(),
+                &renames,
+            );
+            for message in unmapped.into_iter() {
+                if not_copied.insert(message.clone()) {
+                    eprintln!("Not copied: {}", message);
+                }
+            }
+            if !values.iter().any(|(uuid, _, _)| uuid == &dest_key.uuid) {
+                values.push((
+                    dest_key.uuid.clone(),
+                    FieldVal::Str(key.clone()),
+                    UpdateAction::Null,
+                ));
+            }
+
+            let result = match by_key.get(&key) {
+                None if dryrun::enabled() => {
+                    dryrun::print_item_request(&dest, None, &values).await?;
+                    summary.created += 1;
+                    Ok(())
+                }
+                None => {
+                    let created = dest.create_item(&values).await.map(|_| ());
+                    if created.is_ok() {
+                        summary.created += 1;
+                    }
+                    created
+                }
+                Some(existing) => {
+                    let current = entry_values(existing, dest.fields(), UpdateAction::Null);
+                    let changes = changed_values(values, &current, &mapped_fields);
+                    if changes.is_empty() {
+                        summary.unchanged += 1;
+                        continue;
+                    }
+                    let updated = if dryrun::enabled() {
+                        dryrun::print_item_request(&dest, Some(existing.get_id()), &changes)
+                            .await?;
+                        Ok(())
+                    } else {
+                        dest.update_item(existing.get_id(), &changes)
+                            .await
+                            .map(|_| ())
+                    };
+                    if updated.is_ok() {
+                        summary.updated += 1;
+                    }
+                    updated
+                }
+            };
+            if let Err(e) = result {
+                summary.failed.push(format!(
+                    "{} '{}': {}",
+                    source.list().name,
+                    item.display_string,
+                    e.summary()
+                ));
+            }
+        }
+    }
+    Ok(summary)
+}
+
+/// The values that differ from the destination item's `current` values, with
+/// values of mapped fields that were cleared in the source cleared
+fn changed_values(
+    values: Vec<(String, FieldVal, UpdateAction)>,
+    current: &[(String, FieldVal, UpdateAction)],
+    mapped_fields: &[&Element],
+) -> Vec<(String, FieldVal, UpdateAction)> {
+    let current_value = |uuid: &str| {
+        current
+            .iter()
+            .find(|(u, _, _)| u == uuid)
+            .map(|(_, value, _)| strings(value))
+            .unwrap_or_default()
+    };
+    // values cleared in the source
+    let cleared: Vec<&Element> = mapped_fields
+        .iter()
+        .copied()
+        .filter(|f| {
+            !values.iter().any(|(uuid, _, _)| uuid == &f.uuid) && !current_value(&f.uuid).is_empty()
+        })
+        .collect();
+    let mut changes: Vec<(String, FieldVal, UpdateAction)> = values
+        .into_iter()
+        .filter(|(uuid, value, _)| current_value(uuid) != strings(value))
+        .map(|(uuid, value, _)| match value {
+            FieldVal::ArrStr(_) | FieldVal::ArrID(_) => (uuid, value, UpdateAction::Replace),
+            value => (uuid, value, UpdateAction::Null),
+        })
+        .collect();
+    for field in cleared.into_iter() {
+        changes.push(clear_value(field.uuid.clone(), field));
+    }
+    changes
+}
+
+/// A field value as strings, for comparison: multiple values are sorted, and empty
+/// values are omitted
+fn strings(value: &FieldVal) -> Vec<String> {
+    let mut values: Vec<String> = match value {
+        FieldVal::Str(s) | FieldVal::Formatted(s, _) => vec![s.clone()],
+        FieldVal::ArrStr(values) => values.clone(),
+        FieldVal::ArrID(ids) => ids.iter().map(|id| id.to_string()).collect(),
+        FieldVal::Int(n) => vec![n.to_string()],
+        FieldVal::Float(n) => vec![n.to_string()],
+    };
+    values.retain(|v| !v.is_empty());
+    values.sort();
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{choice_field, field};
+
+    #[test]
+    fn title_suffix() {
+        let fields = vec![
+            field(1, "Title", 1),
+            field(2, "Notes", 1),
+            field(3, "Points", 2),
+        ];
+        let mut values = vec![
+            (
+                "field-3".to_string(),
+                FieldVal::Float(3.0),
+                UpdateAction::Null,
+            ),
+            (
+                "field-2".to_string(),
+                FieldVal::Str("Sprint".into()),
+                UpdateAction::Null,
+            ),
+            (
+                "field-1".to_string(),
+                FieldVal::Str("Sprint".into()),
+                UpdateAction::Null,
+            ),
+        ];
+        // the value of the first text field that is the title
+        assert!(add_title_suffix(&mut values, &fields, "Sprint", " (copy)"));
+        assert!(matches!(&values[1].1, FieldVal::Str(s) if s == "Sprint"));
+        assert!(matches!(&values[2].1, FieldVal::Str(s) if s == "Sprint (copy)"));
+        assert!(!add_title_suffix(
+            &mut values,
+            &fields,
+            "Standup",
+            " (copy)"
+        ));
+    }
+
+    #[test]
+    fn value_strings() {
+        assert_eq!(strings(&FieldVal::ArrID(vec![12, 3])), vec!["12", "3"]);
+        assert_eq!(
+            strings(&FieldVal::ArrStr(vec!["b".into(), "".into(), "a".into()])),
+            vec!["a", "b"]
+        );
+        assert_eq!(strings(&FieldVal::Float(2.5)), vec!["2.5"]);
+        assert!(strings(&FieldVal::Str(String::new())).is_empty());
+    }
+
+    #[test]
+    fn changes_to_existing_item() {
+        let notes = field(2, "Notes", 1);
+        let tags = choice_field(3, "Tags", &[(1, "red"), (2, "blue")]);
+        let points = field(4, "Points", 2);
+        let values = vec![
+            (
+                "field-2".to_string(),
+                FieldVal::Str("same".into()),
+                UpdateAction::Null,
+            ),
+            (
+                "field-3".to_string(),
+                FieldVal::ArrID(vec![2, 1]),
+                UpdateAction::Null,
+            ),
+        ];
+        let current = vec![
+            (
+                "field-2".to_string(),
+                FieldVal::Str("same".into()),
+                UpdateAction::Null,
+            ),
+            (
+                "field-3".to_string(),
+                FieldVal::ArrID(vec![1]),
+                UpdateAction::Null,
+            ),
+            (
+                "field-4".to_string(),
+                FieldVal::Float(3.0),
+                UpdateAction::Null,
+            ),
+        ];
+        let changes = changed_values(values, &current, &[&notes, &tags, &points]);
+        assert_eq!(changes.len(), 2);
+        assert!(matches!(
+            &changes[0],
+            (uuid, FieldVal::ArrID(ids), UpdateAction::Replace)
+                if uuid == "field-3" && ids == &[2, 1]
+        ));
+        // cleared in the source
+        assert!(matches!(
+            &changes[1],
+            (uuid, FieldVal::Str(s), UpdateAction::Null) if uuid == "field-4" && s.is_empty()
+        ));
+
+        // the same values, in another order
+        let values = vec![(
+            "field-3".to_string(),
+            FieldVal::ArrID(vec![2, 1]),
+            UpdateAction::Null,
+        )];
+        let current = vec![(
+            "field-3".to_string(),
+            FieldVal::ArrID(vec![1, 2]),
+            UpdateAction::Null,
+        )];
+        assert!(changed_values(values, &current, &[&tags]).is_empty());
+    }
+}
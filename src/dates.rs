@@ -0,0 +1,169 @@
+//! Values of date fields
+use crate::{fuzzy, Error, ListInfo};
+use chrono::{DateTime, Datelike, Duration, Local, NaiveDate, NaiveTime, TimeZone, Utc, Weekday};
+use std::result::Result;
+use zenkit::types::{ElementCategoryId, FieldVal, UpdateAction};
+
+/// Convert values of date fields to the format stored by Zenkit. See `parse_date_value`
+/// for the accepted formats.
+pub(crate) fn resolve_dates(
+    list_info: &ListInfo,
+    values: &mut [(String, FieldVal, UpdateAction)],
+) -> Result<(), Error> {
+    for (name, value, _) in values.iter_mut() {
+        let field = fuzzy::get_field(list_info, name)?;
+        if field.element_category != ElementCategoryId::Date {
+            continue;
+        }
+        if let FieldVal::Str(s) = value {
+            if !s.is_empty() {
+                *s = parse_date_value(s)
+                    .map_err(|e| Error::Invalid(format!("Field '{}': {}", field.name, e)))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Parse a date, optionally followed by a time of day (HH:MM, in the local timezone).
+/// The date may be
+///  - YYYY-MM-DD
+///  - today, tomorrow, or yesterday
+///  - a number of days or weeks from today: +3d, -1w
+///  - a day of the week, for the next such day (including today), or
+///    "next friday" for the next such day after today
+///
+/// RFC 3339 date-times (with a timezone offset) are also accepted.
+/// Dates are returned as YYYY-MM-DD, and date-times as UTC YYYY-MM-DDTHH:MM:SS.000Z
+pub(crate) fn parse_date_value(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Ok(utc_string(&dt.with_timezone(&Utc)));
+    }
+    let (date_part, time) = match value.rfind(' ') {
+        Some(pos) => match NaiveTime::parse_from_str(&value[pos + 1..], "%H:%M") {
+            Ok(time) => (value[..pos].trim(), Some(time)),
+            Err(_) => (value, None),
+        },
+        None => (value, None),
+    };
+    let date = parse_date(date_part, Local::today().naive_local()).ok_or_else(|| {
+        format!(
+            "Invalid date '{}': should be YYYY-MM-DD, today, tomorrow, +Nd, +Nw, or a weekday, with optional HH:MM",
+            value
+        )
+    })?;
+    match time {
+        None => Ok(date.format("%Y-%m-%d").to_string()),
+        Some(time) => {
+            let local = Local
+                .from_local_datetime(&date.and_time(time))
+                .earliest()
+                .ok_or_else(|| format!("Invalid local time '{}'", value))?;
+            Ok(utc_string(&local.with_timezone(&Utc)))
+        }
+    }
+}
+
+/// Parse a date (without time), in any of the forms accepted by `parse_date_value`
+pub(crate) fn parse_day(value: &str) -> Result<NaiveDate, String> {
+    parse_date(value.trim(), Local::today().naive_local()).ok_or_else(|| {
+        format!(
+            "Invalid date '{}': should be YYYY-MM-DD, today, tomorrow, +Nd, +Nw, or a weekday",
+            value
+        )
+    })
+}
+
+/// Local date of a date field value as stored by Zenkit (YYYY-MM-DD, or a UTC date-time)
+pub(crate) fn local_date(value: &str) -> Option<NaiveDate> {
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(dt) => Some(dt.with_timezone(&Local).date().naive_local()),
+        Err(_) => NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok(),
+    }
+}
+
+fn parse_date(s: &str, today: NaiveDate) -> Option<NaiveDate> {
+    let lower = s.to_lowercase();
+    match lower.as_str() {
+        "today" => return Some(today),
+        "tomorrow" => return Some(today.succ()),
+        "yesterday" => return Some(today.pred()),
+        _ => {}
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Some(date);
+    }
+    if let Some(offset) = parse_offset(&lower) {
+        return Some(today + offset);
+    }
+    let (next, day) = match lower.strip_prefix("next ") {
+        Some(day) => (true, day.trim()),
+        None => (false, lower.as_str()),
+    };
+    let weekday: Weekday = day.parse().ok()?;
+    let mut days =
+        (7 + weekday.num_days_from_monday() - today.weekday().num_days_from_monday()) % 7;
+    if days == 0 && next {
+        days = 7;
+    }
+    Some(today + Duration::days(days as i64))
+}
+
+/// Offset from today: "+3d", "-2w"
+fn parse_offset(s: &str) -> Option<Duration> {
+    let sign = match s.chars().next()? {
+        '+' => 1,
+        '-' => -1,
+        _ => return None,
+    };
+    if s.len() < 3 {
+        return None;
+    }
+    let n: i64 = s[1..s.len() - 1].parse().ok()?;
+    match s.chars().last()? {
+        'd' => Some(Duration::days(sign * n)),
+        'w' => Some(Duration::weeks(sign * n)),
+        _ => None,
+    }
+}
+
+/// Date-time in the format used by Zenkit
+fn utc_string(dt: &DateTime<Utc>) -> String {
+    dt.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_dates() {
+        // a Wednesday
+        let today = NaiveDate::from_ymd(2021, 3, 3);
+        let date = |s| parse_date(s, today).map(|d| d.to_string());
+        assert_eq!(date("today").as_deref(), Some("2021-03-03"));
+        assert_eq!(date("Tomorrow").as_deref(), Some("2021-03-04"));
+        assert_eq!(date("yesterday").as_deref(), Some("2021-03-02"));
+        assert_eq!(date("2021-04-01").as_deref(), Some("2021-04-01"));
+        assert_eq!(date("+3d").as_deref(), Some("2021-03-06"));
+        assert_eq!(date("-1w").as_deref(), Some("2021-02-24"));
+        assert_eq!(date("friday").as_deref(), Some("2021-03-05"));
+        assert_eq!(date("wed").as_deref(), Some("2021-03-03"));
+        assert_eq!(date("next Wednesday").as_deref(), Some("2021-03-10"));
+        for invalid in ["+d", "+3m", "someday", "2021-02-30", ""].iter() {
+            assert_eq!(date(invalid), None, "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn date_values() {
+        assert_eq!(parse_date_value(" 2021-04-01 ").unwrap(), "2021-04-01");
+        assert_eq!(
+            parse_date_value("2021-03-01T10:00:00+01:00").unwrap(),
+            "2021-03-01T09:00:00.000Z"
+        );
+        assert!(parse_date_value("2021-04-01 25:00").is_err());
+        assert!(parse_date_value("soon").is_err());
+    }
+}
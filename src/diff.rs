@@ -0,0 +1,363 @@
+//! Compare two backup snapshots
+use crate::{
+    watch::{change_event, changed_fields, ChangeEvent, ChangeKind},
+    DiffOpt, Error, OutputFormat,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+    result::Result,
+};
+use zenkit::types::{Element, Entry, List};
+
+/// Differences between two snapshots. Only lists with changes are included.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct BackupDiff {
+    pub lists: Vec<ListDiff>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct ListDiff {
+    pub list: String,
+    pub uuid: String,
+    pub event: ChangeKind,
+    /// previous name, if the list was renamed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub renamed_from: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fields: Vec<FieldDiff>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub items: Vec<ChangeEvent>,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct FieldDiff {
+    pub field: String,
+    pub uuid: String,
+    pub event: ChangeKind,
+}
+
+/// Compare the backups `opt.from` and `opt.to`, and print the differences
+pub(crate) fn diff_backups(opt: &DiffOpt) -> Result<(), Error> {
+    let from = Snapshot::load(&opt.from)?;
+    let to = Snapshot::load(&opt.to)?;
+    let mut diff = BackupDiff::default();
+
+    for uuid in from.lists.union(&to.lists) {
+        let old = from.read_list(uuid)?;
+        let new = to.read_list(uuid)?;
+        let list_diff = match (old, new) {
+            (None, None) => continue,
+            (None, Some(new)) => ListDiff {
+                list: new.list.name.clone(),
+                uuid: uuid.clone(),
+                event: ChangeKind::Created,
+                renamed_from: None,
+                fields: Vec::new(),
+                items: Vec::new(),
+            },
+            (Some(old), None) => ListDiff {
+                list: old.list.name.clone(),
+                uuid: uuid.clone(),
+                event: ChangeKind::Deleted,
+                renamed_from: None,
+                fields: Vec::new(),
+                items: Vec::new(),
+            },
+            (Some(old), Some(new)) => {
+                let fields = diff_fields(&old.fields, &new.fields);
+                let items = diff_items(&old, &new);
+                let renamed_from = if old.list.name != new.list.name {
+                    Some(old.list.name.clone())
+                } else {
+                    None
+                };
+                if fields.is_empty() && items.is_empty() && renamed_from.is_none() {
+                    continue;
+                }
+                ListDiff {
+                    list: new.list.name.clone(),
+                    uuid: uuid.clone(),
+                    event: ChangeKind::Updated,
+                    renamed_from,
+                    fields,
+                    items,
+                }
+            }
+        };
+        diff.lists.push(list_diff);
+    }
+
+    match opt.output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&diff)?),
+        OutputFormat::Text => print_diff(&diff),
+    }
+    Ok(())
+}
+
+/// A backup folder, or a summary file within a backup folder.
+/// For a summary file, only the lists in the summary are compared.
+struct Snapshot {
+    dir: PathBuf,
+    /// uuids of lists in the backup
+    lists: BTreeSet<String>,
+}
+
+/// Contents of one list's backup files
+struct ListSnapshot {
+    list: List,
+    fields: Vec<Element>,
+    items: Vec<Entry>,
+}
+
+impl Snapshot {
+    fn load(path: &str) -> Result<Self, Error> {
+        let path = Path::new(path);
+        let mut lists = BTreeSet::new();
+        if path.is_dir() {
+            for entry in std::fs::read_dir(path)? {
+                if let Some(uuid) = entry?
+                    .file_name()
+                    .to_str()
+                    .and_then(|name| name.strip_suffix("_list.json"))
+                {
+                    lists.insert(uuid.to_string());
+                }
+            }
+            Ok(Snapshot {
+                dir: path.to_path_buf(),
+                lists,
+            })
+        } else {
+            let summary: serde_json::Value = read_json(path)?;
+            for list in summary
+                .get("lists")
+                .and_then(|v| v.as_array())
+                .map(|v| v.as_slice())
+                .unwrap_or_default()
+            {
+                if let Some(uuid) = list.get("uuid").and_then(|v| v.as_str()) {
+                    lists.insert(uuid.to_string());
+                }
+            }
+            Ok(Snapshot {
+                dir: path
+                    .parent()
+                    .unwrap_or_else(|| Path::new("."))
+                    .to_path_buf(),
+                lists,
+            })
+        }
+    }
+
+    /// Read the list's backup files, or return None if the list is not in this snapshot.
+    /// The items file is optional (e.g., for incremental backups), and treated as empty
+    /// if missing.
+    fn read_list(&self, uuid: &str) -> Result<Option<ListSnapshot>, Error> {
+        if !self.lists.contains(uuid) {
+            return Ok(None);
+        }
+        let list = read_json(&self.dir.join(format!("{}_list.json", uuid)))?;
+        let fields = read_json(&self.dir.join(format!("{}_fields.json", uuid)))?;
+        let items_path = self.dir.join(format!("{}_items.json", uuid));
+        let items = if items_path.exists() {
+            read_json(&items_path)?
+        } else {
+            Vec::new()
+        };
+        Ok(Some(ListSnapshot {
+            list,
+            fields,
+            items,
+        }))
+    }
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, Error> {
+    let data = std::fs::read(path)
+        .map_err(|e| Error::Message(format!("Reading {}: {}", path.display(), e)))?;
+    serde_json::from_slice(&data)
+        .map_err(|e| Error::Invalid(format!("Invalid backup file {}: {}", path.display(), e)))
+}
+
+/// Fields added, removed, or changed (renamed or type changed)
+fn diff_fields(old: &[Element], new: &[Element]) -> Vec<FieldDiff> {
+    let old_fields: BTreeMap<&str, &Element> = old.iter().map(|f| (f.uuid.as_str(), f)).collect();
+    let new_fields: BTreeMap<&str, &Element> = new.iter().map(|f| (f.uuid.as_str(), f)).collect();
+    let mut diffs = Vec::new();
+    for (uuid, field) in new_fields.iter() {
+        let event = match old_fields.get(uuid) {
+            None => ChangeKind::Created,
+            Some(prev)
+                if prev.name != field.name || prev.element_category != field.element_category =>
+            {
+                ChangeKind::Updated
+            }
+            Some(_) => continue,
+        };
+        diffs.push(field_diff(field, event));
+    }
+    for (uuid, field) in old_fields.iter() {
+        if !new_fields.contains_key(uuid) {
+            diffs.push(field_diff(field, ChangeKind::Deleted));
+        }
+    }
+    diffs
+}
+
+fn field_diff(field: &Element, event: ChangeKind) -> FieldDiff {
+    FieldDiff {
+        field: field.name.clone(),
+        uuid: field.uuid.clone(),
+        event,
+    }
+}
+
+/// Items added, removed, or changed, ordered by item id
+fn diff_items(old: &ListSnapshot, new: &ListSnapshot) -> Vec<ChangeEvent> {
+    // use names from both versions, so changes to removed fields are reported
+    let field_names: HashMap<&str, &str> = old
+        .fields
+        .iter()
+        .chain(new.fields.iter())
+        .map(|f| (f.uuid.as_str(), f.name.as_str()))
+        .collect();
+    let old_items: HashMap<&str, &Entry> = old.items.iter().map(|i| (i.uuid.as_str(), i)).collect();
+    let new_items: HashMap<&str, &Entry> = new.items.iter().map(|i| (i.uuid.as_str(), i)).collect();
+    let list_name = &new.list.name;
+    let mut events = Vec::new();
+    for item in new.items.iter() {
+        let (event, changed) = match old_items.get(item.uuid.as_str()) {
+            None => (ChangeKind::Created, Vec::new()),
+            Some(prev) => {
+                let changed = changed_fields(prev, item, &field_names);
+                if changed.is_empty() && prev.display_string == item.display_string {
+                    continue;
+                }
+                (ChangeKind::Updated, changed)
+            }
+        };
+        events.push(change_event(event, list_name, item, changed));
+    }
+    for item in old.items.iter() {
+        if !new_items.contains_key(item.uuid.as_str()) {
+            events.push(change_event(
+                ChangeKind::Deleted,
+                list_name,
+                item,
+                Vec::new(),
+            ));
+        }
+    }
+    events.sort_by_key(|e| e.item_id);
+    events
+}
+
+/// Print a readable summary of the differences
+fn print_diff(diff: &BackupDiff) {
+    let (mut fields, mut items) = (0, 0);
+    for list in diff.lists.iter() {
+        match list.renamed_from {
+            Some(ref prev) => println!(
+                "List {} ({}): {} (renamed from {})",
+                list.list, list.uuid, list.event, prev
+            ),
+            None => println!("List {} ({}): {}", list.list, list.uuid, list.event),
+        }
+        for field in list.fields.iter() {
+            println!("  field {}: {}", field.field, field.event);
+        }
+        for item in list.items.iter() {
+            if item.changed_fields.is_empty() {
+                println!(
+                    "  item {} \"{}\": {}",
+                    item.item_id, item.display_string, item.event
+                );
+            } else {
+                println!(
+                    "  item {} \"{}\": {} ({})",
+                    item.item_id,
+                    item.display_string,
+                    item.event,
+                    item.changed_fields.join(", ")
+                );
+            }
+        }
+        fields += list.fields.len();
+        items += list.items.len();
+    }
+    println!(
+        "{} list(s), {} field(s), {} item(s) changed",
+        diff.lists.len(),
+        fields,
+        items
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{field, item, list_info};
+    use serde_json::json;
+
+    fn snapshot(fields: Vec<Element>, items: Vec<Entry>) -> ListSnapshot {
+        ListSnapshot {
+            list: list_info(Vec::new()).list().clone(),
+            fields,
+            items,
+        }
+    }
+
+    #[test]
+    fn field_changes() {
+        let old = vec![
+            field(1, "Title", 1),
+            field(2, "Points", 2),
+            field(3, "Notes", 1),
+        ];
+        let new = vec![
+            field(1, "Title", 1),
+            field(2, "Estimate", 2),
+            field(4, "Due", 4),
+        ];
+        let changes: Vec<String> = diff_fields(&old, &new)
+            .iter()
+            .map(|f| format!("{} {}", f.event, f.field))
+            .collect();
+        assert_eq!(
+            changes,
+            vec!["updated Estimate", "created Due", "deleted Notes"]
+        );
+    }
+
+    #[test]
+    fn item_changes() {
+        let fields = || vec![field(1, "Title", 1), field(2, "Points", 2)];
+        let old = snapshot(
+            fields(),
+            vec![
+                item(3, "C", json!({})),
+                item(1, "A", json!({"field-2_number": 1})),
+                item(2, "B", json!({})),
+            ],
+        );
+        let new = snapshot(
+            fields(),
+            vec![
+                item(4, "D", json!({})),
+                item(2, "B", json!({})),
+                item(1, "A", json!({"field-2_number": 2})),
+            ],
+        );
+        let changes: Vec<String> = diff_items(&old, &new)
+            .iter()
+            .map(|e| format!("{} {} {:?}", e.event, e.item_id, e.changed_fields))
+            .collect();
+        assert_eq!(
+            changes,
+            vec!["updated 1 [\"Points\"]", "deleted 3 []", "created 4 []"]
+        );
+    }
+}
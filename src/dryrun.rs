@@ -0,0 +1,54 @@
+//! Dry-run mode: requests that would change data are printed instead of sent
+use crate::{Error, ListInfo};
+use serde_json::Value;
+use std::{
+    result::Result,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use zenkit::types::{FieldSetVal, ID};
+
+static DRY_RUN: AtomicBool = AtomicBool::new(false);
+
+/// Enable or disable dry-run mode
+pub(crate) fn configure(enabled: bool) {
+    DRY_RUN.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns true if requests that change data should be printed instead of sent
+pub(crate) fn enabled() -> bool {
+    DRY_RUN.load(Ordering::Relaxed)
+}
+
+/// Print the request method and path, followed by the json body, if any
+pub(crate) fn print_request(method: &str, path: &str, body: Option<&Value>) {
+    println!("{} {}", method, path);
+    if let Some(body) = body {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(body).unwrap_or_else(|_| body.to_string())
+        );
+    }
+}
+
+/// Print the request to create an item (if `item_id` is None) or update an item,
+/// with the body that would be sent (see `ListInfo::item_body`)
+pub(crate) async fn print_item_request(
+    list_info: &ListInfo,
+    item_id: Option<ID>,
+    values: &[FieldSetVal],
+) -> Result<(), Error> {
+    let body = Value::Object(list_info.item_body(values).await?);
+    match item_id {
+        Some(id) => print_request(
+            "PUT",
+            &format!("lists/{}/entries/{}", list_info.get_id(), id),
+            Some(&body),
+        ),
+        None => print_request(
+            "POST",
+            &format!("lists/{}/entries", list_info.get_id()),
+            Some(&body),
+        ),
+    }
+    Ok(())
+}
@@ -0,0 +1,199 @@
+//! Report of overdue and soon-due items
+use crate::{
+    backup::get_all_items,
+    cache,
+    dates::local_date,
+    fuzzy,
+    my_items::due_field,
+    values::{raw_value, ValueFormatter},
+    DueOpt, Error, OutputFormat,
+};
+use chrono::{Local, NaiveDate};
+use serde::Serialize;
+use std::result::Result;
+use zenkit::types::{Element, ElementCategoryId, ZKObjectID, ID};
+
+/// Status of an item's due date
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum DueStatus {
+    Overdue,
+    DueSoon,
+    Ok,
+}
+
+#[derive(Debug, Serialize)]
+struct DueItem {
+    status: DueStatus,
+    id: ID,
+    uuid: String,
+    title: String,
+    /// YYYY-MM-DD
+    due: String,
+    /// days until due, negative if overdue
+    days: i64,
+    assignees: Vec<String>,
+    /// one-line summary, e.g., for `notify`
+    message: String,
+}
+
+/// Classify the list's items with a due date as overdue (before today), due soon
+/// (within `--within` of today), or ok, and print the overdue and due-soon items,
+/// or all items with `--all`. With `--assignee`, only items with the person
+/// in a persons field are included. Returns the number of overdue items.
+pub(crate) async fn due_report(ws_id: ID, opt: &DueOpt) -> Result<usize, Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let field = match opt.field {
+        Some(ref name) => {
+            let field = fuzzy::get_field(&list_info, name)?;
+            if field.element_category != ElementCategoryId::Date {
+                return Err(Error::Invalid(format!(
+                    "Field '{}' is not a date field",
+                    field.name
+                )));
+            }
+            field
+        }
+        None => due_field(list_info.fields(), "Due").ok_or_else(|| {
+            Error::NotFound(format!(
+                "List '{}' has no due date field: use --field",
+                list_info.list().name
+            ))
+        })?,
+    };
+    let within = chrono::Duration::from_std(opt.within)
+        .map_err(|e| Error::Invalid(format!("Invalid --within: {}", e)))?;
+    let today = Local::today().naive_local();
+    let soon = today + within;
+    let persons: Vec<&Element> = list_info
+        .fields()
+        .iter()
+        .filter(|f| f.element_category == ElementCategoryId::Persons)
+        .collect();
+    let formatter = ValueFormatter::new(ws_id).await?;
+
+    let mut report = Vec::new();
+    for item in get_all_items(&list_info.list().uuid, false).await?.iter() {
+        let date = match raw_value(item, field)
+            .and_then(|v| v.as_str())
+            .and_then(local_date)
+        {
+            Some(date) => date,
+            None => continue,
+        };
+        if let Some(ref assignee) = opt.assignee {
+            if !persons
+                .iter()
+                .any(|f| formatter.has_value(item, f, assignee))
+            {
+                continue;
+            }
+        }
+        let status = due_status(date, today, soon);
+        let days = (date - today).num_days();
+        let assignees: Vec<String> = persons
+            .iter()
+            .flat_map(|f| formatter.display_values(item, f))
+            .collect();
+        let mut message = format!("{}: {}", item.display_string, due_in(days));
+        if !assignees.is_empty() {
+            message.push_str(&format!(" - {}", assignees.join(", ")));
+        }
+        report.push(DueItem {
+            status,
+            id: item.get_id(),
+            uuid: item.uuid.clone(),
+            title: item.display_string.clone(),
+            due: date.format("%Y-%m-%d").to_string(),
+            days,
+            assignees,
+            message,
+        });
+    }
+    report.sort_by_key(|item| item.days);
+    let overdue = report
+        .iter()
+        .filter(|item| item.status == DueStatus::Overdue)
+        .count();
+    let ok = report
+        .iter()
+        .filter(|item| item.status == DueStatus::Ok)
+        .count();
+    if !opt.all {
+        report.retain(|item| item.status != DueStatus::Ok);
+    }
+
+    match opt.output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&report)?),
+        OutputFormat::Text => {
+            let sections = [
+                (DueStatus::Overdue, "Overdue"),
+                (DueStatus::DueSoon, "Due soon"),
+                (DueStatus::Ok, "Ok"),
+            ];
+            for (status, heading) in sections.iter() {
+                let items: Vec<&DueItem> = report
+                    .iter()
+                    .filter(|item| item.status == *status)
+                    .collect();
+                if items.is_empty() {
+                    continue;
+                }
+                println!("{} ({})", heading, items.len());
+                for item in items.iter() {
+                    println!("  {}\t{}\t{}", item.due, item.id, item.message);
+                }
+            }
+            if !opt.all && ok > 0 {
+                println!("{} other item(s) not due soon", ok);
+            }
+        }
+    }
+    Ok(overdue)
+}
+
+/// Status of a due date: overdue before today, due soon until `soon` (inclusive)
+fn due_status(date: NaiveDate, today: NaiveDate, soon: NaiveDate) -> DueStatus {
+    if date < today {
+        DueStatus::Overdue
+    } else if date <= soon {
+        DueStatus::DueSoon
+    } else {
+        DueStatus::Ok
+    }
+}
+
+/// When an item is due, e.g., "due tomorrow", from the days until due
+fn due_in(days: i64) -> String {
+    match days {
+        d if d < -1 => format!("overdue by {} days", -d),
+        -1 => "overdue by 1 day".to_string(),
+        0 => "due today".to_string(),
+        1 => "due tomorrow".to_string(),
+        d => format!("due in {} days", d),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn due_statuses() {
+        let day = |d| NaiveDate::from_ymd(2021, 3, d);
+        let status = |d| due_status(day(d), day(3), day(10));
+        assert_eq!(status(2), DueStatus::Overdue);
+        assert_eq!(status(3), DueStatus::DueSoon);
+        assert_eq!(status(10), DueStatus::DueSoon);
+        assert_eq!(status(11), DueStatus::Ok);
+    }
+
+    #[test]
+    fn due_messages() {
+        assert_eq!(due_in(-3), "overdue by 3 days");
+        assert_eq!(due_in(-1), "overdue by 1 day");
+        assert_eq!(due_in(0), "due today");
+        assert_eq!(due_in(1), "due tomorrow");
+        assert_eq!(due_in(14), "due in 14 days");
+    }
+}
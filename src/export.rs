@@ -0,0 +1,478 @@
+//! Export of list items to other formats
+use crate::{
+    backup::{for_each_items_batch, get_all_items},
+    cache, fuzzy,
+    html::export_html,
+    items::{group_items, Column},
+    template::Template,
+    values::{raw_value, ValueFormatter},
+    xlsx::{write_workbook, Cell, Sheet},
+    Error, ExportFormat, ExportOpt, IcsOpt, JsonExportOpt, JsonFormat, ListInfo, MarkdownOpt,
+    MarkdownStyle, TemplateExportOpt, XlsxOpt,
+};
+use chrono::{DateTime, Duration, Local, NaiveDate, Utc};
+use std::{
+    io::{BufWriter, Write},
+    result::Result,
+};
+use zenkit::types::{ElementCategoryId, Entry, List, Workspace, ZKObjectID, ID};
+
+/// Export items in the format of the subcommand
+pub(crate) async fn export(ws: &Workspace, opt: &ExportOpt) -> Result<(), Error> {
+    let ws_id = ws.get_id();
+    match opt.format {
+        ExportFormat::Ics(ref ics_opt) => export_ics(ws_id, ics_opt).await,
+        ExportFormat::Json(ref json_opt) => export_json(ws_id, json_opt).await,
+        ExportFormat::Template(ref template_opt) => export_template(ws_id, template_opt).await,
+        ExportFormat::Md(ref md_opt) => export_markdown(ws_id, md_opt).await,
+        ExportFormat::Xlsx(ref xlsx_opt) => export_xlsx(ws, xlsx_opt).await,
+        ExportFormat::Html(ref html_opt) => export_html(ws_id, html_opt).await,
+    }
+}
+
+/// Write the list's items rendered with the template
+async fn export_template(ws_id: ID, opt: &TemplateExportOpt) -> Result<(), Error> {
+    let template = Template::load(&opt.template)?;
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let items = get_all_items(&list_info.list().uuid, opt.include_archived).await?;
+    let formatter = ValueFormatter::new(ws_id).await?;
+    let text = template.render_items(&list_info, &items, &formatter)?;
+    match opt.out {
+        Some(ref path) => std::fs::write(path, text)
+            .map_err(|e| Error::Message(format!("Writing {}: {}", path, e)))?,
+        None => print!("{}", text),
+    }
+    Ok(())
+}
+
+/// Write the list's items as json: an array, or with jsonl output, one item per line.
+/// Jsonl output is written as each batch of items is received.
+async fn export_json(ws_id: ID, opt: &JsonExportOpt) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let list_uuid = &list_info.list().uuid;
+    let mut out: Box<dyn Write + Send> = match opt.out {
+        Some(ref path) => Box::new(BufWriter::new(
+            std::fs::File::create(path)
+                .map_err(|e| Error::Message(format!("Creating {}: {}", path, e)))?,
+        )),
+        None => Box::new(std::io::stdout()),
+    };
+    match opt.output {
+        JsonFormat::Jsonl => {
+            for_each_items_batch(list_uuid, opt.include_archived, 0, None, |batch| {
+                for item in batch.iter() {
+                    serde_json::to_writer(&mut out, item)?;
+                    out.write_all(b"\n")?;
+                }
+                out.flush()?;
+                Ok(())
+            })
+            .await?;
+        }
+        JsonFormat::Json => {
+            let items = get_all_items(list_uuid, opt.include_archived).await?;
+            serde_json::to_writer_pretty(&mut out, &items)?;
+            out.write_all(b"\n")?;
+        }
+    }
+    out.flush()?;
+    Ok(())
+}
+
+/// Write a Markdown document with the list's items, as a table (or bullet list) with
+/// the `--fields` columns. With `--group-by`, there is a heading for each value of the
+/// column, with the number of items, followed by its items.
+async fn export_markdown(ws_id: ID, opt: &MarkdownOpt) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let columns = opt
+        .fields
+        .split(',')
+        .map(|name| Column::parse(&list_info, name.trim()))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let group_by = match opt.group_by {
+        Some(ref name) => Some(Column::parse(&list_info, name)?),
+        None => None,
+    };
+    let items = get_all_items(&list_info.list().uuid, opt.include_archived).await?;
+    let formatter = ValueFormatter::new(ws_id).await?;
+
+    let md = markdown(
+        opt.title.as_deref().unwrap_or(&list_info.list().name),
+        &columns,
+        group_by.as_ref(),
+        &items,
+        &formatter,
+        &opt.style,
+    );
+    match opt.out {
+        Some(ref path) => std::fs::write(path, md)
+            .map_err(|e| Error::Message(format!("Writing {}: {}", path, e)))?,
+        None => print!("{}", md),
+    }
+    Ok(())
+}
+
+/// Markdown document with the title, and the items in a table or list of the columns,
+/// under a heading for each group of `group_by`
+fn markdown(
+    title: &str,
+    columns: &[Column],
+    group_by: Option<&Column>,
+    items: &[Entry],
+    formatter: &ValueFormatter,
+    style: &MarkdownStyle,
+) -> String {
+    let mut md = format!("# {}\n\n", title);
+    let groups = match group_by {
+        Some(column) => group_items(column, items, Some(formatter)),
+        None => vec![(String::new(), items.iter().collect())],
+    };
+    for (value, group) in groups.iter() {
+        if group_by.is_some() {
+            md.push_str(&format!("## {} ({})\n\n", value, group.len()));
+        }
+        let rows: Vec<Vec<String>> = group
+            .iter()
+            .map(|item| {
+                columns
+                    .iter()
+                    .map(|c| c.value(item, Some(formatter)))
+                    .collect()
+            })
+            .collect();
+        match style {
+            MarkdownStyle::Table => {
+                let cells = |row: Vec<&str>| -> String {
+                    let cells: Vec<String> = row.iter().map(|c| escape_cell(c)).collect();
+                    format!("| {} |\n", cells.join(" | "))
+                };
+                md.push_str(&cells(columns.iter().map(|c| c.name()).collect()));
+                md.push_str(&cells(columns.iter().map(|_| "---").collect()));
+                for row in rows.iter() {
+                    md.push_str(&cells(row.iter().map(|c| c.as_str()).collect()));
+                }
+            }
+            MarkdownStyle::List => {
+                // the first column is the item's text; other non-empty columns follow it
+                for row in rows.iter() {
+                    let details: Vec<String> = columns
+                        .iter()
+                        .zip(row.iter())
+                        .skip(1)
+                        .filter(|(_, value)| !value.is_empty())
+                        .map(|(c, value)| format!("{}: {}", c.name(), one_line(value)))
+                        .collect();
+                    let text = row.first().map(|v| one_line(v)).unwrap_or_default();
+                    if details.is_empty() {
+                        md.push_str(&format!("- {}\n", text));
+                    } else {
+                        md.push_str(&format!("- {} ({})\n", text, details.join(", ")));
+                    }
+                }
+            }
+        }
+        md.push('\n');
+    }
+    md
+}
+
+/// A value on one line, with line breaks replaced by spaces
+fn one_line(value: &str) -> String {
+    value.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// A value for a Markdown table cell: on one line, with `|` escaped
+fn escape_cell(value: &str) -> String {
+    one_line(value).replace('|', "\\|")
+}
+
+/// Write an xlsx workbook with a sheet for the list, or for each active list in the
+/// workspace. Each sheet has a header row (id, title, the field names, created, updated)
+/// and a row per item. Numbers, checkboxes, and dates are written as typed cells,
+/// other fields as their display values.
+async fn export_xlsx(ws: &Workspace, opt: &XlsxOpt) -> Result<(), Error> {
+    let ws_id = ws.get_id();
+    let lists: Vec<List> = match opt.list {
+        Some(ref name) => vec![cache::get_list_info(ws_id, name).await?.list().clone()],
+        None => ws
+            .lists
+            .iter()
+            .filter(|l| l.deprecated_at.is_none())
+            .cloned()
+            .collect(),
+    };
+    let formatter = ValueFormatter::new(ws_id).await?;
+    let mut sheets = Vec::new();
+    for list in lists.iter() {
+        let list_info = cache::get_list_info(ws_id, &list.uuid).await?;
+        let items = get_all_items(&list.uuid, opt.include_archived).await?;
+        sheets.push(item_sheet(&list_info, &items, &formatter));
+    }
+    write_workbook(&opt.out, &sheets)
+}
+
+fn item_sheet(list_info: &ListInfo, items: &[Entry], formatter: &ValueFormatter) -> Sheet {
+    let fields = list_info.fields();
+    let mut header = vec!["id".to_string(), "title".to_string()];
+    header.extend(fields.iter().map(|f| f.name.clone()));
+    header.push("created".to_string());
+    header.push("updated".to_string());
+    let rows = items
+        .iter()
+        .map(|item| {
+            let mut row = vec![
+                Cell::Number(item.get_id() as f64),
+                Cell::Text(item.display_string.clone()),
+            ];
+            for field in fields.iter() {
+                let raw = raw_value(item, field);
+                let cell = match field.element_category {
+                    ElementCategoryId::Number => raw.and_then(|v| {
+                        v.as_f64()
+                            .or_else(|| v.as_str().and_then(|s| s.trim().parse().ok()))
+                            .map(Cell::Number)
+                    }),
+                    ElementCategoryId::Checkbox => raw.and_then(|v| v.as_bool()).map(Cell::Bool),
+                    ElementCategoryId::Date => raw.and_then(|v| v.as_str()).and_then(date_cell),
+                    _ => formatter.display(item, field).map(Cell::Text),
+                };
+                row.push(cell.unwrap_or(Cell::Empty));
+            }
+            row.push(Cell::DateTime(
+                item.created_at.with_timezone(&Local).naive_local(),
+            ));
+            row.push(Cell::DateTime(
+                item.updated_at.with_timezone(&Local).naive_local(),
+            ));
+            row
+        })
+        .collect();
+    Sheet {
+        name: list_info.list().name.clone(),
+        header,
+        rows,
+    }
+}
+
+/// A date cell, from a date field value: YYYY-MM-DD, or a date-time (shown in local time)
+fn date_cell(value: &str) -> Option<Cell> {
+    match DateTime::parse_from_rfc3339(value) {
+        Ok(dt) => Some(Cell::DateTime(dt.with_timezone(&Local).naive_local())),
+        Err(_) => NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d")
+            .ok()
+            .map(Cell::Date),
+    }
+}
+
+/// Maximum length of a line of an iCalendar file, in bytes (excluding the line break)
+const ICS_LINE_LENGTH: usize = 75;
+
+/// Write an iCalendar file with an event (or with `--todo`, a to-do) for each item
+/// with a value in the date field. The event's summary is the item's title, and its
+/// description is the value of the `--description` field, if any.
+async fn export_ics(ws_id: ID, opt: &IcsOpt) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let field = fuzzy::get_field(&list_info, &opt.field)?;
+    if field.element_category != ElementCategoryId::Date {
+        return Err(Error::Invalid(format!(
+            "Field '{}' is not a date field",
+            field.name
+        )));
+    }
+    let description = match opt.description {
+        Some(ref name) => Some((
+            fuzzy::get_field(&list_info, name)?,
+            ValueFormatter::new(ws_id).await?,
+        )),
+        None => None,
+    };
+    let component = if opt.todo { "VTODO" } else { "VEVENT" };
+    let stamp = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        format!("PRODID:-//zenkit-cli//{}//EN", env!("CARGO_PKG_VERSION")),
+        format!("X-WR-CALNAME:{}", escape_text(&list_info.list().name)),
+    ];
+    for item in get_all_items(&list_info.list().uuid, false).await?.iter() {
+        let date = match raw_value(item, field).and_then(|v| v.as_str()) {
+            Some(date) => date,
+            None => continue,
+        };
+        let (start, end) = match ics_date(date) {
+            Some(dates) => dates,
+            None => {
+                eprintln!("Item {}: skipping invalid date '{}'", item.get_id(), date);
+                continue;
+            }
+        };
+        lines.push(format!("BEGIN:{}", component));
+        lines.push(format!("UID:{}@zenkit.com", item.get_uuid()));
+        lines.push(format!("DTSTAMP:{}", stamp));
+        if opt.todo {
+            lines.push(format!("DUE{}", start));
+        } else {
+            lines.push(format!("DTSTART{}", start));
+            if let Some(end) = end {
+                lines.push(format!("DTEND{}", end));
+            }
+        }
+        lines.push(format!("SUMMARY:{}", escape_text(&item.display_string)));
+        if let Some((field, ref formatter)) = description {
+            if let Some(text) = formatter.display(item, field) {
+                lines.push(format!("DESCRIPTION:{}", escape_text(&text)));
+            }
+        }
+        lines.push(format!("END:{}", component));
+    }
+    lines.push("END:VCALENDAR".to_string());
+
+    let mut ics = String::new();
+    for line in lines.iter() {
+        ics.push_str(&fold_line(line));
+        ics.push_str("\r\n");
+    }
+    match opt.out {
+        Some(ref path) => std::fs::write(path, ics)
+            .map_err(|e| Error::Message(format!("Writing {}: {}", path, e)))?,
+        None => print!("{}", ics),
+    }
+    Ok(())
+}
+
+/// Property parameters and values for the start (and for all-day events, the end)
+/// of an event, from a date field value as stored by Zenkit: YYYY-MM-DD, or a UTC date-time.
+fn ics_date(value: &str) -> Option<(String, Option<String>)> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        let utc = dt.with_timezone(&Utc);
+        return Some((format!(":{}", utc.format("%Y%m%dT%H%M%SZ")), None));
+    }
+    let date = NaiveDate::parse_from_str(value.get(..10)?, "%Y-%m-%d").ok()?;
+    Some((
+        format!(";VALUE=DATE:{}", date.format("%Y%m%d")),
+        Some(format!(
+            ";VALUE=DATE:{}",
+            (date + Duration::days(1)).format("%Y%m%d")
+        )),
+    ))
+}
+
+/// Escape a text value (RFC 5545, 3.3.11)
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | ';' | ',' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Split a line longer than ICS_LINE_LENGTH bytes into continuation lines,
+/// which start with a space, without splitting characters
+fn fold_line(line: &str) -> String {
+    let mut folded = String::with_capacity(line.len());
+    let mut len = 0;
+    for c in line.chars() {
+        if len + c.len_utf8() > ICS_LINE_LENGTH {
+            folded.push_str("\r\n ");
+            // the leading space counts toward the length of the continuation line
+            len = 1;
+        }
+        folded.push(c);
+        len += c.len_utf8();
+    }
+    folded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{choice_field, field, item, list_info};
+    use serde_json::json;
+
+    #[test]
+    fn ics_dates() {
+        // all-day events end the next day
+        assert_eq!(
+            ics_date("2021-02-28"),
+            Some((
+                ";VALUE=DATE:20210228".to_string(),
+                Some(";VALUE=DATE:20210301".to_string())
+            ))
+        );
+        assert_eq!(
+            ics_date("2021-03-05T14:30:00+01:00"),
+            Some((":20210305T133000Z".to_string(), None))
+        );
+        assert_eq!(ics_date("next week"), None);
+    }
+
+    #[test]
+    fn ics_text() {
+        assert_eq!(escape_text("a, b; c\\d\r\nnext"), r"a\, b\; c\\d\nnext");
+        let line = format!("SUMMARY:{}", "é".repeat(40));
+        let folded = fold_line(&line);
+        let lines: Vec<&str> = folded.split("\r\n").collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines.iter().all(|l| l.len() <= ICS_LINE_LENGTH));
+        assert!(lines[1].starts_with(' '));
+        assert_eq!(folded.replace("\r\n ", ""), line);
+        assert_eq!(fold_line("SUMMARY:short"), "SUMMARY:short");
+    }
+
+    #[test]
+    fn markdown_document() {
+        let list_info = list_info(vec![
+            field(1, "Title", 1),
+            field(2, "Points", 2),
+            choice_field(3, "Stage", &[(1, "Todo"), (2, "Done")]),
+        ]);
+        let items = vec![
+            item(
+                1,
+                "Fix | pipe",
+                json!({"field-2_number": 3, "field-3_categories": [2]}),
+            ),
+            item(2, "Write\ntests", json!({"field-3_categories": [1]})),
+        ];
+        let formatter = ValueFormatter::default();
+        let columns = vec![
+            Column::parse(&list_info, "name").unwrap(),
+            Column::parse(&list_info, "Points").unwrap(),
+        ];
+        let stage = Column::parse(&list_info, "Stage").unwrap();
+        assert_eq!(
+            markdown(
+                "Sprint",
+                &columns,
+                Some(&stage),
+                &items,
+                &formatter,
+                &MarkdownStyle::Table
+            ),
+            "# Sprint\n\n\
+             ## Todo (1)\n\n| name | Points |\n| --- | --- |\n| Write tests |  |\n\n\
+             ## Done (1)\n\n| name | Points |\n| --- | --- |\n| Fix \\| pipe | 3 |\n\n"
+        );
+        assert_eq!(
+            markdown(
+                "Sprint",
+                &columns,
+                None,
+                &items,
+                &formatter,
+                &MarkdownStyle::List
+            ),
+            "# Sprint\n\n- Fix | pipe (Points: 3)\n- Write tests\n\n"
+        );
+    }
+}
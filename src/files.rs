@@ -0,0 +1,218 @@
+//! File attachments of list items
+use crate::{
+    backup::{safe_file_name, sha256_hex},
+    cache, dryrun, fuzzy,
+    rest::RestClient,
+    retry::retry,
+    AttachOpt, Error, FilesOpt, ListInfo,
+};
+use serde_json::{json, Value};
+use std::{collections::HashSet, path::Path, result::Result};
+use zenkit::types::{Element, ElementCategoryId, Entry, ZKObjectID, ID};
+
+/// Upload a file and add it to the item's files field. Returns the file's metadata,
+/// or Null in dry-run mode.
+pub(crate) async fn attach_file(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &AttachOpt,
+) -> Result<Value, Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let field = files_field(&list_info, opt.field.as_deref())?;
+    let item = retry("get_item", || list_info.get_item(&opt.item)).await?;
+
+    let data = std::fs::read(&opt.file)
+        .map_err(|e| Error::Message(format!("Reading {}: {}", &opt.file, e)))?;
+    let name = Path::new(&opt.file)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(&opt.file);
+    let file = rest
+        .upload(&format!("lists/{}/files", list_info.get_id()), name, &data)
+        .await?;
+    if dryrun::enabled() {
+        return Ok(Value::Null);
+    }
+    let file_id = file
+        .get("id")
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| Error::Message(format!("Upload of {} returned no file id", &opt.file)))?;
+
+    // the field's value is the list of file ids, so existing files are kept
+    let mut ids: Vec<u64> = attached_files(&item, field)
+        .iter()
+        .filter_map(|f| f.get("id").and_then(|v| v.as_u64()))
+        .collect();
+    ids.push(file_id);
+    let mut body = json!({});
+    body[format!("{}_files", field.uuid)] = json!(ids);
+    rest.put(
+        &format!("lists/{}/entries/{}", list_info.get_id(), item.get_id()),
+        &body,
+    )
+    .await?;
+    Ok(file)
+}
+
+/// List the files attached to an item, and with `--download`, save them
+/// to the folder with their original names. Prints one line per file:
+/// id, field, file name, size, and (if downloaded) sha256 checksum.
+pub(crate) async fn item_files(rest: &RestClient, ws_id: ID, opt: &FilesOpt) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let fields: Vec<&Element> = match opt.field {
+        Some(ref name) => vec![files_field(&list_info, Some(name))?],
+        None => list_info
+            .fields()
+            .iter()
+            .filter(|f| f.element_category == ElementCategoryId::Files)
+            .collect(),
+    };
+    let item = retry("get_item", || list_info.get_item(&opt.item)).await?;
+    if let Some(ref dir) = opt.download {
+        std::fs::create_dir_all(dir)?;
+    }
+    // names of downloaded files
+    let mut names = HashSet::new();
+    for field in fields.iter() {
+        for file in attached_files(&item, field).iter() {
+            let (id, name) = match (
+                file.get("id").and_then(|v| v.as_u64()),
+                file.get("fileName").and_then(|v| v.as_str()),
+            ) {
+                (Some(id), Some(name)) => (id, name),
+                _ => continue,
+            };
+            let size = file.get("size").and_then(|v| v.as_u64());
+            match opt.download {
+                Some(ref dir) => {
+                    let data = rest
+                        .get_bytes(&format!("lists/{}/files/{}", list_info.list().uuid, id))
+                        .await?;
+                    let path = Path::new(dir).join(download_name(&mut names, id, name));
+                    std::fs::write(&path, &data)?;
+                    println!(
+                        "{}\t{}\t{}\t{}\t{}",
+                        id,
+                        field.name,
+                        name,
+                        data.len(),
+                        sha256_hex(&data)
+                    );
+                }
+                None => println!(
+                    "{}\t{}\t{}\t{}",
+                    id,
+                    field.name,
+                    name,
+                    size.map(|s| s.to_string()).unwrap_or_default()
+                ),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Name to save a downloaded file as: its file name, made safe, or if a file
+/// with that name was already downloaded (`names`), prefixed with the file id
+fn download_name(names: &mut HashSet<String>, id: u64, name: &str) -> String {
+    let mut file_name = safe_file_name(name);
+    if !names.insert(file_name.clone()) {
+        file_name = format!("{}_{}", id, file_name);
+        names.insert(file_name.clone());
+    }
+    file_name
+}
+
+/// The files field with the given name, or the list's only files field if `name` is None
+fn files_field<'l>(list_info: &'l ListInfo, name: Option<&str>) -> Result<&'l Element, Error> {
+    if let Some(name) = name {
+        let field = fuzzy::get_field(list_info, name)?;
+        if field.element_category != ElementCategoryId::Files {
+            return Err(Error::Invalid(format!(
+                "Field '{}' is not a files field",
+                field.name
+            )));
+        }
+        return Ok(field);
+    }
+    let fields: Vec<&Element> = list_info
+        .fields()
+        .iter()
+        .filter(|f| f.element_category == ElementCategoryId::Files)
+        .collect();
+    match fields.as_slice() {
+        [field] => Ok(field),
+        [] => Err(Error::NotFound(format!(
+            "List '{}' has no files field",
+            list_info.list().name
+        ))),
+        _ => Err(Error::Invalid(format!(
+            "List '{}' has more than one files field. Use --field to select one",
+            list_info.list().name
+        ))),
+    }
+}
+
+/// Descriptions (id, uuid, fileName, size, ...) of the files in the item's files field
+fn attached_files<'e>(item: &'e Entry, field: &Element) -> &'e [Value] {
+    item.fields
+        .get(&format!("{}_files_sort", field.uuid))
+        .and_then(|v| v.as_array())
+        .map(|v| v.as_slice())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{field, item, list_info};
+
+    #[test]
+    fn files_field_of_list() {
+        let files = ElementCategoryId::Files as u8;
+        let one = list_info(vec![field(1, "Title", 1), field(2, "Attachments", files)]);
+        assert_eq!(files_field(&one, None).unwrap().uuid, "field-2");
+        assert_eq!(
+            files_field(&one, Some("Attachments")).unwrap().uuid,
+            "field-2"
+        );
+        assert!(matches!(
+            files_field(&one, Some("Title")),
+            Err(Error::Invalid(_))
+        ));
+        let none = list_info(vec![field(1, "Title", 1)]);
+        assert!(matches!(files_field(&none, None), Err(Error::NotFound(_))));
+        let two = list_info(vec![
+            field(2, "Attachments", files),
+            field(3, "Photos", files),
+        ]);
+        assert!(matches!(files_field(&two, None), Err(Error::Invalid(_))));
+        assert_eq!(files_field(&two, Some("Photos")).unwrap().uuid, "field-3");
+    }
+
+    #[test]
+    fn files_of_item() {
+        let field = field(2, "Attachments", ElementCategoryId::Files as u8);
+        let report = item(
+            1,
+            "Report",
+            json!({
+                "field-2_files": [4],
+                "field-2_files_sort": [{ "id": 4, "fileName": "report.pdf", "size": 1024 }],
+            }),
+        );
+        let files = attached_files(&report, &field);
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0]["fileName"], "report.pdf");
+        assert!(attached_files(&item(2, "Empty", json!({})), &field).is_empty());
+    }
+
+    #[test]
+    fn download_names() {
+        let mut names = HashSet::new();
+        assert_eq!(download_name(&mut names, 4, "report.pdf"), "report.pdf");
+        assert_eq!(download_name(&mut names, 5, "report.pdf"), "5_report.pdf");
+        assert_eq!(download_name(&mut names, 6, "../notes.txt"), "_notes.txt");
+    }
+}
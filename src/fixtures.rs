@@ -0,0 +1,400 @@
+//! Record api requests and responses to a fixtures folder (`--record`), and serve
+//! them back later without network access (`--replay`), for testing scripts that use zk.
+//! A local http server is used as the api endpoint, so requests made by the zenkit
+//! library are recorded and replayed too.
+use crate::{http, Error};
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    result::Result,
+    sync::{Arc, Mutex},
+};
+
+/// Header with the api token. It's forwarded, but not recorded.
+const TOKEN_HEADER: &str = "Zenkit-API-Key";
+
+/// Status of a replayed request that wasn't recorded
+const NOT_RECORDED: u16 = 501;
+
+/// Fields of json request bodies that are set to the current time, e.g., when an item
+/// is archived. They're left out when matching requests, so a replayed request
+/// matches the one recorded at another time.
+const VOLATILE_FIELDS: &[&str] = &["deprecated_at"];
+
+pub(crate) enum FixtureMode {
+    /// Forward requests to the api endpoint, and save the responses
+    Record { upstream: String },
+    /// Serve saved responses
+    Replay,
+}
+
+/// Requests with the same method, path, and body, and their responses in the order
+/// received. Replayed requests get the responses in order, repeating the last one.
+#[derive(Debug, Serialize, Deserialize)]
+struct Fixture {
+    method: String,
+    /// path and query, relative to the api endpoint
+    path: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    body: Option<String>,
+    responses: Vec<RecordedResponse>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct RecordedResponse {
+    status: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    /// json response body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    json: Option<Value>,
+    /// other text response body
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    /// binary response body, e.g., a file download
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bytes: Option<Vec<u8>>,
+}
+
+impl RecordedResponse {
+    fn new(status: u16, content_type: Option<String>, data: Vec<u8>) -> Self {
+        let (json, text, bytes) = match String::from_utf8(data) {
+            Ok(s) if s.trim().is_empty() => (None, None, None),
+            Ok(s) => match serde_json::from_str::<Value>(&s) {
+                Ok(v) => (Some(v), None, None),
+                Err(_) => (None, Some(s), None),
+            },
+            Err(e) => (None, None, Some(e.into_bytes())),
+        };
+        RecordedResponse {
+            status,
+            content_type,
+            json,
+            text,
+            bytes,
+        }
+    }
+
+    fn body(&self) -> Vec<u8> {
+        match (&self.json, &self.text, &self.bytes) {
+            (Some(json), _, _) => json.to_string().into_bytes(),
+            (_, Some(text), _) => text.clone().into_bytes(),
+            (_, _, Some(bytes)) => bytes.clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+struct Fixtures {
+    dir: PathBuf,
+    mode: FixtureMode,
+    client: reqwest::Client,
+    /// fixture file name -> number of requests received in this run
+    seen: Mutex<HashMap<String, usize>>,
+    /// held while a fixture file is updated
+    write_lock: Mutex<()>,
+}
+
+/// Start the local server for recording or replaying fixtures in the folder,
+/// and return its url, to be used as the api endpoint
+pub(crate) async fn start(dir: &str, mode: FixtureMode) -> Result<String, Error> {
+    let dir = PathBuf::from(dir);
+    match mode {
+        FixtureMode::Record { .. } => std::fs::create_dir_all(&dir)?,
+        FixtureMode::Replay if !dir.is_dir() => {
+            return Err(Error::NotFound(format!(
+                "Fixtures folder {} not found",
+                dir.display()
+            )))
+        }
+        FixtureMode::Replay => {}
+    }
+    let fixtures = Arc::new(Fixtures {
+        dir,
+        mode,
+        client: http::client()?,
+        seen: Mutex::new(HashMap::new()),
+        write_lock: Mutex::new(()),
+    });
+    let make_svc = make_service_fn(move |_conn| {
+        let fixtures = fixtures.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle_request(fixtures.clone(), req))) }
+    });
+    let server = Server::try_bind(&SocketAddr::from(([127, 0, 0, 1], 0)))?.serve(make_svc);
+    let url = format!("http://{}", server.local_addr());
+    tokio::spawn(async move {
+        if let Err(e) = server.await {
+            eprintln!("Fixtures server error: {}", e);
+        }
+    });
+    Ok(url)
+}
+
+async fn handle_request(
+    fixtures: Arc<Fixtures>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let resp = match fixtures.handle(req).await {
+        Ok(resp) => resp,
+        Err(e) => RecordedResponse::new(
+            NOT_RECORDED,
+            Some("text/plain".to_string()),
+            e.summary().into_bytes(),
+        ),
+    };
+    let mut builder = Response::builder().status(resp.status);
+    if let Some(ref content_type) = resp.content_type {
+        builder = builder.header("content-type", content_type.as_str());
+    }
+    Ok(builder
+        .body(Body::from(resp.body()))
+        .unwrap_or_else(|_| Response::new(Body::empty())))
+}
+
+impl Fixtures {
+    async fn handle(&self, req: Request<Body>) -> Result<RecordedResponse, Error> {
+        let method = req.method().as_str().to_string();
+        let path = req
+            .uri()
+            .path_and_query()
+            .map(|pq| pq.as_str().to_string())
+            .unwrap_or_else(|| req.uri().path().to_string());
+        let header = |name: &str| {
+            req.headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        };
+        let token = header(TOKEN_HEADER);
+        let content_type = header("content-type");
+        let data = hyper::body::to_bytes(req.into_body())
+            .await
+            .map_err(|e| Error::Message(format!("Reading request: {}", e)))?
+            .to_vec();
+        let body = if data.is_empty() {
+            None
+        } else {
+            Some(String::from_utf8_lossy(&data).to_string())
+        };
+        let fname = fixture_name(&method, &path, body.as_deref());
+        let count = match self.seen.lock() {
+            Ok(mut seen) => {
+                let count = seen.entry(fname.clone()).or_insert(0);
+                *count += 1;
+                *count
+            }
+            Err(_) => 1,
+        };
+        let file = self.dir.join(&fname);
+        match self.mode {
+            FixtureMode::Replay => {
+                let fixture: Fixture = read_fixture(&file).ok_or_else(|| {
+                    Error::Message(format!("No recorded response for {} {}", method, path))
+                })?;
+                fixture
+                    .responses
+                    .get(count - 1)
+                    .or_else(|| fixture.responses.last())
+                    .cloned()
+                    .ok_or_else(|| {
+                        Error::Message(format!("No recorded response for {} {}", method, path))
+                    })
+            }
+            FixtureMode::Record { ref upstream } => {
+                let url = format!("{}{}", upstream.trim_end_matches('/'), path);
+                let method = reqwest::Method::from_bytes(method.as_bytes())
+                    .map_err(|e| Error::Message(e.to_string()))?;
+                let mut upstream_req = self.client.request(method.clone(), &url);
+                if let Some(token) = token {
+                    upstream_req = upstream_req.header(TOKEN_HEADER, token);
+                }
+                if let Some(ref content_type) = content_type {
+                    upstream_req = upstream_req.header("content-type", content_type.as_str());
+                }
+                let upstream_resp = upstream_req.body(data).send().await?;
+                let status = upstream_resp.status().as_u16();
+                let resp_type = upstream_resp
+                    .headers()
+                    .get(reqwest::header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.to_string());
+                let resp_data = upstream_resp.bytes().await?.to_vec();
+                let resp = RecordedResponse::new(status, resp_type, resp_data);
+                let _guard = self.write_lock.lock();
+                // the first response in this run replaces those of earlier recordings
+                let mut fixture = match read_fixture(&file) {
+                    Some(fixture) if count > 1 => fixture,
+                    _ => Fixture {
+                        method: method.to_string(),
+                        path,
+                        body,
+                        responses: Vec::new(),
+                    },
+                };
+                fixture.responses.push(resp.clone());
+                std::fs::write(&file, serde_json::to_string_pretty(&fixture)?)?;
+                Ok(resp)
+            }
+        }
+    }
+}
+
+fn read_fixture(path: &Path) -> Option<Fixture> {
+    let data = std::fs::read(path).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+/// File name of the fixture for requests with the method, path, and body:
+/// `<method>_<path>_<hash>.json`, with the path shortened to letters, digits, and dashes.
+/// The hash of a json body leaves out `VOLATILE_FIELDS`.
+fn fixture_name(method: &str, path: &str, body: Option<&str>) -> String {
+    let mut slug: String = path
+        .split('?')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect::<String>()
+        .trim_matches('-')
+        .to_string();
+    slug.truncate(60);
+    let body = body.map(stable_body);
+    let key = format!("{} {}\n{}", method, path, body.unwrap_or_default());
+    let hash = crate::backup::sha256_hex(key.as_bytes());
+    format!("{}_{}_{}.json", method, slug, &hash[..12])
+}
+
+/// The request body without `VOLATILE_FIELDS`. Other bodies are unchanged, so they
+/// match fixtures recorded before a field was added to the list.
+fn stable_body(body: &str) -> String {
+    match serde_json::from_str::<Value>(body) {
+        Ok(Value::Object(mut map)) if VOLATILE_FIELDS.iter().any(|f| map.contains_key(*f)) => {
+            for field in VOLATILE_FIELDS.iter() {
+                map.remove(*field);
+            }
+            Value::Object(map).to_string()
+        }
+        _ => body.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn fixture_names() {
+        let name = fixture_name("GET", "/v1/lists/1/elements?limit=5", None);
+        assert!(name.starts_with("GET_v1-lists-1-elements_"), "{}", name);
+        assert!(name.ends_with(".json"));
+        assert_eq!(
+            name,
+            fixture_name("GET", "/v1/lists/1/elements?limit=5", None)
+        );
+        assert_ne!(
+            name,
+            fixture_name("GET", "/v1/lists/1/elements?limit=6", None)
+        );
+        assert_ne!(
+            name,
+            fixture_name("POST", "/v1/lists/1/elements?limit=5", None)
+        );
+
+        let path = "/v1/lists/1/entries/2";
+        let archive = |at: &str, extra: Value| {
+            let mut body = json!({ "deprecated_at": at });
+            if let Value::Object(extra) = extra {
+                body.as_object_mut().unwrap().extend(extra);
+            }
+            fixture_name("PUT", path, Some(&body.to_string()))
+        };
+        // requests differing only in the time they were made match the same fixture
+        assert_eq!(
+            archive("2021-03-01T00:00:00+00:00", json!({})),
+            archive("2021-03-02T12:30:00+00:00", json!({}))
+        );
+        assert_ne!(
+            archive("2021-03-01T00:00:00+00:00", json!({})),
+            archive("2021-03-01T00:00:00+00:00", json!({ "displayString": "a" }))
+        );
+        // other bodies are hashed as sent
+        assert_ne!(
+            fixture_name("PUT", path, Some(r#"{"a":1,"b":2}"#)),
+            fixture_name("PUT", path, Some(r#"{"b":2,"a":1}"#))
+        );
+        assert_eq!(stable_body("not json"), "not json");
+    }
+
+    #[test]
+    fn recorded_responses() {
+        let resp = RecordedResponse::new(200, None, br#"{"id": 1}"#.to_vec());
+        assert_eq!(resp.json, Some(json!({ "id": 1 })));
+        assert_eq!(resp.body(), br#"{"id":1}"#.to_vec());
+
+        let resp = RecordedResponse::new(500, Some("text/plain".into()), b"oops".to_vec());
+        assert_eq!(
+            (resp.json.as_ref(), resp.text.as_deref()),
+            (None, Some("oops"))
+        );
+        assert_eq!(resp.body(), b"oops".to_vec());
+
+        let data = vec![0x89, b'P', b'N', b'G', 0xff];
+        let resp = RecordedResponse::new(200, Some("image/png".into()), data.clone());
+        assert_eq!(resp.bytes, Some(data.clone()));
+        assert_eq!(resp.body(), data);
+
+        let resp = RecordedResponse::new(204, None, b" \n".to_vec());
+        assert_eq!((&resp.json, &resp.text, &resp.bytes), (&None, &None, &None));
+        assert!(resp.body().is_empty());
+    }
+
+    #[tokio::test]
+    async fn replay_order() {
+        let dir = std::env::temp_dir().join(format!("zk_fixtures_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = "/v1/users/me";
+        let fixture = Fixture {
+            method: "GET".to_string(),
+            path: path.to_string(),
+            body: None,
+            responses: vec![
+                RecordedResponse::new(429, None, b"slow down".to_vec()),
+                RecordedResponse::new(200, None, br#"{"id": 1}"#.to_vec()),
+            ],
+        };
+        std::fs::write(
+            dir.join(fixture_name("GET", path, None)),
+            serde_json::to_string(&fixture).unwrap(),
+        )
+        .unwrap();
+        let fixtures = Fixtures {
+            dir: dir.clone(),
+            mode: FixtureMode::Replay,
+            client: reqwest::Client::new(),
+            seen: Mutex::new(HashMap::new()),
+            write_lock: Mutex::new(()),
+        };
+        let request = |path: &str| Request::get(path).body(Body::empty()).unwrap();
+
+        // responses in the order recorded, then the last one again
+        let mut statuses = Vec::new();
+        for _ in 0..3 {
+            statuses.push(fixtures.handle(request(path)).await.unwrap().status);
+        }
+        assert_eq!(statuses, vec![429, 200, 200]);
+        assert!(matches!(
+            fixtures.handle(request("/v1/users/2")).await,
+            Err(Error::Message(msg)) if msg.contains("No recorded response for GET /v1/users/2")
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,192 @@
+//! Suggestions for list and field names that weren't found, and with `--fuzzy`,
+//! use of the closest match
+use crate::{aliases, Error, ListInfo};
+use std::{
+    result::Result,
+    sync::atomic::{AtomicBool, Ordering},
+};
+use zenkit::types::{Element, FieldVal, UpdateAction};
+
+static FUZZY: AtomicBool = AtomicBool::new(false);
+
+/// Maximum number of names suggested
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Enable or disable use of the closest match for names that aren't found
+pub(crate) fn configure(enabled: bool) {
+    FUZZY.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns true if the closest match is used for names that aren't found
+pub(crate) fn enabled() -> bool {
+    FUZZY.load(Ordering::Relaxed)
+}
+
+/// The field with the name, id, or uuid, or that the alias stands for. If there's none,
+/// the field with the closest name is used with `--fuzzy`, and otherwise the error
+/// suggests the closest names.
+pub(crate) fn get_field<'l>(list_info: &'l ListInfo, name: &str) -> Result<&'l Element, Error> {
+    let name = &aliases::resolve(name);
+    match list_info.get_field(name) {
+        Ok(field) => Ok(field),
+        Err(e) => {
+            let names: Vec<&str> = list_info.fields().iter().map(|f| f.name.as_str()).collect();
+            match closest_match("Field", name, &names)? {
+                Some(best) => Ok(list_info.get_field(&best)?),
+                None => Err(e),
+            }
+        }
+    }
+}
+
+/// Replace the field names, ids, or aliases of the values with the names of the fields,
+/// as found by `get_field`
+pub(crate) fn resolve_field_names(
+    list_info: &ListInfo,
+    values: &mut [(String, FieldVal, UpdateAction)],
+) -> Result<(), Error> {
+    for (name, _, _) in values.iter_mut() {
+        *name = get_field(list_info, name)?.name.clone();
+    }
+    Ok(())
+}
+
+/// For a `kind` of object (e.g., "List") named `name` that wasn't found: if `--fuzzy` is
+/// set, and one of `candidates` is closest to the name, returns that candidate.
+/// Otherwise, if any candidates are close, returns an error suggesting them.
+/// Returns None if no candidates are close.
+pub(crate) fn closest_match(
+    kind: &str,
+    name: &str,
+    candidates: &[&str],
+) -> Result<Option<String>, Error> {
+    let matches = suggestions(name, candidates);
+    match matches.as_slice() {
+        [] => Ok(None),
+        [(best, distance), rest @ ..]
+            if enabled() && rest.first().map(|(_, d)| d > distance).unwrap_or(true) =>
+        {
+            eprintln!("Using {} '{}' for '{}'", kind.to_lowercase(), best, name);
+            Ok(Some(best.to_string()))
+        }
+        _ => Err(Error::NotFound(format!(
+            "{} '{}' not found. Did you mean {}?",
+            kind,
+            name,
+            matches
+                .iter()
+                .map(|(m, _)| format!("'{}'", m))
+                .collect::<Vec<_>>()
+                .join(" or ")
+        ))),
+    }
+}
+
+/// Candidates close to `name`, ignoring case, with their edit distance, closest first.
+/// A candidate is close if its distance is at most a third of the name's length (and
+/// at least 2), or if one contains the other.
+fn suggestions<'c>(name: &str, candidates: &[&'c str]) -> Vec<(&'c str, usize)> {
+    let lower = name.to_lowercase();
+    let limit = (lower.chars().count() / 3).max(2);
+    let mut matches: Vec<(&str, usize)> = candidates
+        .iter()
+        .filter_map(|c| {
+            let c_lower = c.to_lowercase();
+            let distance = levenshtein(&lower, &c_lower);
+            let contains =
+                lower.len() > 2 && (c_lower.contains(&lower) || lower.contains(&c_lower));
+            if distance <= limit || contains {
+                Some((*c, distance))
+            } else {
+                None
+            }
+        })
+        .collect();
+    matches.sort_by_key(|(_, distance)| *distance);
+    matches.dedup_by(|(a, _), (b, _)| a == b);
+    matches.truncate(MAX_SUGGESTIONS);
+    matches
+}
+
+/// Number of single-character insertions, deletions, and substitutions to change a into b
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            row[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(row[j] + 1);
+        }
+        prev = row;
+    }
+    prev[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{field, list_info};
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("abc", ""), 3);
+        assert_eq!(levenshtein("Status", "Status"), 0);
+        assert_eq!(levenshtein("stauts", "status"), 2);
+    }
+
+    #[test]
+    fn close_names() {
+        let names = ["Status", "Priority", "Assignee"];
+        assert_eq!(suggestions("stauts", &names), vec![("Status", 2)]);
+        assert_eq!(suggestions("STATUS", &names), vec![("Status", 0)]);
+        // contained in the name
+        assert_eq!(suggestions("prio", &names), vec![("Priority", 4)]);
+        assert!(suggestions("Description", &names).is_empty());
+        // closest first, at most MAX_SUGGESTIONS
+        let names = ["Stage", "State", "Stats", "Status"];
+        assert_eq!(
+            suggestions("stat", &names),
+            vec![("State", 1), ("Stats", 1), ("Stage", 2)]
+        );
+    }
+
+    #[test]
+    fn suggested_names() {
+        assert!(matches!(
+            closest_match("List", "Bugs", &["Tasks"]),
+            Ok(None)
+        ));
+        assert!(matches!(
+            closest_match("List", "Taks", &["Tasks", "Notes"]),
+            Err(Error::NotFound(msg)) if msg == "List 'Taks' not found. Did you mean 'Tasks'?"
+        ));
+    }
+
+    #[test]
+    fn fields_by_name() {
+        let info = list_info(vec![field(2, "Status", 6), field(3, "Notes", 1)]);
+        assert_eq!(get_field(&info, "Status").unwrap().id, 2);
+        assert_eq!(get_field(&info, "3").unwrap().name, "Notes");
+        assert!(matches!(
+            get_field(&info, "status"),
+            Err(Error::NotFound(msg)) if msg.ends_with("Did you mean 'Status'?")
+        ));
+        assert!(matches!(
+            get_field(&info, "Assignee"),
+            Err(Error::NotFound(msg)) if msg == "Field 'Assignee' not found in list Tasks"
+        ));
+        let mut values = vec![(
+            "notes".to_string(),
+            FieldVal::Str("x".into()),
+            UpdateAction::Null,
+        )];
+        assert!(resolve_field_names(&info, &mut values).is_err());
+        values[0].0 = "3".to_string();
+        resolve_field_names(&info, &mut values).unwrap();
+        assert_eq!(values[0].0, "Notes");
+    }
+}
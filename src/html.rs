@@ -0,0 +1,346 @@
+//! Export of list items as a standalone html page
+use crate::{
+    backup::get_all_items, cache, fuzzy, items::Column, values::ValueFormatter, Error, HtmlOpt,
+    HtmlStyle,
+};
+use chrono::Local;
+use std::result::Result;
+use zenkit::types::{Element, ElementCategoryId, Entry, ID};
+
+/// Heading of the board column of items with no category
+const NO_VALUE: &str = "(none)";
+
+/// Color of choices without one
+const DEFAULT_COLOR: &str = "#95a5a6";
+
+const STYLE: &str = r#"
+body { font-family: -apple-system, "Segoe UI", Helvetica, Arial, sans-serif; margin: 2em; color: #24292e; }
+h1 { font-size: 1.6em; margin-bottom: 0.2em; }
+.meta { color: #6a737d; margin-bottom: 1.5em; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border-bottom: 1px solid #e1e4e8; padding: 6px 10px; text-align: left; vertical-align: top; }
+th { background: #f6f8fa; cursor: pointer; user-select: none; white-space: nowrap; }
+th.asc::after { content: " \25B2"; }
+th.desc::after { content: " \25BC"; }
+tr:hover td { background: #fafbfc; }
+.badge { display: inline-block; padding: 1px 8px; margin: 1px 2px 1px 0; border-radius: 10px; color: #fff; font-size: 0.85em; }
+.board { display: flex; gap: 12px; align-items: flex-start; overflow-x: auto; }
+.column { background: #f6f8fa; border-radius: 6px; padding: 8px; min-width: 220px; max-width: 300px; flex: 0 0 auto; }
+.column h2 { font-size: 1em; margin: 4px 4px 8px; border-left: 4px solid; padding-left: 6px; }
+.card { background: #fff; border: 1px solid #e1e4e8; border-radius: 6px; padding: 8px; margin-bottom: 8px; }
+.card .title { font-weight: 600; margin-bottom: 4px; }
+.card .field { font-size: 0.85em; color: #586069; }
+"#;
+
+const SORT_SCRIPT: &str = r#"
+document.querySelectorAll("th").forEach(function (th, col) {
+  th.addEventListener("click", function () {
+    var table = th.closest("table"), body = table.tBodies[0];
+    var desc = th.classList.contains("asc");
+    table.querySelectorAll("th").forEach(function (h) { h.classList.remove("asc", "desc"); });
+    th.classList.add(desc ? "desc" : "asc");
+    var rows = Array.prototype.slice.call(body.rows);
+    rows.sort(function (a, b) {
+      var x = a.cells[col].textContent.trim(), y = b.cells[col].textContent.trim();
+      if (x === "" || y === "") { return (x === "") - (y === ""); }
+      var nx = parseFloat(x), ny = parseFloat(y);
+      var c = (!isNaN(nx) && !isNaN(ny) && String(nx) === x && String(ny) === y)
+        ? nx - ny : x.localeCompare(y, undefined, { numeric: true });
+      return desc ? -c : c;
+    });
+    rows.forEach(function (r) { body.appendChild(r); });
+  });
+});
+"#;
+
+/// Write a standalone html page (with embedded styles) with the list's items, as a
+/// table that can be sorted by clicking a column heading, or as a board with a column
+/// of cards for each choice of the `--by` field. Choices are shown with their colors.
+pub(crate) async fn export_html(ws_id: ID, opt: &HtmlOpt) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let columns = match opt.fields {
+        Some(ref names) => names
+            .split(',')
+            .map(|name| Column::parse(&list_info, name.trim()))
+            .collect::<Result<Vec<_>, Error>>()?,
+        None => std::iter::once(Column::Name)
+            .chain(
+                list_info
+                    .fields()
+                    .iter()
+                    .filter(|f| f.element_category != ElementCategoryId::Files)
+                    .map(Column::Field),
+            )
+            .collect(),
+    };
+    let items = get_all_items(&list_info.list().uuid, opt.include_archived).await?;
+    let formatter = ValueFormatter::new(ws_id).await?;
+    let title = opt
+        .title
+        .clone()
+        .unwrap_or_else(|| list_info.list().name.clone());
+
+    let body = match opt.style {
+        HtmlStyle::Table => table(&columns, &items, &formatter),
+        HtmlStyle::Board => {
+            let name = opt
+                .by
+                .as_deref()
+                .ok_or_else(|| Error::Invalid("--style board requires --by".into()))?;
+            let field = fuzzy::get_field(&list_info, name)?;
+            if field.element_category != ElementCategoryId::Categories {
+                return Err(Error::Invalid(format!(
+                    "Field '{}' is not a categories field",
+                    field.name
+                )));
+            }
+            board(field, &columns, &items, &formatter)
+        }
+    };
+    let html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{title}</title>\n<style>{style}</style>\n</head>\n<body>\n\
+         <h1>{title}</h1>\n<div class=\"meta\">{count} items &middot; exported {date}</div>\n\
+         {body}\n<script>{script}</script>\n</body>\n</html>\n",
+        title = escape(&title),
+        style = STYLE,
+        count = items.len(),
+        date = Local::now().format("%Y-%m-%d %H:%M"),
+        body = body,
+        script = if opt.style == HtmlStyle::Table {
+            SORT_SCRIPT
+        } else {
+            ""
+        },
+    );
+    match opt.out {
+        Some(ref path) => std::fs::write(path, html)
+            .map_err(|e| Error::Message(format!("Writing {}: {}", path, e)))?,
+        None => print!("{}", html),
+    }
+    Ok(())
+}
+
+fn table(columns: &[Column], items: &[Entry], formatter: &ValueFormatter) -> String {
+    let mut html = String::from("<table>\n<thead><tr>");
+    for column in columns.iter() {
+        html.push_str(&format!("<th>{}</th>", escape(column.name())));
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+    for item in items.iter() {
+        html.push_str("<tr>");
+        for column in columns.iter() {
+            html.push_str(&format!("<td>{}</td>", cell(column, item, formatter)));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>");
+    html
+}
+
+/// A column of cards for each choice of the field (in the field's order), and for
+/// items without a choice
+fn board(
+    field: &Element,
+    columns: &[Column],
+    items: &[Entry],
+    formatter: &ValueFormatter,
+) -> String {
+    let mut groups: Vec<(String, Vec<&Entry>)> = field
+        .element_data
+        .predefined_categories
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .map(|c| (c.name.clone(), Vec::new()))
+        .collect();
+    let mut uncategorized = Vec::new();
+    for item in items.iter() {
+        let values = formatter.display_values(item, field);
+        if values.is_empty() {
+            uncategorized.push(item);
+        }
+        for value in values {
+            match groups.iter_mut().find(|(name, _)| *name == value) {
+                Some((_, cards)) => cards.push(item),
+                None => groups.push((value, vec![item])),
+            }
+        }
+    }
+    if !uncategorized.is_empty() {
+        groups.push((NO_VALUE.to_string(), uncategorized));
+    }
+
+    let mut html = String::from("<div class=\"board\">\n");
+    for (name, cards) in groups.iter() {
+        html.push_str(&format!(
+            "<div class=\"column\"><h2 style=\"border-color: {}\">{} ({})</h2>\n",
+            choice_color(field, name),
+            escape(name),
+            cards.len()
+        ));
+        for item in cards.iter() {
+            html.push_str(&format!(
+                "<div class=\"card\"><div class=\"title\">{}</div>",
+                escape(&item.display_string)
+            ));
+            for column in columns.iter() {
+                // the title and the board field are already shown
+                let shown = match column {
+                    Column::Name => true,
+                    Column::Field(f) => f.uuid == field.uuid,
+                    _ => false,
+                };
+                if shown || column.value(item, Some(formatter)).is_empty() {
+                    continue;
+                }
+                html.push_str(&format!(
+                    "<div class=\"field\">{}: {}</div>",
+                    escape(column.name()),
+                    cell(column, item, formatter)
+                ));
+            }
+            html.push_str("</div>\n");
+        }
+        html.push_str("</div>\n");
+    }
+    html.push_str("</div>");
+    html
+}
+
+/// Html for the column's value: choices as colored badges, other values as text
+fn cell(column: &Column, item: &Entry, formatter: &ValueFormatter) -> String {
+    match column {
+        Column::Field(field) if field.element_category == ElementCategoryId::Categories => {
+            formatter
+                .display_values(item, field)
+                .iter()
+                .map(|name| {
+                    format!(
+                        "<span class=\"badge\" style=\"background: {}\">{}</span>",
+                        choice_color(field, name),
+                        escape(name)
+                    )
+                })
+                .collect()
+        }
+        _ => escape(&column.value(item, Some(formatter))).replace('\n', "<br>"),
+    }
+}
+
+/// The choice's color (#rrggbb), or a gray if it has none
+fn choice_color(field: &Element, name: &str) -> String {
+    field
+        .element_data
+        .predefined_categories
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .find(|c| c.name == name)
+        .map(|c| c.color.clone())
+        .filter(|hex| {
+            let digits = hex.trim_start_matches('#');
+            digits.len() == 6 && digits.chars().all(|c| c.is_ascii_hexdigit())
+        })
+        .map(|hex| format!("#{}", hex.trim_start_matches('#')))
+        .unwrap_or_else(|| DEFAULT_COLOR.to_string())
+}
+
+/// Escape text for html
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{choice_field, field, item, list_info};
+    use serde_json::json;
+
+    #[test]
+    fn html_table() {
+        let list_info = list_info(vec![
+            field(1, "Title", 1),
+            choice_field(3, "Stage", &[(1, "Todo"), (2, "Done")]),
+        ]);
+        let items = vec![item(1, "Fix <b>", json!({"field-3_categories": [2]}))];
+        let columns = vec![
+            Column::parse(&list_info, "name").unwrap(),
+            Column::parse(&list_info, "Stage").unwrap(),
+        ];
+        assert_eq!(
+            table(&columns, &items, &ValueFormatter::default()),
+            "<table>\n<thead><tr><th>name</th><th>Stage</th></tr></thead>\n<tbody>\n\
+             <tr><td>Fix &lt;b&gt;</td>\
+             <td><span class=\"badge\" style=\"background: #ffffff\">Done</span></td></tr>\n\
+             </tbody>\n</table>"
+        );
+    }
+
+    #[test]
+    fn html_board() {
+        let stage = choice_field(3, "Stage", &[(1, "Todo"), (2, "Done")]);
+        let list_info = list_info(vec![field(1, "Title", 1), field(2, "Points", 2), stage]);
+        let items = vec![
+            item(
+                1,
+                "Ship",
+                json!({"field-2_number": 3, "field-3_categories": [2]}),
+            ),
+            item(2, "Plan", json!({})),
+        ];
+        let columns: Vec<Column> = ["name", "Points", "Stage"]
+            .iter()
+            .map(|name| Column::parse(&list_info, name).unwrap())
+            .collect();
+        let stage = &list_info.fields()[2];
+        let html = board(stage, &columns, &items, &ValueFormatter::default());
+        let headings: Vec<&str> = html
+            .lines()
+            .filter(|line| line.starts_with("<div class=\"column\">"))
+            .collect();
+        assert_eq!(
+            headings,
+            vec![
+                "<div class=\"column\"><h2 style=\"border-color: #ffffff\">Todo (0)</h2>",
+                "<div class=\"column\"><h2 style=\"border-color: #ffffff\">Done (1)</h2>",
+                "<div class=\"column\"><h2 style=\"border-color: #95a5a6\">(none) (1)</h2>",
+            ]
+        );
+        // the title and board field aren't repeated, and empty values are left out
+        assert!(html.contains(
+            "<div class=\"card\"><div class=\"title\">Ship</div>\
+             <div class=\"field\">Points: 3</div></div>\n"
+        ));
+        assert!(html.contains("<div class=\"card\"><div class=\"title\">Plan</div></div>\n"));
+    }
+
+    #[test]
+    fn choice_colors() {
+        let mut stage = choice_field(3, "Stage", &[(1, "Todo"), (2, "Done")]);
+        if let Some(choices) = stage.element_data.predefined_categories.as_mut() {
+            choices[0].color = "3498DB".into();
+            choices[1].color = "green".into();
+        }
+        assert_eq!(choice_color(&stage, "Todo"), "#3498DB");
+        assert_eq!(choice_color(&stage, "Done"), DEFAULT_COLOR);
+        assert_eq!(choice_color(&stage, "Blocked"), DEFAULT_COLOR);
+        assert_eq!(
+            escape("<a href='x'>&</a>"),
+            "&lt;a href=&#39;x&#39;&gt;&amp;&lt;/a&gt;"
+        );
+    }
+}
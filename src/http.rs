@@ -0,0 +1,141 @@
+//! Http client settings: request timeout, proxy, and CA certificates
+use crate::{backup::sha256_hex, Error};
+use std::{
+    path::{Path, PathBuf},
+    result::Result,
+    sync::Mutex,
+    time::Duration,
+};
+
+/// Timeout, proxy, and CA bundle, from the command line, config file, or environment
+#[derive(Clone, Debug, Default)]
+pub(crate) struct HttpSettings {
+    /// Timeout of each request, from connecting until the response body is received
+    pub timeout: Option<Duration>,
+    /// Proxy for http and https requests, e.g., http://proxy.example.com:3128
+    pub proxy: Option<String>,
+    /// PEM file with CA certificates to trust, in addition to the system's
+    pub ca_bundle: Option<PathBuf>,
+}
+
+static SETTINGS: Mutex<Option<HttpSettings>> = Mutex::new(None);
+
+/// Set the options of http clients created with `client`.
+/// The zenkit api client is created by the zenkit crate, which doesn't take these
+/// options, so the proxy is also set in the environment variables it reads
+/// (HTTPS_PROXY and HTTP_PROXY), the CA bundle is added to the certificates in
+/// SSL_CERT_FILE (see `trust_in_api_client`), and the timeout is applied to each
+/// api call by `retry`. Call before the api is initialized.
+pub(crate) fn configure(settings: HttpSettings) -> Result<(), Error> {
+    if let Some(ref path) = settings.ca_bundle {
+        // checked here, so that an invalid bundle is reported before any request
+        read_ca_bundle(path)?;
+        trust_in_api_client(path)?;
+    }
+    if let Some(ref proxy) = settings.proxy {
+        std::env::set_var("HTTPS_PROXY", proxy);
+        std::env::set_var("HTTP_PROXY", proxy);
+    }
+    if let Ok(mut s) = SETTINGS.lock() {
+        *s = Some(settings);
+    }
+    Ok(())
+}
+
+fn settings() -> HttpSettings {
+    SETTINGS
+        .lock()
+        .ok()
+        .and_then(|s| s.clone())
+        .unwrap_or_default()
+}
+
+/// Request timeout, if one is set
+pub(crate) fn timeout() -> Option<Duration> {
+    settings().timeout
+}
+
+/// New http client with the configured timeout, proxy, and CA certificates
+pub(crate) fn client() -> Result<reqwest::Client, Error> {
+    let settings = settings();
+    let mut builder = reqwest::Client::builder();
+    if let Some(timeout) = settings.timeout {
+        builder = builder.timeout(timeout);
+    }
+    if let Some(ref proxy) = settings.proxy {
+        let proxy = reqwest::Proxy::all(proxy.as_str())
+            .map_err(|e| Error::Invalid(format!("Invalid proxy {}: {}", proxy, e)))?;
+        builder = builder.proxy(proxy);
+    }
+    if let Some(ref path) = settings.ca_bundle {
+        for cert in read_ca_bundle(path)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// Add the CA bundle to the certificates trusted by the zenkit api client.
+/// Its tls library (OpenSSL, except on macOS and Windows) reads the trusted
+/// certificates from the file in SSL_CERT_FILE, or from the system's file if that
+/// isn't set. Setting it to the bundle alone would replace the system's certificates,
+/// so it's set to a file in the temp folder with both.
+fn trust_in_api_client(path: &Path) -> Result<(), Error> {
+    let system = std::env::var_os("SSL_CERT_FILE")
+        .map(PathBuf::from)
+        .or_else(|| openssl_probe::probe().cert_file);
+    let mut pem = system
+        .and_then(|file| std::fs::read_to_string(file).ok())
+        .unwrap_or_default();
+    if !pem.is_empty() && !pem.ends_with('\n') {
+        pem.push('\n');
+    }
+    pem.push_str(&read_pem(path)?);
+    // named by content, so runs with the same certificates share the file
+    let combined = std::env::temp_dir().join(format!(
+        "zk-ca-bundle-{}.pem",
+        &sha256_hex(pem.as_bytes())[..16]
+    ));
+    if !combined.is_file() {
+        std::fs::write(&combined, &pem).map_err(|e| {
+            Error::Io(format!(
+                "Writing combined CA bundle {}: {}",
+                combined.display(),
+                e
+            ))
+        })?;
+    }
+    std::env::set_var("SSL_CERT_FILE", &combined);
+    Ok(())
+}
+
+fn read_pem(path: &Path) -> Result<String, Error> {
+    std::fs::read_to_string(path)
+        .map_err(|e| Error::Invalid(format!("Reading CA bundle {}: {}", path.display(), e)))
+}
+
+/// Certificates in the PEM file
+fn read_ca_bundle(path: &Path) -> Result<Vec<reqwest::Certificate>, Error> {
+    const END: &str = "-----END CERTIFICATE-----";
+    let pem = read_pem(path)?;
+    let mut certs = Vec::new();
+    let mut rest = pem.as_str();
+    while let Some(start) = rest.find("-----BEGIN CERTIFICATE-----") {
+        let end = match rest[start..].find(END) {
+            Some(pos) => start + pos + END.len(),
+            None => break,
+        };
+        let cert = reqwest::Certificate::from_pem(&rest.as_bytes()[start..end]).map_err(|e| {
+            Error::Invalid(format!("Invalid certificate in {}: {}", path.display(), e))
+        })?;
+        certs.push(cert);
+        rest = &rest[end..];
+    }
+    if certs.is_empty() {
+        return Err(Error::Invalid(format!(
+            "No certificates found in CA bundle {}",
+            path.display()
+        )));
+    }
+    Ok(certs)
+}
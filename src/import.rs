@@ -0,0 +1,218 @@
+use crate::{
+    aliases, cache, choices::field_choices, dryrun, parse_setval, progress, CsvImportOpt, Error,
+};
+use futures::future::join_all;
+use std::{collections::HashMap, result::Result};
+use zenkit::types::{Element, ElementCategoryId, FieldVal, UpdateAction, ID};
+
+/// Import rows of a csv file as new list items.
+/// The first row of the file must contain column headers. Each column is mapped
+/// to a list field, either explicitly with `--map "Column=Field"`, or by matching
+/// the header to a field name. Columns that don't map to a field are ignored.
+/// Rows with values that don't match the field type are rejected, and reported
+/// at the end (and optionally written to the `--errors` csv file).
+/// The items of each batch of rows are created concurrently.
+pub(crate) async fn import_csv(ws_id: ID, opt: &CsvImportOpt) -> Result<ImportSummary, Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let fields = list_info.fields();
+
+    let mut reader = csv::Reader::from_path(&opt.file)?;
+    let headers = reader.headers()?.clone();
+    let explicit: HashMap<&str, &str> = opt
+        .map
+        .iter()
+        .map(|(col, field)| (col.as_str(), field.as_str()))
+        .collect();
+
+    // column index -> field
+    let mut columns: Vec<(usize, &Element)> = Vec::new();
+    for (col, header) in headers.iter().enumerate() {
+        let field_name = explicit.get(header).copied().unwrap_or(header);
+        match find_field(fields, field_name) {
+            Some(field) => columns.push((col, field)),
+            None if explicit.contains_key(header) => {
+                return Err(Error::NotFound(format!(
+                    "Field '{}' (mapped from column '{}') not found in list '{}'",
+                    field_name, header, opt.list
+                )));
+            }
+            None => eprintln!("Ignoring column '{}': no matching field", header),
+        }
+    }
+    if columns.is_empty() {
+        return Err(Error::Invalid(
+            "No csv columns match list fields. Use --map to map columns to fields".into(),
+        ));
+    }
+
+    let rows = reader.records().collect::<Result<Vec<_>, _>>()?;
+    let mut summary = ImportSummary::default();
+    let rows_done = progress::bar(rows.len(), "Importing");
+    for (batch_num, batch) in rows.chunks(opt.batch_size.max(1)).enumerate() {
+        // line number and values of the valid rows
+        let mut valid = Vec::new();
+        for (n, row) in batch.iter().enumerate() {
+            // line number in file: +1 for header, +1 because lines start at 1
+            let line = batch_num * opt.batch_size.max(1) + n + 2;
+            let mut values = Vec::new();
+            let mut invalid = Vec::new();
+            for (col, field) in columns.iter() {
+                let value = row.get(*col).unwrap_or_default().trim();
+                if value.is_empty() {
+                    continue;
+                }
+                match field_value(field, value) {
+                    Ok(value) => values.push((field.uuid.clone(), value, UpdateAction::Null)),
+                    Err(msg) => invalid.push(format!("{}: {}", field.name, msg)),
+                }
+            }
+            if !invalid.is_empty() {
+                summary
+                    .rejected
+                    .push((line, row.clone(), invalid.join("; ")));
+                continue;
+            }
+            if dryrun::enabled() {
+                dryrun::print_item_request(&list_info, None, &values).await?;
+                summary.created += 1;
+                continue;
+            }
+            valid.push((line, row, values));
+        }
+        let created = join_all(
+            valid
+                .iter()
+                .map(|(_, _, values)| list_info.create_item(values)),
+        )
+        .await;
+        for ((line, row, _), result) in valid.iter().zip(created) {
+            match result {
+                Ok(_) => summary.created += 1,
+                Err(e) => summary.rejected.push((*line, (*row).clone(), e.summary())),
+            }
+        }
+        rows_done.inc(batch.len() as u64);
+        rows_done.set_message(format!("rows ({} rejected)", summary.rejected.len()));
+    }
+    rows_done.finish_and_clear();
+
+    if !summary.rejected.is_empty() {
+        for (line, _, reason) in summary.rejected.iter() {
+            eprintln!("Rejected line {}: {}", line, reason);
+        }
+        if let Some(ref errors_file) = opt.errors {
+            let mut writer = csv::Writer::from_path(errors_file)?;
+            let mut header_row: Vec<&str> = vec!["line", "error"];
+            header_row.extend(headers.iter());
+            writer.write_record(header_row)?;
+            for (line, row, reason) in summary.rejected.iter() {
+                let line = line.to_string();
+                let mut record: Vec<&str> = vec![line.as_str(), reason.as_str()];
+                record.extend(row.iter());
+                writer.write_record(record)?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(summary)
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct ImportSummary {
+    pub created: usize,
+    /// line number, row, and reason
+    pub rejected: Vec<(usize, csv::StringRecord, String)>,
+}
+
+fn find_field<'f>(fields: &'f [Element], name: &str) -> Option<&'f Element> {
+    let name = aliases::resolve(name);
+    fields
+        .iter()
+        .find(|f| f.name == name || f.uuid == name || f.id.to_string() == name)
+}
+
+/// The field value for the csv value, or an error if it can't be stored in the field.
+/// Choices (names or ids) are sent as ids.
+fn field_value(field: &Element, value: &str) -> Result<FieldVal, String> {
+    match field.element_category {
+        ElementCategoryId::Number => value
+            .parse::<f64>()
+            .map(|_| FieldVal::Str(value.to_string()))
+            .map_err(|_| format!("'{}' is not a number", value)),
+        ElementCategoryId::Checkbox => match value.to_lowercase().as_str() {
+            "true" | "yes" | "1" => Ok(FieldVal::Str("true".into())),
+            "false" | "no" | "0" => Ok(FieldVal::Str("false".into())),
+            _ => Err(format!("'{}' is not a boolean", value)),
+        },
+        ElementCategoryId::Date => {
+            if is_date(value) {
+                Ok(FieldVal::Str(value.to_string()))
+            } else {
+                Err(format!("'{}' is not a date (YYYY-MM-DD)", value))
+            }
+        }
+        ElementCategoryId::Categories => {
+            let choices = field_choices(field);
+            let names = value.trim_start_matches('[').trim_end_matches(']');
+            names
+                .split(',')
+                .map(|c| c.trim())
+                .map(|c| {
+                    choices
+                        .iter()
+                        .find(|choice| choice.name == c || choice.id.to_string() == c)
+                        .map(|choice| choice.id)
+                        .ok_or_else(|| format!("'{}' is not a valid choice", c))
+                })
+                .collect::<Result<Vec<ID>, String>>()
+                .map(FieldVal::ArrID)
+        }
+        _ => Ok(parse_setval(value.to_string())),
+    }
+}
+
+/// Returns true if the value begins with a date in the format YYYY-MM-DD
+fn is_date(value: &str) -> bool {
+    let b = value.as_bytes();
+    b.len() >= 10
+        && b[..10].iter().enumerate().all(|(i, c)| match i {
+            4 | 7 => *c == b'-',
+            _ => c.is_ascii_digit(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{choice_field, field};
+
+    #[test]
+    fn csv_field_values() {
+        let points = field(2, "Points", 2);
+        assert!(matches!(field_value(&points, "3.5"), Ok(FieldVal::Str(ref v)) if v == "3.5"));
+        assert!(field_value(&points, "three").is_err());
+
+        let done = field(4, "Done", 5);
+        assert!(matches!(field_value(&done, "Yes"), Ok(FieldVal::Str(ref v)) if v == "true"));
+        assert!(matches!(field_value(&done, "0"), Ok(FieldVal::Str(ref v)) if v == "false"));
+        assert!(field_value(&done, "maybe").is_err());
+
+        let due = field(3, "Due", 4);
+        assert!(field_value(&due, "2021-03-01").is_ok());
+        assert!(field_value(&due, "03/01/2021").is_err());
+
+        let tags = choice_field(5, "Tags", &[(51, "red"), (52, "blue")]);
+        let blue_red = field_value(&tags, "[blue, 51]");
+        assert!(matches!(blue_red, Ok(FieldVal::ArrID(ref ids)) if *ids == vec![52, 51]));
+        assert!(field_value(&tags, "green").is_err());
+    }
+
+    #[test]
+    fn date_prefix() {
+        assert!(is_date("2021-03-01"));
+        assert!(is_date("2021-03-01T12:00:00Z"));
+        assert!(!is_date("2021-3-1"));
+        assert!(!is_date("2021/03/01"));
+        assert!(!is_date(""));
+    }
+}
@@ -0,0 +1,447 @@
+//! Output of the items command
+use crate::{
+    backup::{for_each_items_batch, get_items_range},
+    dates::local_date,
+    fuzzy, offline,
+    style::{Cell, Style, Table},
+    template::Template,
+    values::ValueFormatter,
+    Error, ItemsFormat, ItemsOpt, ListInfo,
+};
+use chrono::Local;
+use serde_json::Value;
+use std::{cmp::Ordering, result::Result};
+use zenkit::types::{Element, ElementCategoryId, Entry, ZKObjectID, ID};
+
+/// A column of the items table
+pub(crate) enum Column<'l> {
+    Id,
+    Uuid,
+    /// The item's title (display string)
+    Name,
+    Field(&'l Element),
+}
+
+impl<'l> Column<'l> {
+    /// Parse a column name: id, uuid, name, or a field name or id
+    pub(crate) fn parse(list_info: &'l ListInfo, name: &str) -> Result<Self, Error> {
+        Ok(match name {
+            "id" => Column::Id,
+            "uuid" => Column::Uuid,
+            "name" => Column::Name,
+            _ => Column::Field(fuzzy::get_field(list_info, name)?),
+        })
+    }
+
+    /// Column heading: id, uuid, name, or the field name
+    pub(crate) fn name(&self) -> &str {
+        match self {
+            Column::Id => "id",
+            Column::Uuid => "uuid",
+            Column::Name => "name",
+            Column::Field(field) => &field.name,
+        }
+    }
+
+    pub(crate) fn value(&self, item: &Entry, formatter: Option<&ValueFormatter>) -> String {
+        match self {
+            Column::Id => item.get_id().to_string(),
+            Column::Uuid => item.get_uuid().to_string(),
+            Column::Name => item.display_string.clone(),
+            Column::Field(field) => formatter
+                .and_then(|f| f.display(item, field))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// The value as a table cell: uuids are dim, choices have their colors, and
+    /// dates before today are red
+    pub(crate) fn cell(&self, item: &Entry, formatter: Option<&ValueFormatter>) -> Cell {
+        match (self, formatter) {
+            (Column::Uuid, _) => Cell::styled(item.get_uuid(), Some(Style::Dim)),
+            (Column::Field(field), Some(f)) => match field.element_category {
+                ElementCategoryId::Categories => {
+                    let mut cell = Cell::default();
+                    for (i, name) in f.display_values(item, field).iter().enumerate() {
+                        if i > 0 {
+                            cell.push(", ", None);
+                        }
+                        cell.push(name.as_str(), choice_style(field, name));
+                    }
+                    cell
+                }
+                ElementCategoryId::Date => {
+                    let value = self.value(item, formatter);
+                    let overdue = local_date(&value)
+                        .map(|date| date < Local::today().naive_local())
+                        .unwrap_or(false);
+                    Cell::styled(value, if overdue { Some(Style::Red) } else { None })
+                }
+                _ => Cell::from(self.value(item, formatter)),
+            },
+            _ => Cell::from(self.value(item, formatter)),
+        }
+    }
+
+    /// Values of the column, for grouping: each value of a multi-value field
+    pub(crate) fn values(&self, item: &Entry, formatter: Option<&ValueFormatter>) -> Vec<String> {
+        match (self, formatter) {
+            (Column::Field(field), Some(f)) => f.display_values(item, field),
+            _ => vec![self.value(item, formatter)],
+        }
+    }
+}
+
+/// Style of the choice's color
+pub(crate) fn choice_style(field: &Element, name: &str) -> Option<Style> {
+    field
+        .element_data
+        .predefined_categories
+        .as_deref()?
+        .iter()
+        .find(|c| c.name == name)
+        .and_then(|c| Style::from_hex(&c.color))
+}
+
+/// Label of the group of items with no value in the group-by field
+const NO_VALUE: &str = "(none)";
+
+/// Print the list's items, one per line, with tab-separated columns (by default: id, uuid,
+/// and name). Items are sorted by `--sort` (if any); field values are shown and sorted by
+/// their display values. With `--group-by`, items are listed under a heading for each value
+/// of the column, and with `--count`, only the number of items (in each group) is shown.
+/// With json or jsonl output, items are printed as json objects with the selected columns,
+/// or if `--columns` isn't used, as returned by Zenkit. Jsonl output of unsorted items
+/// is printed as each batch of items is received. With `--template`, the (sorted) items
+/// are rendered with the template instead.
+pub(crate) async fn list_items(
+    ws_id: ID,
+    list_info: &ListInfo,
+    opt: &ItemsOpt,
+) -> Result<(), Error> {
+    if opt.output != ItemsFormat::Text && (opt.group_by.is_some() || opt.count) {
+        return Err(Error::Invalid(
+            "--group-by and --count can only be used with text output".into(),
+        ));
+    }
+    let columns = match opt.columns {
+        Some(ref names) => names
+            .split(',')
+            .map(|name| Column::parse(list_info, name.trim()))
+            .collect::<Result<Vec<_>, Error>>()?,
+        None => vec![Column::Id, Column::Uuid, Column::Name],
+    };
+    let sort = match opt.sort {
+        Some(ref spec) => {
+            let (name, desc) = match spec.rfind(':') {
+                Some(pos) if spec[pos + 1..].eq_ignore_ascii_case("desc") => (&spec[..pos], true),
+                Some(pos) if spec[pos + 1..].eq_ignore_ascii_case("asc") => (&spec[..pos], false),
+                _ => (spec.as_str(), false),
+            };
+            Some((Column::parse(list_info, name)?, desc))
+        }
+        None => None,
+    };
+    let group_by = match opt.group_by {
+        Some(ref name) => Some(Column::parse(list_info, name)?),
+        None => None,
+    };
+    let template = opt.template.as_deref().map(Template::load).transpose()?;
+    // users are only loaded if field values are shown
+    let formatter = if template.is_some()
+        || columns
+            .iter()
+            .chain(sort.iter().map(|(c, _)| c))
+            .chain(group_by.iter())
+            .any(|c| matches!(c, Column::Field(_)))
+    {
+        Some(ValueFormatter::new(ws_id).await?)
+    } else {
+        None
+    };
+    let to_json = |item: &Entry| -> Result<Value, Error> {
+        Ok(match opt.columns {
+            Some(_) => Value::Object(
+                columns
+                    .iter()
+                    .map(|c| {
+                        (
+                            c.name().to_string(),
+                            c.value(item, formatter.as_ref()).into(),
+                        )
+                    })
+                    .collect(),
+            ),
+            None => serde_json::to_value(item)?,
+        })
+    };
+    // backups are read as one batch
+    let paged = opt.all || opt.limit.is_some() || opt.skip > 0 || offline::enabled();
+
+    if opt.output == ItemsFormat::Jsonl && sort.is_none() && template.is_none() {
+        let print_batch = |batch: Vec<Entry>| -> Result<(), Error> {
+            for item in batch.iter() {
+                println!("{}", serde_json::to_string(&to_json(item)?)?);
+            }
+            Ok(())
+        };
+        if paged {
+            for_each_items_batch(
+                &list_info.list().uuid,
+                false,
+                opt.skip,
+                opt.limit,
+                print_batch,
+            )
+            .await?;
+        } else {
+            print_batch(list_info.get_items().await?)?;
+        }
+        return Ok(());
+    }
+
+    let mut items = if paged {
+        get_items_range(&list_info.list().uuid, false, opt.skip, opt.limit).await?
+    } else {
+        list_info.get_items().await?
+    };
+    if let Some((column, desc)) = sort {
+        let mut keyed: Vec<(String, Entry)> = items
+            .drain(..)
+            .map(|item| (column.value(&item, formatter.as_ref()), item))
+            .collect();
+        keyed.sort_by(|(a, _), (b, _)| compare_values(a, b, desc));
+        items = keyed.into_iter().map(|(_, item)| item).collect();
+    }
+    if let (Some(template), Some(formatter)) = (template, formatter.as_ref()) {
+        print!("{}", template.render_items(list_info, &items, formatter)?);
+        return Ok(());
+    }
+
+    let row = |item: &Entry| {
+        columns
+            .iter()
+            .map(|c| c.cell(item, formatter.as_ref()))
+            .collect::<Vec<_>>()
+    };
+    let headings: Vec<&str> = columns.iter().map(|c| c.name()).collect();
+    let mut table = Table::new(&headings);
+    match (&opt.output, group_by) {
+        (ItemsFormat::Json, _) => {
+            let values = items
+                .iter()
+                .map(to_json)
+                .collect::<Result<Vec<_>, Error>>()?;
+            println!("{}", serde_json::to_string_pretty(&values)?);
+        }
+        (ItemsFormat::Jsonl, _) => {
+            for item in items.iter() {
+                println!("{}", serde_json::to_string(&to_json(item)?)?);
+            }
+        }
+        (ItemsFormat::Text, Some(column)) if opt.count => {
+            let mut table = Table::new(&[column.name(), "count"]);
+            for (value, group) in group_items(&column, &items, formatter.as_ref()).iter() {
+                table.push(vec![
+                    group_cell(&column, value),
+                    Cell::from(group.len().to_string()),
+                ]);
+            }
+            table.print();
+        }
+        (ItemsFormat::Text, Some(column)) => {
+            for (value, group) in group_items(&column, &items, formatter.as_ref()).iter() {
+                let mut heading = group_cell(&column, value);
+                heading.push(format!(" ({})", group.len()), None);
+                table.push_heading(heading);
+                for item in group.iter() {
+                    table.push(row(item));
+                }
+            }
+            table.print();
+        }
+        (ItemsFormat::Text, None) if opt.count => println!("{}", items.len()),
+        (ItemsFormat::Text, None) => {
+            for item in items.iter() {
+                table.push(row(item));
+            }
+            table.print();
+        }
+    }
+    Ok(())
+}
+
+/// Group value as a cell, in the choice's color
+fn group_cell(column: &Column, value: &str) -> Cell {
+    match column {
+        Column::Field(field) if field.element_category == ElementCategoryId::Categories => {
+            Cell::styled(value, choice_style(field, value).or(Some(Style::Bold)))
+        }
+        _ => Cell::styled(value, Some(Style::Bold)),
+    }
+}
+
+/// Items grouped by the values of the column, keeping their order within each group.
+/// An item with several values (of a multi-value field) is in the group of each value.
+/// Groups of a categories field are in the order of its choices, and other groups
+/// are sorted by value. Items without a value are in the last group.
+pub(crate) fn group_items<'i>(
+    column: &Column,
+    items: &'i [Entry],
+    formatter: Option<&ValueFormatter>,
+) -> Vec<(String, Vec<&'i Entry>)> {
+    let mut groups: Vec<(String, Vec<&Entry>)> = Vec::new();
+    for item in items.iter() {
+        let mut values = column.values(item, formatter);
+        values.retain(|v| !v.is_empty());
+        if values.is_empty() {
+            values.push(NO_VALUE.to_string());
+        }
+        for value in values {
+            match groups.iter_mut().find(|(v, _)| *v == value) {
+                Some((_, group)) => group.push(item),
+                None => groups.push((value, vec![item])),
+            }
+        }
+    }
+    let choices: Vec<&str> = match column {
+        Column::Field(field) => field
+            .element_data
+            .predefined_categories
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect(),
+        _ => Vec::new(),
+    };
+    groups.sort_by(|(a, _), (b, _)| {
+        let (a, b) = (a.as_str(), b.as_str());
+        if a == NO_VALUE || b == NO_VALUE {
+            return (a == NO_VALUE).cmp(&(b == NO_VALUE));
+        }
+        match (
+            choices.iter().position(|c| *c == a),
+            choices.iter().position(|c| *c == b),
+        ) {
+            (Some(x), Some(y)) => x.cmp(&y),
+            _ => compare_values(a, b, false),
+        }
+    });
+    groups
+}
+
+/// Compare values as numbers if both are numeric, otherwise as text, ignoring case.
+/// Empty values sort last, in either order.
+pub(crate) fn compare_values(a: &str, b: &str, desc: bool) -> Ordering {
+    match (a.is_empty(), b.is_empty()) {
+        (true, true) => return Ordering::Equal,
+        (true, false) => return Ordering::Greater,
+        (false, true) => return Ordering::Less,
+        _ => {}
+    }
+    let ord = match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+        _ => a.to_lowercase().cmp(&b.to_lowercase()),
+    };
+    if desc {
+        ord.reverse()
+    } else {
+        ord
+    }
+}
+
+/// Number of items with each value of the field, in the order of `group_items`
+pub(crate) fn count_by_field(
+    field: &Element,
+    items: &[Entry],
+    formatter: &ValueFormatter,
+) -> Vec<(String, usize)> {
+    group_items(&Column::Field(field), items, Some(formatter))
+        .into_iter()
+        .map(|(value, group)| (value, group.len()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{choice_field, field, item, list_info};
+    use serde_json::json;
+
+    #[test]
+    fn column_values() {
+        let list_info = list_info(vec![field(1, "Title", 1), field(2, "Points", 2)]);
+        let item = item(7, "Write tests", json!({"field-2_number": 3}));
+        let formatter = ValueFormatter::default();
+        let columns: Vec<Column> = ["id", "uuid", "name", "Points"]
+            .iter()
+            .map(|name| Column::parse(&list_info, name).unwrap())
+            .collect();
+        let names: Vec<&str> = columns.iter().map(|c| c.name()).collect();
+        assert_eq!(names, vec!["id", "uuid", "name", "Points"]);
+        let values: Vec<String> = columns
+            .iter()
+            .map(|c| c.value(&item, Some(&formatter)))
+            .collect();
+        assert_eq!(values, vec!["7", "item-7", "Write tests", "3"]);
+        // field values are empty without a formatter
+        assert_eq!(columns[3].value(&item, None), "");
+        assert!(Column::parse(&list_info, "Nothing like it").is_err());
+    }
+
+    #[test]
+    fn sort_order() {
+        // numbers by value, text ignoring case
+        assert_eq!(compare_values("9", "10", false), Ordering::Less);
+        assert_eq!(compare_values("9", "10", true), Ordering::Greater);
+        assert_eq!(compare_values("apple", "Banana", false), Ordering::Less);
+        assert_eq!(compare_values("Apple", "apple", false), Ordering::Equal);
+        // empty values last, ascending or descending
+        assert_eq!(compare_values("", "apple", false), Ordering::Greater);
+        assert_eq!(compare_values("", "apple", true), Ordering::Greater);
+        assert_eq!(compare_values("apple", "", true), Ordering::Less);
+        assert_eq!(compare_values("", "", false), Ordering::Equal);
+    }
+
+    #[test]
+    fn groups_of_items() {
+        let stage = choice_field(3, "Stage", &[(1, "Todo"), (2, "Doing"), (3, "Done")]);
+        let items = vec![
+            item(1, "a", json!({"field-3_categories": [3]})),
+            item(2, "b", json!({})),
+            item(3, "c", json!({"field-3_categories": [1, 3]})),
+            item(4, "d", json!({"field-3_categories": [3]})),
+        ];
+        let formatter = ValueFormatter::default();
+        let groups = group_items(&Column::Field(&stage), &items, Some(&formatter));
+        let groups: Vec<(&str, Vec<ID>)> = groups
+            .iter()
+            .map(|(value, group)| (value.as_str(), group.iter().map(|i| i.get_id()).collect()))
+            .collect();
+        // in the order of the choices, with items without a value last
+        assert_eq!(
+            groups,
+            vec![
+                ("Todo", vec![3]),
+                ("Done", vec![1, 3, 4]),
+                (NO_VALUE, vec![2])
+            ]
+        );
+        assert_eq!(
+            count_by_field(&stage, &items, &formatter),
+            vec![
+                ("Todo".to_string(), 1),
+                ("Done".to_string(), 3),
+                (NO_VALUE.to_string(), 1)
+            ]
+        );
+        // other columns are grouped by value
+        let names: Vec<String> = group_items(&Column::Name, &items[..2], None)
+            .into_iter()
+            .map(|(value, _)| value)
+            .collect();
+        assert_eq!(names, vec!["a", "b"]);
+    }
+}
@@ -0,0 +1,472 @@
+//! Import of Jira issues, from a csv export or a json (REST API search) export
+use crate::{
+    cache, progress,
+    rest::RestClient,
+    retry::retry,
+    schema::{create_field, create_list},
+    values::user_emails,
+    Error, JiraOpt,
+};
+use serde_json::{json, Map, Value};
+use std::{collections::HashMap, result::Result};
+use zenkit::types::{Element, ElementCategoryId, FieldVal, UpdateAction, ID};
+
+/// Name of the title field, if a new list doesn't have a text field
+const TITLE_FIELD: &str = "Name";
+
+/// An issue, from either export format
+#[derive(Debug)]
+struct Issue {
+    key: String,
+    summary: String,
+    description: String,
+    status: String,
+    assignee: Option<Assignee>,
+    labels: Vec<String>,
+}
+
+#[derive(Debug)]
+struct Assignee {
+    name: String,
+    /// only in json exports, and only if the user's email is visible
+    email: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct JiraSummary {
+    pub items: usize,
+    /// what couldn't be imported
+    pub not_imported: Vec<String>,
+}
+
+/// Import Jira issues into a new list, from a csv export (Filters > Export > CSV) or
+/// a json file of the issues returned by the REST API search (`/rest/api/2/search`).
+/// The summary is the item title; the issue key, description, status (a categories
+/// field), assignee (a persons field), and labels (a multi-choice categories field)
+/// are written to the fields named by the `--*-field` options, which are created.
+/// Assignees are matched to workspace users by email (from `--member name=email`, or
+/// from the json export) or by name.
+pub(crate) async fn import_jira(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &JiraOpt,
+) -> Result<JiraSummary, Error> {
+    let api = zenkit::get_api()?;
+    let issues = if opt.file.to_lowercase().ends_with(".json") {
+        read_json(&opt.file)?
+    } else {
+        read_csv(&opt.file)?
+    };
+    let mut summary = JiraSummary::default();
+    let assignees = match_assignees(rest, ws_id, &issues, opt, &mut summary).await?;
+    let statuses = distinct(issues.iter().map(|i| i.status.as_str()));
+    let labels = distinct(
+        issues
+            .iter()
+            .flat_map(|i| i.labels.iter().map(|l| l.as_str())),
+    );
+
+    let new_list = match create_list(rest, ws_id, &opt.list, None).await? {
+        Some(list) => list,
+        None => {
+            eprintln!(
+                "(dry run) {} issue(s) would be imported into list '{}'",
+                issues.len(),
+                opt.list
+            );
+            return Ok(summary);
+        }
+    };
+    // new lists may start with default fields: the first text field is used for titles,
+    // unless the summary goes to a named field
+    let existing = retry("get_list_elements", || api.get_list_elements(new_list.id)).await?;
+    let mut specs: Vec<(&str, ElementCategoryId, Map<String, Value>)> = Vec::new();
+    match opt.summary_field {
+        Some(ref name) => specs.push((name, ElementCategoryId::Text, Map::new())),
+        None => {
+            if !existing
+                .iter()
+                .any(|f| f.element_category == ElementCategoryId::Text)
+            {
+                specs.push((TITLE_FIELD, ElementCategoryId::Text, Map::new()));
+            }
+        }
+    }
+    specs.push((&opt.key_field, ElementCategoryId::Text, Map::new()));
+    specs.push((&opt.description_field, ElementCategoryId::Text, Map::new()));
+    if !statuses.is_empty() {
+        specs.push((
+            &opt.status_field,
+            ElementCategoryId::Categories,
+            categories_data(&statuses, false),
+        ));
+    }
+    if !assignees.is_empty() {
+        specs.push((&opt.assignee_field, ElementCategoryId::Persons, Map::new()));
+    }
+    if !labels.is_empty() {
+        specs.push((
+            &opt.labels_field,
+            ElementCategoryId::Categories,
+            categories_data(&labels, true),
+        ));
+    }
+    for (field_name, category, data) in specs.into_iter() {
+        if !existing.iter().any(|f| f.name == field_name) {
+            create_field(rest, new_list.id, field_name, category, data).await?;
+        }
+    }
+
+    let list_info = cache::get_list_info(ws_id, &new_list.uuid).await?;
+    let fields = list_info.fields();
+    let field = |name: &str| fields.iter().find(|f| f.name == name);
+    let title = match opt.summary_field {
+        Some(ref name) => field(name),
+        None => fields
+            .iter()
+            .find(|f| f.element_category == ElementCategoryId::Text),
+    };
+    let issues_done = progress::bar(issues.len(), "Importing");
+    for issue in issues.iter() {
+        let mut values = Vec::new();
+        let mut push = |field: Option<&Element>, value: FieldVal| {
+            if let Some(field) = field {
+                values.push((field.uuid.clone(), value, UpdateAction::Null));
+            }
+        };
+        push(title, FieldVal::Str(issue.summary.clone()));
+        push(field(&opt.key_field), FieldVal::Str(issue.key.clone()));
+        if !issue.description.is_empty() {
+            push(
+                field(&opt.description_field),
+                FieldVal::Str(issue.description.clone()),
+            );
+        }
+        let status = field(&opt.status_field);
+        if let Some(id) = status.and_then(|f| choice_id(f, &issue.status)) {
+            push(status, FieldVal::ArrID(vec![id]));
+        }
+        if let Some(uuid) = issue.assignee.as_ref().and_then(|a| assignees.get(&a.name)) {
+            push(
+                field(&opt.assignee_field),
+                FieldVal::ArrStr(vec![uuid.clone()]),
+            );
+        }
+        let labels_field = field(&opt.labels_field);
+        let label_ids: Vec<ID> = issue
+            .labels
+            .iter()
+            .filter_map(|l| labels_field.and_then(|f| choice_id(f, l)))
+            .collect();
+        if !label_ids.is_empty() {
+            push(labels_field, FieldVal::ArrID(label_ids));
+        }
+        match list_info.create_item(&values).await {
+            Ok(_) => summary.items += 1,
+            Err(e) => summary
+                .not_imported
+                .push(format!("issue {}: {}", issue.key, e.summary())),
+        }
+        issues_done.inc(1);
+    }
+    issues_done.finish_and_clear();
+    Ok(summary)
+}
+
+/// Issues from a Jira csv export. Multi-value fields (labels) are exported as
+/// several columns with the same header.
+fn read_csv(path: &str) -> Result<Vec<Issue>, Error> {
+    let mut reader = csv::Reader::from_path(path)?;
+    let headers: Vec<String> = reader
+        .headers()?
+        .iter()
+        .map(|h| h.trim_start_matches('\u{feff}').trim().to_lowercase())
+        .collect();
+    let column = |name: &str| headers.iter().position(|h| h == name);
+    let key = column("issue key")
+        .ok_or_else(|| Error::Invalid(format!("{}: missing 'Issue key' column", path)))?;
+    let summary = column("summary")
+        .ok_or_else(|| Error::Invalid(format!("{}: missing 'Summary' column", path)))?;
+    let description = column("description");
+    let status = column("status");
+    let assignee = column("assignee");
+    let labels: Vec<usize> = (0..headers.len())
+        .filter(|n| headers[*n] == "labels")
+        .collect();
+
+    let mut issues = Vec::new();
+    for row in reader.records() {
+        let row = row?;
+        let get = |col: Option<usize>| {
+            col.and_then(|c| row.get(c))
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        };
+        let name = get(assignee);
+        issues.push(Issue {
+            key: get(Some(key)),
+            summary: get(Some(summary)),
+            description: get(description),
+            status: get(status),
+            assignee: Some(Assignee { name, email: None }).filter(|a| !a.name.is_empty()),
+            labels: labels
+                .iter()
+                .map(|c| get(Some(*c)))
+                .filter(|l| !l.is_empty())
+                .collect(),
+        });
+    }
+    Ok(issues)
+}
+
+/// Issues from a json file: a REST API search result (`{"issues": [...]}`),
+/// or an array of issues
+fn read_json(path: &str) -> Result<Vec<Issue>, Error> {
+    let doc: Value = serde_json::from_slice(&std::fs::read(path)?)
+        .map_err(|e| Error::Invalid(format!("Invalid Jira export {}: {}", path, e)))?;
+    let issues = doc
+        .get("issues")
+        .unwrap_or(&doc)
+        .as_array()
+        .ok_or_else(|| Error::Invalid(format!("{}: expected an array of issues", path)))?;
+    let text = |v: Option<&Value>| match v {
+        Some(Value::String(s)) => s.trim().to_string(),
+        // Atlassian document format, in api v3
+        Some(node @ Value::Object(_)) => adf_text(node).trim().to_string(),
+        _ => String::new(),
+    };
+    Ok(issues
+        .iter()
+        .map(|issue| {
+            let fields = issue.get("fields").unwrap_or(&Value::Null);
+            let assignee = fields.get("assignee").filter(|a| a.is_object());
+            Issue {
+                key: text(issue.get("key")),
+                summary: text(fields.get("summary")),
+                description: text(fields.get("description")),
+                status: text(fields.pointer("/status/name")),
+                assignee: assignee.map(|a| Assignee {
+                    name: text(a.get("displayName")),
+                    email: Some(text(a.get("emailAddress"))).filter(|e| !e.is_empty()),
+                }),
+                labels: fields
+                    .get("labels")
+                    .and_then(|v| v.as_array())
+                    .map(|v| v.as_slice())
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|l| l.as_str())
+                    .map(String::from)
+                    .collect(),
+            }
+        })
+        .collect())
+}
+
+/// Plain text of an Atlassian document: the text nodes, with a line break after
+/// each block
+fn adf_text(node: &Value) -> String {
+    let mut text = node
+        .get("text")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .to_string();
+    for child in node
+        .get("content")
+        .and_then(|v| v.as_array())
+        .map(|v| v.as_slice())
+        .unwrap_or_default()
+    {
+        text.push_str(&adf_text(child));
+    }
+    match node.get("type").and_then(|v| v.as_str()) {
+        Some("paragraph") | Some("heading") | Some("codeBlock") | Some("listItem") => {
+            text.push('\n')
+        }
+        Some("hardBreak") => text.push('\n'),
+        _ => {}
+    }
+    text
+}
+
+/// Workspace user uuids of the assignees (assignee name -> uuid). Assignees are
+/// matched by the email given with `--member` (for the name), or from the export,
+/// or else by display name. Assignees without a match are added to the summary.
+async fn match_assignees(
+    rest: &RestClient,
+    ws_id: ID,
+    issues: &[Issue],
+    opt: &JiraOpt,
+    summary: &mut JiraSummary,
+) -> Result<HashMap<String, String>, Error> {
+    let mut matched = HashMap::new();
+    let mut assignees: Vec<&Assignee> = Vec::new();
+    for assignee in issues.iter().filter_map(|i| i.assignee.as_ref()) {
+        if !assignees.iter().any(|a| a.name == assignee.name) {
+            assignees.push(assignee);
+        }
+    }
+    if assignees.is_empty() {
+        return Ok(matched);
+    }
+    let users = rest.get(&format!("workspaces/{}/users", ws_id)).await?;
+    let users = users.as_array().map(|v| v.as_slice()).unwrap_or_default();
+    for assignee in assignees.iter() {
+        let email = opt
+            .member
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(&assignee.name))
+            .map(|(_, email)| email.as_str())
+            .or(assignee.email.as_deref());
+        let user = users.iter().find(|user| match email {
+            Some(email) => user_emails(user)
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(email)),
+            None => user
+                .get("displayname")
+                .and_then(|v| v.as_str())
+                .map(|name| name.eq_ignore_ascii_case(&assignee.name))
+                .unwrap_or(false),
+        });
+        match user.and_then(|u| u.get("uuid")).and_then(|v| v.as_str()) {
+            Some(uuid) => {
+                matched.insert(assignee.name.clone(), uuid.to_string());
+            }
+            None => summary.not_imported.push(format!(
+                "assignee {}: no workspace user with {}",
+                assignee.name,
+                match email {
+                    Some(email) => format!("email {}", email),
+                    None => "that name (use --member \"name=email\")".to_string(),
+                }
+            )),
+        }
+    }
+    Ok(matched)
+}
+
+/// The non-empty values, without duplicates, in order of first appearance
+fn distinct<'v>(values: impl Iterator<Item = &'v str>) -> Vec<&'v str> {
+    let mut found: Vec<&str> = Vec::new();
+    for value in values.filter(|v| !v.is_empty()) {
+        if !found.contains(&value) {
+            found.push(value);
+        }
+    }
+    found
+}
+
+fn categories_data(choices: &[&str], multiple: bool) -> Map<String, Value> {
+    let choices: Vec<Value> = choices.iter().map(|c| json!({ "name": c })).collect();
+    let mut data = Map::new();
+    data.insert("predefinedCategories".into(), choices.into());
+    data.insert("allowMultiple".into(), multiple.into());
+    data
+}
+
+/// Id of the field's choice with the name
+fn choice_id(field: &Element, name: &str) -> Option<ID> {
+    field
+        .element_data
+        .predefined_categories
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .find(|c| c.name == name)
+        .map(|c| c.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Path of a temporary export file with the name
+    fn export_file(name: &str, text: &str) -> String {
+        let path = std::env::temp_dir().join(format!("zk_jira_{}_{}", std::process::id(), name));
+        std::fs::write(&path, text).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn csv_export() {
+        let path = export_file(
+            "issues.csv",
+            "\u{feff}Summary,Issue key,Status,Assignee,Labels,Labels\n\
+             Fix login,APP-1,Done,Ann Lee,auth,urgent\n\
+             Write docs,APP-2,To Do,,,\n",
+        );
+        let issues = read_csv(&path);
+        std::fs::remove_file(&path).unwrap();
+        let issues = issues.unwrap();
+        assert_eq!(issues.len(), 2);
+        assert_eq!(
+            (issues[0].key.as_str(), issues[0].summary.as_str()),
+            ("APP-1", "Fix login")
+        );
+        assert_eq!(issues[0].status, "Done");
+        assert_eq!(issues[0].assignee.as_ref().unwrap().name, "Ann Lee");
+        assert_eq!(issues[0].labels, vec!["auth", "urgent"]);
+        assert_eq!(issues[1].description, "");
+        assert!(issues[1].assignee.is_none());
+        assert!(issues[1].labels.is_empty());
+
+        let path = export_file("no_key.csv", "Summary,Status\nFix login,Done\n");
+        let issues = read_csv(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(issues, Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn json_export() {
+        let path = export_file(
+            "issues.json",
+            &json!({ "issues": [{
+                "key": "APP-1",
+                "fields": {
+                    "summary": "Fix login",
+                    "description": {
+                        "type": "doc",
+                        "content": [
+                            { "type": "paragraph", "content": [
+                                { "type": "text", "text": "Fails " },
+                                { "type": "text", "text": "on Safari" },
+                            ] },
+                            { "type": "paragraph", "content": [
+                                { "type": "text", "text": "Again" },
+                            ] },
+                        ],
+                    },
+                    "status": { "name": "Done" },
+                    "assignee": { "displayName": "Ann Lee", "emailAddress": "ann@example.com" },
+                    "labels": ["auth"],
+                },
+            }, {
+                "key": "APP-2",
+                "fields": { "summary": "Write docs", "description": "Soon", "assignee": null },
+            }] })
+            .to_string(),
+        );
+        let issues = read_json(&path);
+        std::fs::remove_file(&path).unwrap();
+        let issues = issues.unwrap();
+        assert_eq!(issues.len(), 2);
+        assert_eq!(issues[0].description, "Fails on Safari\nAgain");
+        assert_eq!(issues[0].status, "Done");
+        let assignee = issues[0].assignee.as_ref().unwrap();
+        assert_eq!(
+            (assignee.name.as_str(), assignee.email.as_deref()),
+            ("Ann Lee", Some("ann@example.com"))
+        );
+        assert_eq!(issues[0].labels, vec!["auth"]);
+        assert_eq!(issues[1].description, "Soon");
+        assert_eq!(issues[1].status, "");
+        assert!(issues[1].assignee.is_none());
+    }
+
+    #[test]
+    fn distinct_values() {
+        let values = ["Done", "", "To Do", "Done"];
+        assert_eq!(distinct(values.iter().copied()), vec!["Done", "To Do"]);
+    }
+}
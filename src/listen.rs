@@ -0,0 +1,495 @@
+//! Local http server for receiving Zenkit webhook events
+use crate::{
+    notify::SlackNotifier, publish::Publisher, s3::hex, s3::hmac_sha256, Error, JsonFormat,
+};
+use hyper::{
+    body::HttpBody,
+    header::CONTENT_LENGTH,
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use serde_json::Value;
+use std::{
+    convert::Infallible,
+    fs::{File, OpenOptions},
+    future::Future,
+    io::{BufRead, BufReader, Write},
+    net::SocketAddr,
+    process::{Command, Stdio},
+    result::Result,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::Semaphore;
+
+/// Header with the request signature, when the listener has a secret:
+/// "sha256=" followed by the hex HMAC-SHA256 of the request body, keyed with the secret
+pub(crate) const SIGNATURE_HEADER: &str = "x-signature-256";
+
+/// Environment variable with the secret for signed requests,
+/// used if neither `--secret` nor `--secret-file` is given
+pub(crate) const SECRET_ENV: &str = "ZENKIT_WEBHOOK_SECRET";
+
+/// Largest request body accepted, in bytes. Larger requests are rejected
+/// without reading the rest of the body, before the signature is checked.
+const MAX_BODY_SIZE: usize = 4 * 1024 * 1024;
+
+/// The secret for signed requests: `secret`, the contents of `secret_file`
+/// (without the trailing newline), or SECRET_ENV from the environment
+pub(crate) fn load_secret(
+    secret: Option<String>,
+    secret_file: Option<&str>,
+) -> Result<Option<String>, Error> {
+    if secret.is_some() {
+        return Ok(secret);
+    }
+    if let Some(path) = secret_file {
+        let data = std::fs::read_to_string(path)
+            .map_err(|e| Error::Invalid(format!("Can't read secret file {}: {}", path, e)))?;
+        let secret = data.trim_end_matches(['\n', '\r']);
+        if secret.is_empty() {
+            return Err(Error::Invalid(format!("Secret file {} is empty", path)));
+        }
+        return Ok(Some(secret.to_string()));
+    }
+    Ok(std::env::var(SECRET_ENV).ok().filter(|s| !s.is_empty()))
+}
+
+/// The value of the signature header for the body
+pub(crate) fn signature(secret: &str, body: &[u8]) -> Result<String, Error> {
+    Ok(format!(
+        "sha256={}",
+        hex(&hmac_sha256(secret.as_bytes(), body)?)
+    ))
+}
+
+/// Handles webhook events received by the server
+pub(crate) struct Listener {
+    format: JsonFormat,
+    exec: Option<ExecHook>,
+    publish: Option<Publisher>,
+    notify: Option<SlackNotifier>,
+    /// journal of received events
+    store: Option<Mutex<File>>,
+    /// if set, requests must be signed with this secret
+    secret: Option<String>,
+}
+
+/// Command run for each event
+struct ExecHook {
+    command: String,
+    /// limits the number of commands running at once
+    jobs: Arc<Semaphore>,
+    max_jobs: usize,
+}
+
+impl Listener {
+    pub(crate) fn new(format: JsonFormat) -> Self {
+        Listener {
+            format,
+            exec: None,
+            publish: None,
+            notify: None,
+            store: None,
+            secret: None,
+        }
+    }
+
+    /// Run the command (with sh -c) for each event instead of printing it,
+    /// at most `jobs` at a time
+    pub(crate) fn with_exec(mut self, command: String, jobs: usize) -> Self {
+        let max_jobs = jobs.max(1);
+        self.exec = Some(ExecHook {
+            command,
+            jobs: Arc::new(Semaphore::new(max_jobs)),
+            max_jobs,
+        });
+        self
+    }
+
+    /// Publish each event (as a single line of json) to a broker topic
+    pub(crate) fn with_publish(mut self, publisher: Publisher) -> Self {
+        self.publish = Some(publisher);
+        self
+    }
+
+    /// Post a message for each event to Slack
+    pub(crate) fn with_notify(mut self, notifier: SlackNotifier) -> Self {
+        self.notify = Some(notifier);
+        self
+    }
+
+    /// Append each received event to the file, as a line of json, before handling it
+    pub(crate) fn with_store(mut self, path: &str) -> Result<Self, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| Error::Message(format!("Can't open event store {}: {}", path, e)))?;
+        self.store = Some(Mutex::new(file));
+        Ok(self)
+    }
+
+    /// Reject requests without a valid signature (see SIGNATURE_HEADER)
+    pub(crate) fn with_secret(mut self, secret: String) -> Self {
+        self.secret = Some(secret);
+        self
+    }
+
+    /// True if there is no secret, or the signature is valid for the body
+    fn verify(&self, body: &[u8], signature_header: Option<&[u8]>) -> bool {
+        let secret = match self.secret {
+            Some(ref secret) => secret,
+            None => return true,
+        };
+        let expected = match signature(secret, body) {
+            Ok(expected) => expected,
+            Err(_) => return false,
+        };
+        match signature_header {
+            // constant-time comparison, so the signature can't be guessed from response times
+            Some(found) if found.len() == expected.len() => {
+                openssl::memcmp::eq(found, expected.as_bytes())
+            }
+            _ => false,
+        }
+    }
+
+    /// Wait until all commands started for events have finished
+    pub(crate) async fn finish(&self) {
+        if let Some(ref exec) = self.exec {
+            let _ = exec.jobs.acquire_many(exec.max_jobs as u32).await;
+        }
+    }
+
+    /// Handle each event in a file written by `with_store`, in order.
+    /// Lines that aren't valid json (e.g., an incomplete last line) are reported
+    /// on stderr and skipped. Returns the number of events handled.
+    pub(crate) async fn replay(&self, path: &str) -> Result<usize, Error> {
+        let file = File::open(path)
+            .map_err(|e| Error::Message(format!("Can't open event store {}: {}", path, e)))?;
+        let mut count = 0;
+        for (num, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(&line) {
+                Ok(event) => {
+                    self.handle_event(event).await?;
+                    count += 1;
+                }
+                Err(e) => eprintln!("{}:{}: skipped invalid event: {}", path, num + 1, e),
+            }
+        }
+        Ok(count)
+    }
+
+    /// Append the events to the store, if set
+    fn store_events(&self, events: &[Value]) -> Result<(), Error> {
+        let store = match self.store {
+            Some(ref store) => store,
+            None => return Ok(()),
+        };
+        let mut data = Vec::new();
+        for event in events.iter() {
+            serde_json::to_writer(&mut data, event)?;
+            data.push(b'\n');
+        }
+        let mut file = store
+            .lock()
+            .map_err(|_| Error::Message("Event store lock poisoned".into()))?;
+        file.write_all(&data)?;
+        file.flush()?;
+        Ok(())
+    }
+
+    /// Handle one event: publish it, if a broker is set, post it to Slack, if set,
+    /// and if a command is set, start the command with the event. If `jobs` commands
+    /// are already running, waits until one finishes. If none of these are set,
+    /// the event is printed to stdout as json (by default, a single line).
+    async fn handle_event(&self, event: Value) -> Result<(), Error> {
+        if let Some(ref publisher) = self.publish {
+            publisher.publish(&serde_json::to_vec(&event)?).await?;
+        }
+        if let Some(ref notifier) = self.notify {
+            notifier.notify(&event).await?;
+        }
+        let exec = match self.exec {
+            Some(ref exec) => exec,
+            None if self.publish.is_some() || self.notify.is_some() => return Ok(()),
+            None => return self.format.print(&event),
+        };
+        let json = self.format.to_json(&event)?;
+        let env = event_env(&event);
+        let label = event_label(&env);
+        let command = exec.command.clone();
+        let permit = exec
+            .jobs
+            .clone()
+            .acquire_owned()
+            .await
+            .map_err(|e| Error::Message(e.to_string()))?;
+        tokio::spawn(async move {
+            let _permit = permit;
+            let result =
+                tokio::task::spawn_blocking(move || run_command(&command, &json, &env, &label))
+                    .await;
+            if let Err(e) = result {
+                eprintln!("Error running command for event: {}", e);
+            }
+        });
+        Ok(())
+    }
+
+    /// Handle request body, which may contain a single event or an array of events
+    /// Events are stored before any are handled, so they can be replayed if handling fails.
+    async fn handle_body(&self, body: &[u8]) -> Result<(), Error> {
+        let events = match serde_json::from_slice(body)
+            .map_err(|e| Error::Invalid(format!("Invalid event json: {}", e)))?
+        {
+            Value::Array(events) => events,
+            event => vec![event],
+        };
+        self.store_events(&events)?;
+        for event in events {
+            self.handle_event(event).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Run http server until `shutdown` completes
+pub(crate) async fn serve<F>(
+    listener: Arc<Listener>,
+    addr: SocketAddr,
+    shutdown: F,
+) -> Result<(), Error>
+where
+    F: Future<Output = ()>,
+{
+    let make_svc = make_service_fn(move |_conn| {
+        let listener = listener.clone();
+        async move { Ok::<_, Infallible>(service_fn(move |req| handle_request(listener.clone(), req))) }
+    });
+    Server::try_bind(&addr)?
+        .serve(make_svc)
+        .with_graceful_shutdown(shutdown)
+        .await?;
+    Ok(())
+}
+
+async fn handle_request(
+    listener: Arc<Listener>,
+    req: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    if req.method() != Method::POST {
+        return Ok(status_response(StatusCode::METHOD_NOT_ALLOWED));
+    }
+    let signature = req
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .map(|v| v.as_bytes().to_vec());
+    let content_length = req
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok());
+    if content_length.map(|len| len > MAX_BODY_SIZE as u64) == Some(true) {
+        eprintln!("Rejected request larger than {} bytes", MAX_BODY_SIZE);
+        return Ok(status_response(StatusCode::PAYLOAD_TOO_LARGE));
+    }
+    let status = match read_body(req.into_body(), MAX_BODY_SIZE).await {
+        Ok(None) => {
+            eprintln!("Rejected request larger than {} bytes", MAX_BODY_SIZE);
+            StatusCode::PAYLOAD_TOO_LARGE
+        }
+        Ok(Some(body)) if !listener.verify(&body, signature.as_deref()) => {
+            eprintln!("Rejected request with missing or invalid signature");
+            StatusCode::UNAUTHORIZED
+        }
+        Ok(Some(body)) => match listener.handle_body(&body).await {
+            Ok(()) => StatusCode::OK,
+            Err(e) => {
+                eprintln!("Error handling event: {}", e);
+                StatusCode::BAD_REQUEST
+            }
+        },
+        Err(e) => {
+            eprintln!("Error reading request: {}", e);
+            StatusCode::BAD_REQUEST
+        }
+    };
+    Ok(status_response(status))
+}
+
+/// Read the body, up to `limit` bytes. Returns None if the body is larger.
+async fn read_body(mut body: Body, limit: usize) -> Result<Option<Vec<u8>>, hyper::Error> {
+    let mut data = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if data.len() + chunk.len() > limit {
+            return Ok(None);
+        }
+        data.extend_from_slice(&chunk);
+    }
+    Ok(Some(data))
+}
+
+fn status_response(status: StatusCode) -> Response<Body> {
+    let mut resp = Response::new(Body::empty());
+    *resp.status_mut() = status;
+    resp
+}
+
+/// Environment variables with event metadata, for the exec command:
+///  - ZK_EVENT_ID, ZK_EVENT_UUID: the event's id and uuid
+///  - ZK_LIST_ID: id of the list
+///  - ZK_ITEM_ID: id of the item (listEntryId, or for item events, id)
+///  - ZK_RECEIVED_AT: time the event was received (RFC 3339)
+///
+/// Variables are omitted if the event doesn't have the value.
+fn event_env(event: &Value) -> Vec<(&'static str, String)> {
+    let get = |key: &str| match event.get(key) {
+        Some(Value::String(s)) => Some(s.clone()),
+        Some(Value::Number(n)) => Some(n.to_string()),
+        _ => None,
+    };
+    let item_id = get("listEntryId").or_else(|| match event.get("displayString") {
+        Some(_) => get("id"),
+        None => None,
+    });
+    vec![
+        ("ZK_EVENT_ID", get("id")),
+        ("ZK_EVENT_UUID", get("uuid")),
+        ("ZK_LIST_ID", get("listId")),
+        ("ZK_ITEM_ID", item_id),
+        ("ZK_RECEIVED_AT", Some(chrono::Utc::now().to_rfc3339())),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| value.map(|v| (name, v)))
+    .collect()
+}
+
+/// Run the command with the event json on stdin. Failures are logged to stderr,
+/// with the event's `label` (such as its id) instead of its values.
+pub(crate) fn run_command(command: &str, json: &str, env: &[(&str, String)], label: &str) {
+    let result = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .envs(env.iter().map(|(k, v)| (*k, v.as_str())))
+        .stdin(Stdio::piped())
+        .spawn()
+        .and_then(|mut child| {
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(json.as_bytes())?;
+            }
+            child.wait()
+        });
+    match result {
+        Ok(status) if status.success() => {}
+        Ok(status) => eprintln!(
+            "Command '{}' failed ({}) for event {}",
+            command, status, label
+        ),
+        Err(e) => eprintln!("Command '{}' failed ({}) for event {}", command, e, label),
+    }
+}
+
+/// The event's id, or uuid if it has no id, from the command environment.
+/// Used in log messages, which shouldn't contain the event's values.
+fn event_label(env: &[(&str, String)]) -> String {
+    ["ZK_EVENT_ID", "ZK_EVENT_UUID"]
+        .iter()
+        .find_map(|name| env.iter().find(|(k, _)| k == name))
+        .map(|(_, v)| v.clone())
+        .unwrap_or_else(|| "(no id)".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signature_known_answer() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            signature("Jefe", b"what do ya want for nothing?").unwrap(),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn single_or_batched_events() {
+        let listener = Listener::new(JsonFormat::Jsonl);
+        assert!(listener.handle_body(br#"{"id":1}"#).await.is_ok());
+        assert!(listener
+            .handle_body(br#"[{"id":1},{"id":2}]"#)
+            .await
+            .is_ok());
+        assert!(matches!(
+            listener.handle_body(b"not json").await,
+            Err(Error::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn verify_signature() {
+        let body = br#"[{"type":"item"}]"#;
+        let listener = Listener::new(JsonFormat::Jsonl).with_secret("secret".to_string());
+        let valid = signature("secret", body).unwrap();
+        assert!(listener.verify(body, Some(valid.as_bytes())));
+        let other = signature("other", body).unwrap();
+        assert!(!listener.verify(body, Some(other.as_bytes())));
+        assert!(!listener.verify(b"[]", Some(valid.as_bytes())));
+        assert!(!listener.verify(body, Some(b"sha256=")));
+        assert!(!listener.verify(body, None));
+
+        let unsigned = Listener::new(JsonFormat::Jsonl);
+        assert!(unsigned.verify(body, None));
+    }
+
+    #[test]
+    fn secret_from_file() {
+        let path = std::env::temp_dir().join(format!("zk_secret_{}", std::process::id()));
+        std::fs::write(&path, "from file\n").unwrap();
+        let path_str = path.to_str().unwrap();
+        assert_eq!(
+            load_secret(None, Some(path_str)).unwrap().as_deref(),
+            Some("from file")
+        );
+        assert_eq!(
+            load_secret(Some("option".to_string()), Some(path_str))
+                .unwrap()
+                .as_deref(),
+            Some("option")
+        );
+        std::fs::write(&path, "\n").unwrap();
+        assert!(load_secret(None, Some(path_str)).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn event_label_uses_id() {
+        let event =
+            serde_json::json!({"id": 12, "uuid": "u-1", "listId": 3, "text": "secret value"});
+        assert_eq!(event_label(&event_env(&event)), "12");
+        let event = serde_json::json!({"uuid": "u-1"});
+        assert_eq!(event_label(&event_env(&event)), "u-1");
+        assert_eq!(event_label(&event_env(&serde_json::json!({}))), "(no id)");
+    }
+
+    #[tokio::test]
+    async fn body_size_limit() {
+        let body = read_body(Body::from("0123456789"), 10).await.unwrap();
+        assert_eq!(body.as_deref(), Some(&b"0123456789"[..]));
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for chunk in ["01234", "56789", "x"].iter() {
+                if sender.send_data(chunk.as_bytes().into()).await.is_err() {
+                    break;
+                }
+            }
+        });
+        assert_eq!(read_body(body, 10).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,370 @@
+//! A list and its fields, loaded from the api, the metadata cache, or a backup
+use crate::{
+    backup::get_items_range,
+    retry::{retry, retry_rate_limited},
+    values::{direct_value, is_uuid, value_key},
+    Error,
+};
+use serde_json::{json, Map, Number, Value};
+use std::{collections::HashMap, result::Result};
+use tokio::sync::Mutex;
+use zenkit::types::{
+    AllId, Element, ElementCategoryId, Entry, FieldSetVal, FieldVal, List, NewComment, NumericType,
+    UpdateAction, ID,
+};
+
+/// A list and its fields. Unlike zenkit's ListInfo, it can be created from cached or
+/// backed-up metadata. Workspace users are loaded from the api when they're first
+/// needed, to set persons fields by name.
+pub(crate) struct ListInfo {
+    list: List,
+    fields: Vec<Element>,
+    /// lowercase user name, full name, or uuid -> user id
+    users: Mutex<Option<HashMap<String, ID>>>,
+}
+
+impl ListInfo {
+    pub(crate) fn new(list: List, fields: Vec<Element>) -> Self {
+        ListInfo {
+            list,
+            fields,
+            users: Mutex::new(None),
+        }
+    }
+
+    pub(crate) fn list(&self) -> &List {
+        &self.list
+    }
+
+    pub(crate) fn get_id(&self) -> ID {
+        self.list.id
+    }
+
+    pub(crate) fn fields(&self) -> &[Element] {
+        &self.fields
+    }
+
+    /// The field with the name, id, or uuid
+    pub(crate) fn get_field(&self, field_id: &str) -> Result<&Element, Error> {
+        self.fields
+            .iter()
+            .find(|f| f.name == field_id || f.uuid == field_id || f.id.to_string() == field_id)
+            .ok_or_else(|| {
+                Error::NotFound(format!(
+                    "Field '{}' not found in list {}",
+                    field_id, self.list.name
+                ))
+            })
+    }
+
+    /// The item with the id or uuid. Sends one request.
+    pub(crate) async fn get_item<A: Into<AllId>>(&self, item: A) -> Result<Entry, zenkit::Error> {
+        zenkit::get_api()?.get_entry(self.list.id, item).await
+    }
+
+    /// All items of the list, except archived items, fetched in pages
+    pub(crate) async fn get_items(&self) -> Result<Vec<Entry>, Error> {
+        get_items_range(&self.list.uuid, false, 0, None).await
+    }
+
+    /// Create an item. The request is only repeated if it was rate-limited.
+    pub(crate) async fn create_item(&self, values: &[FieldSetVal]) -> Result<Entry, Error> {
+        let body = Value::Object(self.item_body(values).await?);
+        let api = zenkit::get_api()?;
+        retry_rate_limited("create_entry", || {
+            api.create_entry(self.list.id, body.clone())
+        })
+        .await
+    }
+
+    /// Update fields of the item
+    pub(crate) async fn update_item(
+        &self,
+        item_id: ID,
+        values: &[FieldSetVal],
+    ) -> Result<Entry, Error> {
+        let body = Value::Object(self.item_body(values).await?);
+        let api = zenkit::get_api()?;
+        retry("update_entry", || {
+            api.update_entry(self.list.id, item_id, body.clone())
+        })
+        .await
+    }
+
+    /// The body of the request to create or update an item with the values,
+    /// keyed by `values::value_key`. Choices and persons are converted to ids,
+    /// and for multi-value fields, the update action is added. Values that
+    /// `values::direct_value` converts are sent as it returns them.
+    /// This is the body that's sent, and that's printed in dry-run mode.
+    pub(crate) async fn item_body(
+        &self,
+        values: &[FieldSetVal],
+    ) -> Result<Map<String, Value>, Error> {
+        let mut body = Map::new();
+        for (name, value, action) in values.iter() {
+            let field = self.get_field(name)?;
+            let key = value_key(field).ok_or_else(|| {
+                Error::Invalid(format!("Field '{}' can't be set directly", field.name))
+            })?;
+            if let Some(json) = direct_value(field, value) {
+                body.insert(key, json);
+                continue;
+            }
+            let multi_value = matches!(
+                field.element_category,
+                ElementCategoryId::Categories
+                    | ElementCategoryId::Persons
+                    | ElementCategoryId::References
+            );
+            if !multi_value && *action != UpdateAction::Replace && *action != UpdateAction::Null {
+                return Err(Error::Invalid(format!(
+                    "Action {} can't be used for field '{}', which has a single value",
+                    action, field.name
+                )));
+            }
+            let json = match (field.element_category, value) {
+                (ElementCategoryId::Text, FieldVal::Str(s)) => json!(s),
+                (ElementCategoryId::Text, FieldVal::Formatted(s, format)) => {
+                    body.insert(
+                        format!("{}_textType", field.uuid),
+                        json!(format.to_string()),
+                    );
+                    json!(s)
+                }
+                (ElementCategoryId::Number, _) => number_value(field, value)?,
+                (ElementCategoryId::URL, FieldVal::Str(s))
+                | (ElementCategoryId::Date, FieldVal::Str(s)) => json!(s),
+                (ElementCategoryId::Categories, _) => {
+                    let ids = match value {
+                        FieldVal::Str(s) => vec![field.get_choice_id(s)?],
+                        FieldVal::ArrStr(names) => names
+                            .iter()
+                            .map(|name| field.get_choice_id(name))
+                            .collect::<Result<_, _>>()?,
+                        FieldVal::Int(id) => vec![*id as ID],
+                        FieldVal::ArrID(ids) => ids.clone(),
+                        _ => return Err(invalid_value(field, value)),
+                    };
+                    check_count(field, ids.len())?;
+                    json!(ids)
+                }
+                (ElementCategoryId::Persons, _) => {
+                    let ids = match value {
+                        FieldVal::Str(s) => vec![self.user_id(s).await?],
+                        FieldVal::ArrStr(names) => {
+                            let mut ids = Vec::new();
+                            for name in names.iter() {
+                                ids.push(self.user_id(name).await?);
+                            }
+                            ids
+                        }
+                        FieldVal::Int(id) => vec![*id as ID],
+                        FieldVal::ArrID(ids) => ids.clone(),
+                        _ => return Err(invalid_value(field, value)),
+                    };
+                    check_count(field, ids.len())?;
+                    json!(ids)
+                }
+                (ElementCategoryId::References, _) => {
+                    let uuids = match value {
+                        FieldVal::Str(s) => vec![s.clone()],
+                        FieldVal::ArrStr(uuids) => uuids.clone(),
+                        FieldVal::Int(id) => {
+                            body.insert(key, json!([id]));
+                            continue;
+                        }
+                        _ => return Err(invalid_value(field, value)),
+                    };
+                    check_count(field, uuids.len())?;
+                    if let Some(bad) = uuids.iter().find(|uuid| !is_uuid(uuid)) {
+                        return Err(Error::Invalid(format!(
+                            "Invalid uuid '{}' for field '{}'",
+                            bad, field.name
+                        )));
+                    }
+                    json!(uuids)
+                }
+                _ => return Err(invalid_value(field, value)),
+            };
+            body.insert(key, json);
+            if multi_value && *action != UpdateAction::Null {
+                body.insert("updateAction".to_string(), json!(action.to_string()));
+            }
+        }
+        Ok(body)
+    }
+
+    /// Id of the workspace user with the display name, full name (ignoring case),
+    /// uuid, or id. Users are loaded when first needed.
+    async fn user_id(&self, name: &str) -> Result<ID, Error> {
+        if let Ok(id) = name.parse::<ID>() {
+            return Ok(id);
+        }
+        let mut users = self.users.lock().await;
+        if users.is_none() {
+            let api = zenkit::get_api()?;
+            let ws_id = self.list.workspace_id;
+            let mut ids = HashMap::new();
+            for user in retry("get_users_raw", || api.get_users_raw(ws_id))
+                .await?
+                .iter()
+            {
+                ids.insert(user.display_name.to_lowercase(), user.id);
+                ids.insert(user.full_name.to_lowercase(), user.id);
+                ids.insert(user.uuid.clone(), user.id);
+            }
+            *users = Some(ids);
+        }
+        users
+            .as_ref()
+            .and_then(|ids| ids.get(&name.to_lowercase()).copied())
+            .ok_or_else(|| Error::NotFound(format!("User not found: '{}'", name)))
+    }
+
+    /// Add a comment to the item (id or uuid)
+    pub(crate) async fn add_item_comment(&self, item: &str, message: &str) -> Result<(), Error> {
+        let api = zenkit::get_api()?;
+        let item_id = match item.parse::<ID>() {
+            Ok(id) => id,
+            Err(_) => retry("get_item", || self.get_item(item)).await?.id,
+        };
+        let comment = NewComment {
+            message: message.to_string(),
+        };
+        retry_rate_limited("create_entry_comment", || {
+            api.create_entry_comment(self.list.id, item_id, &comment)
+        })
+        .await?;
+        Ok(())
+    }
+}
+
+/// A number field's value. Strings are parsed as integers or decimals,
+/// depending on the field's format.
+fn number_value(field: &Element, value: &FieldVal) -> Result<Value, Error> {
+    let number = match value {
+        FieldVal::Int(n) => Some(Number::from(*n)),
+        FieldVal::Float(n) => Number::from_f64(*n),
+        FieldVal::Str(s) => match field.numeric_type() {
+            Some(NumericType::Integer) => s.parse::<i64>().ok().map(Number::from),
+            Some(NumericType::Decimal) => s.parse::<f64>().ok().and_then(Number::from_f64),
+            None => {
+                return Err(Error::Invalid(format!(
+                    "Unknown numeric type of field '{}'",
+                    field.name
+                )))
+            }
+        },
+        _ => None,
+    };
+    number
+        .map(Value::Number)
+        .ok_or_else(|| invalid_value(field, value))
+}
+
+/// Error for a field with more than one value, if it only accepts one
+fn check_count(field: &Element, count: usize) -> Result<(), Error> {
+    if count > 1 && !field.element_data.multiple {
+        return Err(Error::Invalid(format!(
+            "Field '{}' can't have more than one value, but {} were given",
+            field.name, count
+        )));
+    }
+    Ok(())
+}
+
+fn invalid_value(field: &Element, value: &FieldVal) -> Error {
+    Error::Invalid(format!(
+        "Invalid value {:?} for field '{}' ({:?})",
+        value, field.name, field.element_category
+    ))
+}
+
+impl std::ops::Deref for ListInfo {
+    type Target = List;
+
+    fn deref(&self) -> &List {
+        &self.list
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{choice_field, field, field_with_data, list_info};
+    use zenkit::types::TextFormat;
+
+    async fn body(values: Vec<FieldSetVal>) -> Result<Value, Error> {
+        let list_info = list_info(vec![
+            field(1, "Title", 1),
+            field_with_data(2, "Points", 2, json!({ "format": { "name": "integer" } })),
+            field(3, "Due", 4),
+            field(4, "Done", 5),
+            choice_field(5, "Tags", &[(51, "red"), (52, "blue")]),
+        ]);
+        Ok(Value::Object(list_info.item_body(&values).await?))
+    }
+
+    #[tokio::test]
+    async fn item_body_values() {
+        let body = body(vec![
+            (
+                "Title".into(),
+                FieldVal::Formatted("**x**".into(), TextFormat::Markdown),
+                UpdateAction::Replace,
+            ),
+            (
+                "Points".into(),
+                FieldVal::Str("3".into()),
+                UpdateAction::Replace,
+            ),
+            (
+                "Due".into(),
+                FieldVal::Str("".into()),
+                UpdateAction::Replace,
+            ),
+            (
+                "Done".into(),
+                FieldVal::Str("true".into()),
+                UpdateAction::Null,
+            ),
+            (
+                "Tags".into(),
+                FieldVal::ArrStr(vec!["blue".into(), "red".into()]),
+                UpdateAction::Append,
+            ),
+        ])
+        .await
+        .unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "field-1_text": "**x**",
+                "field-1_textType": "markdown",
+                "field-2_number": 3,
+                "field-3_date": null,
+                "field-4_checked": true,
+                "field-5_categories": [52, 51],
+                "updateAction": "append",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn item_body_errors() {
+        let cases = vec![
+            ("Nope", FieldVal::Str("x".into()), UpdateAction::Replace),
+            (
+                "Points",
+                FieldVal::Str("three".into()),
+                UpdateAction::Replace,
+            ),
+            ("Title", FieldVal::Str("x".into()), UpdateAction::Append),
+            ("Tags", FieldVal::Str("green".into()), UpdateAction::Replace),
+        ];
+        for (name, value, action) in cases {
+            let result = body(vec![(name.into(), value, action)]).await;
+            assert!(result.is_err(), "{}", name);
+        }
+    }
+}
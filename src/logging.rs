@@ -0,0 +1,40 @@
+//! Diagnostic logging to stderr
+use crate::OutputFormat;
+
+/// Start logging. With verbosity 0, only warnings (such as retries) are logged;
+/// 1 (`-v`) adds each api call (with its attempt number, latency, and outcome), and
+/// each http request sent directly, with its status and latency;
+/// 2 or more (`-vv`) adds request and response bodies.
+pub(crate) fn init(verbose: u8, format: &OutputFormat) {
+    let builder = tracing_subscriber::fmt()
+        .with_env_filter(filter(verbose))
+        .with_writer(std::io::stderr);
+    match format {
+        OutputFormat::Json => builder.json().init(),
+        OutputFormat::Text => builder.init(),
+    }
+}
+
+/// Log filter for the verbosity. Other crates (such as the http client) only log
+/// warnings.
+fn filter(verbose: u8) -> String {
+    let level = match verbose {
+        0 => "warn",
+        1 => "debug",
+        _ => "trace",
+    };
+    format!("warn,{}={}", env!("CARGO_BIN_NAME"), level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_levels() {
+        let bin = env!("CARGO_BIN_NAME");
+        assert_eq!(filter(0), format!("warn,{}=warn", bin));
+        assert_eq!(filter(1), format!("warn,{}=debug", bin));
+        assert_eq!(filter(3), format!("warn,{}=trace", bin));
+    }
+}
@@ -1,27 +1,269 @@
 use clap::Clap;
 use config::Config;
-use std::{fmt, fs, sync::Arc};
+use std::{
+    convert::TryFrom,
+    fmt, fs,
+    io::{self, Write},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
 use zenkit::{
     self,
     types::{
-        ElementCategoryId, FieldVal, NewWebhook, TextFormat, UpdateAction, WebhookTriggerType,
-        Workspace, ID,
+        Element, ElementCategoryId, FieldVal, List, NewWebhook, TextFormat, UpdateAction,
+        WebhookTriggerType, Workspace, ZKObjectID, ID,
     },
     ApiConfig,
 };
 
+mod activity;
+use activity::{
+    get_activities, get_entry_activities, get_notifications, mark_notification_read,
+    ActivitySummary, FieldChange, Notification, FILTER_COMMENTS,
+};
+mod agenda;
+use agenda::print_agenda;
+mod aliases;
 mod backup;
-use backup::{backup_list, BackupItem};
+use backup::{
+    backup_workspace, create_archive, git_commit, last_backup_tstamp, last_uploaded_tstamp,
+    write_summary, AccountSummary, ArchiveFormat, Checkpoint, LATEST_SUMMARY,
+};
+mod batch;
+use batch::run_file;
+mod board;
+mod cache;
+use board::print_board;
+mod bulk;
+use bulk::{bulk_archive, bulk_set};
+mod checklist;
+use checklist::update_checklist;
+mod choices;
+use choices::{add_choice, delete_choice, rename_choice};
+mod copy;
+use copy::{copy_item, move_item, sync_lists};
+mod dates;
+mod diff;
+use diff::diff_backups;
+mod dryrun;
+mod due;
+use due::due_report;
+mod export;
+use export::export;
+mod files;
+use files::{attach_file, item_files};
+mod fixtures;
+use fixtures::FixtureMode;
+mod fuzzy;
+mod html;
+mod http;
+use http::HttpSettings;
+mod import;
+use import::import_csv;
+mod items;
+use items::list_items;
+mod jira;
+use jira::import_jira;
+mod listen;
+use listen::Listener;
+mod listinfo;
+use listinfo::ListInfo;
+mod logging;
+mod members;
+mod metrics;
+use members::{invite_user, list_users, remove_user};
+mod mirror;
+use mirror::mirror;
+mod my_items;
+use my_items::my_items;
+mod notion;
+mod offline;
+use notion::import_notion;
+mod notify;
+use notify::{notify, SlackNotifier};
+mod progress;
+mod prune;
+use prune::prune_backups;
+mod publish;
+use publish::{PublishUrl, Publisher};
+mod query;
+mod ratelimit;
+use query::query;
+use ratelimit::RateLimit;
+mod rest;
+use rest::RestClient;
+mod restore;
+use restore::restore_item;
+mod schema;
+use schema::{
+    apply_schema, clone_list, create_field_spec, create_list, export_schema, FieldSpec, FieldType,
+};
+mod retry;
+use retry::{retry, retry_rate_limited};
+mod s3;
+use s3::{S3Client, S3Dest, S3Settings};
+mod search;
+use search::search;
+mod shell;
+mod stats;
+mod style;
+mod sync;
+use shell::shell;
+use stats::print_stats;
+use style::{Cell, Style, Table};
+use sync::sync;
+mod template;
+use template::Template;
+#[cfg(test)]
+mod testutil;
+mod trello;
+use trello::import_trello;
+mod txn;
+mod undo;
+use undo::undo_changes;
+mod values;
+mod verify;
+use values::{raw_value, resolve_values, user_emails, ValueFormatter};
+use verify::verify_backup;
+mod watch;
+mod webhooks;
+mod weblinks;
+mod xlsx;
+use watch::watch_list;
+use webhooks::{list_webhooks, print_webhook, test_webhook};
+use weblinks::WebSettings;
 
 #[derive(Debug)]
 pub(crate) enum Error {
     Message(String),
+    /// A workspace, list, field, item, or other object that doesn't exist
+    NotFound(String),
+    /// Missing credentials
+    Auth(String),
+    /// An invalid argument, value, or input file
+    Invalid(String),
     Zenkit(zenkit::Error),
     Io(String),
+    /// Failed http request. status is None if there was no response
+    Http {
+        status: Option<u16>,
+        retry_after: Option<Duration>,
+        /// a connection error or timeout, which may not happen again
+        transient: bool,
+        message: String,
+    },
 }
 
 impl std::error::Error for Error {}
 
+/// Category of an error, which determines the exit status
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) enum ErrorKind {
+    Other,
+    NotFound,
+    Auth,
+    RateLimited,
+    Invalid,
+}
+
+impl ErrorKind {
+    /// Exit status: 1 = other errors, 2 = not found, 3 = authentication or permission
+    /// failure, 4 = rate limited, 5 = invalid request or value
+    pub(crate) fn exit_code(&self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::NotFound => 2,
+            ErrorKind::Auth => 3,
+            ErrorKind::RateLimited => 4,
+            ErrorKind::Invalid => 5,
+        }
+    }
+
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Other => "error",
+            ErrorKind::NotFound => "not_found",
+            ErrorKind::Auth => "auth",
+            ErrorKind::RateLimited => "rate_limited",
+            ErrorKind::Invalid => "invalid",
+        }
+    }
+
+    fn from_status(status: u16) -> Self {
+        match status {
+            404 => ErrorKind::NotFound,
+            401 | 403 => ErrorKind::Auth,
+            429 => ErrorKind::RateLimited,
+            400 | 422 => ErrorKind::Invalid,
+            _ => ErrorKind::Other,
+        }
+    }
+}
+
+impl Error {
+    /// One-line error description
+    pub(crate) fn summary(&self) -> String {
+        match self {
+            Error::Message(msg)
+            | Error::NotFound(msg)
+            | Error::Auth(msg)
+            | Error::Invalid(msg)
+            | Error::Io(msg) => msg.clone(),
+            Error::Zenkit(e) => e.to_string(),
+            Error::Http { message, .. } => message.clone(),
+        }
+    }
+
+    /// Http status of the failed request, if the server responded
+    pub(crate) fn status(&self) -> Option<u16> {
+        match self {
+            Error::Http { status, .. } => *status,
+            Error::Zenkit(zenkit::Error::ApiError(status, _)) => Some(*status),
+            Error::Zenkit(zenkit::Error::Reqwest(e)) => e.status().map(|s| s.as_u16()),
+            // zenkit's error for responses without a zenkit error object,
+            // "Server returned status <status>:<body>"
+            Error::Zenkit(zenkit::Error::Other(msg)) => msg
+                .strip_prefix("Server returned status ")?
+                .split(':')
+                .next()?
+                .parse()
+                .ok(),
+            _ => None,
+        }
+    }
+
+    /// Category of the error, from the error variant, the http status,
+    /// or the type of zenkit error
+    pub(crate) fn kind(&self) -> ErrorKind {
+        match self {
+            Error::NotFound(_) => ErrorKind::NotFound,
+            Error::Auth(_) => ErrorKind::Auth,
+            Error::Invalid(_) => ErrorKind::Invalid,
+            Error::Message(_) => ErrorKind::Other,
+            Error::Zenkit(e) if e.is_rate_limit() => ErrorKind::RateLimited,
+            Error::Zenkit(zenkit::Error::MissingApiToken(_)) => ErrorKind::Auth,
+            Error::Zenkit(zenkit::Error::MultiCategory(..)) => ErrorKind::Invalid,
+            Error::Http { .. } | Error::Io(_) | Error::Zenkit(_) => self
+                .status()
+                .map(ErrorKind::from_status)
+                .unwrap_or(ErrorKind::Other),
+        }
+    }
+
+    /// The error as json: kind, exit status, http status (if any), and message
+    pub(crate) fn to_json(&self) -> serde_json::Value {
+        let kind = self.kind();
+        serde_json::json!({
+            "error": kind.name(),
+            "exit_code": kind.exit_code(),
+            "status": self.status(),
+            "message": self.summary(),
+        })
+    }
+}
+
 impl From<Box<dyn std::error::Error>> for Error {
     fn from(e: Box<dyn std::error::Error>) -> Error {
         Error::Message(e.to_string())
@@ -36,7 +278,7 @@ impl From<zenkit::Error> for Error {
 
 impl From<config::ConfigError> for Error {
     fn from(e: config::ConfigError) -> Error {
-        Error::Message(format!("config: {}", e.to_string()))
+        Error::Message(format!("config: {}", e))
     }
 }
 
@@ -46,9 +288,44 @@ impl From<std::io::Error> for Error {
     }
 }
 
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Error {
+        Error::Http {
+            status: e.status().map(|s| s.as_u16()),
+            retry_after: None,
+            transient: e.is_timeout() || e.is_connect(),
+            message: format!("http: {}", e),
+        }
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Error {
+        Error::Message(format!("csv: {}", e))
+    }
+}
+
+impl From<zip::result::ZipError> for Error {
+    fn from(e: zip::result::ZipError) -> Error {
+        Error::Message(format!("zip: {}", e))
+    }
+}
+
+impl From<hyper::Error> for Error {
+    fn from(e: hyper::Error) -> Error {
+        Error::Message(format!("http server: {}", e))
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Error {
+        Error::Message(format!("sqlite: {}", e))
+    }
+}
+
 impl From<serde_json::Error> for Error {
     fn from(e: serde_json::Error) -> Error {
-        Error::Message(format!("Error creating json output: {}", e.to_string()))
+        Error::Message(format!("json: {}", e))
     }
 }
 
@@ -66,6 +343,50 @@ fn parse_try_text_format(s: &str) -> Result<TextFormat, &'static str> {
     }
 }
 
+/// Parse timestamp as milliseconds since the epoch, date (YYYY-MM-DD), or RFC 3339 date-time
+fn parse_timestamp(s: &str) -> Result<u64, String> {
+    let invalid = || {
+        format!(
+            "Invalid timestamp '{}': should be milliseconds, YYYY-MM-DD, or RFC 3339 date-time",
+            s
+        )
+    };
+    if let Ok(ms) = s.parse::<u64>() {
+        return Ok(ms);
+    }
+    let ms = if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(s) {
+        dt.timestamp_millis()
+    } else if let Ok(d) = chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        d.and_hms(0, 0, 0).timestamp_millis()
+    } else {
+        return Err(invalid());
+    };
+    // dates before 1970 are negative
+    u64::try_from(ms).map_err(|_| invalid())
+}
+
+/// Parse duration: number with optional unit ms (milliseconds), s (seconds, default),
+/// m (minutes), h (hours), or d (days)
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    if let Some(ms) = s.strip_suffix("ms").and_then(|n| n.parse::<u64>().ok()) {
+        return Ok(Duration::from_millis(ms));
+    }
+    let (num, secs) = match s.char_indices().last() {
+        Some((i, 's')) => (&s[..i], 1),
+        Some((i, 'm')) => (&s[..i], 60),
+        Some((i, 'h')) => (&s[..i], 3600),
+        Some((i, 'd')) => (&s[..i], 86400),
+        _ => (s, 1),
+    };
+    match num.parse::<u64>().ok().and_then(|n| n.checked_mul(secs)) {
+        Some(secs) => Ok(Duration::from_secs(secs)),
+        None => Err(format!(
+            "Invalid duration '{}': should be a number with optional unit ms, s, m, h, or d",
+            s
+        )),
+    }
+}
+
 #[derive(Clap, PartialEq, Debug)]
 struct SetValueOpt {
     /// List name or id
@@ -76,14 +397,24 @@ struct SetValueOpt {
     #[clap(short, long)]
     item: ID,
 
-    /// Field name or id
+    /// Field name or id. Required unless values are set with -s
     #[clap(short, long)]
-    field: String,
+    field: Option<String>,
+
+    /// -s field=value -s field=value ... Set several fields in one update.
+    /// May be used with or instead of --field. Values use the same format as
+    /// --value. (This is `create -F`; for set, -F is --file.)
+    #[clap(short = 's', long = "set", parse(try_from_str=parse_key_val), number_of_values = 1)]
+    set_values: Vec<(String, String)>,
 
     /// Value (alternate to --file).
-    /// If value is an item reference, it must be a uuid.
-    /// If value is a person, it may be the person's uuid or display name (case-insensitive).
+    /// If value is an item reference, it may be the item's uuid, id, or title.
+    /// If value is a person, it may be the person's uuid, email, or display name (case-insensitive).
     /// If value is a choice, it may be the id or the display name (case-sensitive).
+    /// If value is a date, it may be YYYY-MM-DD, today, tomorrow, +3d, +2w, or a weekday
+    /// ("friday", "next friday"), optionally followed by a time (HH:MM, local time).
+    /// Several values may be given as "[a,b,c]", and text may be prefixed with
+    /// its format ("markdown::", "html::", or "plain::"), as for `create`.
     #[clap(short, long, group = "file_or_value")]
     value: Option<String>,
 
@@ -95,213 +426,2071 @@ struct SetValueOpt {
     /// Read Value from file (alternate to -v). Only applicable for Text fields.
     #[clap(short = 'F', long, group = "file_or_value")]
     file: Option<String>,
+
+    /// How the value is applied: replace the current value, add to or remove from
+    /// a multi-value field (persons, choices, references), or clear the field (null).
+    #[clap(short, long, arg_enum, default_value = "replace")]
+    action: SetAction,
+
+    /// Clear the field value (same as --action null)
+    #[clap(long, conflicts_with_all = &["value", "file"])]
+    null: bool,
 }
 
 #[derive(Clap, PartialEq, Debug)]
-struct CommentOpt {
+pub(crate) struct BulkSetOpt {
     /// List name or id
     #[clap(short, long)]
-    list: String,
+    pub list: String,
 
-    /// Item id or uuid
+    /// Only update items where Field=Value. Value may be the display value or id.
+    /// For multi-value fields, the item matches if any value matches.
+    /// May be repeated; items must match all filters.
+    #[clap(long, parse(try_from_str=parse_key_val), number_of_values = 1)]
+    pub filter: Vec<(String, String)>,
+
+    /// Field name or id to set
     #[clap(short, long)]
-    item: String,
+    pub field: String,
 
-    // User display-name (must be valid user)
-    //#[clap(short, long)]
-    //user: String,
-    /// Comment
+    /// Value, in the format used by `create`
     #[clap(short, long)]
-    comment: String,
+    pub value: String,
+
+    /// How the value is applied (see `set`)
+    #[clap(short, long, arg_enum, default_value = "replace")]
+    pub action: SetAction,
+
+    /// Print the update requests instead of sending them (same as the global `--dry-run`)
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Record each item's previous value, and if any update fails, stop and revert
+    /// the items already updated
+    #[clap(long)]
+    pub transactional: bool,
 }
 
 #[derive(Clap, PartialEq, Debug)]
-struct CreateOpt {
+pub(crate) struct ArchiveOpt {
     /// List name or id
     #[clap(short, long)]
-    list: String,
+    pub list: String,
 
-    /// -F field=value -F field=value ... Field names are case-sensitive.
-    #[clap(short='F', parse(try_from_str=parse_key_val), number_of_values = 1)]
-    fields: Vec<(String, String)>,
+    /// Only archive items where Field=Value (see `bulk-set`). May be repeated;
+    /// items must match all filters.
+    #[clap(long, parse(try_from_str=parse_key_val), number_of_values = 1)]
+    pub filter: Vec<(String, String)>,
+
+    /// Only archive items not updated in this time, e.g., "90d"
+    #[clap(long, parse(try_from_str=parse_duration))]
+    pub older_than: Option<Duration>,
+
+    /// Number of items to archive between progress updates
+    #[clap(long, default_value = "50")]
+    pub batch_size: usize,
+
+    /// Print the archive requests instead of sending them (same as the global `--dry-run`)
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Archive without asking for confirmation
+    #[clap(short, long)]
+    pub yes: bool,
 }
 
 #[derive(Clap, PartialEq, Debug)]
-pub(crate) struct BackupOpt {
-    /// Output folder where json files will be created
+pub(crate) struct UndoOpt {
+    /// List name or id
     #[clap(short, long)]
-    pub output: String,
+    pub list: String,
 
-    /// List - backup single list. If not specified, backs up all lists
+    /// Item id or uuid
     #[clap(short, long)]
-    pub list: Option<String>,
+    pub item: String,
 
-    /// Include archived items
-    #[clap[long]]
-    pub include_archived: bool,
+    /// Number of field changes to undo
+    #[clap(long, default_value = "1")]
+    pub last: usize,
+}
+
+#[derive(Clap, Debug, PartialEq)]
+pub(crate) enum SetAction {
+    Replace,
+    Add,
+    Remove,
+    Null,
+}
+
+impl SetAction {
+    fn update_action(&self) -> UpdateAction {
+        match self {
+            SetAction::Replace => UpdateAction::Replace,
+            SetAction::Add => UpdateAction::Append,
+            SetAction::Remove => UpdateAction::Remove,
+            SetAction::Null => UpdateAction::Null,
+        }
+    }
 }
 
 #[derive(Clap, PartialEq, Debug)]
-enum Sub {
-    /// Show all workspaces and lists
-    Workspaces,
+struct CommentsOpt {
+    /// List name or id
+    #[clap(short, long)]
+    list: String,
 
-    /// Show users in workspace
-    Users,
+    /// Item id or uuid
+    #[clap(short, long)]
+    item: String,
 
-    /// Show lists in workspace
-    Lists,
+    /// Output format
+    #[clap(long, arg_enum, default_value = "text")]
+    output: OutputFormat,
+}
 
-    /// Show items in list
-    #[clap(alias = "list")]
-    Items(ListOpt),
+#[derive(Clap, PartialEq, Debug)]
+struct ActivitiesOpt {
+    /// List name or id. If not specified, shows workspace activities
+    #[clap(short, long)]
+    list: Option<String>,
 
-    /// Show fields for a list
-    Fields(ListOpt),
+    /// Item id or uuid, to show activities of one item
+    #[clap(short, long, requires = "list")]
+    item: Option<String>,
 
-    /// Describe field of a list (detail view)
-    Field(FieldOpt),
+    /// Maximum number of activities
+    #[clap(long, default_value = "50")]
+    limit: usize,
 
-    /// Describe a list item (detail view)
-    Item(ItemOpt),
+    /// Only show activities older than this activity id, to continue from a previous page
+    #[clap(long)]
+    before: Option<u64>,
 
-    /// Show choices for a category field
-    Choices(FieldOpt),
+    /// Output format
+    #[clap(long, arg_enum, default_value = "text")]
+    output: OutputFormat,
+}
 
-    /// Set field value
-    Set(SetValueOpt),
+#[derive(Clap, PartialEq, Debug)]
+struct HistoryOpt {
+    /// List name or id
+    #[clap(short, long)]
+    list: String,
 
-    /// Create new list item
-    Create(CreateOpt),
+    /// Item id or uuid
+    #[clap(short, long)]
+    item: String,
 
-    /// Add comment to list item
-    Comment(CommentOpt),
+    /// Only show changes to this field
+    #[clap(short, long)]
+    field: Option<String>,
 
-    /// Add a webhook
-    #[clap(alias = "new-webhook")]
-    Webhook(WebhookOpt),
+    /// Output format
+    #[clap(long, arg_enum, default_value = "text")]
+    output: OutputFormat,
+}
 
-    /// List webhooks
-    ListWebhooks,
+#[derive(Clap, PartialEq, Debug)]
+struct NotificationsOpt {
+    /// Only show unread notifications
+    #[clap(long)]
+    unread: bool,
 
-    /// Delete webhook
-    DeleteWebhook(DelWebhookOpt),
+    /// Maximum number of notifications
+    #[clap(long, default_value = "50")]
+    limit: usize,
 
-    /// Backup
-    Backup(BackupOpt),
+    /// Output format
+    #[clap(long, arg_enum, default_value = "text")]
+    output: OutputFormat,
 }
 
 #[derive(Clap, PartialEq, Debug)]
-struct ListOpt {
-    /// List name or id
-    #[clap(short, long)]
-    list: String,
+struct MarkReadOpt {
+    /// Notification id. May be repeated.
+    #[clap(long, number_of_values = 1)]
+    id: Vec<ID>,
+
+    /// Mark all unread notifications as read
+    #[clap(long, conflicts_with = "id")]
+    all: bool,
 }
 
 #[derive(Clap, PartialEq, Debug)]
-struct ItemOpt {
-    /// List name or id
+struct EditCommentOpt {
+    /// Comment id (as shown by `comments`)
+    #[clap(long)]
+    id: ID,
+
+    /// New comment text
     #[clap(short, long)]
-    list: String,
+    comment: String,
+}
 
-    /// Item id (integer) or uuid
+#[derive(Clap, PartialEq, Debug)]
+struct DeleteCommentOpt {
+    /// Comment id (as shown by `comments`)
+    #[clap(long)]
+    id: ID,
+
+    /// Delete without asking for confirmation
     #[clap(short, long)]
-    item: String,
+    yes: bool,
 }
 
 #[derive(Clap, PartialEq, Debug)]
-struct FieldOpt {
-    /// List name or id
+pub(crate) struct SearchOpt {
+    /// Text to find, ignoring case
     #[clap(short, long)]
-    list: String,
+    pub query: String,
 
-    /// Field id or name
+    /// List name or id. If not specified, all active lists in the workspace are searched
     #[clap(short, long)]
-    field: String,
+    pub list: Option<String>,
+
+    /// Stop after this many matching items
+    #[clap(long)]
+    pub limit: Option<usize>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct QueryOpt {
+    /// List name or id. With `--saved`, overrides the saved query's list
+    #[clap(short, long, required_unless_present = "saved")]
+    pub list: Option<String>,
+
+    /// Query, e.g., "select id, Name, Due where Status != 'Done' and Due < today order by Due"
+    #[clap(required_unless_present = "saved")]
+    pub query: Option<String>,
+
+    /// Run the query saved in the config file's `[queries.NAME]` section
+    #[clap(long, value_name = "NAME", conflicts_with = "query")]
+    pub saved: Option<String>,
+
+    /// Output format
+    #[clap(long, arg_enum, default_value = "table")]
+    pub output: TableFormat,
 }
 
 #[derive(Clap, Debug, PartialEq)]
-enum WebhookType {
-    Item,
-    Activity,
-    Notification,
-    System,
-    Comment,
-    Field,
+pub(crate) enum TableFormat {
+    Table,
+    Csv,
+    Json,
 }
 
 #[derive(Clap, PartialEq, Debug)]
-struct WebhookOpt {
-    /// Webhook trigger type
-    #[clap(short, long = "type", arg_enum)]
-    trigger_type: WebhookType,
-
-    /// Server url
+pub(crate) struct SyncOpt {
+    /// List name or id
     #[clap(short, long)]
-    url: String,
+    pub list: String,
 
-    /// List id to restrict webhook to this list
-    #[clap(short, long)]
-    list: Option<String>,
+    /// Local file: .json, .toml, or .md. Created on the first sync. The state of
+    /// the last sync is saved in `<file>.sync.json`
+    #[clap(short = 'F', long)]
+    pub file: String,
 
-    /// Item id to restrict webhook to this item
-    #[clap(short, long)]
-    item: Option<String>,
+    /// Resolve conflicts (values changed differently in the file and the list since
+    /// the last sync) with the local or remote value. Without it, conflicts are
+    /// reported and left unchanged.
+    #[clap(long, arg_enum)]
+    pub prefer: Option<SyncPrefer>,
+}
 
-    /// Field id to restrict webhook to this field
+#[derive(Clap, Debug, PartialEq, Clone, Copy)]
+pub(crate) enum SyncPrefer {
+    Local,
+    Remote,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct MirrorOpt {
+    /// Target database
+    #[clap(subcommand)]
+    pub target: MirrorTarget,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) enum MirrorTarget {
+    /// Copy a list to a table in a sqlite database
+    Sqlite(SqliteOpt),
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct SqliteOpt {
+    /// List name or id
     #[clap(short, long)]
-    field: Option<String>,
+    pub list: String,
 
-    /// Locale
-    #[clap(long, default_value = "en")]
-    locale: String,
+    /// Database file. It is created if it doesn't exist
+    #[clap(long)]
+    pub db: String,
 
-    /// Limit to the workspace
+    /// Table name (default: the list name)
     #[clap(short, long)]
-    workspace: bool,
+    pub table: Option<String>,
+
+    /// Copy all items, not only those updated since the last sync
+    #[clap(long)]
+    pub full: bool,
 }
 
 #[derive(Clap, PartialEq, Debug)]
-struct DelWebhookOpt {
-    /// Webhook id
-    #[clap(short = 'W', long)]
-    webhook: u64,
+pub(crate) struct ExportOpt {
+    /// Export format
+    #[clap(subcommand)]
+    pub format: ExportFormat,
 }
 
-/// Zenkit command-line tool. Source and docs at https://github.com/stevelr/zenkit-cli
-/// Zenkit api token must be specified either in config file with -c option
-/// or in environment as `ZENKIT_TOKEN`.
 #[derive(Clap, PartialEq, Debug)]
-#[clap(name = env!("CARGO_BIN_NAME"), version = env!("CARGO_PKG_VERSION"))]
-struct Opt {
-    /// Configuration file  in TOML format, such as:
-    /// ```toml
-    /// [zenkit]
-    /// token = "00000"
-    /// workspace = "My Workspace"
-    /// ```
-    config: Option<String>,
+pub(crate) enum ExportFormat {
+    /// Export items with dates as an iCalendar file
+    Ics(IcsOpt),
 
-    /// Workspace name, id, or uuid. Required unless set in config file or environment
-    /// variable ZENKIT_WORKSPACE
-    #[clap(short, long)]
-    workspace: Option<String>,
+    /// Export items as json, as returned by Zenkit
+    Json(JsonExportOpt),
 
-    /// Subcommand
-    #[clap(subcommand)]
-    cmd: Sub,
+    /// Export items rendered with a Handlebars template, e.g., as Markdown
+    Template(TemplateExportOpt),
+
+    /// Export items as a Markdown document, with a table or list of items per group
+    Md(MarkdownOpt),
+
+    /// Export items as an Excel workbook, with a sheet per list
+    Xlsx(XlsxOpt),
+
+    /// Export items as a standalone html page, with a sortable table or a board
+    Html(HtmlOpt),
 }
 
-/// Parse "key=value" token
-fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn std::error::Error>>
-where
-    T: std::str::FromStr,
-    T::Err: std::error::Error + 'static,
-    U: std::str::FromStr,
-    U::Err: std::error::Error + 'static,
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct JsonExportOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Include archived items
+    #[clap(long)]
+    pub include_archived: bool,
+
+    /// Output format: json (an array of items) or jsonl (one item per line,
+    /// written as each batch of items is received)
+    #[clap(long, arg_enum, default_value = "json")]
+    pub output: JsonFormat,
+
+    /// Output file. If not specified, items are written to stdout
+    #[clap(short, long)]
+    pub out: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct HtmlOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Comma-separated columns: id, uuid, name, or field names.
+    /// Defaults to the name and all fields except files
+    #[clap(short, long)]
+    pub fields: Option<String>,
+
+    /// Show items as a sortable table, or as a board of cards
+    #[clap(long, arg_enum, default_value = "table")]
+    pub style: HtmlStyle,
+
+    /// Categories field whose choices are the board's columns
+    #[clap(short, long)]
+    pub by: Option<String>,
+
+    /// Page title. Defaults to the list name
+    #[clap(long)]
+    pub title: Option<String>,
+
+    /// Include archived items
+    #[clap(long)]
+    pub include_archived: bool,
+
+    /// Output file. If not specified, the page is written to stdout
+    #[clap(short, long)]
+    pub out: Option<String>,
+}
+
+#[derive(Clap, Debug, PartialEq)]
+pub(crate) enum HtmlStyle {
+    Table,
+    Board,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct XlsxOpt {
+    /// List name or id. If not specified, all active lists in the workspace are
+    /// exported, one sheet per list
+    #[clap(short, long)]
+    pub list: Option<String>,
+
+    /// Output file
+    #[clap(short, long)]
+    pub out: String,
+
+    /// Include archived items
+    #[clap(long)]
+    pub include_archived: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct MarkdownOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Comma-separated columns: id, uuid, name, or field names
+    #[clap(short, long, default_value = "name")]
+    pub fields: String,
+
+    /// Group items under a heading for each value of a column (usually a categories field)
+    #[clap(short, long)]
+    pub group_by: Option<String>,
+
+    /// Show items as a table, or as a bullet list
+    #[clap(long, arg_enum, default_value = "table")]
+    pub style: MarkdownStyle,
+
+    /// Document title. Defaults to the list name
+    #[clap(long)]
+    pub title: Option<String>,
+
+    /// Include archived items
+    #[clap(long)]
+    pub include_archived: bool,
+
+    /// Output file. If not specified, the document is written to stdout
+    #[clap(short, long)]
+    pub out: Option<String>,
+}
+
+#[derive(Clap, Debug, PartialEq)]
+pub(crate) enum MarkdownStyle {
+    Table,
+    List,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct TemplateExportOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Handlebars template file
+    #[clap(short, long)]
+    pub template: String,
+
+    /// Include archived items
+    #[clap(long)]
+    pub include_archived: bool,
+
+    /// Output file. If not specified, the output is written to stdout
+    #[clap(short, long)]
+    pub out: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct IcsOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Date field of the events
+    #[clap(short, long)]
+    pub field: String,
+
+    /// Field for the event descriptions
+    #[clap(short, long)]
+    pub description: Option<String>,
+
+    /// Export to-dos (with a due date) instead of events
+    #[clap(long)]
+    pub todo: bool,
+
+    /// Output file. If not specified, the calendar is written to stdout
+    #[clap(short, long)]
+    pub out: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct AgendaOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Date field
+    #[clap(short, long)]
+    pub field: String,
+
+    /// First day: YYYY-MM-DD, today, +Nd, a weekday, ...
+    #[clap(long, default_value = "today")]
+    pub from: String,
+
+    /// Last day: YYYY-MM-DD, today, +Nd, a weekday, ...
+    #[clap(long, default_value = "+14d")]
+    pub to: String,
+
+    /// Also show overdue items dated before the first day
+    #[clap(long)]
+    pub overdue: bool,
+
+    /// Only show items assigned to you (in any persons field)
+    #[clap(long)]
+    pub mine: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct MyItemsOpt {
+    /// Show items in all workspaces
+    #[clap(long)]
+    pub all_workspaces: bool,
+
+    /// Name of the due date field. If a list has no date field with this name,
+    /// its first date field with "due" in the name is used
+    #[clap(long, default_value = "Due")]
+    pub due_field: String,
+
+    /// Output format
+    #[clap(long, arg_enum, default_value = "text")]
+    pub output: OutputFormat,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct DueOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Due date field. If not specified, the date field named "Due", or else the
+    /// first date field with "due" in its name
+    #[clap(short, long)]
+    pub field: Option<String>,
+
+    /// Items due within this time from today are due soon, e.g., "7d"
+    #[clap(long, parse(try_from_str=parse_duration), default_value = "7d")]
+    pub within: Duration,
+
+    /// Only include items with this person (display name) in a persons field
+    #[clap(short, long)]
+    pub assignee: Option<String>,
+
+    /// Also show items that aren't due soon
+    #[clap(long)]
+    pub all: bool,
+
+    /// Output format. json includes a "message" for each item, for `notify`
+    #[clap(long, arg_enum, default_value = "text")]
+    pub output: OutputFormat,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct BoardOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Categories field whose choices are the columns
+    #[clap(short, long)]
+    pub by: String,
+
+    /// Width of the board, in characters (default: $COLUMNS, or 120)
+    #[clap(long)]
+    pub width: Option<usize>,
+
+    /// Don't show columns without items
+    #[clap(long)]
+    pub hide_empty: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct StatsOpt {
+    /// List name or id. If not specified, all active lists in the workspace are counted
+    #[clap(short, long)]
+    pub list: Option<String>,
+
+    /// Count items created and updated in this many days
+    #[clap(long, default_value = "7")]
+    pub days: u32,
+
+    /// Output format
+    #[clap(long, arg_enum, default_value = "text")]
+    pub output: OutputFormat,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct UrlOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Item id or uuid. Without it, the link is to the list
+    #[clap(short, long)]
+    pub item: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct ChecklistOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Item id or uuid
+    #[clap(short, long)]
+    pub item: String,
+
+    /// Checklist name or uuid. Required if the item has more than one checklist
+    #[clap(short = 'f', long = "field")]
+    pub checklist: Option<String>,
+
+    /// Add an unchecked entry with this text. May be repeated
+    #[clap(long, number_of_values = 1)]
+    pub add: Vec<String>,
+
+    /// Remove the entry with this text. May be repeated
+    #[clap(long, number_of_values = 1)]
+    pub remove: Vec<String>,
+
+    /// Check the entry with this text. May be repeated
+    #[clap(long, number_of_values = 1)]
+    pub check: Vec<String>,
+
+    /// Uncheck the entry with this text. May be repeated
+    #[clap(long, number_of_values = 1)]
+    pub uncheck: Vec<String>,
+
+    /// Toggle the entry with this text. May be repeated
+    #[clap(long, number_of_values = 1)]
+    pub toggle: Vec<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct AttachOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Item id or uuid
+    #[clap(short, long)]
+    pub item: String,
+
+    /// File to upload
+    #[clap(short = 'F', long)]
+    pub file: String,
+
+    /// Files field. Required if the list has more than one files field
+    #[clap(short, long)]
+    pub field: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct FilesOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Item id or uuid
+    #[clap(short, long)]
+    pub item: String,
+
+    /// Only show files in this field
+    #[clap(short, long)]
+    pub field: Option<String>,
+
+    /// Download the files into this folder, with their original names
+    #[clap(long)]
+    pub download: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct CommentOpt {
+    /// List name or id
+    #[clap(short, long)]
+    list: String,
+
+    /// Item id or uuid
+    #[clap(short, long)]
+    item: String,
+
+    // User display-name (must be valid user)
+    //#[clap(short, long)]
+    //user: String,
+    /// Comment
+    #[clap(short, long)]
+    comment: String,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct CreateOpt {
+    /// List name or id
+    #[clap(short, long)]
+    list: String,
+
+    /// -F field=value -F field=value ... Field names are case-sensitive.
+    #[clap(short='F', parse(try_from_str=parse_key_val), number_of_values = 1)]
+    fields: Vec<(String, String)>,
+
+    /// Read field values from a json file (or stdin, if "-") containing an object
+    /// keyed by field name. May be combined with -F.
+    #[clap(long)]
+    json: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct DeleteItemOpt {
+    /// List name or id
+    #[clap(short, long)]
+    list: String,
+
+    /// Item id or uuid. May be repeated to delete several items.
+    #[clap(short, long, required = true, number_of_values = 1)]
+    item: Vec<String>,
+
+    /// Delete without asking for confirmation
+    #[clap(short, long)]
+    yes: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct CopyItemOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Item id or uuid
+    #[clap(short, long)]
+    pub item: String,
+
+    /// Text added to the title of the copy
+    #[clap(long, default_value = " (copy)")]
+    pub title_suffix: String,
+
+    /// Copy the item's comments
+    #[clap(long)]
+    pub with_comments: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct MoveItemOpt {
+    /// Source list name or id
+    #[clap(short, long)]
+    pub from: String,
+
+    /// Item id or uuid
+    #[clap(short, long)]
+    pub item: String,
+
+    /// Destination list name or id
+    #[clap(short, long)]
+    pub to: String,
+
+    /// --map "Old Field=New Field" ... Copies a field's value to a field with another name.
+    /// Other fields are copied to the field with the same name and type, if there is one.
+    #[clap(short, long, parse(try_from_str=parse_key_val), number_of_values = 1)]
+    pub map: Vec<(String, String)>,
+
+    /// Delete the source item after copying it
+    #[clap(long)]
+    pub delete: bool,
+
+    /// Archive (deprecate) the source item after copying it
+    #[clap(long, conflicts_with = "delete")]
+    pub deprecate: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct SyncListsOpt {
+    /// Source list name or id. May be repeated
+    #[clap(short, long, number_of_values = 1, required = true)]
+    pub from: Vec<String>,
+
+    /// Destination list name or id
+    #[clap(short, long)]
+    pub to: String,
+
+    /// Key matching source items to destination items: "Source=Dest", where Source
+    /// is a field, or uuid, id, or name, and Dest is the destination field that
+    /// stores it; or a field name, if it's the same in both lists
+    #[clap(short, long)]
+    pub key: String,
+
+    /// --map "Source Field=Dest Field" ... Copies a field's value to a field with another
+    /// name. Other fields are copied to the field with the same name and type, if there is one.
+    #[clap(short, long, parse(try_from_str=parse_key_val), number_of_values = 1)]
+    pub map: Vec<(String, String)>,
+
+    /// Only copy items where Field=Value. Value may be the display value or id.
+    /// May be repeated; items must match all filters.
+    #[clap(long, parse(try_from_str=parse_key_val), number_of_values = 1)]
+    pub filter: Vec<(String, String)>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct CreateListOpt {
+    /// List name
+    #[clap(short, long)]
+    name: String,
+
+    /// List description
+    #[clap(short, long)]
+    description: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct CloneListOpt {
+    /// Source list name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Name of the new list
+    #[clap(short, long)]
+    pub name: String,
+
+    /// Workspace for the new list. Defaults to the source list's workspace.
+    #[clap(long)]
+    pub to_workspace: Option<String>,
+
+    /// Copy the list's items
+    #[clap(long)]
+    pub with_items: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct DeleteListOpt {
+    /// List name or id
+    #[clap(short, long)]
+    list: String,
+
+    /// Don't ask for confirmation
+    #[clap(short, long)]
+    yes: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct RestoreItemOpt {
+    /// Backup folder containing the list's items file
+    #[clap(long)]
+    pub from: String,
+
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Item uuid or id (as saved in the backup)
+    #[clap(short, long)]
+    pub item: String,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct ImportOpt {
+    /// Import source
+    #[clap(subcommand)]
+    pub source: ImportSource,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) enum ImportSource {
+    /// Import items from a csv file into a list
+    Csv(CsvImportOpt),
+
+    /// Import a Trello board (json export) into new lists
+    Trello(TrelloOpt),
+
+    /// Import the databases of a Notion export (Markdown & CSV) into new lists
+    Notion(NotionOpt),
+
+    /// Import Jira issues (csv or json export) into a new list
+    Jira(JiraOpt),
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct JiraOpt {
+    /// Jira export: a csv file, or a json file of issues from the REST API search
+    #[clap(short = 'F', long)]
+    pub file: String,
+
+    /// Name of the new list
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Field for the issue key (e.g., PROJ-123)
+    #[clap(long, default_value = "Issue key")]
+    pub key_field: String,
+
+    /// Field for the summary. Defaults to the list's title field
+    #[clap(long)]
+    pub summary_field: Option<String>,
+
+    /// Field for the description
+    #[clap(long, default_value = "Description")]
+    pub description_field: String,
+
+    /// Categories field for the status
+    #[clap(long, default_value = "Status")]
+    pub status_field: String,
+
+    /// Persons field for the assignee
+    #[clap(long, default_value = "Assignee")]
+    pub assignee_field: String,
+
+    /// Categories field for the labels
+    #[clap(long, default_value = "Labels")]
+    pub labels_field: String,
+
+    /// --member "name=email" ... Email of the Zenkit user for a Jira assignee
+    /// (csv exports don't include emails). Assignees without one are matched by name.
+    #[clap(short, long, parse(try_from_str=parse_key_val), number_of_values = 1)]
+    pub member: Vec<(String, String)>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct NotionOpt {
+    /// Folder of the unzipped Notion export. Each database (csv file) in the folder
+    /// and its subfolders is imported into a new list.
+    #[clap(short, long)]
+    pub dir: String,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct TrelloOpt {
+    /// Trello board json file (Menu > More > Print and export > Export as JSON)
+    #[clap(short = 'F', long)]
+    pub file: String,
+
+    /// Name of the new list. Defaults to the board name
+    #[clap(short, long, conflicts_with = "split-lists")]
+    pub list: Option<String>,
+
+    /// Create a list for each Trello list, instead of one list with a field for them
+    #[clap(long)]
+    pub split_lists: bool,
+
+    /// Name of the categories field with the Trello list of each card
+    #[clap(long, default_value = "Stage")]
+    pub stage_field: String,
+
+    /// --member "username=email" ... Email of the Zenkit user for a Trello member
+    /// (Trello exports don't include emails). Members without one are matched by name.
+    #[clap(short, long, parse(try_from_str=parse_key_val), number_of_values = 1)]
+    pub member: Vec<(String, String)>,
+
+    /// Also import archived cards and lists
+    #[clap(long)]
+    pub include_archived: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct CsvImportOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Csv file to import. The first row must contain column headers.
+    #[clap(short = 'F', long)]
+    pub file: String,
+
+    /// --map "Column=Field" ... Maps a csv column to a list field.
+    /// Columns without a mapping are matched to fields by name (case-sensitive).
+    #[clap(short, long, parse(try_from_str=parse_key_val), number_of_values = 1)]
+    pub map: Vec<(String, String)>,
+
+    /// Number of rows to create concurrently, and between progress updates
+    #[clap(long, default_value = "50")]
+    pub batch_size: usize,
+
+    /// Write rejected rows, with the reason, to this csv file
+    #[clap(long)]
+    pub errors: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct WatchOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Polling interval, e.g., "30s", "5m"
+    #[clap(long, default_value = "30s", parse(try_from_str=parse_duration))]
+    pub interval: Duration,
+
+    /// Run command (with sh -c) for each change, with the json event on stdin.
+    /// If not set, events are printed to stdout.
+    #[clap(short, long)]
+    pub exec: Option<String>,
+
+    /// Event format: jsonl (one line per event) or json (pretty-printed)
+    #[clap(long, arg_enum, default_value = "jsonl")]
+    pub output: JsonFormat,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct BackupCmdOpt {
+    /// Output folder where json files will be created (required, except for `prune`
+    /// and `verify`)
+    #[clap(short, long)]
+    pub output: Option<String>,
+
+    #[clap(flatten)]
+    pub backup: BackupOpt,
+
+    #[clap(subcommand)]
+    pub action: Option<BackupAction>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) enum BackupAction {
+    /// Delete old backups in a backup folder, keeping those selected by a retention policy.
+    /// Only summaries, incremental items files, and archives are deleted: the list,
+    /// fields, and items files of full backups are shared by all backups in the folder.
+    Prune(BackupPruneOpt),
+
+    /// Check a backup folder for missing or corrupt files
+    Verify(BackupVerifyOpt),
+}
+
+#[derive(Clap, Clone, PartialEq, Debug)]
+pub(crate) struct BackupOpt {
+    /// Output folder, from `--output`
+    #[clap(skip)]
+    pub output: String,
+
+    /// List - backup single list. If not specified, backs up all lists
+    #[clap(short, long)]
+    pub list: Option<String>,
+
+    /// Backup all workspaces, each to a subfolder named with the workspace uuid
+    #[clap(long, conflicts_with = "list")]
+    pub all_workspaces: bool,
+
+    /// Include archived items
+    #[clap[long]]
+    pub include_archived: bool,
+
+    /// Only backup items modified after this time, given as milliseconds since the epoch
+    /// (as in summary file names), a date (YYYY-MM-DD), or RFC 3339 date-time.
+    /// Modified items are written to <uuid>_items_since_<since>.json.
+    /// The api can't filter items by modification time, so all items are still
+    /// downloaded; only the files written are smaller.
+    #[clap(long, parse(try_from_str=parse_timestamp))]
+    pub since: Option<u64>,
+
+    /// Only backup items modified since the most recent backup in the output folder.
+    /// All items are still downloaded (see --since)
+    #[clap(long, conflicts_with = "since")]
+    pub incremental: bool,
+
+    /// Continue an unfinished backup in the output folder, from its checkpoint file.
+    /// The other options should be the same as for the unfinished backup.
+    #[clap(long, conflicts_with_all = &["since", "incremental"])]
+    pub resume: bool,
+
+    /// Include item comments, saved to <uuid>_comments.json
+    #[clap(long)]
+    pub with_comments: bool,
+
+    /// Download file attachments to <output>/files/<item-uuid>/
+    #[clap(long)]
+    pub with_files: bool,
+
+    /// Write the backup, including summary, to a single compressed archive
+    /// backup_<tstamp>.<ext> in the output folder. Format is "tar.gz" or "zip".
+    #[clap(long)]
+    pub archive: Option<ArchiveFormat>,
+
+    /// Number of lists to backup concurrently
+    #[clap(short, long, default_value = "4")]
+    pub jobs: usize,
+
+    /// Number of pages of items to fetch concurrently, for each list
+    #[clap(long, default_value = "4")]
+    pub page_jobs: usize,
+
+    /// Upload the backup to S3-compatible storage: s3://bucket/prefix.
+    /// Each list's files are written to the output folder, then uploaded and removed
+    /// when the list is complete. Large files are uploaded in parts, so they aren't
+    /// read into memory.
+    #[clap(long)]
+    pub dest: Option<S3Dest>,
+
+    /// Write json files pretty-printed and in a stable order, and commit the changes
+    /// to a git repository in the output folder (created if needed)
+    #[clap(long, conflicts_with_all = &["archive", "dest"])]
+    pub git: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct BackupPruneOpt {
+    /// Backup folder
+    #[clap(short, long)]
+    pub output: String,
+
+    /// Keep the most recent N backups
+    #[clap(long, default_value = "0")]
+    pub keep_last: usize,
+
+    /// Keep the most recent backup of each of the last N days with backups
+    #[clap(long, default_value = "0")]
+    pub keep_daily: usize,
+
+    /// Keep the most recent backup of each of the last N weeks with backups
+    #[clap(long, default_value = "0")]
+    pub keep_weekly: usize,
+
+    /// Keep the most recent backup of each of the last N months with backups
+    #[clap(long, default_value = "0")]
+    pub keep_monthly: usize,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct BackupVerifyOpt {
+    /// Backup folder
+    #[clap(short, long)]
+    pub dir: String,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+enum Sub {
+    /// Show all workspaces and lists
+    Workspaces,
+
+    /// Show users in workspace, with their roles
+    Users,
+
+    /// Invite a user to the workspace by email
+    InviteUser(InviteUserOpt),
+
+    /// Remove a user from the workspace
+    RemoveUser(RemoveUserOpt),
+
+    /// Show the user authenticated by the api token
+    Whoami,
+
+    /// Clear the metadata cache, and reload the workspace's lists and fields
+    RefreshCache,
+
+    /// Show lists in workspace
+    Lists,
+
+    /// Show items in list
+    #[clap(alias = "list")]
+    Items(ItemsOpt),
+
+    /// Show fields for a list
+    Fields(ListOpt),
+
+    /// Describe field of a list (detail view)
+    Field(FieldOpt),
+
+    /// Describe a list item (detail view)
+    Item(ItemOpt),
+
+    /// Show the value of an item's field
+    Get(GetOpt),
+
+    /// Print the link to a list or item in the Zenkit web app
+    Url(UrlOpt),
+
+    /// Open a list or item in the Zenkit web app, in the default browser
+    Open(UrlOpt),
+
+    /// Show choices for a category field
+    Choices(FieldOpt),
+
+    /// Add a choice to a category field
+    AddChoice(AddChoiceOpt),
+
+    /// Rename a choice of a category field, and optionally change its color
+    RenameChoice(RenameChoiceOpt),
+
+    /// Delete a choice from a category field, after showing how many items use it
+    DeleteChoice(DeleteChoiceOpt),
+
+    /// Add a field to a list
+    CreateField(CreateFieldOpt),
+
+    /// Update a list's fields to match a schema file, creating missing fields and choices
+    ApplySchema(ApplySchemaOpt),
+
+    /// Write a list's field definitions as a schema file
+    ExportSchema(ExportSchemaOpt),
+
+    /// Set field value
+    Set(SetValueOpt),
+
+    /// Set a field value on all items matching a filter
+    BulkSet(BulkSetOpt),
+
+    /// Archive (deprecate) all items matching a filter
+    Archive(ArchiveOpt),
+
+    /// Restore the values of an item's fields before your most recent changes
+    Undo(UndoOpt),
+
+    /// Create new list item
+    Create(CreateOpt),
+
+    /// Delete list item(s)
+    DeleteItem(DeleteItemOpt),
+
+    /// Duplicate an item in the same list, optionally with its comments
+    CopyItem(CopyItemOpt),
+
+    /// Copy an item to another list, mapping fields by name, and optionally
+    /// delete or archive the original
+    MoveItem(MoveItemOpt),
+
+    /// Copy matching items from one or more lists to another, updating the items
+    /// copied before (matched by a key field)
+    SyncLists(SyncListsOpt),
+
+    /// Create a new list in the workspace
+    CreateList(CreateListOpt),
+
+    /// Copy a list's fields, and optionally its items, to a new list
+    CloneList(CloneListOpt),
+
+    /// Deprecate (archive) a list
+    DeprecateList(DeleteListOpt),
+
+    /// Permanently delete a list and all its items
+    DeleteList(DeleteListOpt),
+
+    /// Restore an item from a backup, recreating it if it was deleted
+    RestoreItem(RestoreItemOpt),
+
+    /// Import list items from a csv file, or lists from other tools
+    Import(ImportOpt),
+
+    /// Find items containing text, in a list or the whole workspace
+    Search(SearchOpt),
+
+    /// Show item counts of a list or workspace: per choice, per person, and recent changes
+    Stats(StatsOpt),
+
+    /// Show a list as a board, with a column of items for each choice of a categories field
+    Board(BoardOpt),
+
+    /// Show upcoming items by day, from a date field
+    Agenda(AgendaOpt),
+
+    /// Show items assigned to you in all lists of the workspace, sorted by due date
+    MyItems(MyItemsOpt),
+
+    /// Report overdue and soon-due items in a list
+    Due(DueOpt),
+
+    /// Export list items to another format
+    Export(ExportOpt),
+
+    /// Copy list items to a local database, updating it on each run
+    Mirror(MirrorOpt),
+
+    /// Two-way sync of a list with a local json, toml, or Markdown file
+    Sync(SyncOpt),
+
+    /// Select list items with a SQL-like query
+    Query(QueryOpt),
+
+    /// Show an item's checklist, or check, uncheck, add, or remove its entries
+    Checklist(ChecklistOpt),
+
+    /// Upload a file and attach it to a list item
+    Attach(AttachOpt),
+
+    /// Show files attached to a list item, and optionally download them
+    Files(FilesOpt),
+
+    /// Add comment to list item
+    Comment(CommentOpt),
+
+    /// Show comments of a list item, oldest first
+    Comments(CommentsOpt),
+
+    /// Show activities (changes and comments) of the workspace, a list, or an item,
+    /// newest first
+    Activities(ActivitiesOpt),
+
+    /// Show the changes to an item's fields, oldest first
+    History(HistoryOpt),
+
+    /// Show your notifications, newest first
+    Notifications(NotificationsOpt),
+
+    /// Mark notifications as read
+    MarkRead(MarkReadOpt),
+
+    /// Change the text of a comment
+    EditComment(EditCommentOpt),
+
+    /// Delete a comment
+    DeleteComment(DeleteCommentOpt),
+
+    /// Add a webhook
+    #[clap(alias = "new-webhook")]
+    Webhook(WebhookOpt),
+
+    /// Change a webhook's url, trigger type, or restrictions, keeping its id
+    UpdateWebhook(UpdateWebhookOpt),
+
+    /// List webhooks, optionally filtered by list, type, or url
+    ListWebhooks(ListWebhooksOpt),
+
+    /// Trigger a webhook, or post a sample event to its url, to test the endpoint
+    TestWebhook(TestWebhookOpt),
+
+    /// Delete webhook
+    DeleteWebhook(DelWebhookOpt),
+
+    /// Receive webhook events and print them to stdout as json lines
+    Listen(Box<ListenOpt>),
+
+    /// Handle events stored by `listen --store` again, as if they were received
+    Replay(ReplayOpt),
+
+    /// Poll list for changes and print created/updated/deleted items as json lines
+    Watch(WatchOpt),
+
+    /// Post a message, or events or query results read from stdin, to a Slack channel
+    Notify(NotifyOpt),
+
+    /// Backup, or with `prune`, delete old backups, or with `verify`, check a backup
+    Backup(BackupCmdOpt),
+
+    /// Compare two backups, showing lists, fields, and items added, removed, or changed
+    Diff(DiffOpt),
+
+    /// Interactive session: run commands against the workspace, with history and
+    /// tab-completion of list and field names
+    Shell,
+
+    /// Run the commands in a file, one per line, in a single process
+    Run(RunOpt),
+}
+
+impl Sub {
+    /// Set by the command's own `--dry-run` flag, which enables dry-run mode like the
+    /// global flag
+    fn dry_run(&self) -> bool {
+        match self {
+            Sub::ApplySchema(opt) => opt.dry_run,
+            Sub::BulkSet(opt) => opt.dry_run,
+            Sub::Archive(opt) => opt.dry_run,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct ListOpt {
+    /// List name or id
+    #[clap(short, long)]
+    list: String,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct ItemsOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Maximum number of items to show
+    #[clap(long, conflicts_with = "all")]
+    pub limit: Option<usize>,
+
+    /// Number of items to skip
+    #[clap(long, default_value = "0")]
+    pub skip: usize,
+
+    /// Show all items, fetching them in batches
+    #[clap(long)]
+    pub all: bool,
+
+    /// Sort by a column: id, uuid, name, or a field. Append ":desc" for descending order
+    #[clap(long)]
+    pub sort: Option<String>,
+
+    /// Comma-separated columns to show: id, uuid, name, or field names (default "id,uuid,name")
+    #[clap(long)]
+    pub columns: Option<String>,
+
+    /// Group items by the values of a column (usually a categories field)
+    #[clap(long)]
+    pub group_by: Option<String>,
+
+    /// Only show the number of items (in each group, with --group-by)
+    #[clap(long)]
+    pub count: bool,
+
+    /// Output format. jsonl prints one item per line
+    #[clap(long, arg_enum, default_value = "text")]
+    pub output: ItemsFormat,
+
+    /// Render the items with a Handlebars template file, instead of --output
+    #[clap(short, long, conflicts_with_all = &["group-by", "count", "columns"])]
+    pub template: Option<String>,
+}
+
+#[derive(Clap, Debug, PartialEq)]
+pub(crate) enum ItemsFormat {
+    Text,
+    Json,
+    Jsonl,
+}
+
+/// Format of streamed json output
+#[derive(Clap, Debug, PartialEq, Clone, Copy)]
+pub(crate) enum JsonFormat {
+    /// Pretty-printed json
+    Json,
+    /// One json value per line
+    Jsonl,
+}
+
+impl JsonFormat {
+    /// Print the value to stdout in this format
+    pub(crate) fn print<T: serde::Serialize>(&self, value: &T) -> Result<(), Error> {
+        println!("{}", self.to_json(value)?);
+        Ok(())
+    }
+
+    /// The value as json in this format
+    pub(crate) fn to_json<T: serde::Serialize>(self, value: &T) -> Result<String, Error> {
+        Ok(match self {
+            JsonFormat::Json => serde_json::to_string_pretty(value)?,
+            JsonFormat::Jsonl => serde_json::to_string(value)?,
+        })
+    }
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct ItemOpt {
+    /// List name or id
+    #[clap(short, long)]
+    list: String,
+
+    /// Item id (integer) or uuid
+    #[clap(short, long)]
+    item: String,
+
+    /// Show field names and display values (choice names, person names,
+    /// and referenced item titles), instead of the raw item
+    #[clap(short, long)]
+    resolve: bool,
+
+    /// Render the item with a Handlebars template file
+    #[clap(short, long, conflicts_with = "resolve")]
+    template: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct GetOpt {
+    /// List name or id
+    #[clap(short, long)]
+    list: String,
+
+    /// Item id (integer) or uuid
+    #[clap(short, long)]
+    item: String,
+
+    /// Field name or id
+    #[clap(short, long)]
+    field: String,
+
+    /// Print the value as stored by Zenkit (json), instead of the display value
+    #[clap(long)]
+    raw: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct FieldOpt {
+    /// List name or id
+    #[clap(short, long)]
+    list: String,
+
+    /// Field id or name
+    #[clap(short, long)]
+    field: String,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct AddChoiceOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Field name or id
+    #[clap(short, long)]
+    pub field: String,
+
+    /// Choice name
+    #[clap(short, long)]
+    pub name: String,
+
+    /// Color: #rrggbb, or red, orange, yellow, green, teal, blue, purple, pink, brown, or gray
+    #[clap(short, long)]
+    pub color: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct RenameChoiceOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Field name or id
+    #[clap(short, long)]
+    pub field: String,
+
+    /// Current choice name or id
+    #[clap(short, long)]
+    pub name: String,
+
+    /// New choice name
+    #[clap(short, long)]
+    pub to: String,
+
+    /// New color (see `add-choice`)
+    #[clap(short, long)]
+    pub color: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct DeleteChoiceOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Field name or id
+    #[clap(short, long)]
+    pub field: String,
+
+    /// Choice name or id
+    #[clap(short, long)]
+    pub name: String,
+
+    /// Delete without asking for confirmation
+    #[clap(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct InviteUserOpt {
+    /// Email address of the user to invite
+    #[clap(short, long)]
+    pub email: String,
+
+    /// Role in the workspace
+    #[clap(short, long, arg_enum, default_value = "editor")]
+    pub role: UserRole,
+}
+
+/// Role of a user in a workspace
+#[derive(Clap, Debug, PartialEq, Clone, Copy)]
+pub(crate) enum UserRole {
+    Admin,
+    Editor,
+    Commenter,
+    Reader,
+}
+
+impl UserRole {
+    /// The role's id in the Zenkit api
+    pub(crate) fn role_id(&self) -> &'static str {
+        match self {
+            UserRole::Admin => "admin",
+            UserRole::Editor => "editor",
+            UserRole::Commenter => "commenter",
+            UserRole::Reader => "reader",
+        }
+    }
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct RemoveUserOpt {
+    /// User display name, email, id, or uuid
+    #[clap(short, long)]
+    pub user: String,
+
+    /// Remove without asking for confirmation
+    #[clap(short, long)]
+    pub yes: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct CreateFieldOpt {
+    /// List name or id
+    #[clap(short, long)]
+    list: String,
+
+    /// Field name
+    #[clap(short, long)]
+    name: String,
+
+    /// Field type
+    #[clap(short = 't', long = "type", arg_enum)]
+    field_type: FieldType,
+
+    /// Choice for a categories field. May be repeated.
+    #[clap(short, long, number_of_values = 1)]
+    choice: Vec<String>,
+
+    /// Allow multiple values (categories, persons, and references fields)
+    #[clap(short, long)]
+    multiple: bool,
+
+    /// Referenced list name or id, for a references field
+    #[clap(long)]
+    target_list: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct ApplySchemaOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Schema file (json)
+    #[clap(short = 'F', long)]
+    pub file: String,
+
+    /// Report differences without changing the list (same as the global `--dry-run`)
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct ExportSchemaOpt {
+    /// List name or id
+    #[clap(short, long)]
+    pub list: String,
+
+    /// Output file. If not specified, the schema is written to stdout.
+    #[clap(short, long)]
+    pub output: Option<String>,
+}
+
+#[derive(Clap, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clap, Clone, Copy, Debug, PartialEq)]
+pub(crate) enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct DiffOpt {
+    /// Earlier backup: a backup folder, or a summary file in a backup folder
+    pub from: String,
+
+    /// Later backup: a backup folder, or a summary file in a backup folder
+    pub to: String,
+
+    /// Output format
+    #[clap(long, arg_enum, default_value = "text")]
+    pub output: OutputFormat,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct RunOpt {
+    /// File with one command per line, as entered in `zk shell`, or "-" for stdin
+    #[clap(short = 'F', long)]
+    pub file: String,
+
+    /// Stop at the first command that fails (the default)
+    #[clap(long, conflicts_with = "continue-on-error")]
+    pub stop_on_error: bool,
+
+    /// Run the remaining commands after a command fails
+    #[clap(long = "continue")]
+    pub continue_on_error: bool,
+
+    /// If a command fails, revert the changes made by the earlier `set`, `create`,
+    /// and `bulk-set` commands. Other commands that change Zenkit aren't allowed
+    #[clap(long, conflicts_with = "continue-on-error")]
+    pub transactional: bool,
+}
+
+#[derive(Clap, Debug, PartialEq)]
+pub(crate) enum WebhookType {
+    Item,
+    Activity,
+    Notification,
+    System,
+    Comment,
+    Field,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct WebhookOpt {
+    /// Webhook trigger type
+    #[clap(short, long = "type", arg_enum)]
+    trigger_type: WebhookType,
+
+    /// Server url
+    #[clap(short, long)]
+    url: String,
+
+    #[clap(flatten)]
+    scope: WebhookScopeOpt,
+}
+
+/// Webhook restrictions to workspace, list, item, or field
+#[derive(Clap, PartialEq, Debug)]
+struct WebhookScopeOpt {
+    /// List id to restrict webhook to this list
+    #[clap(short, long)]
+    list: Option<String>,
+
+    /// Item id to restrict webhook to this item
+    #[clap(short, long)]
+    item: Option<String>,
+
+    /// Field id to restrict webhook to this field
+    #[clap(short, long)]
+    field: Option<String>,
+
+    /// Locale
+    #[clap(long, default_value = "en")]
+    locale: String,
+
+    /// Limit to the workspace
+    #[clap(short, long)]
+    workspace: bool,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct ListenOpt {
+    /// Address to listen on
+    #[clap(short, long, default_value = "127.0.0.1")]
+    bind: String,
+
+    /// Port to listen on
+    #[clap(short, long, default_value = "8080")]
+    port: u16,
+
+    /// Public url of this listener. If set, a webhook is registered for this url,
+    /// and deleted when the listener exits (Ctrl-C).
+    #[clap(short, long)]
+    url: Option<String>,
+
+    /// Id of an existing webhook that sends events to this listener (alternative to --url)
+    #[clap(short = 'W', long, conflicts_with = "url")]
+    webhook: Option<u64>,
+
+    /// Webhook trigger type, for a webhook registered with --url
+    #[clap(short, long = "type", arg_enum, default_value = "item")]
+    trigger_type: WebhookType,
+
+    #[clap(flatten)]
+    scope: WebhookScopeOpt,
+
+    #[clap(flatten)]
+    handler: EventHandlerOpt,
+
+    /// Reject requests that aren't signed with the secret: the X-Signature-256 header must be
+    /// "sha256=" and the hex HMAC-SHA256 of the body. Zenkit doesn't sign webhook requests,
+    /// so this is for events forwarded by a relay that signs them.
+    /// The secret may also be set in the environment as ZENKIT_WEBHOOK_SECRET
+    /// (ignored with --url).
+    #[clap(long, conflicts_with = "url")]
+    secret: Option<String>,
+
+    /// Read the secret (see --secret) from the file
+    #[clap(long, conflicts_with_all = &["url", "secret"])]
+    secret_file: Option<String>,
+
+    /// Append each received event to the file as a json line, before handling it.
+    /// Stored events can be handled again with `replay`.
+    #[clap(short, long)]
+    store: Option<String>,
+}
+
+/// How received (or replayed) webhook events are handled
+#[derive(Clap, PartialEq, Debug)]
+struct EventHandlerOpt {
+    /// Event format: jsonl (one line per event) or json (pretty-printed)
+    #[clap(long, arg_enum, default_value = "jsonl")]
+    output: JsonFormat,
+
+    /// Run command (with sh -c) for each event, with the json event on stdin and
+    /// event metadata in ZK_* environment variables. If not set, events are printed to stdout.
+    #[clap(short, long)]
+    exec: Option<String>,
+
+    /// Maximum number of commands (--exec) running at once
+    #[clap(short, long, default_value = "4")]
+    jobs: usize,
+
+    /// Publish each event to a broker: nats://[user:pass@]host[:port]/subject
+    /// or redis://[:pass@]host[:port]/channel (tls:// or rediss:// for TLS). If set
+    /// (without --exec), events aren't printed.
+    #[clap(long)]
+    publish: Option<PublishUrl>,
+
+    /// Post a message for each event to a Slack (or Mattermost) incoming webhook url.
+    /// If set (without --exec), events aren't printed.
+    #[clap(long)]
+    slack_url: Option<String>,
+
+    /// Slack message text, with {key} replaced by the event's value for key,
+    /// e.g., "{displayString} was updated". Default: a summary of the event
+    #[clap(long, requires = "slack-url")]
+    template: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct NotifyOpt {
+    /// Slack (or Mattermost) incoming webhook url
+    #[clap(long)]
+    pub slack_url: String,
+
+    /// Message to post. If not set, a message is posted for each json value
+    /// read from stdin (e.g., output of `watch`, or `query --output json`)
+    #[clap(long)]
+    pub text: Option<String>,
+
+    /// Message text for each json value, with {key} replaced by the value's value for key.
+    /// For an array, the text for each element is on its own line
+    #[clap(long, conflicts_with = "text")]
+    pub template: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct ReplayOpt {
+    /// File of events stored by `listen --store`
+    file: String,
+
+    #[clap(flatten)]
+    handler: EventHandlerOpt,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct UpdateWebhookOpt {
+    /// Webhook id
+    #[clap(short = 'W', long)]
+    webhook: u64,
+
+    /// New server url
+    #[clap(short, long)]
+    url: Option<String>,
+
+    /// New trigger type
+    #[clap(short, long = "type", arg_enum)]
+    trigger_type: Option<WebhookType>,
+
+    /// New restrictions. If any of list, item, field, or workspace is used,
+    /// they replace the webhook's restrictions.
+    #[clap(flatten)]
+    scope: WebhookScopeOpt,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct ListWebhooksOpt {
+    /// Only webhooks for this list (name or id)
+    #[clap(short, long)]
+    pub list: Option<String>,
+
+    /// Only webhooks with this trigger type
+    #[clap(short, long = "type", arg_enum)]
+    pub trigger_type: Option<WebhookType>,
+
+    /// Only webhooks whose url contains this text
+    #[clap(long)]
+    pub url_contains: Option<String>,
+
+    /// Output format
+    #[clap(long, arg_enum, default_value = "text")]
+    pub output: OutputFormat,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+pub(crate) struct TestWebhookOpt {
+    /// Webhook id
+    #[clap(short = 'W', long)]
+    pub webhook: Option<u64>,
+
+    /// Url of a registered webhook, or with --simulate, any url
+    #[clap(short, long, conflicts_with = "webhook")]
+    pub url: Option<String>,
+
+    /// Post a sample event to the url, and check the response,
+    /// instead of making a change in Zenkit that triggers the webhook
+    #[clap(long)]
+    pub simulate: bool,
+
+    /// List in which to make the change, if the webhook isn't restricted to a list
+    #[clap(short, long)]
+    pub list: Option<String>,
+
+    /// Event type to simulate, for a url that isn't registered as a webhook
+    #[clap(short, long = "type", arg_enum, default_value = "item")]
+    pub trigger_type: WebhookType,
+
+    /// Sign the sample event with the secret, as checked by `listen --secret`.
+    /// The secret may also be set in the environment as ZENKIT_WEBHOOK_SECRET.
+    #[clap(long, requires = "simulate")]
+    pub secret: Option<String>,
+
+    /// Read the secret (see --secret) from the file
+    #[clap(long, requires = "simulate", conflicts_with = "secret")]
+    pub secret_file: Option<String>,
+}
+
+#[derive(Clap, PartialEq, Debug)]
+struct DelWebhookOpt {
+    /// Webhook id
+    #[clap(short = 'W', long)]
+    webhook: u64,
+}
+
+/// Zenkit command-line tool. Source and docs at https://github.com/stevelr/zenkit-cli
+/// Zenkit api token must be specified either in config file with -c option
+/// or in environment as `ZENKIT_TOKEN`.
+#[derive(Clap, PartialEq, Debug)]
+#[clap(name = env!("CARGO_BIN_NAME"), version = env!("CARGO_PKG_VERSION"))]
+struct Opt {
+    /// Configuration file  in TOML format, such as:
+    /// ```toml
+    /// [zenkit]
+    /// token = "00000"
+    /// workspace = "My Workspace"
+    ///
+    /// [profiles.work]
+    /// token = "11111"
+    /// workspace = "Work Workspace"
+    /// ```
+    config: Option<String>,
+
+    /// Workspace name, id, or uuid. Required unless set in config file or environment
+    /// variable ZENKIT_WORKSPACE
+    #[clap(short, long)]
+    workspace: Option<String>,
+
+    /// Configuration profile: use the token, workspace, and endpoint from the
+    /// `[profiles.<name>]` section of the config file. May also be set in
+    /// environment as ZENKIT_PROFILE
+    #[clap(long)]
+    profile: Option<String>,
+
+    /// Number of times to retry api requests that fail with transient errors
+    /// (rate limiting, server errors, or timeouts)
+    #[clap(long, default_value = "3")]
+    retries: u32,
+
+    /// Delay before the first retry, e.g., "500ms" or "2s". The delay doubles after
+    /// each attempt. A Retry-After header from the server takes precedence.
+    #[clap(long, default_value = "1s", parse(try_from_str=parse_duration))]
+    retry_delay: Duration,
+
+    /// Don't use or update the cache of workspace, list, and field metadata
+    #[clap(long)]
+    no_cache: bool,
+
+    /// Maximum age of cached workspace, list, and field metadata, e.g., "10m" or "1h".
+    /// The cache is in `$XDG_CACHE_HOME/zenkit-cli` (or `~/.cache/zenkit-cli`), and
+    /// is cleared when lists or fields are changed with this tool.
+    #[clap(long, default_value = "10m", parse(try_from_str=parse_duration))]
+    cache_ttl: Duration,
+
+    /// Maximum rate of api requests, across concurrent tasks, e.g., "8/s" or "300/m".
+    /// Also set with `rate_limit` in the config file.
+    #[clap(long, value_name = "RATE")]
+    rate_limit: Option<RateLimit>,
+
+    /// Timeout of each http request, e.g., "30s". Also set with `http.timeout` in the
+    /// config file.
+    #[clap(long, parse(try_from_str=parse_duration))]
+    http_timeout: Option<Duration>,
+
+    /// Proxy for http and https requests, e.g., http://proxy.example.com:3128.
+    /// Also set with `http.proxy` in the config file.
+    #[clap(long)]
+    proxy: Option<String>,
+
+    /// PEM file with CA certificates to trust, in addition to the system's, e.g., for a
+    /// TLS-intercepting proxy. Also set with `http.ca_bundle` in the config file.
+    #[clap(long, value_name = "PEM_FILE")]
+    ca_bundle: Option<String>,
+
+    /// Record api requests and responses to json files in this folder, for `--replay`
+    #[clap(long, value_name = "FIXTURES_DIR", conflicts_with = "replay")]
+    record: Option<String>,
+
+    /// Serve api responses recorded with `--record` from this folder, without network
+    /// access. A token isn't required.
+    #[clap(long, value_name = "FIXTURES_DIR")]
+    replay: Option<String>,
+
+    /// Answer read-only commands (items, item, fields, field, choices, search, and stats)
+    /// from a backup folder, without using the api or a token
+    #[clap(long, value_name = "BACKUP_DIR")]
+    offline: Option<String>,
+
+    /// Print the api requests that would change data (method, path, and json body)
+    /// instead of sending them
+    #[clap(long)]
+    dry_run: bool,
+
+    /// When a list or field name isn't found, use the closest match instead of
+    /// suggesting it
+    #[clap(long)]
+    fuzzy: bool,
+
+    /// At the end of the command, print the number of api requests, bytes transferred,
+    /// latency percentiles, and retries to stderr
+    #[clap(long)]
+    stats: bool,
+
+    /// Format of `--stats`: text or json
+    #[clap(long, arg_enum, default_value = "text")]
+    stats_format: OutputFormat,
+
+    /// Colored output: auto (if stdout is a terminal and NO_COLOR isn't set), always,
+    /// or never
+    #[clap(long, arg_enum, default_value = "auto")]
+    color: ColorChoice,
+
+    /// Format of errors printed to stderr: text, or json objects with the error kind,
+    /// exit status, http status, and message
+    #[clap(long = "errors", arg_enum, default_value = "text")]
+    error_format: OutputFormat,
+
+    /// Don't show progress of long-running commands (backup, import, restore-item)
+    #[clap(long)]
+    quiet: bool,
+
+    /// Log each api request (method, path, status, and latency) to stderr.
+    /// Repeat (`-vv`) to also log request and response bodies
+    #[clap(short, long, parse(from_occurrences))]
+    verbose: u8,
+
+    /// Log format: text or json
+    #[clap(long, arg_enum, default_value = "text")]
+    log_format: OutputFormat,
+
+    /// Subcommand
+    #[clap(subcommand)]
+    cmd: Sub,
+}
+
+/// Parse "key=value" token
+fn parse_key_val<T, U>(s: &str) -> Result<(T, U), Box<dyn std::error::Error>>
+where
+    T: std::str::FromStr,
+    T::Err: std::error::Error + 'static,
+    U: std::str::FromStr,
+    U::Err: std::error::Error + 'static,
 {
     let pos = s.find('=').ok_or_else(|| {
-        Box::new(Error::Message(format!(
+        Box::new(Error::Invalid(format!(
             "invalid KEY=value: no `=` found in `{}`",
             s
         )))
@@ -324,12 +2513,192 @@ fn parse_setval(s: String) -> FieldVal {
     }
 }
 
+/// Read field values from a json object keyed by field name, from the file,
+/// or from stdin if `path` is "-". Strings are used as-is (without the `[a,b]` and
+/// `markdown::` conventions of -F), arrays are multiple values, and an object
+/// `{"value": "..", "format": "markdown"}` is formatted text (format may also be
+/// "plain" or "html"). Null values are skipped.
+fn json_values(path: &str) -> Result<Vec<(String, FieldVal)>, Error> {
+    let data = if path == "-" {
+        let mut data = String::new();
+        io::Read::read_to_string(&mut io::stdin(), &mut data)?;
+        data
+    } else {
+        fs::read_to_string(path)?
+    };
+    let obj = match serde_json::from_str(&data)
+        .map_err(|e| Error::Invalid(format!("Invalid json field values {}: {}", path, e)))?
+    {
+        serde_json::Value::Object(obj) => obj,
+        _ => {
+            return Err(Error::Invalid(
+                "json field values must be an object keyed by field name".to_string(),
+            ))
+        }
+    };
+    let mut values = Vec::new();
+    for (field, value) in obj.into_iter() {
+        let value = match value {
+            serde_json::Value::Null => continue,
+            serde_json::Value::String(s) => FieldVal::Str(s),
+            serde_json::Value::Array(arr) => FieldVal::ArrStr(
+                arr.into_iter()
+                    .map(|v| match v {
+                        serde_json::Value::String(s) => s,
+                        v => v.to_string(),
+                    })
+                    .collect(),
+            ),
+            serde_json::Value::Object(obj) => {
+                let text = obj.get("value").and_then(|v| v.as_str());
+                let format = obj
+                    .get("format")
+                    .and_then(|v| v.as_str())
+                    .and_then(|f| parse_try_text_format(f).ok());
+                match (text, format) {
+                    (Some(text), Some(format)) => FieldVal::Formatted(text.to_string(), format),
+                    _ => {
+                        return Err(Error::Invalid(format!(
+                            "Field '{}': objects must have \"value\" and \"format\" (plain, markdown, or html)",
+                            field
+                        )))
+                    }
+                }
+            }
+            v => FieldVal::Str(v.to_string()),
+        };
+        values.push((field, value));
+    }
+    Ok(values)
+}
+
+/// The user's id, uuid, display name, and email address, tab-separated
+fn user_line(user: &serde_json::Value) -> String {
+    let str_field = |key: &str| user.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+    format!(
+        "{}\t{}\t{}\t{}",
+        user.get("id").and_then(|v| v.as_u64()).unwrap_or_default(),
+        str_field("uuid"),
+        str_field("displayname"),
+        user_emails(user).first().copied().unwrap_or_default()
+    )
+}
+
+/// Update that clears the field. Multi-value fields are cleared by replacing
+/// their values with an empty list. An empty value of a number, date, or link field
+/// is sent as null (see `values::direct_value`).
+fn clear_value(field: String, element: &Element) -> (String, FieldVal, UpdateAction) {
+    match element.element_category {
+        ElementCategoryId::Categories | ElementCategoryId::Persons => {
+            (field, FieldVal::ArrID(Vec::new()), UpdateAction::Replace)
+        }
+        ElementCategoryId::References => {
+            (field, FieldVal::ArrStr(Vec::new()), UpdateAction::Replace)
+        }
+        _ => (field, FieldVal::Str(String::new()), UpdateAction::Null),
+    }
+}
+
+/// Build a webhook definition, resolving list, item, and field names to ids
+async fn new_webhook(
+    ws: &Workspace,
+    trigger_type: &WebhookType,
+    url: String,
+    scope: WebhookScopeOpt,
+) -> Result<NewWebhook, Error> {
+    let mut item_id: Option<ID> = None;
+    let mut list_id: Option<ID> = None;
+    let mut field_id: Option<ID> = None; // experimental
+    let mut workspace_id: Option<ID> = if scope.workspace {
+        Some(ws.get_id())
+    } else {
+        None
+    }; // Some(ws.get_id());
+    let list_info;
+    match scope.list {
+        Some(li) => {
+            list_info = cache::get_list_info(ws.get_id(), &li).await?;
+            list_id = Some(list_info.get_id());
+
+            if let Some(it) = scope.item {
+                let item = retry("get_item", || list_info.get_item(&it)).await?;
+                item_id = Some(item.get_id());
+            }
+
+            // watching field of list (experimental)
+            if let Some(fi) = scope.field {
+                let field = fuzzy::get_field(&list_info, &fi)?;
+                field_id = Some(field.id);
+            }
+        }
+        None => {
+            if scope.item.is_some() {
+                return Err(Error::Invalid(
+                    "If you use item id, you must also specify list id".to_string(),
+                ));
+            } else {
+                // no list, no item; must be looking for workspace
+                workspace_id = Some(ws.get_id());
+            }
+        }
+    };
+    Ok(NewWebhook {
+        trigger_type: webhook_trigger_type(trigger_type),
+        url,
+        list_id,
+        list_entry_id: item_id,
+        workspace_id,
+        element_id: field_id,
+        locale: scope.locale,
+    })
+}
+
+pub(crate) fn webhook_trigger_type(trigger_type: &WebhookType) -> WebhookTriggerType {
+    match trigger_type {
+        WebhookType::Item => WebhookTriggerType::Entry,
+        WebhookType::Activity => WebhookTriggerType::Activity,
+        WebhookType::Notification => WebhookTriggerType::Notification,
+        WebhookType::System => WebhookTriggerType::SystemMessage,
+        WebhookType::Comment => WebhookTriggerType::Comment,
+        WebhookType::Field => WebhookTriggerType::Element, // experimental
+    }
+}
+
+/// Ask the user for confirmation on stdin. Returns true if the answer starts with 'y'.
+/// In dry-run mode, requests are printed instead of sent, so this returns true
+/// without asking.
+fn confirm(prompt: &str) -> Result<bool, Error> {
+    if dryrun::enabled() {
+        return Ok(true);
+    }
+    print!("{} [y/N] ", prompt);
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_lowercase().starts_with('y'))
+}
+
 #[tokio::main(flavor = "current_thread")]
 async fn main() {
     let opt = Opt::parse();
-    if let Err(e) = run(opt).await {
-        eprintln!("Error: {:#?}", e);
-        std::process::exit(1);
+    logging::init(opt.verbose, &opt.log_format);
+    let stats = if opt.stats {
+        Some(opt.stats_format)
+    } else {
+        None
+    };
+    let error_format = opt.error_format;
+    metrics::configure(opt.stats);
+    let result = run(opt).await;
+    if let Some(format) = stats {
+        metrics::print(format);
+    }
+    if let Err(e) = result {
+        match error_format {
+            OutputFormat::Json => eprintln!("{}", e.to_json()),
+            OutputFormat::Text => eprintln!("Error: {:#?}", e),
+        }
+        std::process::exit(e.kind().exit_code());
     }
 }
 
@@ -346,69 +2715,754 @@ fn load_config(file: Option<String>) -> Result<Config, Error> {
     Ok(settings)
 }
 
+/// Get a setting from the selected profile's section `[profiles.<name>]`,
+/// or from the `[zenkit]` section if it's not in the profile.
+fn get_setting(settings: &Config, profile: Option<&str>, key: &str) -> Option<String> {
+    profile
+        .and_then(|name| settings.get_str(&format!("profiles.{}.{}", name, key)).ok())
+        .or_else(|| settings.get_str(&format!("zenkit.{}", key)).ok())
+}
+
 async fn run(opt: Opt) -> Result<(), Error> {
+    retry::configure(opt.retries, opt.retry_delay);
+    dryrun::configure(opt.dry_run || opt.cmd.dry_run());
+    fuzzy::configure(opt.fuzzy);
+    style::configure(opt.color);
+    progress::configure(opt.quiet);
     let settings = load_config(opt.config)?;
-    let token = match settings.get_str("zenkit.token") {
-        Ok(token) => token,
-        Err(_) => settings.get_str("zenkit.api.token") // deprecated name
-            .map_err(|_| Error::Message(
+    aliases::configure(&settings)?;
+    query::configure(&settings)?;
+    if let Some(ref dir) = opt.offline {
+        return run_offline(opt.cmd, dir).await;
+    }
+    let profile = match opt.profile {
+        Some(name) => Some(name),
+        None => settings.get_str("zenkit.profile").ok(),
+    };
+    if let Some(ref name) = profile {
+        settings
+            .get_table(&format!("profiles.{}", name))
+            .map_err(|_| Error::NotFound(format!("Profile '{}' not found in config file", name)))?;
+    }
+    let profile = profile.as_deref();
+    let http_timeout = match opt.http_timeout {
+        Some(timeout) => Some(timeout),
+        None => get_setting(&settings, profile, "http.timeout")
+            .map(|s| parse_duration(&s))
+            .transpose()
+            .map_err(|e| Error::Invalid(format!("http.timeout: {}", e)))?,
+    };
+    ratelimit::configure(match opt.rate_limit {
+        Some(limit) => Some(limit),
+        None => get_setting(&settings, profile, "rate_limit")
+            .map(|s| s.parse::<RateLimit>())
+            .transpose()
+            .map_err(|e| Error::Invalid(format!("rate_limit: {}", e)))?,
+    });
+    http::configure(HttpSettings {
+        timeout: http_timeout,
+        proxy: opt
+            .proxy
+            .or_else(|| get_setting(&settings, profile, "http.proxy")),
+        ca_bundle: opt
+            .ca_bundle
+            .or_else(|| get_setting(&settings, profile, "http.ca_bundle"))
+            .map(PathBuf::from),
+    })?;
+    // diff, backup prune, backup verify, replay, and notify don't use the api
+    match opt.cmd {
+        Sub::Replay(ref replay_opt) => return replay(replay_opt).await,
+        Sub::Notify(ref notify_opt) => return notify(notify_opt).await,
+        Sub::Diff(ref diff_opt) => return diff_backups(diff_opt),
+        Sub::Backup(BackupCmdOpt {
+            action: Some(BackupAction::Prune(ref prune_opt)),
+            ..
+        }) => return prune_backups(prune_opt).map(|_| ()),
+        Sub::Backup(BackupCmdOpt {
+            action: Some(BackupAction::Verify(ref verify_opt)),
+            ..
+        }) => return verify_backup(verify_opt),
+        _ => {}
+    }
+    let token = match get_setting(&settings, profile, "token") {
+        Some(token) => token,
+        // recorded responses don't depend on the token
+        None if opt.replay.is_some() => String::from("replay"),
+        None => settings.get_str("zenkit.api.token") // deprecated name
+            .map_err(|_| Error::Auth(
                 "Missing zenkit token. add to config file with `-c` option or set in environment as ZENKIT_TOKEN".into()))?,
     };
 
     let ws_name = match opt.cmd {
         // we only need to get workspace for some commands
-        Sub::Workspaces | Sub::ListWebhooks | Sub::DeleteWebhook(_) => String::from(""),
+        Sub::Workspaces
+        | Sub::ListWebhooks(_)
+        | Sub::TestWebhook(_)
+        | Sub::DeleteWebhook(_)
+        | Sub::Whoami
+        | Sub::EditComment(_)
+        | Sub::Notifications(_)
+        | Sub::MarkRead(_)
+        | Sub::DeleteComment(_)
+        | Sub::Backup(BackupCmdOpt {
+            backup: BackupOpt {
+                all_workspaces: true,
+                ..
+            },
+            ..
+        })
+        | Sub::MyItems(MyItemsOpt {
+            all_workspaces: true,
+            ..
+        }) => String::from(""),
+        Sub::Listen(ref listen_opt) if listen_opt.url.is_none() => String::from(""),
         _ => match opt.workspace {
                 Some(name) => name,
-                None => settings.get_str("zenkit.workspace").map_err(|_| Error::Message(
+                None => get_setting(&settings, profile, "workspace").ok_or_else(|| Error::Invalid(
                     "Workspace must be specified in config file with `-c` option or in environment as ZENKIT_WORKSPACE".into())
                 )?,
             },
     };
-    let endpoint = settings
-        .get_str("zenkit.endpoint")
-        .unwrap_or_else(|_| zenkit::ApiConfig::default().endpoint);
-    let api = zenkit::init_api(ApiConfig { token, endpoint })?;
+    let endpoint = get_setting(&settings, profile, "endpoint")
+        .unwrap_or_else(|| zenkit::ApiConfig::default().endpoint);
+    weblinks::configure(WebSettings {
+        base: get_setting(&settings, profile, "web.url")
+            .unwrap_or_else(|| weblinks::web_base(&endpoint)),
+        list_url: get_setting(&settings, profile, "web.list_url"),
+        item_url: get_setting(&settings, profile, "web.item_url"),
+    });
+    // with fixtures, the endpoint is a local server, and metadata isn't cached
+    let use_fixtures = opt.record.is_some() || opt.replay.is_some();
+    let endpoint = match (opt.record, opt.replay) {
+        (Some(dir), _) => fixtures::start(&dir, FixtureMode::Record { upstream: endpoint }).await?,
+        (None, Some(dir)) => fixtures::start(&dir, FixtureMode::Replay).await?,
+        (None, None) => endpoint,
+    };
+    let rest = RestClient::new(&endpoint, &token)?;
+    cache::configure(
+        if opt.no_cache || use_fixtures {
+            None
+        } else {
+            cache::default_dir()
+        },
+        opt.cache_ttl,
+        &endpoint,
+        &token,
+    );
+    zenkit::init_api(ApiConfig { token, endpoint })?;
+    s3::configure(S3Settings {
+        access_key: get_setting(&settings, profile, "s3_access_key")
+            .or_else(|| std::env::var("AWS_ACCESS_KEY_ID").ok()),
+        secret_key: get_setting(&settings, profile, "s3_secret_key")
+            .or_else(|| std::env::var("AWS_SECRET_ACCESS_KEY").ok()),
+        session_token: get_setting(&settings, profile, "s3_session_token")
+            .or_else(|| std::env::var("AWS_SESSION_TOKEN").ok()),
+        region: get_setting(&settings, profile, "s3_region")
+            .or_else(|| std::env::var("AWS_REGION").ok())
+            .or_else(|| std::env::var("AWS_DEFAULT_REGION").ok()),
+        endpoint: get_setting(&settings, profile, "s3_endpoint")
+            .or_else(|| std::env::var("AWS_ENDPOINT_URL").ok()),
+    });
 
     match opt.cmd {
+        Sub::Shell => shell(&ws_name, &rest).await,
+        Sub::Run(ref run_opt) => run_file(run_opt, &ws_name, &rest).await,
+        cmd => run_cmd(cmd, &ws_name, &rest).await,
+    }
+}
+
+/// Listener that handles events as selected by the options
+fn event_listener(opt: &EventHandlerOpt) -> Result<Listener, Error> {
+    let mut listener = Listener::new(opt.output);
+    if let Some(ref command) = opt.exec {
+        listener = listener.with_exec(command.clone(), opt.jobs);
+    }
+    if let Some(ref url) = opt.publish {
+        eprintln!("Publishing events to {}", url);
+        listener = listener.with_publish(Publisher::new(url.clone()));
+    }
+    if let Some(ref url) = opt.slack_url {
+        listener = listener.with_notify(SlackNotifier::new(url.clone(), opt.template.clone())?);
+    }
+    Ok(listener)
+}
+
+/// Handle the stored events, and wait for commands (--exec) to finish
+async fn replay(opt: &ReplayOpt) -> Result<(), Error> {
+    let listener = event_listener(&opt.handler)?;
+    let count = listener.replay(&opt.file).await;
+    listener.finish().await;
+    eprintln!("Replayed {} events from {}", count?, opt.file);
+    Ok(())
+}
+
+/// Run a read-only command with the data in a backup folder (`--offline`),
+/// without using the api
+async fn run_offline(cmd: Sub, dir: &str) -> Result<(), Error> {
+    let dir = PathBuf::from(dir);
+    if !dir.is_dir() {
+        return Err(Error::NotFound(format!(
+            "Backup folder {} not found",
+            dir.display()
+        )));
+    }
+    offline::configure(Some(dir.clone()));
+    let ws_id = offline::WORKSPACE_ID;
+    match cmd {
+        Sub::Items(items_opt) => {
+            let list_info = cache::get_list_info(ws_id, &items_opt.list).await?;
+            list_items(ws_id, &list_info, &items_opt).await?;
+        }
+        Sub::Fields(list_opt) => print_fields(ws_id, &list_opt).await?,
+        Sub::Field(field_opt) => print_field(ws_id, &field_opt).await?,
+        Sub::Choices(choices_opt) => print_choices(ws_id, &choices_opt).await?,
+        Sub::Item(item_opt) => print_item(ws_id, &item_opt).await?,
+        Sub::Search(search_opt) => {
+            if search_opt.query.is_empty() {
+                return Err(Error::Invalid("Search query must not be empty".into()));
+            }
+            let found = search(ws_id, &offline::get_lists(&dir)?, &search_opt).await?;
+            eprintln!("{} matching item(s)", found);
+        }
+        Sub::Stats(stats_opt) => {
+            let ws_name = offline::workspace_name(&dir);
+            print_stats(ws_id, &ws_name, &offline::get_lists(&dir)?, &stats_opt).await?;
+        }
+        _ => {
+            return Err(Error::Invalid(
+                "--offline can only be used with items, item, fields, field, choices, search, and stats"
+                    .into(),
+            ))
+        }
+    }
+    Ok(())
+}
+
+/// Print the list's fields: id, uuid, name, and type. The type is shown by name in
+/// a terminal, and by number otherwise.
+async fn print_fields(ws_id: ID, opt: &ListOpt) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let mut table = Table::new(&["id", "uuid", "name", "type"]);
+    for field in list_info.fields().iter() {
+        table.push(vec![
+            Cell::from(field.id.to_string()),
+            Cell::styled(field.uuid.as_str(), Some(Style::Dim)),
+            Cell::from(field.name.as_str()),
+            Cell::from(field_type(field)),
+        ]);
+    }
+    table.print();
+    Ok(())
+}
+
+/// Field type: its name in a terminal, and its number otherwise
+fn field_type(field: &Element) -> String {
+    if style::aligned() {
+        format!("{:?}", field.element_category)
+    } else {
+        (field.element_category as u64).to_string()
+    }
+}
+
+/// Print the field's definition: id, uuid, name, type, and choices
+async fn print_field(ws_id: ID, opt: &FieldOpt) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let field = find_field(list_info.fields(), &opt.field)
+        .ok_or_else(|| Error::NotFound(format!("Field '{}' not found", opt.field)))?;
+    let mut table = Table::new(&[]);
+    table.push(vec![Cell::from("id"), Cell::from(field.id.to_string())]);
+    table.push(vec![
+        Cell::from("uuid"),
+        Cell::styled(field.uuid.as_str(), Some(Style::Dim)),
+    ]);
+    table.push(vec![Cell::from("name"), Cell::from(field.name.as_str())]);
+    table.push(vec![Cell::from("type"), Cell::from(field_type(field))]);
+    if let Some(categories) = &field.element_data.predefined_categories {
+        let mut choices = Cell::default();
+        for (i, c) in categories.iter().enumerate() {
+            if i > 0 {
+                choices.push(", ", None);
+            }
+            choices.push(c.name.as_str(), items::choice_style(field, &c.name));
+        }
+        table.push(vec![Cell::from("choices"), choices]);
+    }
+    table.print();
+    Ok(())
+}
+
+/// Print the choices of a categories field: id and name, in the choice's color
+async fn print_choices(ws_id: ID, opt: &FieldOpt) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let field = find_field(list_info.fields(), &opt.field)
+        .ok_or_else(|| Error::NotFound(format!("Field '{}' not found", opt.field)))?;
+    if field.element_category != ElementCategoryId::Categories {
+        return Err(Error::Invalid(format!(
+            "Field '{}' is not a choice field",
+            opt.field
+        )));
+    }
+    let mut table = Table::new(&["id", "name"]);
+    if let Some(categories) = &field.element_data.predefined_categories {
+        for c in categories {
+            table.push(vec![
+                Cell::from(c.id.to_string()),
+                Cell::styled(c.name.as_str(), items::choice_style(field, &c.name)),
+            ]);
+        }
+    }
+    table.print();
+    Ok(())
+}
+
+/// The field with the name, uuid, or id, or that the alias stands for
+fn find_field<'f>(fields: &'f [Element], name: &str) -> Option<&'f Element> {
+    let name = aliases::resolve(name);
+    fields
+        .iter()
+        .find(|f| f.name == name || f.uuid == name || f.id.to_string() == name)
+}
+
+/// Print the item: with a template, as field names and display values (`--resolve`),
+/// or as returned by Zenkit. In offline mode, the item is read from the backup.
+async fn print_item(ws_id: ID, opt: &ItemOpt) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let item = match offline::backup_dir() {
+        Some(dir) => offline::get_item(&dir, &list_info.list().uuid, &opt.item)?,
+        None => {
+            let api = zenkit::get_api()?;
+            retry("get_entry", || api.get_entry(list_info.get_id(), &opt.item)).await?
+        }
+    };
+    if let Some(ref path) = opt.template {
+        let template = Template::load(path)?;
+        let formatter = ValueFormatter::new(ws_id).await?;
+        print!("{}", template.render_item(&list_info, &item, &formatter)?);
+    } else if opt.resolve {
+        let formatter = ValueFormatter::new(ws_id).await?;
+        let mut table = Table::new(&[]);
+        let mut row = |name: &str, value: Cell| table.push(vec![Cell::from(name), value]);
+        row("id", Cell::from(item.id.to_string()));
+        row("uuid", Cell::styled(item.uuid.as_str(), Some(Style::Dim)));
+        row("title", Cell::from(item.display_string.as_str()));
+        row("created", Cell::from(item.created_at.to_rfc3339()));
+        row("updated", Cell::from(item.updated_at.to_rfc3339()));
+        if let Some(ref deprecated) = item.deprecated_at {
+            row("archived", Cell::from(deprecated.to_rfc3339()));
+        }
+        for field in list_info.fields().iter() {
+            row(
+                &field.name,
+                items::Column::Field(field).cell(&item, Some(&formatter)),
+            );
+        }
+        table.print();
+    } else {
+        println!("{}", serde_json::to_string_pretty(&item)?);
+    }
+    Ok(())
+}
+
+/// Row of a list: id, uuid, name, and whether it's deprecated
+fn list_row(list: &List) -> Vec<Cell> {
+    let dep_status = match list.deprecated_at {
+        Some(_) => " (Deprecated)",
+        None => "",
+    };
+    vec![
+        Cell::from(list.id.to_string()),
+        Cell::styled(list.uuid.as_str(), Some(Style::Dim)),
+        Cell::from(list.name.as_str()),
+        Cell::styled(dep_status, Some(Style::Dim)),
+    ]
+}
+
+/// Run one subcommand. The api must be initialized, and `ws_name` must be set
+/// for commands that operate on a workspace.
+async fn run_cmd(cmd: Sub, ws_name: &str, rest: &RestClient) -> Result<(), Error> {
+    let api = zenkit::get_api()?;
+    match cmd {
         Sub::Workspaces => {
             // list all workspaces and lists
-            let workspaces: Vec<Arc<Workspace>> = api.get_all_workspaces_and_lists().await?;
+            let workspaces: Vec<Arc<Workspace>> = retry("get_all_workspaces_and_lists", || {
+                api.get_all_workspaces_and_lists()
+            })
+            .await?;
+            if !style::aligned() {
+                for ws in workspaces.iter() {
+                    println!("\nW\t{}\t{}\t{}", ws.id, ws.uuid, ws.name);
+                    for list in ws.lists.iter() {
+                        let dep_status = match list.deprecated_at {
+                            Some(_) => " (Deprecated)",
+                            None => "",
+                        };
+                        println!(
+                            "L\t{}\t{}\t{}\t{}",
+                            list.id, list.uuid, list.name, dep_status
+                        );
+                    }
+                }
+                return Ok(());
+            }
+            let mut table = Table::new(&["id", "uuid", "list", ""]);
             for ws in workspaces.iter() {
-                println!("\nW\t{}\t{}\t{}", ws.id, ws.uuid, ws.name);
+                let mut heading = Cell::styled(ws.name.as_str(), Some(Style::Bold));
+                heading.push(format!(" {} ", ws.id), None);
+                heading.push(ws.uuid.as_str(), Some(Style::Dim));
+                table.push_heading(heading);
                 for list in ws.lists.iter() {
-                    let dep_status = match list.deprecated_at {
-                        Some(_) => " (Deprecated)",
-                        None => "",
+                    table.push(list_row(list));
+                }
+            }
+            table.print();
+        }
+        Sub::Lists => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let mut table = Table::new(&["id", "uuid", "name", ""]);
+            for list in ws.lists.iter() {
+                table.push(list_row(list));
+            }
+            table.print();
+        }
+        Sub::RefreshCache => {
+            cache::clear()?;
+            let ws = cache::get_workspace(ws_name).await?;
+            let mut count = 0;
+            for list in ws.lists.iter().filter(|l| l.deprecated_at.is_none()) {
+                cache::get_list_info(ws.get_id(), &list.uuid).await?;
+                count += 1;
+            }
+            eprintln!("Cached {} list(s) of workspace '{}'", count, ws.name);
+        }
+        Sub::Whoami => {
+            let user = rest.get("users/me").await?;
+            println!("{}", user_line(&user));
+        }
+        Sub::Users => {
+            let ws = cache::get_workspace(ws_name).await?;
+            list_users(rest, ws.get_id()).await?;
+        }
+        Sub::InviteUser(invite_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            invite_user(rest, ws.get_id(), &invite_opt).await?;
+        }
+        Sub::RemoveUser(remove_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            if !remove_user(rest, ws.get_id(), &remove_opt).await? {
+                println!("Cancelled");
+            }
+        }
+        Sub::Items(items_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let list_info = cache::get_list_info(ws.get_id(), &items_opt.list).await?;
+            list_items(ws.get_id(), &list_info, &items_opt).await?;
+        }
+        Sub::Fields(list_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            print_fields(ws.get_id(), &list_opt).await?;
+        }
+        Sub::Field(field_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            print_field(ws.get_id(), &field_opt).await?;
+        }
+        Sub::Choices(choices_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            print_choices(ws.get_id(), &choices_opt).await?;
+        }
+        Sub::AddChoice(choice_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            add_choice(rest, ws.get_id(), &choice_opt).await?;
+        }
+        Sub::RenameChoice(choice_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            rename_choice(rest, ws.get_id(), &choice_opt).await?;
+        }
+        Sub::DeleteChoice(choice_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            if !delete_choice(rest, ws.get_id(), &choice_opt).await? {
+                println!("Cancelled");
+            }
+        }
+        Sub::Item(item_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            print_item(ws.get_id(), &item_opt).await?;
+        }
+        Sub::CreateField(field_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let list_info = cache::get_list_info(ws.get_id(), &field_opt.list).await?;
+            let spec = FieldSpec {
+                name: field_opt.name,
+                field_type: field_opt.field_type,
+                choices: field_opt.choice,
+                multiple: field_opt.multiple,
+                target_list: field_opt.target_list,
+            };
+            if let Some(field) =
+                create_field_spec(rest, ws.get_id(), list_info.get_id(), &spec).await?
+            {
+                println!(
+                    "{}\t{}\t{}\t{}",
+                    field.id, field.uuid, field.name, field.element_category as u64
+                );
+            }
+        }
+        Sub::ApplySchema(schema_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            apply_schema(rest, ws.get_id(), &schema_opt).await?;
+        }
+        Sub::ExportSchema(schema_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            export_schema(rest, ws.get_id(), &schema_opt).await?;
+        }
+        Sub::Get(get_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let list_info = cache::get_list_info(ws.get_id(), &get_opt.list).await?;
+            let item = retry("get_entry", || {
+                api.get_entry(list_info.get_id(), &get_opt.item)
+            })
+            .await?;
+            let field = fuzzy::get_field(&list_info, &get_opt.field)?;
+            if get_opt.raw {
+                let value = raw_value(&item, field).unwrap_or(&serde_json::Value::Null);
+                println!("{}", serde_json::to_string(value)?);
+            } else {
+                let formatter = ValueFormatter::new(ws.get_id()).await?;
+                println!("{}", formatter.display(&item, field).unwrap_or_default());
+            }
+        }
+        Sub::Set(set_opt) => {
+            // set value
+            let ws = cache::get_workspace(ws_name).await?;
+            let (list, item) = (set_opt.list, set_opt.item);
+            let list_info = cache::get_list_info(ws.get_id(), &list).await?;
+            let action = set_opt.action.update_action();
+            let mut values = Vec::new();
+            if let Some(field) = set_opt.field {
+                if set_opt.null || set_opt.action == SetAction::Null {
+                    let element = fuzzy::get_field(&list_info, &field)?;
+                    values.push(clear_value(field, element));
+                } else {
+                    let from_file = set_opt.file.is_some();
+                    let sval = if let Some(value) = set_opt.value {
+                        value
+                    } else if let Some(fname) = set_opt.file {
+                        println!("Reading value from file {}", &fname);
+                        fs::read_to_string(&fname)?
+                    } else {
+                        return Err(Error::Invalid(
+                            "Either --value, --file, or --null must be used with --field"
+                                .to_string(),
+                        ));
                     };
+                    let value = match (set_opt.text, from_file) {
+                        (Some(fmt), _) => FieldVal::Formatted(sval, fmt),
+                        (None, true) => FieldVal::Str(sval),
+                        (None, false) => parse_setval(sval),
+                    };
+                    values.push((field, value, action));
+                }
+            }
+            for (field, value) in set_opt.set_values {
+                values.push((field, parse_setval(value), action));
+            }
+            if values.is_empty() {
+                return Err(Error::Invalid(
+                    "Either --field or -s field=value must be used for set option".to_string(),
+                ));
+            }
+            resolve_values(rest, ws.get_id(), &list_info, &mut values).await?;
+            if dryrun::enabled() {
+                dryrun::print_item_request(&list_info, Some(item), &values).await?;
+                return Ok(());
+            }
+            if txn::active() {
+                let item_id = item.to_string();
+                let before = retry("get_item", || list_info.get_item(&item_id)).await?;
+                txn::record_update(ws.get_id(), &list_info, &before, &values)?;
+            }
+            list_info.update_item(item, &values).await?;
+        }
+        Sub::BulkSet(bulk_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let summary = bulk_set(rest, ws.get_id(), &bulk_opt).await?;
+            if dryrun::enabled() {
+                eprintln!("{} item(s) would be updated", summary.updated);
+            } else {
+                eprintln!(
+                    "Updated {} item(s), {} failed",
+                    summary.updated,
+                    summary.failed.len()
+                );
+            }
+            if !summary.failed.is_empty() {
+                return Err(Error::Message(format!(
+                    "Failed to update items: {:?}",
+                    summary.failed
+                )));
+            }
+        }
+        Sub::Archive(archive_opt) => {
+            if archive_opt.filter.is_empty() && archive_opt.older_than.is_none() {
+                return Err(Error::Invalid(
+                    "At least one --filter or --older-than is required".into(),
+                ));
+            }
+            if !archive_opt.yes {
+                let prompt = format!(
+                    "Archive all matching items in list '{}'? (use --dry-run to see them)",
+                    archive_opt.list
+                );
+                if !confirm(&prompt)? {
+                    println!("Cancelled");
+                    return Ok(());
+                }
+            }
+            let ws = cache::get_workspace(ws_name).await?;
+            let summary = bulk_archive(rest, ws.get_id(), &archive_opt).await?;
+            if dryrun::enabled() {
+                eprintln!("{} item(s) would be archived", summary.updated);
+            } else {
+                eprintln!(
+                    "Archived {} item(s), {} failed",
+                    summary.updated,
+                    summary.failed.len()
+                );
+            }
+            if !summary.failed.is_empty() {
+                return Err(Error::Message(format!(
+                    "Failed to archive items: {:?}",
+                    summary.failed
+                )));
+            }
+        }
+        Sub::Undo(undo_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let restored = undo_changes(rest, ws.get_id(), &undo_opt).await?;
+            eprintln!("Restored {} field(s)", restored);
+        }
+        Sub::Create(mut create_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let list_info = cache::get_list_info(ws.get_id(), &create_opt.list).await?;
+            // create item
+            let mut fields: Vec<_> = match create_opt.json {
+                Some(ref path) => json_values(path)?
+                    .into_iter()
+                    .map(|(k, v)| (k, v, UpdateAction::Null))
+                    .collect(),
+                None => Vec::new(),
+            };
+            fields.extend(
+                create_opt
+                    .fields
+                    .drain(..)
+                    .map(|(k, v)| (k, parse_setval(v), UpdateAction::Null)),
+            );
+            if fields.is_empty() {
+                return Err(Error::Invalid(
+                    "Either -F field=value or --json must be used for create".to_string(),
+                ));
+            }
+            resolve_values(rest, ws.get_id(), &list_info, &mut fields).await?;
+            if dryrun::enabled() {
+                dryrun::print_item_request(&list_info, None, &fields).await?;
+                return Ok(());
+            }
+            let new_item = list_info.create_item(&fields).await?;
+            txn::record_created(list_info.get_id(), new_item.get_id());
+            let mut table = Table::new(&["id", "uuid", "title"]);
+            table.push(vec![
+                Cell::from(new_item.id.to_string()),
+                Cell::styled(new_item.uuid.as_str(), Some(Style::Dim)),
+                Cell::from(new_item.display_string.as_str()),
+            ]);
+            table.print();
+        }
+        Sub::DeleteItem(delete_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let list_info = cache::get_list_info(ws.get_id(), &delete_opt.list).await?;
+            let mut items = Vec::new();
+            for it in delete_opt.item.iter() {
+                items.push(retry("get_item", || list_info.get_item(it)).await?);
+            }
+            if !delete_opt.yes {
+                for item in items.iter() {
                     println!(
-                        "L\t{}\t{}\t{}\t{}",
-                        list.id, list.uuid, list.name, dep_status
+                        "{}\t{}\t{}",
+                        item.get_id(),
+                        item.get_uuid(),
+                        item.display_string
                     );
                 }
+                let prompt = format!(
+                    "Delete {} item(s) from list '{}'?",
+                    items.len(),
+                    list_info.list().name
+                );
+                if !confirm(&prompt)? {
+                    println!("Cancelled");
+                    return Ok(());
+                }
+            }
+            for item in items.iter() {
+                rest.delete(&format!(
+                    "lists/{}/entries/{}",
+                    list_info.get_id(),
+                    item.get_id()
+                ))
+                .await?;
+                if !dryrun::enabled() {
+                    println!("Deleted {}\t{}", item.get_id(), item.display_string);
+                }
             }
         }
-        Sub::Lists => {
-            let ws = api.get_workspace(&ws_name).await?;
-            for list in ws.lists.iter() {
-                let dep_status = match list.deprecated_at {
-                    Some(_) => " (Deprecated)",
-                    None => "",
-                };
-                println!("{}\t{}\t{}\t{}", list.id, list.uuid, list.name, dep_status);
+        Sub::CreateList(list_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            if let Some(list) = create_list(
+                rest,
+                ws.get_id(),
+                &list_opt.name,
+                list_opt.description.as_deref(),
+            )
+            .await?
+            {
+                println!("{}\t{}\t{}", list.id, list.uuid, list.name);
             }
         }
-        Sub::Users => {
-            let ws = api.get_workspace(&ws_name).await?;
-            for u in api.get_users(ws.get_id()).await?.iter() {
-                println!("{}\t{}\t{}", u.id, u.uuid.clone(), u.display_name.clone());
+        Sub::CloneList(clone_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            if let Some(list) = clone_list(rest, ws.get_id(), &clone_opt).await? {
+                println!("{}\t{}\t{}", list.id, list.uuid, list.name);
             }
         }
-        Sub::Items(list_opt) => {
-            let ws = api.get_workspace(&ws_name).await?;
-            let list_info = api.get_list_info(ws.get_id(), &list_opt.list).await?;
-            let items = list_info.get_items().await?;
-            for item in items.iter() {
+        Sub::DeprecateList(list_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let list_info = cache::get_list_info(ws.get_id(), &list_opt.list).await?;
+            let prompt = format!("Deprecate list '{}'?", list_info.list().name);
+            if !list_opt.yes && !confirm(&prompt)? {
+                println!("Cancelled");
+                return Ok(());
+            }
+            let body = serde_json::json!({ "deprecated_at": chrono::Utc::now().to_rfc3339() });
+            rest.put(&format!("lists/{}", list_info.get_id()), &body)
+                .await?;
+        }
+        Sub::DeleteList(list_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let list_info = cache::get_list_info(ws.get_id(), &list_opt.list).await?;
+            let prompt = format!(
+                "Permanently delete list '{}' and all its items?",
+                list_info.list().name
+            );
+            if !list_opt.yes && !confirm(&prompt)? {
+                println!("Cancelled");
+                return Ok(());
+            }
+            rest.delete(&format!("lists/{}", list_info.get_id()))
+                .await?;
+        }
+        Sub::CopyItem(copy_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            if let Some(item) = copy_item(rest, ws.get_id(), &copy_opt).await? {
                 println!(
                     "{}\t{}\t{}",
                     item.get_id(),
@@ -417,217 +3471,1136 @@ async fn run(opt: Opt) -> Result<(), Error> {
                 );
             }
         }
-        Sub::Fields(list_opt) => {
-            // show fields for list
-            let ws = api.get_workspace(&ws_name).await?;
-            let list_info = api.get_list_info(ws.get_id(), &list_opt.list).await?;
-            for field in api.get_list_elements(list_info.get_id()).await?.iter() {
+        Sub::MoveItem(move_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            if let Some(item) = move_item(rest, ws.get_id(), &move_opt).await? {
                 println!(
-                    "{}\t{}\t{}\t{}",
-                    field.id, field.uuid, field.name, field.element_category as u64
-                )
+                    "{}\t{}\t{}",
+                    item.get_id(),
+                    item.get_uuid(),
+                    item.display_string
+                );
             }
         }
-        Sub::Field(field_opt) => {
-            // show field detailed definition
-            let ws = api.get_workspace(&ws_name).await?;
-            let list_info = api.get_list_info(ws.get_id(), &field_opt.list).await?;
-            match api
-                .get_list_elements(list_info.get_id())
-                .await?
-                .iter()
-                .find(|f| {
-                    f.name == field_opt.field
-                        || f.uuid == field_opt.field
-                        || f.id.to_string() == field_opt.field
-                }) {
-                Some(f) => println!("{:#?}", f),
-                None => println!("Field '{}' not found", field_opt.field),
+        Sub::SyncLists(sync_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let summary = sync_lists(ws.get_id(), &sync_opt).await?;
+            eprintln!(
+                "Created {} item(s), updated {}, unchanged {}, failed {}",
+                summary.created,
+                summary.updated,
+                summary.unchanged,
+                summary.failed.len()
+            );
+            if !summary.failed.is_empty() {
+                return Err(Error::Message(format!(
+                    "Failed to sync items:\n{}",
+                    summary.failed.join("\n")
+                )));
             }
         }
-        Sub::Choices(choices_opt) => {
-            // show choices for field
-            let ws = api.get_workspace(&ws_name).await?;
-            let list_info = api.get_list_info(ws.get_id(), &choices_opt.list).await?;
-            match api
-                .get_list_elements(list_info.get_id())
-                .await?
-                .iter()
-                .find(|f| {
-                    f.name == choices_opt.field
-                        || f.uuid == choices_opt.field
-                        || f.id.to_string() == choices_opt.field
-                }) {
-                Some(field) => {
-                    if field.element_category == ElementCategoryId::Categories {
-                        if let Some(categories) = &field.element_data.predefined_categories {
-                            for c in categories {
-                                println!("{}\t{}", c.id, c.name);
-                            }
-                        }
-                    } else {
-                        println!("Field '{}' is not a choice field", choices_opt.field)
+        Sub::RestoreItem(restore_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let item = restore_item(ws.get_id(), &restore_opt).await?;
+            println!(
+                "{}\t{}\t{}",
+                item.get_id(),
+                item.get_uuid(),
+                item.display_string
+            );
+        }
+        Sub::Import(import_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            match import_opt.source {
+                ImportSource::Csv(csv_opt) => {
+                    let summary = import_csv(ws.get_id(), &csv_opt).await?;
+                    println!(
+                        "Created {} items, rejected {} rows",
+                        summary.created,
+                        summary.rejected.len()
+                    );
+                }
+                ImportSource::Trello(trello_opt) => {
+                    let summary = import_trello(rest, ws.get_id(), &trello_opt).await?;
+                    for message in summary.not_imported.iter() {
+                        eprintln!("Not imported: {}", message);
+                    }
+                    println!(
+                        "Created {} list(s) with {} items, {} checklists, and {} comments",
+                        summary.lists, summary.items, summary.checklists, summary.comments
+                    );
+                }
+                ImportSource::Jira(jira_opt) => {
+                    let summary = import_jira(rest, ws.get_id(), &jira_opt).await?;
+                    for message in summary.not_imported.iter() {
+                        eprintln!("Not imported: {}", message);
+                    }
+                    println!("Created {} items", summary.items);
+                }
+                ImportSource::Notion(notion_opt) => {
+                    let summary = import_notion(rest, ws.get_id(), &notion_opt).await?;
+                    for message in summary.rejected.iter() {
+                        eprintln!("Not imported: {}", message);
                     }
+                    println!(
+                        "Created {} list(s) with {} items, rejected {} rows",
+                        summary.lists,
+                        summary.items,
+                        summary.rejected.len()
+                    );
                 }
-                None => println!("Field '{}' not found", choices_opt.field),
             }
         }
-        Sub::Item(item_opt) => {
-            let ws = api.get_workspace(&ws_name).await?;
-            let list_info = api.get_list_info(ws.get_id(), &item_opt.list).await?;
-            let item = api.get_entry(list_info.get_id(), &item_opt.item).await?;
-            println!("{:#?}", item);
+        Sub::Search(search_opt) => {
+            if search_opt.query.is_empty() {
+                return Err(Error::Invalid("Search query must not be empty".into()));
+            }
+            let ws = cache::get_workspace(ws_name).await?;
+            let found = search(ws.get_id(), &ws.lists, &search_opt).await?;
+            eprintln!("{} matching item(s)", found);
         }
-        Sub::Set(set_opt) => {
-            // set value
-            let sval = if let Some(value) = set_opt.value {
-                value
-            } else if let Some(fname) = set_opt.file {
-                println!("Reading value from file {}", &fname);
-                fs::read_to_string(&fname)?
+        Sub::Stats(stats_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            print_stats(ws.get_id(), &ws.name, &ws.lists, &stats_opt).await?;
+        }
+        Sub::Query(query_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            query(ws.get_id(), &query_opt).await?;
+        }
+        Sub::Mirror(mirror_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            mirror(ws.get_id(), &mirror_opt).await?;
+        }
+        Sub::Sync(sync_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let summary = sync(rest, ws.get_id(), &sync_opt).await?;
+            eprintln!(
+                "Pushed {} update(s), created {}, archived {}; pulled {} update(s), added {}, removed {}",
+                summary.pushed,
+                summary.created,
+                summary.archived,
+                summary.pulled,
+                summary.added,
+                summary.removed
+            );
+            if !summary.conflicts.is_empty() {
+                for conflict in summary.conflicts.iter() {
+                    eprintln!("Conflict: {}", conflict);
+                }
+                return Err(Error::Message(format!(
+                    "{} conflict(s) not synced: use --prefer local or --prefer remote",
+                    summary.conflicts.len()
+                )));
+            }
+        }
+        Sub::Export(export_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            export(&ws, &export_opt).await?;
+        }
+        Sub::Agenda(agenda_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            print_agenda(rest, ws.get_id(), &agenda_opt).await?;
+        }
+        Sub::MyItems(my_opt) => {
+            let workspaces = if my_opt.all_workspaces {
+                retry("get_all_workspaces_and_lists", || {
+                    api.get_all_workspaces_and_lists()
+                })
+                .await?
             } else {
-                // this case is already handled by clap, but Rust doesn't know that
-                return Err(Error::Message(
-                    "Either --value or --field must be used for set option".to_string(),
-                ));
-            };
-            let value = match set_opt.text {
-                None => FieldVal::Str(sval),
-                Some(fmt) => FieldVal::Formatted(sval, fmt),
+                vec![cache::get_workspace(ws_name).await?]
             };
-            let ws = api.get_workspace(&ws_name).await?;
-            let list_info = api.get_list_info(ws.get_id(), &set_opt.list).await?;
-            list_info
-                .update_item(
-                    set_opt.item,
-                    vec![(set_opt.field.clone(), value, UpdateAction::Replace)],
-                )
-                .await?;
+            let found = my_items(rest, &workspaces, &my_opt).await?;
+            eprintln!("{} item(s)", found);
         }
-        Sub::Create(mut create_opt) => {
-            let ws = api.get_workspace(&ws_name).await?;
-            let list_info = api.get_list_info(ws.get_id(), &create_opt.list).await?;
-            // create item
-            let fields = create_opt
-                .fields
-                .drain(..)
-                .map(|(k, v)| (k, parse_setval(v), UpdateAction::Null))
-                .collect();
-            let new_item = list_info.create_item(fields).await?;
-            println!("{:#?}", new_item);
+        Sub::Due(due_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            due_report(ws.get_id(), &due_opt).await?;
+        }
+        Sub::Board(board_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            print_board(ws.get_id(), &board_opt).await?;
+        }
+        Sub::Url(url_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            println!("{}", weblinks::url(rest, ws.get_id(), &url_opt).await?);
+        }
+        Sub::Open(url_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            weblinks::open(&weblinks::url(rest, ws.get_id(), &url_opt).await?)?;
+        }
+        Sub::Checklist(checklist_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            update_checklist(rest, ws.get_id(), &checklist_opt).await?;
+        }
+        Sub::Attach(attach_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let file = attach_file(rest, ws.get_id(), &attach_opt).await?;
+            if !file.is_null() {
+                println!("{}", serde_json::to_string_pretty(&file)?);
+            }
+        }
+        Sub::Files(files_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            item_files(rest, ws.get_id(), &files_opt).await?;
         }
         Sub::Comment(comment_opt) => {
-            let ws = api.get_workspace(&ws_name).await?;
-            let list_info = api.get_list_info(ws.get_id(), &comment_opt.list).await?;
+            let ws = cache::get_workspace(ws_name).await?;
+            let list_info = cache::get_list_info(ws.get_id(), &comment_opt.list).await?;
+            if dryrun::enabled() {
+                dryrun::print_request(
+                    "POST",
+                    &format!(
+                        "users/me/lists/{}/entries/{}/activities",
+                        list_info.get_id(),
+                        comment_opt.item
+                    ),
+                    Some(&serde_json::json!({ "message": comment_opt.comment })),
+                );
+                return Ok(());
+            }
             list_info
                 .add_item_comment(
                     &comment_opt.item, // entry id or uuid
-                    comment_opt.comment,
+                    &comment_opt.comment,
                 )
                 .await?;
         }
-        Sub::ListWebhooks => {
-            let resp = api.get_webhooks().await?;
-            println!("{:#?}", resp);
+        Sub::Comments(comments_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let list_info = cache::get_list_info(ws.get_id(), &comments_opt.list).await?;
+            let item = retry("get_item", || list_info.get_item(&comments_opt.item)).await?;
+            let mut comments: Vec<ActivitySummary> =
+                get_entry_activities(rest, list_info.get_id(), item.get_id(), FILTER_COMMENTS)
+                    .await?
+                    .iter()
+                    .map(ActivitySummary::from_activity)
+                    .collect();
+            // activities are returned newest first
+            comments.reverse();
+            match comments_opt.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&comments)?),
+                OutputFormat::Text => {
+                    let mut table = Table::new(&["id", "created", "author", "comment"]);
+                    for c in comments.iter() {
+                        table.push(vec![
+                            Cell::styled(c.id.to_string(), Some(Style::Dim)),
+                            Cell::from(c.created_at.to_string()),
+                            Cell::styled(c.author.to_string(), Some(Style::Bold)),
+                            Cell::from(c.message.to_string()),
+                        ]);
+                    }
+                    table.print();
+                }
+            }
         }
-        Sub::DeleteWebhook(del_opt) => {
-            let resp = api.delete_webhook(del_opt.webhook).await?;
-            println!("{:#?}", resp);
+        Sub::History(history_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let list_info = cache::get_list_info(ws.get_id(), &history_opt.list).await?;
+            let item = retry("get_item", || list_info.get_item(&history_opt.item)).await?;
+            let field_name = match history_opt.field {
+                Some(ref field) => Some(fuzzy::get_field(&list_info, field)?.name.clone()),
+                None => None,
+            };
+            let path = format!(
+                "lists/{}/entries/{}/activities",
+                list_info.get_id(),
+                item.get_id()
+            );
+            let mut changes: Vec<FieldChange> = get_activities(rest, &path, None, None, None)
+                .await?
+                .iter()
+                .filter_map(FieldChange::from_activity)
+                .filter(|c| field_name.as_ref().map(|f| f == &c.field).unwrap_or(true))
+                .collect();
+            // activities are newest first
+            changes.reverse();
+            match history_opt.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&changes)?),
+                OutputFormat::Text => {
+                    for c in changes.iter() {
+                        println!(
+                            "{}\t{}\t{}\t{} -> {}",
+                            c.created_at, c.author, c.field, c.old_value, c.new_value
+                        );
+                    }
+                }
+            }
         }
-        Sub::Webhook(webhook_opt) => {
-            let ws = api.get_workspace(&ws_name).await?;
-            let mut item_id: Option<ID> = None;
-            let mut list_id: Option<ID> = None;
-            let mut field_id: Option<ID> = None; // experimental
-            let mut workspace_id: Option<ID> = if webhook_opt.workspace {
-                Some(ws.get_id())
-            } else {
-                None
-            }; // Some(ws.get_id());
-            let list_info;
-            match webhook_opt.list {
-                Some(li) => {
-                    list_info = api.get_list_info(ws.get_id(), &li).await?;
-                    list_id = Some(list_info.get_id());
-
-                    if let Some(it) = webhook_opt.item {
-                        let item = list_info.get_item(&it).await?;
-                        item_id = Some(item.get_id());
+        Sub::Activities(activities_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let path = match activities_opt.list {
+                None => format!("workspaces/{}/activities", ws.get_id()),
+                Some(ref list) => {
+                    let list_info = cache::get_list_info(ws.get_id(), list).await?;
+                    match activities_opt.item {
+                        None => format!("lists/{}/activities", list_info.get_id()),
+                        Some(ref item) => {
+                            let item = retry("get_item", || list_info.get_item(item)).await?;
+                            format!(
+                                "lists/{}/entries/{}/activities",
+                                list_info.get_id(),
+                                item.get_id()
+                            )
+                        }
                     }
-
-                    // watching field of list (experimental)
-                    if let Some(fi) = webhook_opt.field {
-                        let field = list_info.get_field(&fi)?;
-                        field_id = Some(field.id);
+                }
+            };
+            let activities: Vec<ActivitySummary> = get_activities(
+                rest,
+                &path,
+                None,
+                Some(activities_opt.limit),
+                activities_opt.before,
+            )
+            .await?
+            .iter()
+            .map(ActivitySummary::from_activity)
+            .collect();
+            match activities_opt.output {
+                OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&activities)?),
+                OutputFormat::Text => {
+                    for a in activities.iter() {
+                        println!(
+                            "{}\t{}\t{}\t{}\t{}\t{}\t{}",
+                            a.id,
+                            a.created_at,
+                            a.author,
+                            a.activity_type,
+                            a.item,
+                            a.field,
+                            a.message
+                        );
                     }
                 }
-                None => {
-                    if webhook_opt.item.is_some() {
-                        return Err(Error::Message(
-                            "If you use item id, you must also specify list id".to_string(),
-                        ));
-                    } else {
-                        // no list, no item; must be looking for workspace
-                        workspace_id = Some(ws.get_id());
+            }
+        }
+        Sub::Notifications(notif_opt) => {
+            let notifications: Vec<Notification> =
+                get_notifications(rest, notif_opt.unread, Some(notif_opt.limit))
+                    .await?
+                    .iter()
+                    .map(Notification::from_json)
+                    .collect();
+            match notif_opt.output {
+                OutputFormat::Json => {
+                    println!("{}", serde_json::to_string_pretty(&notifications)?)
+                }
+                OutputFormat::Text => {
+                    for n in notifications.iter() {
+                        let read = if n.is_read { "" } else { "unread" };
+                        println!(
+                            "{}\t{}\t{}\t{}\t{}\t{}",
+                            n.id,
+                            n.activity.created_at,
+                            read,
+                            n.activity.author,
+                            n.activity.item,
+                            n.activity.message
+                        );
                     }
                 }
+            }
+        }
+        Sub::MarkRead(read_opt) => {
+            let ids: Vec<ID> = if read_opt.all {
+                get_notifications(rest, true, None)
+                    .await?
+                    .iter()
+                    .map(|n| Notification::from_json(n).id)
+                    .collect()
+            } else if !read_opt.id.is_empty() {
+                read_opt.id
+            } else {
+                return Err(Error::Invalid("Either --id or --all must be used".into()));
             };
-            let hook = NewWebhook {
-                trigger_type: match webhook_opt.trigger_type {
-                    WebhookType::Item => WebhookTriggerType::Entry,
-                    WebhookType::Activity => WebhookTriggerType::Activity,
-                    WebhookType::Notification => WebhookTriggerType::Notification,
-                    WebhookType::System => WebhookTriggerType::SystemMessage,
-                    WebhookType::Comment => WebhookTriggerType::Comment,
-                    WebhookType::Field => WebhookTriggerType::Element, // experimental
-                },
-                url: webhook_opt.url,
-                list_id,
-                list_entry_id: item_id,
-                workspace_id,
-                element_id: field_id,
-                locale: webhook_opt.locale,
+            for id in ids.iter() {
+                mark_notification_read(rest, *id).await?;
+            }
+            if !dryrun::enabled() {
+                println!("Marked {} notification(s) read", ids.len());
+            }
+        }
+        Sub::EditComment(edit_opt) => {
+            let body = serde_json::json!({ "message": edit_opt.comment });
+            rest.put(&format!("activities/{}", edit_opt.id), &body)
+                .await?;
+        }
+        Sub::DeleteComment(delete_opt) => {
+            let prompt = format!("Delete comment {}?", delete_opt.id);
+            if !delete_opt.yes && !confirm(&prompt)? {
+                println!("Cancelled");
+                return Ok(());
+            }
+            rest.delete(&format!("activities/{}", delete_opt.id))
+                .await?;
+        }
+        Sub::ListWebhooks(list_opt) => list_webhooks(rest, &list_opt).await?,
+        Sub::TestWebhook(test_opt) => test_webhook(rest, &test_opt).await?,
+        Sub::DeleteWebhook(del_opt) => {
+            if dryrun::enabled() {
+                dryrun::print_request("DELETE", &format!("webhooks/{}", del_opt.webhook), None);
+                return Ok(());
+            }
+            let hook = retry("delete_webhook", || api.delete_webhook(del_opt.webhook)).await?;
+            println!("Deleted webhook {}\t{}", hook.id, hook.url);
+        }
+        Sub::Webhook(webhook_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            let hook = new_webhook(
+                &ws,
+                &webhook_opt.trigger_type,
+                webhook_opt.url,
+                webhook_opt.scope,
+            )
+            .await?;
+            if dryrun::enabled() {
+                dryrun::print_request("POST", "webhooks", Some(&serde_json::to_value(&hook)?));
+                return Ok(());
+            }
+            let hook = retry_rate_limited("create_webhook", || api.create_webhook(&hook)).await?;
+            print_webhook(&hook).await?;
+        }
+        Sub::UpdateWebhook(update_opt) => {
+            let current = retry("get_webhooks", || api.get_webhooks())
+                .await?
+                .into_iter()
+                .find(|w| w.id == update_opt.webhook)
+                .ok_or_else(|| {
+                    Error::NotFound(format!("Webhook {} not found", update_opt.webhook))
+                })?;
+            let ws = cache::get_workspace(ws_name).await?;
+            let scope_changed = update_opt.scope.list.is_some()
+                || update_opt.scope.item.is_some()
+                || update_opt.scope.field.is_some()
+                || update_opt.scope.workspace;
+            let url = update_opt.url.unwrap_or_else(|| current.url.clone());
+            let mut hook = new_webhook(
+                &ws,
+                update_opt
+                    .trigger_type
+                    .as_ref()
+                    .unwrap_or(&WebhookType::Item),
+                url,
+                update_opt.scope,
+            )
+            .await?;
+            // options not given are unchanged
+            if update_opt.trigger_type.is_none() {
+                hook.trigger_type = current.trigger_type;
+            }
+            if !scope_changed {
+                hook.list_id = current.list_id;
+                hook.list_entry_id = current.list_entry_id;
+                hook.workspace_id = current.workspace_id;
+                hook.element_id = current.element_id;
+            }
+            // not wrapped by the zenkit crate
+            let response = rest
+                .put(
+                    &format!("webhooks/{}", update_opt.webhook),
+                    &serde_json::to_value(&hook)?,
+                )
+                .await?;
+            if !dryrun::enabled() {
+                println!("{}", serde_json::to_string_pretty(&response)?);
+            }
+        }
+        Sub::Listen(listen_opt) => {
+            let addr: SocketAddr = format!("{}:{}", listen_opt.bind, listen_opt.port)
+                .parse()
+                .map_err(|_| {
+                    Error::Invalid(format!(
+                        "Invalid listen address {}:{}",
+                        listen_opt.bind, listen_opt.port
+                    ))
+                })?;
+            // requests to a registered webhook aren't signed
+            let secret = match listen_opt.url {
+                Some(_) => None,
+                None => listen::load_secret(listen_opt.secret, listen_opt.secret_file.as_deref())?,
             };
-            let response = api.create_webhook(&hook).await?;
-            println!("{:#?}", response);
+            let mut created_hook = None;
+            if let Some(url) = listen_opt.url {
+                let ws = cache::get_workspace(ws_name).await?;
+                let hook =
+                    new_webhook(&ws, &listen_opt.trigger_type, url, listen_opt.scope).await?;
+                if dryrun::enabled() {
+                    dryrun::print_request("POST", "webhooks", Some(&serde_json::to_value(&hook)?));
+                } else {
+                    let webhook =
+                        retry_rate_limited("create_webhook", || api.create_webhook(&hook)).await?;
+                    eprintln!("Registered webhook {} for {}", webhook.id, webhook.url);
+                    created_hook = Some(webhook.id);
+                }
+            } else if let Some(id) = listen_opt.webhook {
+                match retry("get_webhooks", || api.get_webhooks())
+                    .await?
+                    .iter()
+                    .find(|w| w.id == id)
+                {
+                    Some(webhook) => eprintln!("Using webhook {} for {}", webhook.id, webhook.url),
+                    None => return Err(Error::NotFound(format!("Webhook {} not found", id))),
+                }
+            }
+            let mut listener = event_listener(&listen_opt.handler)?;
+            if let Some(secret) = secret {
+                listener = listener.with_secret(secret);
+            }
+            if let Some(path) = listen_opt.store {
+                listener = listener.with_store(&path)?;
+                eprintln!("Storing events in {}", path);
+            }
+            eprintln!("Listening on {}", addr);
+            let listener = Arc::new(listener);
+            let result = listen::serve(listener.clone(), addr, async {
+                let _ = tokio::signal::ctrl_c().await;
+            })
+            .await;
+            listener.finish().await;
+            if let Some(id) = created_hook {
+                retry("delete_webhook", || api.delete_webhook(id)).await?;
+                eprintln!("Deleted webhook {}", id);
+            }
+            result?;
+        }
+        Sub::Watch(watch_opt) => {
+            let ws = cache::get_workspace(ws_name).await?;
+            watch_list(ws.get_id(), &watch_opt).await?;
+        }
+        Sub::Replay(replay_opt) => replay(&replay_opt).await?,
+        Sub::Notify(notify_opt) => notify(&notify_opt).await?,
+        Sub::Diff(diff_opt) => diff_backups(&diff_opt)?,
+        Sub::Backup(BackupCmdOpt {
+            action: Some(BackupAction::Prune(prune_opt)),
+            ..
+        }) => {
+            prune_backups(&prune_opt)?;
+        }
+        Sub::Backup(BackupCmdOpt {
+            action: Some(BackupAction::Verify(verify_opt)),
+            ..
+        }) => verify_backup(&verify_opt)?,
+        Sub::Shell => {
+            return Err(Error::Invalid("Already in a shell session".into()));
         }
-        Sub::Backup(backup_opt) => {
+        Sub::Run(_) => {
+            return Err(Error::Invalid(
+                "run can't be used in a shell session".into(),
+            ));
+        }
+        Sub::Backup(BackupCmdOpt {
+            output,
+            backup: mut backup_opt,
+            action: None,
+        }) => {
             use std::time::SystemTime;
-            let ws = api.get_workspace(&ws_name).await?;
-            let mut lists: Vec<BackupItem> = Vec::new();
-            if let Some(ref lname) = backup_opt.list {
-                lists.push(backup_list(ws.get_id(), &lname, &backup_opt).await?);
+            backup_opt.output =
+                output.ok_or_else(|| Error::Invalid("--output is required".into()))?;
+            let upload = match backup_opt.dest {
+                Some(ref dest) => Some(S3Client::new(dest, &backup_opt.output)?),
+                None => None,
+            };
+            // progress is saved to the checkpoint, so a failed backup can be resumed
+            let checkpoint = if backup_opt.resume {
+                let checkpoint = Checkpoint::load(&backup_opt.output)?;
+                backup_opt.since = checkpoint.since()?;
+                checkpoint
             } else {
-                // backup all lists
-                for list in ws.lists.iter() {
-                    lists.push(backup_list(ws.get_id(), &list.uuid, &backup_opt).await?);
+                if backup_opt.incremental {
+                    let (last, location) = match (&upload, &backup_opt.dest) {
+                        (Some(upload), Some(dest)) => (
+                            last_uploaded_tstamp(upload, &backup_opt.output).await?,
+                            dest.to_string(),
+                        ),
+                        _ => (
+                            last_backup_tstamp(&backup_opt.output)?,
+                            backup_opt.output.clone(),
+                        ),
+                    };
+                    let last = last.ok_or_else(|| {
+                        Error::NotFound(format!(
+                            "No previous backup summary found in {} for incremental backup",
+                            location
+                        ))
+                    })?;
+                    backup_opt.since = Some(last);
                 }
-            }
-            // create summary_tstamp.json
-            let tstamp = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-                Ok(n) => n.as_millis() as u64,
-                Err(_) => 0,
+                // backup start time, used in summary and archive file names
+                let tstamp = match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+                    Ok(n) => n.as_millis() as u64,
+                    Err(_) => 0,
+                };
+                fs::create_dir_all(&backup_opt.output)?;
+                Checkpoint::create(&backup_opt.output, tstamp, backup_opt.since)?
             };
-            let summary = BackupSummary {
-                workspace: ws.name.clone(),
-                uuid: ws.uuid.clone(),
-                tstamp,
-                lists,
+            let tstamp = checkpoint.tstamp()?;
+            // when creating an archive, files are first written to a staging folder,
+            // and the archive is uploaded instead of each list's files
+            let archive_dest = backup_opt.output.clone();
+            let list_upload = match backup_opt.archive {
+                Some(_) => None,
+                None => upload.as_ref(),
+            };
+            if backup_opt.archive.is_some() {
+                backup_opt.output = format!("{}/.backup_{}", &archive_dest, tstamp);
+                fs::create_dir_all(&backup_opt.output)?;
+            }
+            // name of the workspace, for the git commit message, and the summary
+            let (backed_up, summary_data) = if backup_opt.all_workspaces {
+                let workspaces: Vec<Arc<Workspace>> = retry("get_all_workspaces_and_lists", || {
+                    api.get_all_workspaces_and_lists()
+                })
+                .await?;
+                let mut summaries = Vec::new();
+                for ws in workspaces.iter() {
+                    // each workspace is saved to a subfolder named with its uuid
+                    let mut ws_opt = backup_opt.clone();
+                    ws_opt.output = format!("{}/{}", &backup_opt.output, &ws.uuid);
+                    fs::create_dir_all(&ws_opt.output)?;
+                    let summary =
+                        backup_workspace(rest, ws, &ws_opt, tstamp, &checkpoint, list_upload)
+                            .await?;
+                    write_summary(
+                        &ws_opt.output,
+                        tstamp,
+                        &summary,
+                        backup_opt.git,
+                        list_upload,
+                    )
+                    .await?;
+                    summaries.push(summary);
+                }
+                let summary = AccountSummary {
+                    tstamp,
+                    since: backup_opt.since,
+                    workspaces: summaries,
+                };
+                let summary_data = write_summary(
+                    &backup_opt.output,
+                    tstamp,
+                    &summary,
+                    backup_opt.git,
+                    list_upload,
+                )
+                .await?;
+                (String::from("all workspaces"), summary_data)
+            } else {
+                // not from the cache, so the lists' metadata matches their items
+                let ws = retry("get_workspace", || api.get_workspace(ws_name)).await?;
+                let summary =
+                    backup_workspace(rest, &ws, &backup_opt, tstamp, &checkpoint, list_upload)
+                        .await?;
+                let summary_data = write_summary(
+                    &backup_opt.output,
+                    tstamp,
+                    &summary,
+                    backup_opt.git,
+                    list_upload,
+                )
+                .await?;
+                (ws.name.clone(), summary_data)
             };
-            let summary_fname = format!("{}/summary_{}.json", &backup_opt.output, tstamp);
-            let summary_data = serde_json::to_string(&summary).map_err(|e| {
-                Error::Message(format!("Error generating summary: {}", e.to_string()))
-            })?;
-            fs::write(summary_fname, &summary_data)?;
+            if let Some(format) = backup_opt.archive {
+                let archive_fname =
+                    format!("{}/backup_{}.{}", &archive_dest, tstamp, format.extension());
+                create_archive(&backup_opt.output, &archive_fname, format)?;
+                fs::remove_dir_all(&backup_opt.output)?;
+                println!("{}", archive_fname);
+                if let Some(ref upload) = upload {
+                    upload
+                        .upload_file(std::path::Path::new(&archive_fname))
+                        .await?;
+                }
+            }
+            if let Some(ref upload) = upload {
+                // uploaded last, so an incomplete backup isn't used for --incremental
+                upload
+                    .put(
+                        &format!("{}/{}", &archive_dest, LATEST_SUMMARY),
+                        summary_data,
+                    )
+                    .await?;
+            }
+            checkpoint.remove()?;
+            if backup_opt.git {
+                use chrono::TimeZone;
+                let message = format!(
+                    "Backup of {} at {}",
+                    backed_up,
+                    chrono::Local
+                        .timestamp_millis(tstamp as i64)
+                        .format("%Y-%m-%d %H:%M:%S")
+                );
+                if !git_commit(&backup_opt.output, &message)? {
+                    eprintln!("No changes since the last backup");
+                }
+            }
         }
     }
     Ok(())
 }
 
-#[derive(Debug, serde::Serialize)]
-struct BackupSummary {
-    workspace: String,
-    uuid: String,
-    tstamp: u64,
-    lists: Vec<BackupItem>,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The subcommand parsed from the arguments after the binary name
+    fn parse(args: &[&str]) -> Result<Sub, clap::Error> {
+        Opt::try_parse_from(std::iter::once("zk").chain(args.iter().copied())).map(|opt| opt.cmd)
+    }
+
+    #[test]
+    fn delete_item_options() {
+        assert_eq!(
+            parse(&[
+                "delete-item",
+                "-l",
+                "Tasks",
+                "-i",
+                "1",
+                "--item",
+                "item-2",
+                "-y"
+            ])
+            .unwrap(),
+            Sub::DeleteItem(DeleteItemOpt {
+                list: "Tasks".into(),
+                item: vec!["1".into(), "item-2".into()],
+                yes: true,
+            })
+        );
+        // at least one item is required
+        assert!(parse(&["delete-item", "-l", "Tasks"]).is_err());
+    }
+
+    #[test]
+    fn parse_timestamps() {
+        assert_eq!(parse_timestamp("1614556800000"), Ok(1_614_556_800_000));
+        assert_eq!(parse_timestamp("2021-03-01"), Ok(1_614_556_800_000));
+        assert_eq!(
+            parse_timestamp("2021-03-01T00:00:00+01:00"),
+            Ok(1_614_553_200_000)
+        );
+        assert!(parse_timestamp("03/01/2021").is_err());
+        assert!(parse_timestamp("-5").is_err());
+        assert!(parse_timestamp("1969-12-31").is_err());
+        assert!(parse_timestamp("1969-12-31T23:59:59Z").is_err());
+        assert_eq!(parse_timestamp("1970-01-01"), Ok(0));
+    }
+
+    #[test]
+    fn parse_durations() {
+        assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("30s"), Ok(Duration::from_secs(30)));
+        assert_eq!(parse_duration("250ms"), Ok(Duration::from_millis(250)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+        assert_eq!(parse_duration("1d"), Ok(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("0s"), Ok(Duration::from_secs(0)));
+        for invalid in [
+            "",
+            "s",
+            "ms",
+            "1.5s",
+            "-1s",
+            "5w",
+            "m5",
+            "18446744073709551615d",
+        ]
+        .iter()
+        {
+            assert!(
+                parse_duration(invalid).is_err(),
+                "{} should be invalid",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn backup_jobs() {
+        let jobs = |args: &[&str]| match parse(args).unwrap() {
+            Sub::Backup(opt) => opt.backup.jobs,
+            _ => unreachable!(),
+        };
+        assert_eq!(jobs(&["backup", "-o", "out"]), 4);
+        assert_eq!(jobs(&["backup", "-o", "out", "-j", "2"]), 2);
+        assert!(parse(&["backup", "-o", "out", "--jobs", "two"]).is_err());
+    }
+
+    #[test]
+    fn backup_actions() {
+        assert!(matches!(
+            parse(&["backup", "verify", "--dir", "out"]).unwrap(),
+            Sub::Backup(BackupCmdOpt {
+                action: Some(BackupAction::Verify(BackupVerifyOpt { dir })),
+                ..
+            }) if dir == "out"
+        ));
+        assert!(matches!(
+            parse(&["backup", "prune", "-o", "out", "--keep-last", "3"]).unwrap(),
+            Sub::Backup(BackupCmdOpt {
+                action: Some(BackupAction::Prune(BackupPruneOpt { keep_last: 3, .. })),
+                ..
+            })
+        ));
+        assert!(parse(&["backup-verify", "--dir", "out"]).is_err());
+    }
+
+    #[test]
+    fn create_list_options() {
+        assert_eq!(
+            parse(&["create-list", "-n", "Tasks", "-d", "Things to do"]).unwrap(),
+            Sub::CreateList(CreateListOpt {
+                name: "Tasks".into(),
+                description: Some("Things to do".into()),
+            })
+        );
+        assert_eq!(
+            parse(&["create-list", "--name", "Tasks"]).unwrap(),
+            Sub::CreateList(CreateListOpt {
+                name: "Tasks".into(),
+                description: None,
+            })
+        );
+        assert!(parse(&["create-list"]).is_err());
+    }
+
+    #[test]
+    fn create_field_options() {
+        assert_eq!(
+            parse(&[
+                "create-field",
+                "-l",
+                "Tasks",
+                "-n",
+                "Tags",
+                "-t",
+                "categories",
+                "-c",
+                "red",
+                "--choice",
+                "blue",
+                "-m",
+            ])
+            .unwrap(),
+            Sub::CreateField(CreateFieldOpt {
+                list: "Tasks".into(),
+                name: "Tags".into(),
+                field_type: FieldType::Categories,
+                choice: vec!["red".into(), "blue".into()],
+                multiple: true,
+                target_list: None,
+            })
+        );
+        assert!(matches!(
+            parse(&[
+                "create-field",
+                "-l",
+                "Tasks",
+                "-n",
+                "Related",
+                "--type",
+                "references",
+                "--target-list",
+                "Projects",
+            ])
+            .unwrap(),
+            Sub::CreateField(CreateFieldOpt {
+                field_type: FieldType::References,
+                target_list: Some(_),
+                ..
+            })
+        ));
+        assert!(parse(&["create-field", "-l", "Tasks", "-n", "Due", "-t", "time"]).is_err());
+    }
+
+    #[test]
+    fn delete_list_options() {
+        assert_eq!(
+            parse(&["delete-list", "-l", "Tasks", "-y"]).unwrap(),
+            Sub::DeleteList(DeleteListOpt {
+                list: "Tasks".into(),
+                yes: true,
+            })
+        );
+        assert_eq!(
+            parse(&["deprecate-list", "--list", "Tasks"]).unwrap(),
+            Sub::DeprecateList(DeleteListOpt {
+                list: "Tasks".into(),
+                yes: false,
+            })
+        );
+        assert!(parse(&["delete-list", "-y"]).is_err());
+    }
+
+    #[test]
+    fn set_actions() {
+        let action = |extra: &[&str]| {
+            let args = [
+                "set",
+                "-l",
+                "Tasks",
+                "-i",
+                "1",
+                "-f",
+                "Tags",
+                "-v",
+                "[red,blue]",
+            ];
+            match parse(&[&args[..], extra].concat()).unwrap() {
+                Sub::Set(opt) => opt.action,
+                _ => unreachable!(),
+            }
+        };
+        assert_eq!(action(&[]), SetAction::Replace);
+        assert_eq!(action(&["-a", "add"]), SetAction::Add);
+        assert_eq!(action(&["--action", "remove"]), SetAction::Remove);
+        assert!(matches!(
+            SetAction::Add.update_action(),
+            UpdateAction::Append
+        ));
+        assert!(matches!(
+            SetAction::Remove.update_action(),
+            UpdateAction::Remove
+        ));
+        assert!(matches!(
+            parse_setval("[red,blue]".into()),
+            FieldVal::ArrStr(values) if values == ["red", "blue"]
+        ));
+    }
+
+    #[test]
+    fn set_several_values() {
+        match parse(&[
+            "set",
+            "-l",
+            "Tasks",
+            "-i",
+            "1",
+            "-s",
+            "Status=Done",
+            "--set",
+            "Notes=a=b",
+        ])
+        .unwrap()
+        {
+            Sub::Set(opt) => {
+                assert_eq!(opt.field, None);
+                assert_eq!(
+                    opt.set_values,
+                    vec![
+                        ("Status".to_string(), "Done".to_string()),
+                        ("Notes".to_string(), "a=b".to_string()),
+                    ]
+                );
+            }
+            _ => unreachable!(),
+        }
+        assert!(parse(&["set", "-l", "Tasks", "-i", "1", "-s", "Status"]).is_err());
+        assert_eq!(
+            parse_key_val::<String, String>("Title=").unwrap(),
+            ("Title".to_string(), String::new())
+        );
+    }
+
+    #[test]
+    fn clear_values() {
+        use testutil::{choice_field, field};
+        assert!(matches!(
+            clear_value("Tags".into(), &choice_field(3, "Tags", &[(1, "red")])),
+            (_, FieldVal::ArrID(ids), UpdateAction::Replace) if ids.is_empty()
+        ));
+        assert!(matches!(
+            clear_value("Due".into(), &field(4, "Due", 4)),
+            (name, FieldVal::Str(s), UpdateAction::Null) if name == "Due" && s.is_empty()
+        ));
+        let set = ["set", "-l", "Tasks", "-i", "1", "-f", "Due", "--null"];
+        assert!(matches!(
+            parse(&set),
+            Ok(Sub::Set(SetValueOpt { null: true, .. }))
+        ));
+        assert!(parse(&[&set[..], &["-v", "today"]].concat()).is_err());
+    }
+
+    #[test]
+    fn item_options() {
+        assert_eq!(
+            parse(&["item", "-l", "Tasks", "-i", "item-1", "-r"]).unwrap(),
+            Sub::Item(ItemOpt {
+                list: "Tasks".into(),
+                item: "item-1".into(),
+                resolve: true,
+                template: None,
+            })
+        );
+        assert!(matches!(
+            parse(&["item", "-l", "Tasks", "-i", "1"]),
+            Ok(Sub::Item(ItemOpt { resolve: false, .. }))
+        ));
+    }
+
+    #[test]
+    fn comment_options() {
+        assert_eq!(
+            parse(&["edit-comment", "--id", "12", "-c", "Updated"]).unwrap(),
+            Sub::EditComment(EditCommentOpt {
+                id: 12,
+                comment: "Updated".into(),
+            })
+        );
+        assert_eq!(
+            parse(&["delete-comment", "--id", "12", "-y"]).unwrap(),
+            Sub::DeleteComment(DeleteCommentOpt { id: 12, yes: true })
+        );
+        assert!(parse(&["edit-comment", "--id", "x", "-c", "Updated"]).is_err());
+    }
+
+    #[test]
+    fn whoami_line() {
+        let user = serde_json::json!({
+            "id": 7,
+            "uuid": "user-7",
+            "displayname": "Ann",
+            "emails": [{ "email": "ann@example.com" }],
+        });
+        assert_eq!(user_line(&user), "7\tuser-7\tAnn\tann@example.com");
+        assert_eq!(user_line(&serde_json::json!({})), "0\t\t\t");
+        assert_eq!(parse(&["whoami"]).unwrap(), Sub::Whoami);
+    }
+
+    #[test]
+    fn profile_settings() {
+        let mut settings = Config::default();
+        settings
+            .merge(config::File::from_str(
+                r#"
+                [zenkit]
+                token = "00000"
+                workspace = "Home"
+
+                [profiles.work]
+                token = "11111"
+                "#,
+                config::FileFormat::Toml,
+            ))
+            .unwrap();
+        let setting = |profile, key| get_setting(&settings, profile, key);
+        assert_eq!(setting(None, "token").as_deref(), Some("00000"));
+        assert_eq!(setting(Some("work"), "token").as_deref(), Some("11111"));
+        // settings not in the profile are read from [zenkit]
+        assert_eq!(setting(Some("work"), "workspace").as_deref(), Some("Home"));
+        assert_eq!(setting(Some("work"), "endpoint"), None);
+    }
+
+    #[test]
+    fn log_options() {
+        let opt = Opt::try_parse_from(&["zk", "lists"]).unwrap();
+        assert_eq!((opt.verbose, opt.log_format), (0, OutputFormat::Text));
+        let opt = Opt::try_parse_from(&["zk", "-vv", "--log-format", "json", "lists"]).unwrap();
+        assert_eq!((opt.verbose, opt.log_format), (2, OutputFormat::Json));
+        assert!(Opt::try_parse_from(&["zk", "--log-format", "xml", "lists"]).is_err());
+    }
+
+    #[test]
+    fn dry_run_flags() {
+        let opt = Opt::try_parse_from(&["zk", "--dry-run", "delete-list", "-l", "Tasks"]).unwrap();
+        assert!(opt.dry_run && !opt.cmd.dry_run());
+        let bulk_set = ["bulk-set", "-l", "Tasks", "-f", "Status", "-v", "Done"];
+        assert!(!parse(&bulk_set).unwrap().dry_run());
+        assert!(parse(&[&bulk_set[..], &["--dry-run"]].concat())
+            .unwrap()
+            .dry_run());
+        assert!(parse(&["archive", "-l", "Tasks", "--dry-run"])
+            .unwrap()
+            .dry_run());
+    }
+
+    #[test]
+    fn json_field_values() {
+        let path = std::env::temp_dir().join(format!("zk_values_{}.json", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        std::fs::write(
+            &path,
+            r#"{
+                "Title": "Write tests",
+                "Points": 3,
+                "Tags": ["red", "blue"],
+                "Notes": { "value": "**soon**", "format": "markdown" },
+                "Due": null
+            }"#,
+        )
+        .unwrap();
+        let values = json_values(path_str).unwrap();
+        let value = |name: &str| values.iter().find(|(f, _)| f == name).map(|(_, v)| v);
+        assert_eq!(values.len(), 4);
+        assert!(matches!(value("Title"), Some(FieldVal::Str(s)) if s == "Write tests"));
+        assert!(matches!(value("Points"), Some(FieldVal::Str(s)) if s == "3"));
+        assert!(matches!(value("Tags"), Some(FieldVal::ArrStr(v)) if v == &["red", "blue"]));
+        assert!(matches!(
+            value("Notes"),
+            Some(FieldVal::Formatted(s, TextFormat::Markdown)) if s == "**soon**"
+        ));
+
+        std::fs::write(&path, r#"{ "Notes": { "value": "x" } }"#).unwrap();
+        assert!(matches!(json_values(path_str), Err(Error::Invalid(_))));
+        std::fs::write(&path, r#"["Write tests"]"#).unwrap();
+        assert!(matches!(json_values(path_str), Err(Error::Invalid(_))));
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn archive_options() {
+        assert_eq!(
+            parse(&[
+                "archive",
+                "-l",
+                "Tasks",
+                "--filter",
+                "Status=Done",
+                "--filter",
+                "Owner=Ann",
+                "--older-than",
+                "90d",
+            ])
+            .unwrap(),
+            Sub::Archive(ArchiveOpt {
+                list: "Tasks".into(),
+                filter: vec![
+                    ("Status".into(), "Done".into()),
+                    ("Owner".into(), "Ann".into())
+                ],
+                older_than: Some(Duration::from_secs(90 * 86400)),
+                batch_size: 50,
+                dry_run: false,
+                yes: false,
+            })
+        );
+        assert!(parse(&["archive", "-l", "Tasks", "--older-than", "3 months"]).is_err());
+        assert!(parse(&["archive", "-l", "Tasks", "--filter", "Done"]).is_err());
+    }
+
+    #[test]
+    fn user_options() {
+        assert_eq!(
+            parse(&["invite-user", "-e", "ann@example.com"]).unwrap(),
+            Sub::InviteUser(InviteUserOpt {
+                email: "ann@example.com".into(),
+                role: UserRole::Editor,
+            })
+        );
+        match parse(&["invite-user", "-e", "ann@example.com", "-r", "reader"]).unwrap() {
+            Sub::InviteUser(opt) => assert_eq!(opt.role.role_id(), "reader"),
+            sub => panic!("unexpected {:?}", sub),
+        }
+        assert!(parse(&["invite-user", "-e", "ann@example.com", "-r", "owner"]).is_err());
+        assert_eq!(
+            parse(&["remove-user", "-u", "Ann", "-y"]).unwrap(),
+            Sub::RemoveUser(RemoveUserOpt {
+                user: "Ann".into(),
+                yes: true,
+            })
+        );
+    }
 }
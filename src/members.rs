@@ -0,0 +1,144 @@
+//! Workspace members: list with roles, invite, and remove
+use crate::{
+    confirm, dryrun, rest::RestClient, values::user_emails, Error, InviteUserOpt, RemoveUserOpt,
+};
+use serde_json::{json, Value};
+use std::result::Result;
+use zenkit::types::ID;
+
+/// Print the workspace's users, with their role in the workspace
+pub(crate) async fn list_users(rest: &RestClient, ws_id: ID) -> Result<(), Error> {
+    for user in workspace_users(rest, ws_id).await?.iter() {
+        println!(
+            "{}\t{}\t{}\t{}",
+            user.get("id").cloned().unwrap_or_default(),
+            str_value(user, "uuid"),
+            str_value(user, "displayname"),
+            user_role(user).unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Invite a user, by email address, to the workspace
+pub(crate) async fn invite_user(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &InviteUserOpt,
+) -> Result<(), Error> {
+    let body = json!({ "email": opt.email, "roleId": opt.role.role_id() });
+    let resp = rest
+        .post(&format!("workspaces/{}/users", ws_id), &body)
+        .await?;
+    if !dryrun::enabled() {
+        println!(
+            "Invited {} as {}{}",
+            opt.email,
+            opt.role.role_id(),
+            resp.get("id")
+                .map(|id| format!(" (user {})", id))
+                .unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Remove a user from the workspace. Asks for confirmation unless `--yes` is used.
+/// Returns false if cancelled.
+pub(crate) async fn remove_user(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &RemoveUserOpt,
+) -> Result<bool, Error> {
+    let users = workspace_users(rest, ws_id).await?;
+    let matches: Vec<&Value> = users
+        .iter()
+        .filter(|user| user_matches(user, &opt.user))
+        .collect();
+    let user = match matches.as_slice() {
+        [user] => *user,
+        [] => return Err(Error::NotFound(format!("User '{}' not found", opt.user))),
+        _ => {
+            return Err(Error::Invalid(format!(
+                "'{}' matches {} users: use the user's id or email",
+                opt.user,
+                matches.len()
+            )))
+        }
+    };
+    let id = user
+        .get("id")
+        .and_then(|v| v.as_u64())
+        .ok_or_else(|| Error::Message("User has no id".into()))?;
+    let name = str_value(user, "displayname");
+    if !opt.yes && !confirm(&format!("Remove {} from the workspace?", name))? {
+        return Ok(false);
+    }
+    rest.delete(&format!("workspaces/{}/users/{}", ws_id, id))
+        .await?;
+    if !dryrun::enabled() {
+        println!("Removed {}\t{}", id, name);
+    }
+    Ok(true)
+}
+
+async fn workspace_users(rest: &RestClient, ws_id: ID) -> Result<Vec<Value>, Error> {
+    match rest.get(&format!("workspaces/{}/users", ws_id)).await? {
+        Value::Array(users) => Ok(users),
+        _ => Ok(Vec::new()),
+    }
+}
+
+/// True if the user has the id, uuid, display name, or email address (ignoring case)
+fn user_matches(user: &Value, name: &str) -> bool {
+    user.get("id").map(|id| id.to_string()).as_deref() == Some(name)
+        || str_value(user, "uuid") == name
+        || str_value(user, "displayname") == name
+        || user_emails(user)
+            .iter()
+            .any(|email| email.eq_ignore_ascii_case(name))
+}
+
+/// The user's role in the workspace, from the user's access to it
+fn user_role(user: &Value) -> Option<&str> {
+    user.get("roleId")
+        .or_else(|| user.get("access").and_then(|a| a.get("roleId")))
+        .or_else(|| user.get("role"))
+        .and_then(|v| v.as_str())
+}
+
+fn str_value<'v>(value: &'v Value, key: &str) -> &'v str {
+    value.get(key).and_then(|v| v.as_str()).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matching_users() {
+        let user = json!({
+            "id": 7,
+            "uuid": "user-7",
+            "displayname": "Ann Lee",
+            "emails": [{ "email": "ann@example.com" }],
+        });
+        for name in ["7", "user-7", "Ann Lee", "ANN@example.com"].iter() {
+            assert!(user_matches(&user, name), "{} should match", name);
+        }
+        for name in ["Ann", "8", "ann@example"].iter() {
+            assert!(!user_matches(&user, name), "{} shouldn't match", name);
+        }
+    }
+
+    #[test]
+    fn user_roles() {
+        assert_eq!(user_role(&json!({ "roleId": "admin" })), Some("admin"));
+        assert_eq!(
+            user_role(&json!({ "access": { "roleId": "reader" } })),
+            Some("reader")
+        );
+        assert_eq!(user_role(&json!({ "role": "editor" })), Some("editor"));
+        assert_eq!(user_role(&json!({ "displayname": "Ann" })), None);
+    }
+}
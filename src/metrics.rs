@@ -0,0 +1,155 @@
+//! Api usage and timing of the run, printed with `--stats`
+use crate::OutputFormat;
+use serde::Serialize;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static METRICS: Mutex<Metrics> = Mutex::new(Metrics {
+    failed: 0,
+    rate_limited: 0,
+    retries: 0,
+    bytes_sent: 0,
+    bytes_received: 0,
+    latencies_ms: Vec::new(),
+});
+
+struct Metrics {
+    failed: u64,
+    rate_limited: u64,
+    retries: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    /// latency of each request
+    latencies_ms: Vec<u64>,
+}
+
+#[derive(Debug, Serialize)]
+struct Summary {
+    requests: usize,
+    failed: u64,
+    rate_limited: u64,
+    retries: u64,
+    bytes_sent: u64,
+    bytes_received: u64,
+    latency_ms: Latency,
+}
+
+#[derive(Debug, Serialize)]
+struct Latency {
+    total: u64,
+    p50: u64,
+    p90: u64,
+    p99: u64,
+    max: u64,
+}
+
+/// Enable or disable collection of metrics
+pub(crate) fn configure(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Returns true if metrics are collected
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+fn update<F: FnOnce(&mut Metrics)>(f: F) {
+    if enabled() {
+        if let Ok(mut m) = METRICS.lock() {
+            f(&mut m);
+        }
+    }
+}
+
+/// Record a request (one attempt), with its latency, and whether it failed
+/// or was rejected by rate limiting
+pub(crate) fn record_request(latency: Duration, failed: bool, rate_limited: bool) {
+    update(|m| {
+        m.latencies_ms.push(latency.as_millis() as u64);
+        if failed {
+            m.failed += 1;
+        }
+        if rate_limited {
+            m.rate_limited += 1;
+        }
+    })
+}
+
+/// Record a retry of a failed request
+pub(crate) fn record_retry() {
+    update(|m| m.retries += 1)
+}
+
+/// Record the size of a request body and response body
+pub(crate) fn record_bytes(sent: usize, received: usize) {
+    update(|m| {
+        m.bytes_sent += sent as u64;
+        m.bytes_received += received as u64;
+    })
+}
+
+/// Print the metrics to stderr, as text or pretty-printed json
+pub(crate) fn print(format: OutputFormat) {
+    let summary = match METRICS.lock() {
+        Ok(m) => summarize(&m),
+        Err(_) => return,
+    };
+    match format {
+        OutputFormat::Json => {
+            if let Ok(json) = serde_json::to_string_pretty(&summary) {
+                eprintln!("{}", json);
+            }
+        }
+        OutputFormat::Text => {
+            eprintln!(
+                "{} api requests ({} failed, {} rate-limited), {} retries",
+                summary.requests, summary.failed, summary.rate_limited, summary.retries
+            );
+            eprintln!(
+                "{} bytes sent, {} bytes received",
+                summary.bytes_sent, summary.bytes_received
+            );
+            let l = &summary.latency_ms;
+            eprintln!(
+                "latency: total {}ms, p50 {}ms, p90 {}ms, p99 {}ms, max {}ms",
+                l.total, l.p50, l.p90, l.p99, l.max
+            );
+        }
+    }
+}
+
+fn summarize(m: &Metrics) -> Summary {
+    let mut sorted = m.latencies_ms.clone();
+    sorted.sort_unstable();
+    Summary {
+        requests: sorted.len(),
+        failed: m.failed,
+        rate_limited: m.rate_limited,
+        retries: m.retries,
+        bytes_sent: m.bytes_sent,
+        bytes_received: m.bytes_received,
+        latency_ms: Latency {
+            total: sorted.iter().sum(),
+            p50: percentile(&sorted, 50),
+            p90: percentile(&sorted, 90),
+            p99: percentile(&sorted, 99),
+            max: sorted.last().copied().unwrap_or(0),
+        },
+    }
+}
+
+/// Nearest-rank percentile of sorted values, or 0 if there are none
+fn percentile(sorted: &[u64], p: usize) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = (p * sorted.len()).div_ceil(100);
+    sorted[rank.max(1) - 1]
+}
@@ -0,0 +1,356 @@
+//! Mirror lists into a local database
+use crate::{
+    backup::get_all_items,
+    cache,
+    values::{raw_value, ValueFormatter},
+    Error, MirrorOpt, MirrorTarget, SqliteOpt,
+};
+use chrono::Utc;
+use rusqlite::{params, types::Value as SqlValue, Connection, OptionalExtension};
+use std::{collections::HashSet, result::Result};
+use zenkit::types::{Element, ElementCategoryId, Entry, ZKObjectID, ID};
+
+/// Copy list items to the target database
+pub(crate) async fn mirror(ws_id: ID, opt: &MirrorOpt) -> Result<(), Error> {
+    match opt.target {
+        MirrorTarget::Sqlite(ref sqlite_opt) => mirror_sqlite(ws_id, sqlite_opt).await,
+    }
+}
+
+/// Table recording the last sync of each list
+const SYNC_TABLE: &str = "_zk_sync";
+
+/// A column of a list's table
+struct Column<'l> {
+    name: String,
+    sql_type: &'static str,
+    /// None for the item's id, uuid, title, and timestamps
+    field: Option<&'l Element>,
+}
+
+/// Copy the list's items to a table in a sqlite database, with a column for each field.
+/// The table is created, and columns are added for new fields, as needed. Items updated
+/// since the last sync are inserted or replaced, and rows of deleted items are removed.
+/// The sync time and the latest item update time (the watermark) are recorded in `_zk_sync`.
+async fn mirror_sqlite(ws_id: ID, opt: &SqliteOpt) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let list = list_info.list();
+    let table = opt.table.clone().unwrap_or_else(|| list.name.clone());
+    let columns = table_columns(list_info.fields());
+
+    let mut conn = Connection::open(&opt.db)?;
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {} (list_uuid TEXT PRIMARY KEY, list_name TEXT NOT NULL, \
+         table_name TEXT NOT NULL, synced_at TEXT NOT NULL, watermark INTEGER NOT NULL)",
+        SYNC_TABLE
+    ))?;
+    let previous: Option<(String, i64)> = conn
+        .query_row(
+            &format!(
+                "SELECT table_name, watermark FROM {} WHERE list_uuid = ?1",
+                SYNC_TABLE
+            ),
+            params![list.uuid],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    let added = create_table(&conn, &table, &columns)?;
+    // all items are copied if the table or its columns are new
+    let watermark = match previous {
+        Some((ref name, watermark)) if !opt.full && added == 0 && *name == table => watermark,
+        _ => 0,
+    };
+
+    let items = get_all_items(&list.uuid, false).await?;
+    let formatter = ValueFormatter::new(ws_id).await?;
+    let insert = format!(
+        "INSERT OR REPLACE INTO {} ({}) VALUES ({})",
+        quote(&table),
+        columns
+            .iter()
+            .map(|c| quote(&c.name))
+            .collect::<Vec<_>>()
+            .join(", "),
+        (1..=columns.len())
+            .map(|n| format!("?{}", n))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let tx = conn.transaction()?;
+    let mut updated = 0;
+    for item in items
+        .iter()
+        .filter(|item| item.updated_at.timestamp_millis() > watermark)
+    {
+        let values: Vec<SqlValue> = columns
+            .iter()
+            .map(|c| column_value(item, c, &formatter))
+            .collect();
+        tx.execute(&insert, rusqlite::params_from_iter(values.iter()))?;
+        updated += 1;
+    }
+    let ids: HashSet<i64> = items.iter().map(|item| item.get_id() as i64).collect();
+    let stored: Vec<i64> = {
+        let mut stmt = tx.prepare(&format!("SELECT id FROM {}", quote(&table)))?;
+        let rows = stmt.query_map(params![], |row| row.get(0))?;
+        rows.collect::<Result<Vec<i64>, rusqlite::Error>>()?
+    };
+    let mut deleted = 0;
+    for id in stored.iter().filter(|id| !ids.contains(id)) {
+        tx.execute(
+            &format!("DELETE FROM {} WHERE id = ?1", quote(&table)),
+            params![id],
+        )?;
+        deleted += 1;
+    }
+    let new_watermark = items
+        .iter()
+        .map(|item| item.updated_at.timestamp_millis())
+        .max()
+        .unwrap_or(0)
+        .max(watermark);
+    tx.execute(
+        &format!(
+            "INSERT OR REPLACE INTO {} (list_uuid, list_name, table_name, synced_at, watermark) \
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            SYNC_TABLE
+        ),
+        params![
+            list.uuid,
+            list.name,
+            table,
+            Utc::now().to_rfc3339(),
+            new_watermark
+        ],
+    )?;
+    tx.commit()?;
+    println!(
+        "{}: {} items, {} inserted or updated, {} deleted",
+        table,
+        items.len(),
+        updated,
+        deleted
+    );
+    Ok(())
+}
+
+/// Columns for the item's id, uuid, title, and timestamps, and one for each field
+/// (except files). Columns are named after fields, with the field's id appended
+/// if the name is already used (column names are not case-sensitive).
+fn table_columns(fields: &[Element]) -> Vec<Column<'_>> {
+    let mut columns = vec![
+        Column {
+            name: "id".into(),
+            sql_type: "INTEGER PRIMARY KEY",
+            field: None,
+        },
+        Column {
+            name: "uuid".into(),
+            sql_type: "TEXT NOT NULL",
+            field: None,
+        },
+        Column {
+            name: "title".into(),
+            sql_type: "TEXT",
+            field: None,
+        },
+        Column {
+            name: "created_at".into(),
+            sql_type: "TEXT",
+            field: None,
+        },
+        Column {
+            name: "updated_at".into(),
+            sql_type: "TEXT",
+            field: None,
+        },
+    ];
+    for field in fields
+        .iter()
+        .filter(|f| f.element_category != ElementCategoryId::Files)
+    {
+        let mut name = field.name.clone();
+        if columns.iter().any(|c| c.name.eq_ignore_ascii_case(&name)) {
+            name = format!("{}_{}", field.name, field.id);
+        }
+        let sql_type = match field.element_category {
+            ElementCategoryId::Number => "REAL",
+            ElementCategoryId::Checkbox => "INTEGER",
+            _ => "TEXT",
+        };
+        columns.push(Column {
+            name,
+            sql_type,
+            field: Some(field),
+        });
+    }
+    columns
+}
+
+/// Create the table if it doesn't exist, and add any missing columns.
+/// Returns the number of columns added to an existing table.
+fn create_table(conn: &Connection, table: &str, columns: &[Column]) -> Result<usize, Error> {
+    conn.execute_batch(&format!(
+        "CREATE TABLE IF NOT EXISTS {} ({})",
+        quote(table),
+        columns
+            .iter()
+            .map(|c| format!("{} {}", quote(&c.name), c.sql_type))
+            .collect::<Vec<_>>()
+            .join(", ")
+    ))?;
+    let existing: Vec<String> = {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", quote(table)))?;
+        let rows = stmt.query_map(params![], |row| row.get(1))?;
+        rows.collect::<Result<Vec<String>, rusqlite::Error>>()?
+    };
+    let mut added = 0;
+    for column in columns
+        .iter()
+        .filter(|c| !existing.iter().any(|e| e.eq_ignore_ascii_case(&c.name)))
+    {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            quote(table),
+            quote(&column.name),
+            column.sql_type
+        ))?;
+        added += 1;
+    }
+    Ok(added)
+}
+
+/// Value of the column for the item. Numbers and checkboxes are stored as numbers,
+/// dates as stored by Zenkit, and other fields as display values.
+fn column_value(item: &Entry, column: &Column, formatter: &ValueFormatter) -> SqlValue {
+    let field = match column.field {
+        Some(field) => field,
+        None => {
+            return match column.name.as_str() {
+                "id" => SqlValue::Integer(item.get_id() as i64),
+                "uuid" => SqlValue::Text(item.get_uuid().to_string()),
+                "title" => SqlValue::Text(item.display_string.clone()),
+                "created_at" => SqlValue::Text(item.created_at.to_rfc3339()),
+                _ => SqlValue::Text(item.updated_at.to_rfc3339()),
+            }
+        }
+    };
+    let raw = raw_value(item, field);
+    match field.element_category {
+        ElementCategoryId::Number => raw
+            .and_then(|v| v.as_f64())
+            .map(SqlValue::Real)
+            .unwrap_or(SqlValue::Null),
+        ElementCategoryId::Checkbox => raw
+            .and_then(|v| v.as_bool())
+            .map(|b| SqlValue::Integer(b as i64))
+            .unwrap_or(SqlValue::Null),
+        ElementCategoryId::Date => raw
+            .and_then(|v| v.as_str())
+            .map(|s| SqlValue::Text(s.to_string()))
+            .unwrap_or(SqlValue::Null),
+        _ => formatter
+            .display(item, field)
+            .map(SqlValue::Text)
+            .unwrap_or(SqlValue::Null),
+    }
+}
+
+/// Quoted sql identifier
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{field, item};
+    use serde_json::json;
+
+    #[test]
+    fn columns_of_fields() {
+        let fields = vec![
+            field(1, "Title", 1),
+            field(2, "Points", 2),
+            field(3, "Done", 5),
+            field(4, "Attachments", ElementCategoryId::Files as u8),
+            field(5, "ID", 1),
+            field(6, "Due", 4),
+        ];
+        let columns = table_columns(&fields);
+        let columns: Vec<(&str, &str)> = columns
+            .iter()
+            .map(|c| (c.name.as_str(), c.sql_type))
+            .collect();
+        assert_eq!(
+            columns,
+            vec![
+                ("id", "INTEGER PRIMARY KEY"),
+                ("uuid", "TEXT NOT NULL"),
+                ("title", "TEXT"),
+                ("created_at", "TEXT"),
+                ("updated_at", "TEXT"),
+                ("Title_1", "TEXT"),
+                ("Points", "REAL"),
+                ("Done", "INTEGER"),
+                ("ID_5", "TEXT"),
+                ("Due", "TEXT"),
+            ]
+        );
+    }
+
+    #[test]
+    fn column_values() {
+        let fields = vec![
+            field(2, "Points", 2),
+            field(3, "Done", 5),
+            field(6, "Due", 4),
+            field(7, "Notes", 1),
+        ];
+        let columns = table_columns(&fields);
+        let item = item(
+            9,
+            "Write tests",
+            json!({
+                "field-2_number": 2.5,
+                "field-3_checked": true,
+                "field-6_date": "2021-03-05",
+                "field-7_text": null,
+            }),
+        );
+        let formatter = ValueFormatter::default();
+        let values: Vec<SqlValue> = columns
+            .iter()
+            .map(|c| column_value(&item, c, &formatter))
+            .collect();
+        assert_eq!(values[0], SqlValue::Integer(9));
+        assert_eq!(values[1], SqlValue::Text("item-9".into()));
+        assert_eq!(values[2], SqlValue::Text("Write tests".into()));
+        assert_eq!(
+            values[5..].to_vec(),
+            vec![
+                SqlValue::Real(2.5),
+                SqlValue::Integer(1),
+                SqlValue::Text("2021-03-05".into()),
+                SqlValue::Null,
+            ]
+        );
+    }
+
+    #[test]
+    fn add_columns() {
+        let conn = Connection::open_in_memory().unwrap();
+        let fields = vec![field(2, "Points", 2), field(3, "Due \"date\"", 4)];
+        let columns = table_columns(&fields);
+        assert_eq!(create_table(&conn, "Tasks", &columns[..6]).unwrap(), 0);
+        // a new field is added as a column
+        assert_eq!(create_table(&conn, "Tasks", &columns).unwrap(), 1);
+        assert_eq!(create_table(&conn, "Tasks", &columns).unwrap(), 0);
+        // column names are quoted
+        conn.execute_batch(
+            r#"INSERT INTO "Tasks" (id, uuid, "Due ""date""") VALUES (1, 'a', 'x')"#,
+        )
+        .unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+//! Items assigned to the authenticated user, across lists
+use crate::{
+    backup::get_all_items,
+    cache,
+    dates::local_date,
+    rest::RestClient,
+    values::{raw_value, ValueFormatter},
+    Error, MyItemsOpt, OutputFormat,
+};
+use chrono::{Local, NaiveDate};
+use serde::Serialize;
+use std::{result::Result, sync::Arc};
+use zenkit::types::{Element, ElementCategoryId, Workspace, ZKObjectID, ID};
+
+/// An item with the user in one of its persons fields
+#[derive(Debug, Serialize)]
+struct MyItem {
+    workspace: String,
+    list: String,
+    id: ID,
+    uuid: String,
+    title: String,
+    /// due date, YYYY-MM-DD
+    due: Option<String>,
+    overdue: bool,
+    /// persons fields with the user
+    fields: Vec<String>,
+    #[serde(skip)]
+    due_date: Option<NaiveDate>,
+}
+
+/// Print the items, in all active lists of the workspaces, that have the authenticated
+/// user in a persons field, sorted by due date (items without one last).
+/// The due date is the value of the date field named `--due-field` (ignoring case),
+/// or else of the first date field with "due" in its name.
+/// Returns the number of items.
+pub(crate) async fn my_items(
+    rest: &RestClient,
+    workspaces: &[Arc<Workspace>],
+    opt: &MyItemsOpt,
+) -> Result<usize, Error> {
+    let user = rest.get("users/me").await?;
+    let uuid = user
+        .get("uuid")
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| Error::Message("Authenticated user has no uuid".into()))?
+        .to_string();
+    let today = Local::today().naive_local();
+
+    let mut found = Vec::new();
+    for ws in workspaces.iter() {
+        let ws_id = ws.get_id();
+        let formatter = ValueFormatter::new(ws_id).await?;
+        for list in ws.lists.iter().filter(|l| l.deprecated_at.is_none()) {
+            let list_info = cache::get_list_info(ws_id, &list.uuid).await?;
+            let persons: Vec<&Element> = list_info
+                .fields()
+                .iter()
+                .filter(|f| f.element_category == ElementCategoryId::Persons)
+                .collect();
+            if persons.is_empty() {
+                continue;
+            }
+            let due_field = due_field(list_info.fields(), &opt.due_field);
+            for item in get_all_items(&list.uuid, false).await?.iter() {
+                let fields: Vec<String> = persons
+                    .iter()
+                    .filter(|f| formatter.has_value(item, f, &uuid))
+                    .map(|f| f.name.clone())
+                    .collect();
+                if fields.is_empty() {
+                    continue;
+                }
+                let due_date = due_field
+                    .and_then(|f| raw_value(item, f))
+                    .and_then(|v| v.as_str())
+                    .and_then(local_date);
+                found.push(MyItem {
+                    workspace: ws.name.clone(),
+                    list: list.name.clone(),
+                    id: item.get_id(),
+                    uuid: item.uuid.clone(),
+                    title: item.display_string.clone(),
+                    due: due_date.map(|d| d.format("%Y-%m-%d").to_string()),
+                    overdue: due_date.map(|d| d < today).unwrap_or(false),
+                    fields,
+                    due_date,
+                });
+            }
+        }
+    }
+    // items without a due date last
+    found.sort_by_key(|item| (item.due_date.is_none(), item.due_date));
+
+    match opt.output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&found)?),
+        OutputFormat::Text => {
+            for item in found.iter() {
+                let list = if workspaces.len() > 1 {
+                    format!("{}/{}", item.workspace, item.list)
+                } else {
+                    item.list.clone()
+                };
+                println!(
+                    "{}\t{}\t{}\t{}{}",
+                    item.due.as_deref().unwrap_or("-"),
+                    list,
+                    item.id,
+                    item.title,
+                    if item.overdue { "\t(overdue)" } else { "" }
+                );
+            }
+        }
+    }
+    Ok(found.len())
+}
+
+/// The date field named `name` (ignoring case), or the first date field with "due"
+/// in its name
+pub(crate) fn due_field<'f>(fields: &'f [Element], name: &str) -> Option<&'f Element> {
+    let dates = || {
+        fields
+            .iter()
+            .filter(|f| f.element_category == ElementCategoryId::Date)
+    };
+    dates()
+        .find(|f| f.name.eq_ignore_ascii_case(name))
+        .or_else(|| dates().find(|f| f.name.to_lowercase().contains("due")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::field;
+
+    #[test]
+    fn due_date_field() {
+        let fields = vec![
+            field(1, "Title", 1),
+            field(2, "Due (text)", 1),
+            field(3, "Start", 4),
+            field(4, "Due date", 4),
+            field(5, "Deadline", 4),
+        ];
+        let name = |name| due_field(&fields, name).map(|f| f.name.as_str());
+        assert_eq!(name("deadline"), Some("Deadline"));
+        // the first date field with "due" in its name
+        assert_eq!(name("Due"), Some("Due date"));
+        assert_eq!(name("Title"), Some("Due date"));
+        assert_eq!(due_field(&fields[..3], "Due"), None);
+    }
+}
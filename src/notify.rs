@@ -0,0 +1,169 @@
+//! Post events and query results to a Slack (or Mattermost) channel,
+//! with an incoming webhook url
+use crate::{dryrun, http, retry::retry_rate_limited, Error, NotifyOpt};
+use serde_json::{json, Value};
+use std::{
+    io::{BufRead, BufReader},
+    result::Result,
+};
+
+/// Maximum number of rows of an array (e.g., query results) in one message
+const MAX_ROWS: usize = 50;
+
+/// Formats values as messages and posts them to an incoming webhook
+pub(crate) struct SlackNotifier {
+    client: reqwest::Client,
+    url: String,
+    /// message text, with {key} replaced by the value's top-level values
+    template: Option<String>,
+}
+
+impl SlackNotifier {
+    pub(crate) fn new(url: String, template: Option<String>) -> Result<Self, Error> {
+        Ok(SlackNotifier {
+            client: http::client()?,
+            url,
+            template,
+        })
+    }
+
+    /// Message text for a value: the template, if set, or a summary of a webhook event
+    /// or watch event, or the value as json. For an array, the text for each element,
+    /// one per line. Returns None for an empty array.
+    pub(crate) fn message(&self, value: &Value) -> Option<String> {
+        match value {
+            Value::Array(rows) if rows.is_empty() => None,
+            Value::Array(rows) => {
+                let mut lines: Vec<String> = rows
+                    .iter()
+                    .take(MAX_ROWS)
+                    .map(|row| self.format(row))
+                    .collect();
+                if rows.len() > MAX_ROWS {
+                    lines.push(format!("... and {} more", rows.len() - MAX_ROWS));
+                }
+                Some(lines.join("\n"))
+            }
+            value => Some(self.format(value)),
+        }
+    }
+
+    fn format(&self, value: &Value) -> String {
+        if let Some(ref template) = self.template {
+            return fill_template(template, value);
+        }
+        let text = |key: &str| value.get(key).and_then(|v| v.as_str());
+        // watch event
+        if let (Some(event), Some(name)) = (text("event"), text("display_string")) {
+            let mut message = format!("{} in {}: {}", event, text("list").unwrap_or(""), name);
+            if let Some(fields) = value.get("changed_fields").and_then(|v| v.as_array()) {
+                let names: Vec<&str> = fields.iter().filter_map(|f| f.as_str()).collect();
+                message.push_str(&format!(" ({})", names.join(", ")));
+            }
+            return message;
+        }
+        // webhook event: activity or comment, or item
+        if let Some(message) = text("message") {
+            return message.to_string();
+        }
+        if let Some(name) = text("displayString") {
+            return format!("Item changed: {}", name);
+        }
+        format!("```{}```", value)
+    }
+
+    /// Post the message to the webhook
+    pub(crate) async fn post(&self, text: &str) -> Result<(), Error> {
+        let body = json!({ "text": text });
+        if dryrun::enabled() {
+            dryrun::print_request("POST", &self.url, Some(&body));
+            return Ok(());
+        }
+        retry_rate_limited("slack post", || async {
+            let resp = self.client.post(&self.url).json(&body).send().await?;
+            let status = resp.status();
+            if !status.is_success() {
+                let text = resp.text().await.unwrap_or_default();
+                return Err(Error::Http {
+                    status: Some(status.as_u16()),
+                    retry_after: None,
+                    transient: false,
+                    message: format!("Slack webhook failed ({}): {}", status, text),
+                });
+            }
+            Ok(())
+        })
+        .await
+    }
+
+    /// Post a message for the value, unless it's an empty array
+    pub(crate) async fn notify(&self, value: &Value) -> Result<(), Error> {
+        match self.message(value) {
+            Some(text) => self.post(&text).await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// Replace each {key} in the template with the value's top-level value for key:
+/// strings as is, other values as json, and missing keys with nothing
+fn fill_template(template: &str, value: &Value) -> String {
+    let mut text = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        text.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+        match rest.find('}') {
+            Some(end) => {
+                match value.get(&rest[..end]) {
+                    Some(Value::String(s)) => text.push_str(s),
+                    Some(Value::Null) | None => {}
+                    Some(v) => text.push_str(&v.to_string()),
+                }
+                rest = &rest[end + 1..];
+            }
+            None => {
+                text.push('{');
+                break;
+            }
+        }
+    }
+    text.push_str(rest);
+    text
+}
+
+/// Post the message text, or a message for each json value read from stdin
+/// (json lines, as from `watch` or `listen`, or json, as from `query --output json`)
+pub(crate) async fn notify(opt: &NotifyOpt) -> Result<(), Error> {
+    let notifier = SlackNotifier::new(opt.slack_url.clone(), opt.template.clone())?;
+    if let Some(ref text) = opt.text {
+        return notifier.post(text).await;
+    }
+    let stdin = std::io::stdin();
+    let mut reader = BufReader::new(stdin.lock());
+    // read a line at a time, so that a message is posted as soon as each event arrives
+    let mut pending = String::new();
+    loop {
+        let mut line = String::new();
+        let eof = reader.read_line(&mut line)? == 0;
+        pending.push_str(&line);
+        if pending.trim().is_empty() {
+            pending.clear();
+        } else {
+            // a pretty-printed value spans lines: wait for the rest
+            match serde_json::from_str::<Value>(&pending) {
+                Ok(value) => {
+                    notifier.notify(&value).await?;
+                    pending.clear();
+                }
+                Err(e) if eof || !e.is_eof() => {
+                    return Err(Error::Invalid(format!("Invalid json input: {}", e)));
+                }
+                Err(_) => {}
+            }
+        }
+        if eof {
+            return Ok(());
+        }
+    }
+}
@@ -0,0 +1,548 @@
+//! Import of Notion databases, from a Notion export (csv and Markdown)
+use crate::{
+    cache, progress,
+    rest::RestClient,
+    retry::retry,
+    schema::{create_field, create_list, FieldType},
+    Error, NotionOpt,
+};
+use chrono::NaiveDate;
+use serde_json::{json, Map, Value};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    result::Result,
+};
+use zenkit::types::{Element, ElementCategoryId, FieldVal, TextFormat, UpdateAction, ID};
+
+/// Maximum number of distinct values of a column inferred as a categories field
+const MAX_CHOICES: usize = 30;
+/// Name of the field with the text of each row's page
+const CONTENT_FIELD: &str = "Content";
+/// Separator of multi-select values in Notion csv exports
+const MULTI_SEPARATOR: &str = ", ";
+/// Date formats of Notion csv exports
+const DATE_FORMATS: &[&str] = &["%B %d, %Y", "%Y/%m/%d", "%Y-%m-%d", "%m/%d/%Y", "%d/%m/%Y"];
+
+/// A database in the export: its csv file and the folder of its pages
+struct Database {
+    name: String,
+    csv: PathBuf,
+    pages: PathBuf,
+}
+
+/// A csv column, with its inferred type
+struct ColumnType {
+    name: String,
+    field_type: FieldType,
+    /// choices, for categories
+    choices: Vec<String>,
+    multiple: bool,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct NotionSummary {
+    pub lists: usize,
+    pub items: usize,
+    /// rows that couldn't be imported
+    pub rejected: Vec<String>,
+}
+
+/// Create a list for each database (csv file) in the Notion export folder, with a field
+/// for each column, of a type inferred from the column's values: numbers, checkboxes
+/// (Yes/No), dates, urls, and categories (columns with few distinct values; multi-select
+/// if values are comma-separated); other columns are text. The first column is the
+/// item title. If the export has pages for the rows (Markdown files), the text of each
+/// page is added in a `Content` field. In dry-run mode, the inferred fields are printed.
+pub(crate) async fn import_notion(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &NotionOpt,
+) -> Result<NotionSummary, Error> {
+    let api = zenkit::get_api()?;
+    let databases = find_databases(Path::new(&opt.dir))?;
+    if databases.is_empty() {
+        return Err(Error::Message(format!(
+            "No csv files found in {}: unzip the Notion export first",
+            opt.dir
+        )));
+    }
+    let mut summary = NotionSummary::default();
+    for db in databases.iter() {
+        let mut reader = csv::Reader::from_path(&db.csv)?;
+        let headers: Vec<String> = reader.headers()?.iter().map(clean_header).collect();
+        let rows = reader.records().collect::<Result<Vec<_>, _>>()?;
+        if headers.is_empty() {
+            continue;
+        }
+        let columns: Vec<ColumnType> = headers
+            .iter()
+            .enumerate()
+            .map(|(col, name)| {
+                let values: Vec<&str> = rows
+                    .iter()
+                    .map(|row| row.get(col).unwrap_or_default().trim())
+                    .collect();
+                // the first column is the title
+                if col == 0 {
+                    text_column(name)
+                } else {
+                    infer_column(name, &values)
+                }
+            })
+            .collect();
+        let pages = read_pages(&db.pages, &headers);
+
+        eprintln!("List '{}': {} rows", db.name, rows.len());
+        for column in columns.iter() {
+            eprintln!("  {}\t{:?}", column.name, column.field_type);
+        }
+        let new_list = match create_list(rest, ws_id, &db.name, None).await? {
+            Some(list) => list,
+            None => {
+                eprintln!(
+                    "(dry run) {} row(s) would be imported into list '{}'",
+                    rows.len(),
+                    db.name
+                );
+                continue;
+            }
+        };
+        summary.lists += 1;
+
+        // new lists may start with default fields: the first text field is used for titles
+        let existing = retry("get_list_elements", || api.get_list_elements(new_list.id)).await?;
+        let has_title = existing
+            .iter()
+            .any(|f| f.element_category == ElementCategoryId::Text);
+        for (n, column) in columns.iter().enumerate() {
+            if (n == 0 && has_title) || existing.iter().any(|f| f.name == column.name) {
+                continue;
+            }
+            let mut data = Map::new();
+            if column.field_type == FieldType::Categories {
+                let choices: Vec<Value> = column
+                    .choices
+                    .iter()
+                    .map(|c| json!({ "name": c }))
+                    .collect();
+                data.insert("predefinedCategories".into(), choices.into());
+                data.insert("allowMultiple".into(), column.multiple.into());
+            }
+            create_field(
+                rest,
+                new_list.id,
+                &column.name,
+                column.field_type.category(),
+                data,
+            )
+            .await?;
+        }
+        if !pages.is_empty() && !headers.iter().any(|h| h == CONTENT_FIELD) {
+            create_field(
+                rest,
+                new_list.id,
+                CONTENT_FIELD,
+                ElementCategoryId::Text,
+                Map::new(),
+            )
+            .await?;
+        }
+
+        let list_info = cache::get_list_info(ws_id, &new_list.uuid).await?;
+        let fields = list_info.fields();
+        let title = fields
+            .iter()
+            .find(|f| f.element_category == ElementCategoryId::Text);
+        let content = fields.iter().find(|f| f.name == CONTENT_FIELD);
+        let rows_done = progress::bar(rows.len(), "Importing");
+        for row in rows.iter() {
+            let mut values = Vec::new();
+            for (col, column) in columns.iter().enumerate() {
+                let value = row.get(col).unwrap_or_default().trim();
+                let field = match col {
+                    0 => title,
+                    _ => fields.iter().find(|f| f.name == column.name),
+                };
+                if let Some(field) = field {
+                    if let Some(value) = field_value(column, field, value) {
+                        values.push((field.uuid.clone(), value, UpdateAction::Null));
+                    }
+                }
+            }
+            let row_title = row.get(0).unwrap_or_default().trim();
+            if let (Some(field), Some(text)) = (content, pages.get(row_title)) {
+                values.push((
+                    field.uuid.clone(),
+                    FieldVal::Formatted(text.clone(), TextFormat::Markdown),
+                    UpdateAction::Null,
+                ));
+            }
+            match list_info.create_item(&values).await {
+                Ok(_) => summary.items += 1,
+                Err(e) => {
+                    summary
+                        .rejected
+                        .push(format!("{}: '{}': {}", db.name, row_title, e.summary()))
+                }
+            }
+            rows_done.inc(1);
+        }
+        rows_done.finish_and_clear();
+    }
+    Ok(summary)
+}
+
+/// The databases in the export folder and its subfolders. A database exported with
+/// both a view (`Name id.csv`) and all rows (`Name id_all.csv`) is imported from
+/// the file with all rows.
+fn find_databases(dir: &Path) -> Result<Vec<Database>, Error> {
+    let mut csv_files = Vec::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in std::fs::read_dir(&dir)
+            .map_err(|e| Error::Message(format!("Reading {}: {}", dir.display(), e)))?
+        {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path.extension().map(|e| e == "csv").unwrap_or(false) {
+                csv_files.push(path);
+            }
+        }
+    }
+    csv_files.sort();
+    let mut databases = Vec::new();
+    for path in csv_files.iter() {
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (stem, all) = match stem.strip_suffix("_all") {
+            Some(stem) => (stem.to_string(), true),
+            None => (stem, false),
+        };
+        if !all && csv_files.contains(&path.with_file_name(format!("{}_all.csv", stem))) {
+            continue;
+        }
+        databases.push(Database {
+            name: strip_id(&stem).to_string(),
+            csv: path.clone(),
+            pages: path.with_file_name(&stem),
+        });
+    }
+    Ok(databases)
+}
+
+/// The text of the database's pages (Markdown files), by title. The title heading
+/// and the property lines (`Property: value`) at the top of each page are removed.
+fn read_pages(dir: &Path, headers: &[String]) -> HashMap<String, String> {
+    let mut pages = HashMap::new();
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return pages,
+    };
+    for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+        if !path.extension().map(|e| e == "md").unwrap_or(false) {
+            continue;
+        }
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(_) => continue,
+        };
+        let mut lines = text.lines().peekable();
+        let title = match lines.peek() {
+            Some(line) if line.starts_with("# ") => line[2..].trim().to_string(),
+            _ => continue,
+        };
+        lines.next();
+        let body: Vec<&str> = lines
+            .skip_while(|line| {
+                line.trim().is_empty()
+                    || line
+                        .find(": ")
+                        .map(|end| headers.iter().any(|h| *h == line[..end]))
+                        .unwrap_or(false)
+            })
+            .collect();
+        let body = body.join("\n").trim().to_string();
+        if !body.is_empty() {
+            pages.insert(title, body);
+        }
+    }
+    pages
+}
+
+/// The name without the id that Notion appends to file names (" " and 32 hex digits)
+fn strip_id(name: &str) -> &str {
+    match name.rfind(' ') {
+        Some(pos)
+            if name.len() - pos == 33 && name[pos + 1..].chars().all(|c| c.is_ascii_hexdigit()) =>
+        {
+            &name[..pos]
+        }
+        _ => name,
+    }
+}
+
+/// Column header without the byte order mark that Notion writes at the start of the file
+fn clean_header(header: &str) -> String {
+    header.trim_start_matches('\u{feff}').trim().to_string()
+}
+
+fn text_column(name: &str) -> ColumnType {
+    ColumnType {
+        name: name.to_string(),
+        field_type: FieldType::Text,
+        choices: Vec::new(),
+        multiple: false,
+    }
+}
+
+/// The column's type, from its (non-empty) values
+fn infer_column(name: &str, values: &[&str]) -> ColumnType {
+    let values: Vec<&str> = values.iter().copied().filter(|v| !v.is_empty()).collect();
+    let field_type = if values.is_empty() {
+        FieldType::Text
+    } else if values.iter().all(|v| v.parse::<f64>().is_ok()) {
+        FieldType::Number
+    } else if values.iter().all(|v| *v == "Yes" || *v == "No") {
+        FieldType::Checkbox
+    } else if values.iter().all(|v| notion_date(v).is_some()) {
+        FieldType::Date
+    } else if values
+        .iter()
+        .all(|v| v.starts_with("http://") || v.starts_with("https://"))
+    {
+        FieldType::Url
+    } else {
+        FieldType::Text
+    };
+    if field_type != FieldType::Text || values.is_empty() {
+        return ColumnType {
+            field_type,
+            ..text_column(name)
+        };
+    }
+    // categories: few distinct values, each used more than once on average
+    let multiple = values.iter().any(|v| v.contains(MULTI_SEPARATOR));
+    let mut choices: Vec<String> = Vec::new();
+    let mut count = 0;
+    for value in values.iter() {
+        let parts: Vec<&str> = if multiple {
+            value.split(MULTI_SEPARATOR).collect()
+        } else {
+            vec![*value]
+        };
+        for part in parts.iter().map(|p| p.trim()).filter(|p| !p.is_empty()) {
+            count += 1;
+            if !choices.iter().any(|c| c == part) {
+                choices.push(part.to_string());
+            }
+        }
+    }
+    if !choices.is_empty() && choices.len() <= MAX_CHOICES && choices.len() * 2 <= count {
+        ColumnType {
+            name: name.to_string(),
+            field_type: FieldType::Categories,
+            choices,
+            multiple,
+        }
+    } else {
+        text_column(name)
+    }
+}
+
+/// The date of a Notion date value, e.g., "April 20, 2021", "April 20, 2021 3:30 PM",
+/// or a range "April 20, 2021 → April 22, 2021" (the start date). Times are ignored.
+fn notion_date(value: &str) -> Option<NaiveDate> {
+    let start = value.split(" → ").next()?.trim();
+    DATE_FORMATS.iter().find_map(|format| {
+        // Month D, YYYY is followed by the time, if any
+        let date = match *format {
+            "%B %d, %Y" => start.get(..start.find(',')? + 6)?,
+            _ => start.split(' ').next()?,
+        };
+        NaiveDate::parse_from_str(date, format).ok()
+    })
+}
+
+/// The field value of a csv value, or None if it's empty or invalid
+fn field_value(column: &ColumnType, field: &Element, value: &str) -> Option<FieldVal> {
+    if value.is_empty() {
+        return None;
+    }
+    Some(match field.element_category {
+        ElementCategoryId::Number => FieldVal::Str(value.parse::<f64>().ok()?.to_string()),
+        ElementCategoryId::Checkbox => FieldVal::Str((value == "Yes").to_string()),
+        ElementCategoryId::Date => {
+            FieldVal::Str(notion_date(value)?.format("%Y-%m-%d").to_string())
+        }
+        ElementCategoryId::Categories => {
+            let choices = field
+                .element_data
+                .predefined_categories
+                .as_deref()
+                .unwrap_or_default();
+            let names: Vec<&str> = if column.multiple {
+                value.split(MULTI_SEPARATOR).map(|v| v.trim()).collect()
+            } else {
+                vec![value]
+            };
+            let ids: Vec<ID> = names
+                .iter()
+                .filter_map(|name| choices.iter().find(|c| c.name == *name))
+                .map(|c| c.id)
+                .collect();
+            if ids.is_empty() {
+                return None;
+            }
+            FieldVal::ArrID(ids)
+        }
+        _ => FieldVal::Str(value.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{choice_field, field};
+
+    /// Id that Notion appends to file names
+    const PAGE_ID: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn names() {
+        assert_eq!(strip_id(&format!("Tasks {}", PAGE_ID)), "Tasks");
+        assert_eq!(strip_id("Tasks 2021"), "Tasks 2021");
+        assert_eq!(clean_header("\u{feff}Name "), "Name");
+    }
+
+    #[test]
+    fn notion_dates() {
+        let april = |d| NaiveDate::from_ymd(2021, 4, d);
+        assert_eq!(notion_date("April 20, 2021"), Some(april(20)));
+        assert_eq!(notion_date("April 20, 2021 3:30 PM"), Some(april(20)));
+        assert_eq!(
+            notion_date("April 20, 2021 → April 22, 2021"),
+            Some(april(20))
+        );
+        assert_eq!(notion_date("2021/04/20"), Some(april(20)));
+        assert_eq!(notion_date("04/20/2021"), Some(april(20)));
+        assert_eq!(notion_date("20/04/2021"), Some(april(20)));
+        assert_eq!(notion_date("soon"), None);
+    }
+
+    #[test]
+    fn column_types() {
+        let infer = |values: &[&str]| {
+            let column = infer_column("Column", values);
+            (column.field_type, column.choices, column.multiple)
+        };
+        assert_eq!(infer(&["1", "2.5", ""]).0, FieldType::Number);
+        assert_eq!(infer(&["Yes", "No"]).0, FieldType::Checkbox);
+        assert_eq!(infer(&["April 20, 2021", "2021-04-21"]).0, FieldType::Date);
+        assert_eq!(infer(&["https://a.com", "http://b.com"]).0, FieldType::Url);
+        assert_eq!(
+            infer(&["Done", "Todo", "Done", "Todo"]),
+            (
+                FieldType::Categories,
+                vec!["Done".to_string(), "Todo".to_string()],
+                false
+            )
+        );
+        assert_eq!(
+            infer(&["a, b", "b", "a"]),
+            (
+                FieldType::Categories,
+                vec!["a".to_string(), "b".to_string()],
+                true
+            )
+        );
+        // too many distinct values for categories
+        assert_eq!(infer(&["x", "y"]).0, FieldType::Text);
+        assert_eq!(infer(&["", ""]).0, FieldType::Text);
+    }
+
+    #[test]
+    fn csv_values() {
+        let value = |field: &Element, multiple: bool, value: &str| {
+            let column = ColumnType {
+                multiple,
+                ..text_column("Column")
+            };
+            field_value(&column, field, value)
+        };
+        let str_value = |v: Option<FieldVal>| match v {
+            Some(FieldVal::Str(s)) => Some(s),
+            _ => None,
+        };
+        assert_eq!(
+            str_value(value(&field(2, "Points", 2), false, "2.50")),
+            Some("2.5".into())
+        );
+        assert_eq!(
+            str_value(value(&field(3, "Done", 5), false, "Yes")),
+            Some("true".into())
+        );
+        assert_eq!(
+            str_value(value(&field(4, "Due", 4), false, "April 20, 2021")),
+            Some("2021-04-20".into())
+        );
+        assert!(value(&field(4, "Due", 4), false, "soon").is_none());
+        assert!(value(&field(5, "Notes", 1), false, "").is_none());
+        let tags = choice_field(6, "Tags", &[(1, "a"), (2, "b, c")]);
+        assert!(matches!(
+            value(&tags, true, "a, x"),
+            Some(FieldVal::ArrID(ids)) if ids == [1]
+        ));
+        assert!(matches!(
+            value(&tags, false, "b, c"),
+            Some(FieldVal::ArrID(ids)) if ids == [2]
+        ));
+        assert!(value(&tags, true, "x").is_none());
+    }
+
+    #[test]
+    fn export_folder() {
+        let dir = std::env::temp_dir().join(format!("zk_notion_{}", std::process::id()));
+        let pages = dir.join(format!("Tasks {}", PAGE_ID));
+        std::fs::create_dir_all(&pages).unwrap();
+        for name in [
+            format!("Tasks {}.csv", PAGE_ID),
+            format!("Tasks {}_all.csv", PAGE_ID),
+        ]
+        .iter()
+        {
+            std::fs::write(dir.join(name), "Name\n").unwrap();
+        }
+        std::fs::write(pages.join(format!("Notes {}.csv", PAGE_ID)), "Name\n").unwrap();
+        std::fs::write(
+            pages.join(format!("Ship {}.md", PAGE_ID)),
+            "# Ship\n\nStatus: Done\nOwner: Ann\n\nShip it.\nStatus: later\n",
+        )
+        .unwrap();
+        std::fs::write(pages.join("untitled.md"), "no heading").unwrap();
+
+        let databases = find_databases(&dir).unwrap();
+        let found: Vec<(&str, PathBuf)> = databases
+            .iter()
+            .map(|db| (db.name.as_str(), db.csv.clone()))
+            .collect();
+        let headers = vec!["Status".to_string(), "Owner".to_string()];
+        let text = read_pages(&databases[1].pages, &headers);
+        std::fs::remove_dir_all(&dir).unwrap();
+        // the database's view is skipped, in favor of all its rows
+        assert_eq!(
+            found,
+            vec![
+                ("Notes", pages.join(format!("Notes {}.csv", PAGE_ID))),
+                ("Tasks", dir.join(format!("Tasks {}_all.csv", PAGE_ID))),
+            ]
+        );
+        assert_eq!(databases[1].pages, pages);
+        assert_eq!(text.len(), 1);
+        assert_eq!(text["Ship"], "Ship it.\nStatus: later");
+    }
+}
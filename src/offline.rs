@@ -0,0 +1,151 @@
+//! Offline mode (`--offline`): read-only commands are answered from a backup folder
+use crate::{backup::last_backup_tstamp, restore::find_backup_item, Error, ListInfo};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    result::Result,
+    sync::Mutex,
+};
+use zenkit::types::{Element, Entry, List, ID};
+
+/// Workspace id used in offline mode. Backups don't include the workspace id,
+/// and it isn't needed to read the backup files.
+pub(crate) const WORKSPACE_ID: ID = 0;
+
+static BACKUP_DIR: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// Read from the backup folder instead of the api, or use the api (None)
+pub(crate) fn configure(dir: Option<PathBuf>) {
+    if let Ok(mut d) = BACKUP_DIR.lock() {
+        *d = dir;
+    }
+}
+
+/// The backup folder, if offline mode is enabled
+pub(crate) fn backup_dir() -> Option<PathBuf> {
+    BACKUP_DIR.lock().ok().and_then(|d| d.clone())
+}
+
+/// Returns true if commands read from a backup folder instead of the api
+pub(crate) fn enabled() -> bool {
+    backup_dir().is_some()
+}
+
+/// Workspace name from the most recent backup summary, or the folder name
+pub(crate) fn workspace_name(dir: &Path) -> String {
+    let from_summary = dir
+        .to_str()
+        .and_then(|d| last_backup_tstamp(d).ok().flatten())
+        .and_then(|tstamp| read_json::<Value>(&dir.join(format!("summary_{}.json", tstamp))).ok())
+        .and_then(|summary| {
+            summary
+                .get("workspace")
+                .and_then(|v| v.as_str())
+                .map(|s| s.to_string())
+        });
+    from_summary.unwrap_or_else(|| {
+        dir.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    })
+}
+
+/// Lists in the backup folder (with a <uuid>_list.json file), sorted by name
+pub(crate) fn get_lists(dir: &Path) -> Result<Vec<List>, Error> {
+    let mut lists: Vec<List> = Vec::new();
+    for entry in std::fs::read_dir(dir)
+        .map_err(|e| Error::Message(format!("Reading backup {}: {}", dir.display(), e)))?
+    {
+        let path = entry?.path();
+        let is_list_file = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.ends_with("_list.json"))
+            .unwrap_or(false);
+        if is_list_file {
+            lists.push(read_json(&path)?);
+        }
+    }
+    lists.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(lists)
+}
+
+/// The list (name, id, or uuid) and its fields, from the backup folder
+pub(crate) fn get_list_info(dir: &Path, list: &str) -> Result<ListInfo, Error> {
+    let found = get_lists(dir)?
+        .into_iter()
+        .find(|l| l.name == list || l.uuid == list || l.id.to_string() == list)
+        .ok_or_else(|| {
+            Error::NotFound(format!(
+                "List '{}' not found in backup {}",
+                list,
+                dir.display()
+            ))
+        })?;
+    let fields: Vec<Element> = read_json(&dir.join(format!("{}_fields.json", found.uuid)))?;
+    Ok(ListInfo::new(found, fields))
+}
+
+/// Items of the list (uuid) in the backup: the full backup <uuid>_items.json, updated
+/// with items from any incremental backups <uuid>_items_since_<tstamp>.json.
+/// Items are in the order of the full backup, followed by items only in incremental
+/// backups. Archived items are skipped unless `include_archived` is set.
+pub(crate) fn get_items(
+    dir: &Path,
+    list_uuid: &str,
+    include_archived: bool,
+) -> Result<Vec<Entry>, Error> {
+    let full_fname = format!("{}_items.json", list_uuid);
+    let since_prefix = format!("{}_items_since_", list_uuid);
+    let mut files: Vec<PathBuf> = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if name == full_fname => files.insert(0, path),
+            Some(name) if name.starts_with(&since_prefix) && name.ends_with(".json") => {
+                files.push(path)
+            }
+            _ => {}
+        }
+    }
+    if files.is_empty() && !dir.join(format!("{}_list.json", list_uuid)).exists() {
+        return Err(Error::NotFound(format!(
+            "List {} not found in backup {}",
+            list_uuid,
+            dir.display()
+        )));
+    }
+    let mut items: Vec<Entry> = Vec::new();
+    // item uuid -> position in items
+    let mut index: HashMap<String, usize> = HashMap::new();
+    for path in files.iter() {
+        for entry in read_json::<Vec<Entry>>(path)? {
+            match index.get(&entry.uuid) {
+                Some(&pos) if *items[pos].updated_at >= *entry.updated_at => {}
+                Some(&pos) => items[pos] = entry,
+                None => {
+                    index.insert(entry.uuid.clone(), items.len());
+                    items.push(entry);
+                }
+            }
+        }
+    }
+    if !include_archived {
+        items.retain(|item| item.deprecated_at.is_none());
+    }
+    Ok(items)
+}
+
+/// The item (id or uuid) in the list's backup files
+pub(crate) fn get_item(dir: &Path, list_uuid: &str, item: &str) -> Result<Entry, Error> {
+    find_backup_item(&dir.to_string_lossy(), list_uuid, item)
+}
+
+fn read_json<T: DeserializeOwned>(path: &Path) -> Result<T, Error> {
+    let data = std::fs::read(path)
+        .map_err(|e| Error::Message(format!("Reading {}: {}", path.display(), e)))?;
+    serde_json::from_slice(&data)
+        .map_err(|e| Error::Invalid(format!("Invalid backup file {}: {}", path.display(), e)))
+}
@@ -0,0 +1,48 @@
+//! Progress display on stderr for long-running commands
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Hide (or show) progress. Progress is also hidden if stderr is not a terminal.
+pub(crate) fn configure(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Container for progress bars of concurrent tasks, drawn together
+pub(crate) fn multi() -> MultiProgress {
+    if QUIET.load(Ordering::Relaxed) {
+        MultiProgress::with_draw_target(ProgressDrawTarget::hidden())
+    } else {
+        MultiProgress::new()
+    }
+}
+
+/// Progress bar for `len` steps, with the number done, the message, and estimated time left
+pub(crate) fn bar(len: usize, prefix: &str) -> ProgressBar {
+    let style = ProgressStyle::with_template("{prefix} [{bar:30}] {pos}/{len} {msg} (eta {eta})")
+        .unwrap_or_else(|_| ProgressStyle::default_bar())
+        .progress_chars("=> ");
+    new_bar(ProgressBar::new(len as u64), style, prefix)
+}
+
+/// Spinner with a count, for work of unknown length (such as fetching a list's items)
+pub(crate) fn counter(prefix: &str) -> ProgressBar {
+    let style = ProgressStyle::with_template("{spinner} {prefix}: {pos} {msg} ({per_sec})")
+        .unwrap_or_else(|_| ProgressStyle::default_spinner());
+    let pb = new_bar(ProgressBar::new_spinner(), style, prefix);
+    pb.enable_steady_tick(Duration::from_millis(200));
+    pb
+}
+
+fn new_bar(pb: ProgressBar, style: ProgressStyle, prefix: &str) -> ProgressBar {
+    if QUIET.load(Ordering::Relaxed) {
+        pb.set_draw_target(ProgressDrawTarget::hidden());
+    }
+    pb.set_style(style);
+    pb.set_prefix(prefix.to_string());
+    pb
+}
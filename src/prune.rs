@@ -0,0 +1,238 @@
+//! Delete old backups according to a retention policy
+use crate::{dryrun, BackupPruneOpt, Error};
+use chrono::{Local, TimeZone};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    result::Result,
+};
+
+/// Archive extensions written by `backup --archive`
+const ARCHIVE_EXTENSIONS: &[&str] = &["tar.gz", "zip"];
+
+/// Delete the files of backups in the folder that aren't kept by the retention policy.
+/// A backup (snapshot) is identified by its timestamp, and consists of its summary
+/// `summary_<tstamp>.json` (also in workspace subfolders, for `--all-workspaces`),
+/// for incremental backups, the `<uuid>_items_since_<since>.json` files, and its
+/// archive `backup_<tstamp>.<ext>`. The items, list, and fields files of full backups are
+/// overwritten by each backup into the folder, so they are never deleted.
+/// A backup is kept if it's one of the `keep_last` most recent, or the most recent in one
+/// of the `keep_daily` most recent days (`keep_weekly` weeks, `keep_monthly` months)
+/// with backups. Returns the number of backups deleted (or that would be, with --dry-run).
+pub(crate) fn prune_backups(opt: &BackupPruneOpt) -> Result<usize, Error> {
+    if opt.keep_last + opt.keep_daily + opt.keep_weekly + opt.keep_monthly == 0 {
+        return Err(Error::Invalid(
+            "At least one of --keep-last, --keep-daily, --keep-weekly, or --keep-monthly is required"
+                .into(),
+        ));
+    }
+    let snapshots = find_snapshots(Path::new(&opt.output))?;
+    // newest first
+    let tstamps: Vec<u64> = snapshots.keys().rev().copied().collect();
+    let mut keep: BTreeSet<u64> = tstamps.iter().take(opt.keep_last).copied().collect();
+    keep.extend(newest_per_period(&tstamps, opt.keep_daily, "%Y-%m-%d"));
+    keep.extend(newest_per_period(&tstamps, opt.keep_weekly, "%G-W%V"));
+    keep.extend(newest_per_period(&tstamps, opt.keep_monthly, "%Y-%m"));
+
+    // a file shared with a kept backup (same `since`) stays
+    let kept_files: BTreeSet<&PathBuf> = keep
+        .iter()
+        .filter_map(|ts| snapshots.get(ts))
+        .flatten()
+        .collect();
+    let mut removed = 0;
+    for (tstamp, files) in snapshots.iter().filter(|(ts, _)| !keep.contains(ts)) {
+        for path in files.iter().filter(|p| !kept_files.contains(p)) {
+            if dryrun::enabled() {
+                println!("would remove {}", path.display());
+            } else {
+                std::fs::remove_file(path)?;
+            }
+        }
+        println!(
+            "{}\t{}\t{}",
+            if dryrun::enabled() {
+                "would prune"
+            } else {
+                "pruned"
+            },
+            tstamp,
+            local_time(*tstamp).format("%Y-%m-%d %H:%M:%S")
+        );
+        removed += 1;
+    }
+    Ok(removed)
+}
+
+/// Backups in the folder and its workspace subfolders: timestamp -> files
+fn find_snapshots(dir: &Path) -> Result<BTreeMap<u64, Vec<PathBuf>>, Error> {
+    let mut snapshots = BTreeMap::new();
+    scan_folder(dir, &mut snapshots)?;
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            scan_folder(&path, &mut snapshots)?;
+        }
+    }
+    Ok(snapshots)
+}
+
+/// Add the summaries, incremental items files, and archives in one folder
+fn scan_folder(dir: &Path, snapshots: &mut BTreeMap<u64, Vec<PathBuf>>) -> Result<(), Error> {
+    let mut names = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_file() {
+            if let Ok(name) = entry.file_name().into_string() {
+                names.push(name);
+            }
+        }
+    }
+    for name in names.iter() {
+        if let Some(tstamp) = name
+            .strip_prefix("summary_")
+            .and_then(|s| s.strip_suffix(".json"))
+            .and_then(|ts| ts.parse::<u64>().ok())
+        {
+            let path = dir.join(name);
+            let since = summary_since(&path)?;
+            let files = snapshots.entry(tstamp).or_default();
+            files.push(path);
+            if let Some(since) = since {
+                let suffix = format!("_items_since_{}.json", since);
+                files.extend(
+                    names
+                        .iter()
+                        .filter(|n| n.ends_with(&suffix))
+                        .map(|n| dir.join(n)),
+                );
+            }
+        } else if let Some(tstamp) = name.strip_prefix("backup_").and_then(|s| {
+            ARCHIVE_EXTENSIONS
+                .iter()
+                .find_map(|ext| s.strip_suffix(ext)?.strip_suffix('.'))
+                .and_then(|ts| ts.parse::<u64>().ok())
+        }) {
+            snapshots.entry(tstamp).or_default().push(dir.join(name));
+        }
+    }
+    Ok(())
+}
+
+/// The `since` timestamp of an incremental backup summary
+fn summary_since(path: &Path) -> Result<Option<u64>, Error> {
+    let summary: Value = serde_json::from_slice(&std::fs::read(path)?)
+        .map_err(|e| Error::Invalid(format!("Invalid summary {}: {}", path.display(), e)))?;
+    Ok(summary.get("since").and_then(|v| v.as_u64()))
+}
+
+/// The newest backup in each of the `count` most recent periods with backups, where
+/// the period of a backup is its local time formatted with `period`.
+/// `tstamps` must be sorted newest first.
+fn newest_per_period(tstamps: &[u64], count: usize, period: &str) -> Vec<u64> {
+    let mut kept = Vec::new();
+    let mut last_period = None;
+    for tstamp in tstamps.iter() {
+        if kept.len() >= count {
+            break;
+        }
+        let this_period = local_time(*tstamp).format(period).to_string();
+        if last_period.as_ref() != Some(&this_period) {
+            kept.push(*tstamp);
+            last_period = Some(this_period);
+        }
+    }
+    kept
+}
+
+fn local_time(tstamp: u64) -> chrono::DateTime<Local> {
+    Local.timestamp_millis(tstamp as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tstamp(y: i32, m: u32, d: u32, h: u32) -> u64 {
+        Local.ymd(y, m, d).and_hms(h, 0, 0).timestamp_millis() as u64
+    }
+
+    #[test]
+    fn newest_backup_per_period() {
+        // newest first
+        let tstamps = vec![
+            tstamp(2021, 3, 2, 18),
+            tstamp(2021, 3, 2, 9),
+            tstamp(2021, 3, 1, 12),
+            tstamp(2021, 2, 27, 12),
+            tstamp(2021, 1, 15, 12),
+        ];
+        assert_eq!(
+            newest_per_period(&tstamps, 2, "%Y-%m-%d"),
+            vec![tstamps[0], tstamps[2]]
+        );
+        assert_eq!(
+            newest_per_period(&tstamps, 10, "%Y-%m-%d"),
+            vec![tstamps[0], tstamps[2], tstamps[3], tstamps[4]]
+        );
+        assert_eq!(
+            newest_per_period(&tstamps, 3, "%Y-%m"),
+            vec![tstamps[0], tstamps[3], tstamps[4]]
+        );
+        // 2021-03-01 and 2021-03-02 are in the same ISO week, 2021-02-27 in the week before
+        assert_eq!(
+            newest_per_period(&tstamps, 2, "%G-W%V"),
+            vec![tstamps[0], tstamps[3]]
+        );
+        assert!(newest_per_period(&tstamps, 0, "%Y-%m-%d").is_empty());
+        assert!(newest_per_period(&[], 3, "%Y-%m-%d").is_empty());
+    }
+
+    #[test]
+    fn loose_snapshot_files() {
+        let dir = std::env::temp_dir().join(format!("zk_prune_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let files = [
+            ("summary_100.json", "{}"),
+            ("summary_200.json", r#"{"since": 100}"#),
+            ("list-1_list.json", "{}"),
+            ("list-1_fields.json", "[]"),
+            ("list-1_items.json", "[]"),
+            ("list-1_items_since_100.json", "[]"),
+            ("backup_300.tar.gz", ""),
+        ];
+        for (name, data) in files.iter() {
+            std::fs::write(dir.join(name), data).unwrap();
+        }
+        let snapshots = find_snapshots(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let names: Vec<(u64, Vec<String>)> = snapshots
+            .into_iter()
+            .map(|(tstamp, paths)| {
+                let mut names: Vec<String> = paths
+                    .iter()
+                    .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+                    .collect();
+                names.sort();
+                (tstamp, names)
+            })
+            .collect();
+        // the files of the full backup are shared, so they aren't in any snapshot
+        assert_eq!(
+            names,
+            vec![
+                (100, vec!["summary_100.json".to_string()]),
+                (
+                    200,
+                    vec![
+                        "list-1_items_since_100.json".to_string(),
+                        "summary_200.json".to_string()
+                    ]
+                ),
+                (300, vec!["backup_300.tar.gz".to_string()]),
+            ]
+        );
+    }
+}
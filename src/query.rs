@@ -0,0 +1,813 @@
+//! Queries of list items, in a small SQL-like language:
+//!
+//! ```text
+//! [select col, ...] [where condition] [order by col [asc|desc], ...] [limit n]
+//! ```
+//!
+//! Columns are `id`, `uuid`, `name` (the item's title), or field names. Names with spaces
+//! are quoted with double quotes or backticks, and text values with single quotes.
+//! Conditions compare a column with a value or another column (`=`, `!=`, `<`, `<=`, `>`,
+//! `>=`, `contains`), or test for empty values (`is empty`, `is not empty`), and are combined
+//! with `and`, `or`, `not`, and parentheses. An unquoted word that isn't a column is a date,
+//! in any of the forms accepted by `set` (e.g., `today`, `+7d`, `friday`).
+//!
+//! Queries used often can be saved in the config file, and run with `query --saved NAME`:
+//!
+//! ```toml
+//! [queries.overdue]
+//! list = "Tasks"
+//! filter = "Status != 'Done' and Due < today"
+//! columns = ["id", "Name", "Due"]
+//! sort = "Due"
+//! ```
+use crate::{
+    backup::get_all_items,
+    cache,
+    dates::{local_date, parse_day},
+    items::{compare_values, Column},
+    style::Table,
+    values::ValueFormatter,
+    Error, ListInfo, QueryOpt, TableFormat,
+};
+use config::Config;
+use serde::Deserialize;
+use std::{cmp::Ordering, collections::HashMap, result::Result, sync::Mutex};
+use zenkit::types::{Entry, ID};
+
+/// Query saved in the config file's `[queries.NAME]` section
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct SavedQuery {
+    /// List name or id
+    pub list: String,
+    /// Condition, as in `where`
+    #[serde(default)]
+    pub filter: Option<String>,
+    /// Columns to show, as in `select`
+    #[serde(default)]
+    pub columns: Vec<String>,
+    /// Sort columns, as in `order by`, e.g., "Due desc, Name"
+    #[serde(default)]
+    pub sort: Option<String>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+impl SavedQuery {
+    /// The query, in the query language
+    fn to_query(&self) -> String {
+        let mut parts = Vec::new();
+        if !self.columns.is_empty() {
+            let columns: Vec<String> = self.columns.iter().map(|c| quote_column(c)).collect();
+            parts.push(format!("select {}", columns.join(", ")));
+        }
+        if let Some(ref filter) = self.filter {
+            parts.push(format!("where {}", filter));
+        }
+        if let Some(ref sort) = self.sort {
+            parts.push(format!("order by {}", sort));
+        }
+        if let Some(limit) = self.limit {
+            parts.push(format!("limit {}", limit));
+        }
+        parts.join(" ")
+    }
+}
+
+/// Quote a column name that isn't a single word
+fn quote_column(name: &str) -> String {
+    let name = name.trim();
+    if name.starts_with('"') || name.starts_with('`') || is_word(name) {
+        name.to_string()
+    } else if name.contains('"') {
+        format!("`{}`", name)
+    } else {
+        format!("\"{}\"", name)
+    }
+}
+
+fn is_word(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_alphanumeric() || c == '_')
+}
+
+static SAVED: Mutex<Option<HashMap<String, SavedQuery>>> = Mutex::new(None);
+
+/// Load the saved queries from the config file's `[queries]` section
+pub(crate) fn configure(settings: &Config) -> Result<(), Error> {
+    let saved = match settings.get::<HashMap<String, SavedQuery>>("queries") {
+        Ok(saved) => saved,
+        Err(config::ConfigError::NotFound(_)) => HashMap::new(),
+        Err(e) => return Err(Error::Message(format!("queries: {}", e))),
+    };
+    if let Ok(mut s) = SAVED.lock() {
+        *s = Some(saved);
+    }
+    Ok(())
+}
+
+/// The saved query with the name (ignoring case)
+fn saved_query(name: &str) -> Result<SavedQuery, Error> {
+    let saved = SAVED
+        .lock()
+        .ok()
+        .and_then(|s| s.clone())
+        .unwrap_or_default();
+    match saved.iter().find(|(key, _)| key.eq_ignore_ascii_case(name)) {
+        Some((_, query)) => Ok(query.clone()),
+        None => {
+            let mut names: Vec<&str> = saved.keys().map(|k| k.as_str()).collect();
+            names.sort_unstable();
+            Err(Error::NotFound(format!(
+                "Saved query '{}' not found in config file. Saved queries: {}",
+                name,
+                if names.is_empty() {
+                    "(none)".to_string()
+                } else {
+                    names.join(", ")
+                }
+            )))
+        }
+    }
+}
+
+/// Run the query on the list's items, and print the selected columns of matching items
+pub(crate) async fn query(ws_id: ID, opt: &QueryOpt) -> Result<(), Error> {
+    let (list, text) = match opt.saved {
+        Some(ref name) => {
+            let saved = saved_query(name)?;
+            // -l overrides the saved list
+            (
+                opt.list.clone().unwrap_or_else(|| saved.list.clone()),
+                saved.to_query(),
+            )
+        }
+        None => (
+            opt.list
+                .clone()
+                .ok_or_else(|| Error::Invalid("--list is required".to_string()))?,
+            opt.query.clone().unwrap_or_default(),
+        ),
+    };
+    let list_info = cache::get_list_info(ws_id, &list).await?;
+    let query = Parser::new(&list_info, &text)?.parse()?;
+    let formatter = ValueFormatter::new(ws_id).await?;
+    let f = Some(&formatter);
+
+    let items = get_all_items(&list_info.list().uuid, false).await?;
+    let mut rows: Vec<&Entry> = items
+        .iter()
+        .filter(|item| match query.condition {
+            Some(ref cond) => cond.eval(item, &formatter),
+            None => true,
+        })
+        .collect();
+    rows.sort_by(|a, b| {
+        query
+            .order
+            .iter()
+            .map(|(col, desc)| compare_values(&col.value(a, f), &col.value(b, f), *desc))
+            .find(|ord| *ord != Ordering::Equal)
+            .unwrap_or(Ordering::Equal)
+    });
+    if let Some(limit) = query.limit {
+        rows.truncate(limit);
+    }
+
+    let headings: Vec<&str> = query.columns.iter().map(|c| c.name()).collect();
+    let values: Vec<Vec<String>> = rows
+        .iter()
+        .map(|item| query.columns.iter().map(|c| c.value(item, f)).collect())
+        .collect();
+    match opt.output {
+        TableFormat::Table => {
+            let mut table = Table::new(&headings);
+            for item in rows.iter() {
+                table.push(query.columns.iter().map(|c| c.cell(item, f)).collect());
+            }
+            table.print_aligned();
+        }
+        TableFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            writer.write_record(&headings)?;
+            for row in values.iter() {
+                writer.write_record(row)?;
+            }
+            writer.flush()?;
+        }
+        TableFormat::Json => {
+            let objects: Vec<serde_json::Map<String, serde_json::Value>> = values
+                .into_iter()
+                .map(|row| {
+                    headings
+                        .iter()
+                        .map(|h| h.to_string())
+                        .zip(row.into_iter().map(serde_json::Value::from))
+                        .collect()
+                })
+                .collect();
+            println!("{}", serde_json::to_string_pretty(&objects)?);
+        }
+    }
+    Ok(())
+}
+
+struct Query<'l> {
+    columns: Vec<Column<'l>>,
+    condition: Option<Condition<'l>>,
+    /// sort columns, and true for descending order
+    order: Vec<(Column<'l>, bool)>,
+    limit: Option<usize>,
+}
+
+enum Operand<'l> {
+    Column(Column<'l>),
+    Value(String),
+}
+
+impl<'l> Operand<'l> {
+    /// Non-empty values of the operand
+    fn values(&self, item: &Entry, formatter: &ValueFormatter) -> Vec<String> {
+        match self {
+            Operand::Column(col) => col
+                .values(item, Some(formatter))
+                .into_iter()
+                .filter(|v| !v.is_empty())
+                .collect(),
+            Operand::Value(v) => vec![v.clone()],
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+enum Condition<'l> {
+    And(Box<Condition<'l>>, Box<Condition<'l>>),
+    Or(Box<Condition<'l>>, Box<Condition<'l>>),
+    Not(Box<Condition<'l>>),
+    Compare(Operand<'l>, Op, Operand<'l>),
+    IsEmpty(Operand<'l>),
+}
+
+impl<'l> Condition<'l> {
+    /// Evaluate the condition for the item. A comparison is true if any value of a
+    /// multi-value field matches, except `!=`, which is true if no value is equal.
+    /// Comparisons with an empty field are false, except `!=`.
+    fn eval(&self, item: &Entry, formatter: &ValueFormatter) -> bool {
+        match self {
+            Condition::And(a, b) => a.eval(item, formatter) && b.eval(item, formatter),
+            Condition::Or(a, b) => a.eval(item, formatter) || b.eval(item, formatter),
+            Condition::Not(c) => !c.eval(item, formatter),
+            Condition::IsEmpty(operand) => operand.values(item, formatter).is_empty(),
+            Condition::Compare(left, op, right) => {
+                let left = left.values(item, formatter);
+                let right = right.values(item, formatter);
+                let any = |test: &dyn Fn(&str, &str) -> bool| {
+                    left.iter()
+                        .any(|l| right.iter().any(|r| test(l.as_str(), r.as_str())))
+                };
+                match op {
+                    Op::Ne => !any(&|l, r| compare(l, r) == Ordering::Equal),
+                    Op::Eq => any(&|l, r| compare(l, r) == Ordering::Equal),
+                    Op::Lt => any(&|l, r| compare(l, r) == Ordering::Less),
+                    Op::Le => any(&|l, r| compare(l, r) != Ordering::Greater),
+                    Op::Gt => any(&|l, r| compare(l, r) == Ordering::Greater),
+                    Op::Ge => any(&|l, r| compare(l, r) != Ordering::Less),
+                    Op::Contains => any(&|l, r| l.to_lowercase().contains(&r.to_lowercase())),
+                }
+            }
+        }
+    }
+}
+
+/// Compare values as numbers, then as dates, then as text ignoring case
+fn compare(a: &str, b: &str) -> Ordering {
+    if let (Ok(x), Ok(y)) = (a.parse::<f64>(), b.parse::<f64>()) {
+        return x.partial_cmp(&y).unwrap_or(Ordering::Equal);
+    }
+    if let (Some(x), Some(y)) = (local_date(a), local_date(b)) {
+        return x.cmp(&y);
+    }
+    a.to_lowercase().cmp(&b.to_lowercase())
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    /// Unquoted word: a keyword, column, or date
+    Word(String),
+    /// Column name in double quotes or backticks
+    Quoted(String),
+    /// Text in single quotes
+    Text(String),
+    Number(String),
+    Op(String),
+    Comma,
+    Open,
+    Close,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, Error> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Token::Open);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::Close);
+                i += 1;
+            }
+            '=' | '!' | '<' | '>' => {
+                let two: String = chars[i..(i + 2).min(chars.len())].iter().collect();
+                let op = match two.as_str() {
+                    "!=" | "<>" | "<=" | ">=" => two,
+                    _ if c == '!' => {
+                        return Err(Error::Invalid("Query: expected '=' after '!'".into()))
+                    }
+                    _ => c.to_string(),
+                };
+                i += op.len();
+                tokens.push(Token::Op(op));
+            }
+            '\'' | '"' | '`' => {
+                // a doubled quote character is a literal quote
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => {
+                            return Err(Error::Invalid(format!(
+                                "Query: missing closing {} quote",
+                                c
+                            )))
+                        }
+                        Some(q) if *q == c && chars.get(i + 1) == Some(&c) => {
+                            text.push(c);
+                            i += 2;
+                        }
+                        Some(q) if *q == c => {
+                            i += 1;
+                            break;
+                        }
+                        Some(q) => {
+                            text.push(*q);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push(if c == '\'' {
+                    Token::Text(text)
+                } else {
+                    Token::Quoted(text)
+                });
+            }
+            _ => {
+                let start = i;
+                while i < chars.len()
+                    && !chars[i].is_whitespace()
+                    && !",()=!<>'\"`".contains(chars[i])
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push(if word.parse::<f64>().is_ok() {
+                    Token::Number(word)
+                } else {
+                    Token::Word(word)
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser<'l> {
+    list_info: &'l ListInfo,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl<'l> Parser<'l> {
+    fn new(list_info: &'l ListInfo, query: &str) -> Result<Self, Error> {
+        Ok(Parser {
+            list_info,
+            tokens: tokenize(query)?,
+            pos: 0,
+        })
+    }
+
+    fn parse(mut self) -> Result<Query<'l>, Error> {
+        let columns = if self.keyword("select") {
+            self.column_list()?
+        } else {
+            vec![Column::Id, Column::Uuid, Column::Name]
+        };
+        let condition = if self.keyword("where") {
+            Some(self.or_condition()?)
+        } else {
+            None
+        };
+        let mut order = Vec::new();
+        if self.keyword("order") {
+            self.expect_keyword("by")?;
+            loop {
+                let column = self.column()?;
+                let desc = if self.keyword("desc") {
+                    true
+                } else {
+                    self.keyword("asc");
+                    false
+                };
+                order.push((column, desc));
+                if self.peek() != Some(&Token::Comma) {
+                    break;
+                }
+                self.pos += 1;
+            }
+        }
+        let limit = if self.keyword("limit") {
+            match self.next() {
+                Some(Token::Number(n)) => Some(
+                    n.parse()
+                        .map_err(|_| Error::Invalid(format!("Query: invalid limit '{}'", n)))?,
+                ),
+                _ => {
+                    return Err(Error::Invalid(
+                        "Query: expected a number after 'limit'".into(),
+                    ))
+                }
+            }
+        } else {
+            None
+        };
+        if let Some(token) = self.peek() {
+            return Err(Error::Invalid(format!(
+                "Query: unexpected {}",
+                describe(token)
+            )));
+        }
+        Ok(Query {
+            columns,
+            condition,
+            order,
+            limit,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    /// If the next token is the (unquoted) keyword, consume it and return true
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), Error> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(Error::Invalid(format!("Query: expected '{}'", keyword)))
+        }
+    }
+
+    fn column_list(&mut self) -> Result<Vec<Column<'l>>, Error> {
+        let mut columns = vec![self.column()?];
+        while self.peek() == Some(&Token::Comma) {
+            self.pos += 1;
+            columns.push(self.column()?);
+        }
+        Ok(columns)
+    }
+
+    fn column(&mut self) -> Result<Column<'l>, Error> {
+        match self.next() {
+            Some(Token::Word(name)) | Some(Token::Quoted(name)) => {
+                Column::parse(self.list_info, &name)
+            }
+            token => Err(Error::Invalid(format!(
+                "Query: expected a column, found {}",
+                token.as_ref().map(describe).unwrap_or_else(|| "end".into())
+            ))),
+        }
+    }
+
+    fn or_condition(&mut self) -> Result<Condition<'l>, Error> {
+        let mut cond = self.and_condition()?;
+        while self.keyword("or") {
+            cond = Condition::Or(Box::new(cond), Box::new(self.and_condition()?));
+        }
+        Ok(cond)
+    }
+
+    fn and_condition(&mut self) -> Result<Condition<'l>, Error> {
+        let mut cond = self.not_condition()?;
+        while self.keyword("and") {
+            cond = Condition::And(Box::new(cond), Box::new(self.not_condition()?));
+        }
+        Ok(cond)
+    }
+
+    fn not_condition(&mut self) -> Result<Condition<'l>, Error> {
+        if self.keyword("not") {
+            return Ok(Condition::Not(Box::new(self.not_condition()?)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let cond = self.or_condition()?;
+            match self.next() {
+                Some(Token::Close) => return Ok(cond),
+                _ => return Err(Error::Invalid("Query: expected ')'".into())),
+            }
+        }
+        let left = self.operand()?;
+        if self.keyword("is") {
+            let negate = self.keyword("not");
+            self.expect_keyword("empty")?;
+            let cond = Condition::IsEmpty(left);
+            return Ok(if negate {
+                Condition::Not(Box::new(cond))
+            } else {
+                cond
+            });
+        }
+        let op = if self.keyword("contains") {
+            Op::Contains
+        } else {
+            match self.next() {
+                Some(Token::Op(op)) => match op.as_str() {
+                    "=" => Op::Eq,
+                    "!=" | "<>" => Op::Ne,
+                    "<" => Op::Lt,
+                    "<=" => Op::Le,
+                    ">" => Op::Gt,
+                    _ => Op::Ge,
+                },
+                token => {
+                    return Err(Error::Invalid(format!(
+                        "Query: expected a comparison, found {}",
+                        token.as_ref().map(describe).unwrap_or_else(|| "end".into())
+                    )))
+                }
+            }
+        };
+        let right = self.operand()?;
+        Ok(Condition::Compare(left, op, right))
+    }
+
+    /// A column, value, or date. Unquoted words are columns if the list has such a field,
+    /// otherwise dates.
+    fn operand(&mut self) -> Result<Operand<'l>, Error> {
+        match self.next() {
+            Some(Token::Quoted(name)) => Ok(Operand::Column(Column::parse(self.list_info, &name)?)),
+            Some(Token::Text(text)) | Some(Token::Number(text)) => Ok(Operand::Value(text)),
+            Some(Token::Word(word)) => match Column::parse(self.list_info, &word) {
+                Ok(column) => Ok(Operand::Column(column)),
+                Err(_) => match parse_day(&word) {
+                    Ok(date) => Ok(Operand::Value(date.format("%Y-%m-%d").to_string())),
+                    Err(_) => Err(Error::Invalid(format!(
+                        "Query: '{}' is not a field or date. Use single quotes for text values",
+                        word
+                    ))),
+                },
+            },
+            token => Err(Error::Invalid(format!(
+                "Query: expected a column or value, found {}",
+                token.as_ref().map(describe).unwrap_or_else(|| "end".into())
+            ))),
+        }
+    }
+}
+
+fn describe(token: &Token) -> String {
+    match token {
+        Token::Word(w) | Token::Number(w) | Token::Op(w) | Token::Text(w) => format!("'{}'", w),
+        Token::Quoted(w) => format!("\"{}\"", w),
+        Token::Comma => "','".into(),
+        Token::Open => "'('".into(),
+        Token::Close => "')'".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil;
+    use serde_json::json;
+
+    fn list_info() -> ListInfo {
+        testutil::list_info(vec![
+            testutil::field(1, "Title", 1),
+            testutil::field(2, "Status", 1),
+            testutil::field(3, "Points", 2),
+            testutil::field(4, "Due Date", 4),
+        ])
+    }
+
+    fn item(id: u64, title: &str, status: Option<&str>, points: Option<i64>) -> Entry {
+        testutil::item(
+            id,
+            title,
+            json!({ "field-2_text": status, "field-3_number": points }),
+        )
+    }
+
+    fn parse<'l>(list_info: &'l ListInfo, query: &str) -> Result<Query<'l>, Error> {
+        Parser::new(list_info, query)?.parse()
+    }
+
+    /// Ids of the items matching the query's condition
+    fn matching(query: &str, items: &[Entry]) -> Vec<u64> {
+        let list_info = list_info();
+        let query = parse(&list_info, query).unwrap();
+        let condition = query.condition.unwrap();
+        let formatter = ValueFormatter::default();
+        items
+            .iter()
+            .filter(|item| condition.eval(item, &formatter))
+            .map(|item| item.id)
+            .collect()
+    }
+
+    #[test]
+    fn tokenize_query() {
+        let tokens =
+            tokenize("select id, \"Due Date\" where Status != 'it''s' and Points>=3").unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Word("select".into()),
+                Token::Word("id".into()),
+                Token::Comma,
+                Token::Quoted("Due Date".into()),
+                Token::Word("where".into()),
+                Token::Word("Status".into()),
+                Token::Op("!=".into()),
+                Token::Text("it's".into()),
+                Token::Word("and".into()),
+                Token::Word("Points".into()),
+                Token::Op(">=".into()),
+                Token::Number("3".into()),
+            ]
+        );
+        assert_eq!(
+            tokenize("(`a`<>-1.5)").unwrap(),
+            vec![
+                Token::Open,
+                Token::Quoted("a".into()),
+                Token::Op("<>".into()),
+                Token::Number("-1.5".into()),
+                Token::Close,
+            ]
+        );
+        assert!(tokenize("Status = 'open").is_err());
+        assert!(tokenize("Status ! 'open'").is_err());
+    }
+
+    #[test]
+    fn parse_clauses() {
+        let list_info = list_info();
+        let query = parse(
+            &list_info,
+            "SELECT id, Status, `Due Date` WHERE Points > 1 ORDER BY Points desc, name LIMIT 5",
+        )
+        .unwrap();
+        let columns: Vec<&str> = query.columns.iter().map(|c| c.name()).collect();
+        assert_eq!(columns, vec!["id", "Status", "Due Date"]);
+        assert!(query.condition.is_some());
+        let order: Vec<(&str, bool)> = query.order.iter().map(|(c, d)| (c.name(), *d)).collect();
+        assert_eq!(order, vec![("Points", true), ("name", false)]);
+        assert_eq!(query.limit, Some(5));
+
+        // defaults
+        let query = parse(&list_info, "").unwrap();
+        let columns: Vec<&str> = query.columns.iter().map(|c| c.name()).collect();
+        assert_eq!(columns, vec!["id", "uuid", "name"]);
+        assert!(query.condition.is_none() && query.order.is_empty() && query.limit.is_none());
+    }
+
+    #[test]
+    fn parse_errors() {
+        let list_info = list_info();
+        for invalid in [
+            "select",
+            "where",
+            "where Status",
+            "where Status = ",
+            "where (Status = 'open'",
+            "where Status is 'open'",
+            "where Nonsense = 'open'",
+            "order Status",
+            "limit ten",
+            "where Status = 'open' extra",
+        ]
+        .iter()
+        {
+            assert!(
+                parse(&list_info, invalid).is_err(),
+                "'{}' should be invalid",
+                invalid
+            );
+        }
+    }
+
+    #[test]
+    fn eval_conditions() {
+        let items = vec![
+            item(1, "Write tests", Some("Open"), Some(3)),
+            item(2, "Fix bug", Some("Done"), Some(1)),
+            item(3, "Plan", None, None),
+        ];
+        assert_eq!(matching("where Status = 'open'", &items), vec![1]);
+        assert_eq!(matching("where Status != 'Open'", &items), vec![2, 3]);
+        // numbers are compared as numbers
+        assert_eq!(matching("where Points >= 2", &items), vec![1]);
+        assert_eq!(matching("where Points < 10", &items), vec![1, 2]);
+        assert_eq!(matching("where Title contains 'T'", &items), vec![1]);
+        assert_eq!(matching("where Status is empty", &items), vec![3]);
+        assert_eq!(matching("where Status is not empty", &items), vec![1, 2]);
+        assert_eq!(
+            matching("where not (Status = 'Done' or Points > 2)", &items),
+            vec![3]
+        );
+        // and binds tighter than or
+        assert_eq!(
+            matching(
+                "where Status = 'Done' or Status = 'Open' and Points > 5",
+                &items
+            ),
+            vec![2]
+        );
+        assert_eq!(
+            matching("where id = 2 or uuid = 'item-3'", &items),
+            vec![2, 3]
+        );
+    }
+
+    #[test]
+    fn quoted_columns() {
+        assert_eq!(quote_column("Status"), "Status");
+        assert_eq!(quote_column(" due_date "), "due_date");
+        assert_eq!(quote_column("Due Date"), "\"Due Date\"");
+        assert_eq!(quote_column("Size \"L\""), "`Size \"L\"`");
+        assert_eq!(quote_column("`Due Date`"), "`Due Date`");
+    }
+
+    #[test]
+    fn saved_queries() {
+        let saved = SavedQuery {
+            list: "Tasks".into(),
+            filter: Some("Status != 'Done'".into()),
+            columns: vec!["id".into(), "Due Date".into()],
+            sort: Some("Points desc".into()),
+            limit: Some(10),
+        };
+        let text = saved.to_query();
+        assert_eq!(
+            text,
+            "select id, \"Due Date\" where Status != 'Done' order by Points desc limit 10"
+        );
+        let list_info = list_info();
+        let query = parse(&list_info, &text).unwrap();
+        let columns: Vec<&str> = query.columns.iter().map(|c| c.name()).collect();
+        assert_eq!(columns, vec!["id", "Due Date"]);
+        assert_eq!(query.limit, Some(10));
+
+        let mut queries = HashMap::new();
+        queries.insert("Open".to_string(), saved);
+        *SAVED.lock().unwrap() = Some(queries);
+        assert_eq!(saved_query("open").unwrap().list, "Tasks");
+        assert!(matches!(
+            saved_query("closed"),
+            Err(Error::NotFound(msg)) if msg.ends_with("Saved queries: Open")
+        ));
+    }
+}
@@ -0,0 +1,116 @@
+//! Client-side rate limit of api requests, shared by all concurrent tasks
+use std::{
+    result::Result,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+/// Minimum time between the start of requests, in microseconds. 0 if not limited.
+static INTERVAL_US: AtomicU64 = AtomicU64::new(0);
+
+/// Earliest time the next request may start
+static NEXT_SLOT: Mutex<Option<Instant>> = Mutex::new(None);
+
+/// Requests per period, e.g., "8/s", "100/m", "1/500ms"
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(crate) struct RateLimit {
+    pub requests: u32,
+    pub per: Duration,
+}
+
+impl std::str::FromStr for RateLimit {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid rate limit '{}', expected e.g. '8/s'", s);
+        let pos = s.find('/').ok_or_else(invalid)?;
+        let requests = s[..pos].trim().parse::<u32>().map_err(|_| invalid())?;
+        let per = s[pos + 1..].trim();
+        // "s" is "1s"
+        let per = if per.starts_with(|c: char| c.is_ascii_digit()) {
+            crate::parse_duration(per)?
+        } else {
+            crate::parse_duration(&format!("1{}", per))?
+        };
+        if requests == 0 || per.as_micros() == 0 {
+            return Err(invalid());
+        }
+        Ok(RateLimit { requests, per })
+    }
+}
+
+/// Limit requests to the rate, or remove the limit (None)
+pub(crate) fn configure(limit: Option<RateLimit>) {
+    let interval = limit
+        .map(|l| (l.per.as_micros() / l.requests as u128) as u64)
+        .unwrap_or(0);
+    INTERVAL_US.store(interval, Ordering::Relaxed);
+}
+
+/// Wait until a request may be sent. Requests are spaced evenly, in the order
+/// they call `acquire`.
+pub(crate) async fn acquire() {
+    let interval = INTERVAL_US.load(Ordering::Relaxed);
+    if interval == 0 {
+        return;
+    }
+    let wait = match NEXT_SLOT.lock() {
+        Ok(mut next) => {
+            let now = Instant::now();
+            let slot = next.filter(|t| *t > now).unwrap_or(now);
+            *next = Some(slot + Duration::from_micros(interval));
+            slot - now
+        }
+        Err(_) => return,
+    };
+    if wait > Duration::from_millis(0) {
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rate(s: &str) -> Result<RateLimit, String> {
+        s.parse()
+    }
+
+    #[test]
+    fn parse_rate_limit() {
+        assert_eq!(
+            rate("8/s"),
+            Ok(RateLimit {
+                requests: 8,
+                per: Duration::from_secs(1)
+            })
+        );
+        assert_eq!(
+            rate("100/m"),
+            Ok(RateLimit {
+                requests: 100,
+                per: Duration::from_secs(60)
+            })
+        );
+        assert_eq!(
+            rate(" 1 / 500ms "),
+            Ok(RateLimit {
+                requests: 1,
+                per: Duration::from_millis(500)
+            })
+        );
+        assert_eq!(
+            rate("5/10s"),
+            Ok(RateLimit {
+                requests: 5,
+                per: Duration::from_secs(10)
+            })
+        );
+        for invalid in ["8", "x/s", "0/s", "8/0s", "8/0ms", "8/w", "-1/s", ""].iter() {
+            assert!(rate(invalid).is_err(), "{} should be invalid", invalid);
+        }
+    }
+}
@@ -0,0 +1,202 @@
+//! Direct calls to Zenkit REST endpoints that aren't wrapped by the zenkit crate.
+//! Responses are returned as untyped json.
+use crate::{
+    cache, dryrun, http, metrics,
+    retry::{retry, retry_rate_limited},
+    Error,
+};
+use reqwest::{multipart, Method};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use tracing::{debug, trace};
+
+/// Request body
+#[derive(Clone, Copy)]
+enum Payload<'a> {
+    Json(&'a Value),
+    /// file sent as multipart form data
+    File {
+        name: &'a str,
+        data: &'a [u8],
+    },
+}
+
+pub(crate) struct RestClient {
+    client: reqwest::Client,
+    endpoint: String,
+    token: String,
+}
+
+impl RestClient {
+    pub(crate) fn new(endpoint: &str, token: &str) -> Result<Self, Error> {
+        Ok(RestClient {
+            client: http::client()?,
+            endpoint: endpoint.trim_end_matches('/').to_string(),
+            token: token.to_string(),
+        })
+    }
+
+    pub(crate) async fn get(&self, path: &str) -> Result<Value, Error> {
+        self.send(Method::GET, path, None).await
+    }
+
+    pub(crate) async fn post(&self, path: &str, body: &Value) -> Result<Value, Error> {
+        self.send(Method::POST, path, Some(Payload::Json(body)))
+            .await
+    }
+
+    pub(crate) async fn put(&self, path: &str, body: &Value) -> Result<Value, Error> {
+        self.send(Method::PUT, path, Some(Payload::Json(body)))
+            .await
+    }
+
+    pub(crate) async fn delete(&self, path: &str) -> Result<Value, Error> {
+        self.send(Method::DELETE, path, None).await
+    }
+
+    /// POST a file as multipart form data, in the form field "file"
+    pub(crate) async fn upload(&self, path: &str, name: &str, data: &[u8]) -> Result<Value, Error> {
+        self.send(Method::POST, path, Some(Payload::File { name, data }))
+            .await
+    }
+
+    /// GET raw response body, for file downloads
+    pub(crate) async fn get_bytes(&self, path: &str) -> Result<Vec<u8>, Error> {
+        let resp = self.send_request(Method::GET, path, None).await?;
+        let data = resp.bytes().await?.to_vec();
+        metrics::record_bytes(0, data.len());
+        Ok(data)
+    }
+
+    /// Send request and parse the json response. In dry-run mode, requests other than GET
+    /// are printed instead of sent, and return Null.
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Payload<'_>>,
+    ) -> Result<Value, Error> {
+        if method != Method::GET && dryrun::enabled() {
+            match body {
+                Some(Payload::Json(body)) => {
+                    dryrun::print_request(method.as_str(), path, Some(body))
+                }
+                Some(Payload::File { name, data }) => dryrun::print_request(
+                    method.as_str(),
+                    path,
+                    Some(&serde_json::json!({ "file": name, "size": data.len() })),
+                ),
+                None => dryrun::print_request(method.as_str(), path, None),
+            }
+            return Ok(Value::Null);
+        }
+        let changes_schema = method != Method::GET && is_schema_path(path);
+        let text = self.send_request(method, path, body).await?.text().await?;
+        metrics::record_bytes(0, text.len());
+        if changes_schema {
+            cache::clear()?;
+        }
+        trace!(path, body = %text, "response body");
+        if text.trim().is_empty() {
+            Ok(Value::Null)
+        } else {
+            Ok(serde_json::from_str(&text)?)
+        }
+    }
+
+    /// Send request, with retries. POST requests are only retried if rate-limited.
+    async fn send_request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Payload<'_>>,
+    ) -> Result<reqwest::Response, Error> {
+        let operation = format!("{} {}", method, path);
+        if method == Method::POST {
+            retry_rate_limited(&operation, || self.try_send(method.clone(), path, body)).await
+        } else {
+            retry(&operation, || self.try_send(method.clone(), path, body)).await
+        }
+    }
+
+    /// Send request, returning an error if the response status is not success
+    async fn try_send(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<Payload<'_>>,
+    ) -> Result<reqwest::Response, Error> {
+        let url = format!("{}/{}", self.endpoint, path.trim_start_matches('/'));
+        let mut req = self
+            .client
+            .request(method.clone(), &url)
+            .header("Zenkit-API-Key", &self.token);
+        match body {
+            Some(Payload::Json(body)) => {
+                trace!(%method, path, body = %body, "request body");
+                req = req.json(body);
+            }
+            Some(Payload::File { name, data }) => {
+                trace!(%method, path, file = name, size = data.len(), "request file");
+                let part = multipart::Part::bytes(data.to_vec()).file_name(name.to_string());
+                req = req.multipart(multipart::Form::new().part("file", part));
+            }
+            None => {}
+        }
+        if metrics::enabled() {
+            let sent = match body {
+                Some(Payload::Json(body)) => body.to_string().len(),
+                Some(Payload::File { data, .. }) => data.len(),
+                None => 0,
+            };
+            metrics::record_bytes(sent, 0);
+        }
+        let start = Instant::now();
+        let resp = match req.send().await {
+            Ok(resp) => resp,
+            Err(e) => {
+                debug!(
+                    %method,
+                    path,
+                    latency_ms = start.elapsed().as_millis() as u64,
+                    error = %e,
+                    "request failed"
+                );
+                return Err(e.into());
+            }
+        };
+        let status = resp.status();
+        debug!(
+            %method,
+            path,
+            status = status.as_u16(),
+            latency_ms = start.elapsed().as_millis() as u64,
+            "request"
+        );
+        if !status.is_success() {
+            // Retry-After may also be an http date, which is ignored
+            let retry_after = resp
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse::<u64>().ok())
+                .map(Duration::from_secs);
+            let text = resp.text().await.unwrap_or_default();
+            return Err(Error::Http {
+                status: Some(status.as_u16()),
+                retry_after,
+                transient: false,
+                message: format!("{} {} failed ({}): {}", method, path, status, text),
+            });
+        }
+        Ok(resp)
+    }
+}
+
+/// Returns true if the path is for lists or fields (not items), so that requests
+/// changing it invalidate cached metadata
+fn is_schema_path(path: &str) -> bool {
+    (path.starts_with("workspaces/") && path.contains("/lists"))
+        || (path.starts_with("lists/") && !path.contains("/entries"))
+        || path.starts_with("elements/")
+}
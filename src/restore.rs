@@ -0,0 +1,197 @@
+//! Restore list items from a backup folder
+use crate::{cache, dryrun, progress, retry::retry, values::value_key, Error, RestoreItemOpt};
+use serde_json::Value;
+use std::result::Result;
+use zenkit::types::{Element, ElementCategoryId, Entry, FieldVal, UpdateAction, ZKObjectID, ID};
+
+/// Restore one item from the backup. If the item still exists in the list,
+/// its field values are replaced with the backed-up values; otherwise, a new
+/// item is created with those values. Returns the restored item.
+pub(crate) async fn restore_item(ws_id: ID, opt: &RestoreItemOpt) -> Result<Entry, Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let saved = find_backup_item(&opt.from, &list_info.list().uuid, &opt.item)?;
+    let values = entry_values(&saved, list_info.fields(), UpdateAction::Replace);
+    match retry("get_item", || list_info.get_item(&saved.uuid)).await {
+        Ok(current) if dryrun::enabled() => {
+            dryrun::print_item_request(&list_info, Some(current.get_id()), &values).await?;
+            Ok(current)
+        }
+        Ok(current) => Ok(list_info.update_item(current.get_id(), &values).await?),
+        Err(_) => {
+            let values = entry_values(&saved, list_info.fields(), UpdateAction::Null);
+            if dryrun::enabled() {
+                dryrun::print_item_request(&list_info, None, &values).await?;
+                return Ok(saved);
+            }
+            Ok(list_info.create_item(&values).await?)
+        }
+    }
+}
+
+/// Find the item (by id or uuid) in the list's backup files in the folder:
+/// the full backup <list-uuid>_items.json, and any incremental backups
+/// <list-uuid>_items_since_<tstamp>.json. If the item appears in more than one file,
+/// the most recently modified version is returned.
+pub(crate) fn find_backup_item(dir: &str, list_uuid: &str, item: &str) -> Result<Entry, Error> {
+    let prefix = format!("{}_items", list_uuid);
+    let mut files = Vec::new();
+    for dir_entry in std::fs::read_dir(dir)? {
+        let path = dir_entry?.path();
+        let is_items_file = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map(|name| name.starts_with(&prefix) && name.ends_with(".json"))
+            .unwrap_or(false);
+        if is_items_file {
+            files.push(path);
+        }
+    }
+    let files_done = progress::bar(files.len(), "Reading backup");
+    files_done.set_message("files");
+    let mut found: Option<Entry> = None;
+    for path in files.iter() {
+        let items: Vec<Entry> = serde_json::from_slice(&std::fs::read(path)?).map_err(|e| {
+            Error::Invalid(format!("Invalid backup file {}: {}", path.display(), e))
+        })?;
+        files_done.inc(1);
+        if let Some(entry) = items
+            .into_iter()
+            .find(|e| e.uuid == item || e.id.to_string() == item)
+        {
+            match found {
+                Some(ref prev) if *prev.updated_at >= *entry.updated_at => {}
+                _ => found = Some(entry),
+            }
+        }
+    }
+    files_done.finish_and_clear();
+    found.ok_or_else(|| {
+        Error::NotFound(format!(
+            "Item {} not found in backup of list {} in {}",
+            item, list_uuid, dir
+        ))
+    })
+}
+
+/// Convert the business field values of a backed-up entry into field updates.
+/// Fields that no longer exist in the list are skipped, as are formula fields
+/// (computed by the server) and file attachments.
+pub(crate) fn entry_values(
+    entry: &Entry,
+    fields: &[Element],
+    action: UpdateAction,
+) -> Vec<(String, FieldVal, UpdateAction)> {
+    let mut values = Vec::new();
+    for field in fields.iter() {
+        let key = match value_key(field) {
+            Some(key) => key,
+            None => continue,
+        };
+        let value = match field.element_category {
+            ElementCategoryId::Categories
+            | ElementCategoryId::Persons
+            | ElementCategoryId::References => entry.fields.get(&key).and_then(as_arr_val),
+            _ => entry.fields.get(&key).and_then(as_str_val),
+        };
+        if let Some(value) = value {
+            values.push((field.uuid.clone(), value, action));
+        }
+    }
+    values
+}
+
+/// Scalar json value as a string field value. Nulls are skipped.
+fn as_str_val(v: &Value) -> Option<FieldVal> {
+    match v {
+        Value::Null => None,
+        Value::String(s) => Some(FieldVal::Str(s.clone())),
+        v => Some(FieldVal::Str(v.to_string())),
+    }
+}
+
+/// Array of ids (choices and persons) or uuids (references) as a list field value
+fn as_arr_val(v: &Value) -> Option<FieldVal> {
+    let arr = v.as_array()?;
+    if !arr.is_empty() && arr.iter().all(|v| v.is_u64()) {
+        return Some(FieldVal::ArrID(
+            arr.iter().filter_map(|v| v.as_u64()).collect(),
+        ));
+    }
+    Some(FieldVal::ArrStr(
+        arr.iter()
+            .map(|v| match v {
+                Value::String(s) => s.clone(),
+                v => v.to_string(),
+            })
+            .collect(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{choice_field, field, item};
+    use serde_json::json;
+
+    #[test]
+    fn latest_backup_item() {
+        let dir = std::env::temp_dir().join(format!("zk_restore_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, items: Vec<Entry>| {
+            std::fs::write(dir.join(name), serde_json::to_vec(&items).unwrap()).unwrap()
+        };
+        write("list-1_items.json", vec![item(7, "First", json!({}))]);
+        write(
+            "list-1_items_since_1614556800000.json",
+            vec![item(
+                7,
+                "Second",
+                json!({"updated_at": "2021-03-02T00:00:00Z"}),
+            )],
+        );
+        write("list-2_items.json", vec![item(8, "Other", json!({}))]);
+        let dir_str = dir.to_str().unwrap();
+
+        let found = find_backup_item(dir_str, "list-1", "7").unwrap();
+        assert_eq!(found.fields["field-1_text"], json!("Second"));
+        let found = find_backup_item(dir_str, "list-1", "item-7").unwrap();
+        assert_eq!(found.fields["field-1_text"], json!("Second"));
+        assert!(matches!(
+            find_backup_item(dir_str, "list-1", "8"),
+            Err(Error::NotFound(_))
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn backed_up_values() {
+        let fields = vec![
+            field(1, "Title", 1),
+            field(2, "Points", 2),
+            choice_field(3, "Tags", &[(1, "red"), (2, "blue")]),
+            field(4, "Notes", 1),
+        ];
+        let entry = item(
+            7,
+            "Write tests",
+            json!({"field-2_number": 3, "field-3_categories": [2, 1], "field-4_text": null}),
+        );
+        let values = entry_values(&entry, &fields, UpdateAction::Replace);
+        assert_eq!(values.len(), 3);
+        assert!(matches!(
+            &values[0],
+            (uuid, FieldVal::Str(s), UpdateAction::Replace)
+                if uuid == "field-1" && s == "Write tests"
+        ));
+        assert!(matches!(
+            &values[1],
+            (uuid, FieldVal::Str(s), UpdateAction::Replace) if uuid == "field-2" && s == "3"
+        ));
+        assert!(matches!(
+            &values[2],
+            (uuid, FieldVal::ArrID(ids), UpdateAction::Replace)
+                if uuid == "field-3" && *ids == [2, 1]
+        ));
+    }
+}
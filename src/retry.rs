@@ -0,0 +1,169 @@
+//! Retry api calls that fail with transient errors (rate limits, server errors, timeouts),
+//! with exponential backoff.
+use crate::{http, metrics, ratelimit, Error};
+use std::{
+    future::Future,
+    result::Result,
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+use tracing::{debug, warn};
+
+static RETRIES: AtomicU32 = AtomicU32::new(3);
+static RETRY_DELAY_MS: AtomicU64 = AtomicU64::new(1000);
+
+/// Set the number of retries, and the delay before the first retry.
+/// The delay doubles after each attempt, unless the server sends `Retry-After`.
+pub(crate) fn configure(retries: u32, delay: Duration) {
+    RETRIES.store(retries, Ordering::Relaxed);
+    RETRY_DELAY_MS.store(delay.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Returns true if the request may succeed if repeated: connection errors, timeouts,
+/// rate limits, and server errors
+fn is_transient(e: &Error) -> bool {
+    match e {
+        Error::Http {
+            transient: true, ..
+        } => true,
+        Error::Zenkit(zenkit::Error::Reqwest(re)) if re.is_timeout() || re.is_connect() => true,
+        Error::Zenkit(ze) if ze.is_rate_limit() => true,
+        _ => matches!(e.status(), Some(status) if status == 429 || status >= 500),
+    }
+}
+
+/// Returns true if the server rejected the request due to rate limiting
+fn is_rate_limited(e: &Error) -> bool {
+    match e {
+        Error::Zenkit(ze) if ze.is_rate_limit() => true,
+        _ => e.status() == Some(429),
+    }
+}
+
+/// Call `f` until it succeeds, fails with a non-transient error, or runs out of retries.
+/// `operation` names the api call in the log, e.g., "get_list_entries".
+/// Use only for requests that are safe to repeat (reads, updates, and deletes).
+/// Each call of `f` must send (at most) one request: the client-side rate limit
+/// counts attempts.
+pub(crate) async fn retry<T, E, F, Fut>(operation: &str, f: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Into<Error>,
+{
+    retry_if(operation, f, is_transient).await
+}
+
+/// Like `retry`, but only retries requests rejected by rate limiting,
+/// for requests that must not be repeated if the server may have processed them
+/// (e.g., creating items or comments).
+pub(crate) async fn retry_rate_limited<T, E, F, Fut>(operation: &str, f: F) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Into<Error>,
+{
+    retry_if(operation, f, is_rate_limited).await
+}
+
+/// Call `f` until it succeeds, or fails with an error that `should_retry` rejects,
+/// or runs out of retries. Each attempt is logged (with `-v`), with its elapsed time
+/// and outcome.
+async fn retry_if<T, E, F, Fut>(
+    operation: &str,
+    mut f: F,
+    should_retry: fn(&Error) -> bool,
+) -> Result<T, Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Into<Error>,
+{
+    let retries = RETRIES.load(Ordering::Relaxed);
+    let mut delay = Duration::from_millis(RETRY_DELAY_MS.load(Ordering::Relaxed));
+    let mut attempt = 0;
+    loop {
+        ratelimit::acquire().await;
+        let start = Instant::now();
+        let result = match http::timeout() {
+            // also applies to the zenkit api client, which has no timeout setting
+            Some(timeout) => match tokio::time::timeout(timeout, f()).await {
+                Ok(result) => result.map_err(Into::into),
+                Err(_) => Err(Error::Http {
+                    status: None,
+                    retry_after: None,
+                    transient: true,
+                    message: format!("request timed out after {}s", timeout.as_secs_f32()),
+                }),
+            },
+            None => f().await.map_err(Into::into),
+        };
+        debug!(
+            operation,
+            attempt = attempt + 1,
+            latency_ms = start.elapsed().as_millis() as u64,
+            outcome = %match &result {
+                Ok(_) => String::from("ok"),
+                Err(e) => e.summary(),
+            },
+            "api call"
+        );
+        metrics::record_request(
+            start.elapsed(),
+            result.is_err(),
+            result.as_ref().err().map(is_rate_limited).unwrap_or(false),
+        );
+        match result {
+            Ok(v) => return Ok(v),
+            Err(e) if attempt < retries && should_retry(&e) => {
+                attempt += 1;
+                metrics::record_retry();
+                let wait = match &e {
+                    Error::Http {
+                        retry_after: Some(after),
+                        ..
+                    } => *after,
+                    _ => delay,
+                };
+                warn!(
+                    attempt,
+                    retries,
+                    delay_ms = wait.as_millis() as u64,
+                    error = %e.summary(),
+                    "retrying"
+                );
+                tokio::time::sleep(wait).await;
+                delay *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn http(status: Option<u16>, transient: bool) -> Error {
+        Error::Http {
+            status,
+            retry_after: None,
+            transient,
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn transient_errors() {
+        // timeouts and connection errors
+        assert!(is_transient(&http(None, true)));
+        // other errors without a response, such as an invalid response body
+        assert!(!is_transient(&http(None, false)));
+        assert!(is_transient(&http(Some(503), false)));
+        assert!(is_transient(&http(Some(429), false)));
+        assert!(!is_transient(&http(Some(404), false)));
+        assert!(!is_transient(&Error::Invalid("bad value".into())));
+        assert!(is_rate_limited(&http(Some(429), false)));
+        assert!(!is_rate_limited(&http(Some(503), false)));
+    }
+}
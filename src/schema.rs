@@ -0,0 +1,658 @@
+//! Create lists and fields, and copy list schemas
+use crate::{
+    backup::get_all_items,
+    cache,
+    choices::{choice_value, field_choices, update_choices},
+    dryrun,
+    rest::RestClient,
+    restore::entry_values,
+    retry::retry,
+    ApplySchemaOpt, CloneListOpt, Error, ExportSchemaOpt,
+};
+use clap::Clap;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::{collections::HashMap, result::Result};
+use zenkit::types::{Element, ElementCategoryId, FieldVal, List, UpdateAction, ID};
+
+/// Field types that can be created
+#[derive(Clap, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum FieldType {
+    Text,
+    Number,
+    Url,
+    Date,
+    Checkbox,
+    Categories,
+    Persons,
+    References,
+}
+
+impl FieldType {
+    pub(crate) fn category(&self) -> ElementCategoryId {
+        match self {
+            FieldType::Text => ElementCategoryId::Text,
+            FieldType::Number => ElementCategoryId::Number,
+            FieldType::Url => ElementCategoryId::URL,
+            FieldType::Date => ElementCategoryId::Date,
+            FieldType::Checkbox => ElementCategoryId::Checkbox,
+            FieldType::Categories => ElementCategoryId::Categories,
+            FieldType::Persons => ElementCategoryId::Persons,
+            FieldType::References => ElementCategoryId::References,
+        }
+    }
+
+    /// Field type of the element category, or None if the category can't be
+    /// created from a schema (e.g., formulas and files)
+    pub(crate) fn from_category(category: ElementCategoryId) -> Option<FieldType> {
+        match category {
+            ElementCategoryId::Text => Some(FieldType::Text),
+            ElementCategoryId::Number => Some(FieldType::Number),
+            ElementCategoryId::URL => Some(FieldType::Url),
+            ElementCategoryId::Date => Some(FieldType::Date),
+            ElementCategoryId::Checkbox => Some(FieldType::Checkbox),
+            ElementCategoryId::Categories => Some(FieldType::Categories),
+            ElementCategoryId::Persons => Some(FieldType::Persons),
+            ElementCategoryId::References => Some(FieldType::References),
+            _ => None,
+        }
+    }
+}
+
+/// Declarative field definition, as used in schema files
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct FieldSpec {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub field_type: FieldType,
+    /// choices, for a categories field
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<String>,
+    /// allow multiple values (categories, persons, and references fields)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub multiple: bool,
+    /// referenced list name or id, for a references field
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target_list: Option<String>,
+}
+
+/// Schema file: the list's fields
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ListSchema {
+    pub fields: Vec<FieldSpec>,
+}
+
+/// Create a list in the workspace. Returns None in dry-run mode.
+pub(crate) async fn create_list(
+    rest: &RestClient,
+    ws_id: ID,
+    name: &str,
+    description: Option<&str>,
+) -> Result<Option<List>, Error> {
+    let mut body = json!({ "name": name });
+    if let Some(description) = description {
+        body["description"] = description.into();
+    }
+    let resp = rest
+        .post(&format!("workspaces/{}/lists", ws_id), &body)
+        .await?;
+    if dryrun::enabled() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_value(resp)?))
+}
+
+/// Add a field to the list. `element_data` holds category-specific settings,
+/// such as `predefinedCategories` for a categories field. Returns None in dry-run mode.
+pub(crate) async fn create_field(
+    rest: &RestClient,
+    list_id: ID,
+    name: &str,
+    category: ElementCategoryId,
+    element_data: Map<String, Value>,
+) -> Result<Option<Element>, Error> {
+    let body = json!({
+        "name": name,
+        "elementcategory": category as u64,
+        "elementData": element_data,
+    });
+    let resp = rest
+        .post(&format!("lists/{}/elements", list_id), &body)
+        .await?;
+    if dryrun::enabled() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_value(resp)?))
+}
+
+/// Add a field to the list from its definition. Returns None in dry-run mode.
+pub(crate) async fn create_field_spec(
+    rest: &RestClient,
+    ws_id: ID,
+    list_id: ID,
+    spec: &FieldSpec,
+) -> Result<Option<Element>, Error> {
+    let mut element_data = Map::new();
+    match spec.field_type {
+        FieldType::Categories => {
+            let choices: Vec<Value> = spec
+                .choices
+                .iter()
+                .map(|name| json!({ "name": name }))
+                .collect();
+            element_data.insert("predefinedCategories".into(), choices.into());
+            element_data.insert("allowMultiple".into(), spec.multiple.into());
+        }
+        FieldType::Persons => {
+            element_data.insert("allowMultiple".into(), spec.multiple.into());
+        }
+        FieldType::References => {
+            let target = spec.target_list.as_deref().ok_or_else(|| {
+                Error::Invalid(format!(
+                    "Field '{}': target list is required for references fields",
+                    spec.name
+                ))
+            })?;
+            let target_info = cache::get_list_info(ws_id, target).await?;
+            element_data.insert(
+                "childListUUID".into(),
+                target_info.list().uuid.clone().into(),
+            );
+            element_data.insert("allowMultiple".into(), spec.multiple.into());
+        }
+        FieldType::Text
+        | FieldType::Number
+        | FieldType::Url
+        | FieldType::Date
+        | FieldType::Checkbox => {}
+    }
+    create_field(
+        rest,
+        list_id,
+        &spec.name,
+        spec.field_type.category(),
+        element_data,
+    )
+    .await
+}
+
+/// Update the list to match the schema file: missing fields are created, and
+/// missing choices are added to categories fields. Differences that can't be
+/// fixed without losing data (fields with a different type, or fields not in the schema)
+/// are reported but not changed. With `--dry-run`, only reports differences.
+pub(crate) async fn apply_schema(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &ApplySchemaOpt,
+) -> Result<(), Error> {
+    let schema: ListSchema = serde_json::from_slice(&std::fs::read(&opt.file)?)
+        .map_err(|e| Error::Invalid(format!("Invalid schema file {}: {}", opt.file, e)))?;
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let list_id = list_info.get_id();
+    let prefix = if dryrun::enabled() { "(dry run) " } else { "" };
+
+    for spec in schema.fields.iter() {
+        let field = match list_info.fields().iter().find(|f| f.name == spec.name) {
+            Some(field) => field,
+            None => {
+                println!("{}create field '{}'", prefix, spec.name);
+                if !dryrun::enabled() {
+                    create_field_spec(rest, ws_id, list_id, spec).await?;
+                }
+                continue;
+            }
+        };
+        if field.element_category != spec.field_type.category() {
+            println!(
+                "drift: field '{}' has category {}, schema type is {:?}",
+                spec.name, field.element_category as u64, spec.field_type
+            );
+            continue;
+        }
+        if spec.field_type == FieldType::Categories {
+            let existing = field
+                .element_data
+                .predefined_categories
+                .as_deref()
+                .unwrap_or_default();
+            let missing: Vec<&String> = spec
+                .choices
+                .iter()
+                .filter(|name| !existing.iter().any(|c| &&c.name == name))
+                .collect();
+            if missing.is_empty() {
+                continue;
+            }
+            for name in missing.iter() {
+                println!("{}add choice '{}' to field '{}'", prefix, name, spec.name);
+            }
+            if !dryrun::enabled() {
+                let mut choices: Vec<Value> = existing.iter().map(choice_value).collect();
+                choices.extend(missing.iter().map(|name| json!({ "name": name })));
+                update_choices(rest, list_id, field, choices).await?;
+            }
+        }
+    }
+    for field in list_info.fields().iter() {
+        if !schema.fields.iter().any(|spec| spec.name == field.name) {
+            println!("drift: field '{}' is not in schema", field.name);
+        }
+    }
+    Ok(())
+}
+
+/// Write the list's field definitions as a schema file, for use with `apply-schema`.
+/// Fields of types that can't be created from a schema are skipped, with a warning.
+pub(crate) async fn export_schema(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &ExportSchemaOpt,
+) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    // raw element definitions include settings not in the Element struct
+    let raw_fields = rest
+        .get(&format!("lists/{}/elements", list_info.get_id()))
+        .await?;
+    let raw_data = |uuid: &str, key: &str| -> Option<Value> {
+        raw_fields
+            .as_array()?
+            .iter()
+            .find(|f| f.get("uuid").and_then(|v| v.as_str()) == Some(uuid))?
+            .get("elementData")?
+            .get(key)
+            .cloned()
+    };
+    let mut fields = Vec::new();
+    for field in list_info.fields().iter() {
+        let field_type = match FieldType::from_category(field.element_category) {
+            Some(t) => t,
+            None => {
+                eprintln!(
+                    "Skipping field '{}': category {} is not supported in schemas",
+                    field.name, field.element_category as u64
+                );
+                continue;
+            }
+        };
+        let choices = match field_type {
+            FieldType::Categories => field
+                .element_data
+                .predefined_categories
+                .as_deref()
+                .unwrap_or_default()
+                .iter()
+                .map(|c| c.name.clone())
+                .collect(),
+            _ => Vec::new(),
+        };
+        let target_list = match field_type {
+            FieldType::References => raw_data(&field.uuid, "childListUUID")
+                .and_then(|v| v.as_str().map(|s| s.to_string())),
+            _ => None,
+        };
+        fields.push(FieldSpec {
+            name: field.name.clone(),
+            field_type,
+            choices,
+            multiple: raw_data(&field.uuid, "allowMultiple")
+                .and_then(|v| v.as_bool())
+                .unwrap_or(false),
+            target_list,
+        });
+    }
+    let json = serde_json::to_string_pretty(&ListSchema { fields })?;
+    match opt.output {
+        Some(ref path) => std::fs::write(path, json)?,
+        None => println!("{}", json),
+    }
+    Ok(())
+}
+
+/// Copy a list's fields, and optionally its items, to a new list
+/// in the same or another workspace. Returns the new list.
+/// Field values of copied items are mapped to the new fields and choices by name.
+/// In dry-run mode, only the request to create the list is printed, since the
+/// requests that follow depend on the new list, and None is returned.
+pub(crate) async fn clone_list(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &CloneListOpt,
+) -> Result<Option<List>, Error> {
+    let api = zenkit::get_api()?;
+    let source = cache::get_list_info(ws_id, &opt.list).await?;
+    let dest_ws_id = match opt.to_workspace {
+        Some(ref name) => cache::get_workspace(name).await?.get_id(),
+        None => ws_id,
+    };
+    let description = Some(source.list().description.as_str()).filter(|d| !d.is_empty());
+    let new_list = match create_list(rest, dest_ws_id, &opt.name, description).await? {
+        Some(list) => list,
+        None => return Ok(None),
+    };
+
+    // new lists may start with default fields; fields with the same name are reused
+    let existing = retry("get_list_elements", || api.get_list_elements(new_list.id)).await?;
+    // raw element definitions include settings not in the Element struct
+    let raw_fields = rest
+        .get(&format!("lists/{}/elements", source.get_id()))
+        .await?;
+    for raw in raw_fields
+        .as_array()
+        .map(|v| v.as_slice())
+        .unwrap_or_default()
+    {
+        let field = match source
+            .fields()
+            .iter()
+            .find(|f| Some(f.uuid.as_str()) == raw.get("uuid").and_then(|v| v.as_str()))
+        {
+            Some(field) => field,
+            None => continue,
+        };
+        if existing.iter().any(|f| f.name == field.name) {
+            continue;
+        }
+        let element_data = copy_element_data(raw.get("elementData"));
+        create_field(
+            rest,
+            new_list.id,
+            &field.name,
+            field.element_category,
+            element_data,
+        )
+        .await?;
+    }
+
+    if opt.with_items {
+        let dest = cache::get_list_info(dest_ws_id, &new_list.uuid).await?;
+        let items = get_all_items(&source.list().uuid, false).await?;
+        for item in items.iter() {
+            // the new list has the same fields and choices
+            let (values, _) = map_values(
+                entry_values(item, source.fields(), UpdateAction::Null),
+                source.fields(),
+                dest.fields(),
+                &HashMap::new(),
+            );
+            dest.create_item(&values).await?;
+        }
+        eprintln!("Copied {} items", items.len());
+    }
+    Ok(Some(new_list))
+}
+
+/// Copy field settings, dropping ids of predefined categories,
+/// which are assigned by the server when the field is created
+fn copy_element_data(element_data: Option<&Value>) -> Map<String, Value> {
+    let mut data = match element_data {
+        Some(Value::Object(data)) => data.clone(),
+        _ => Map::new(),
+    };
+    if let Some(Value::Array(categories)) = data.get("predefinedCategories") {
+        let categories: Vec<Value> = categories
+            .iter()
+            .map(|c| json!({ "name": c.get("name"), "colorHex": c.get("colorHex") }))
+            .collect();
+        data.insert("predefinedCategories".into(), categories.into());
+    }
+    data
+}
+
+/// Map field values from the source list's fields to the destination list's fields,
+/// by field name, or by `renames` (source name -> destination name), translating
+/// category choice ids by choice name. Returns the mapped values, and a description
+/// of each value that couldn't be mapped: fields without a match in the destination,
+/// or of a different type, and choices without a match in the destination field.
+pub(crate) fn map_values(
+    values: Vec<(String, FieldVal, UpdateAction)>,
+    source: &[Element],
+    dest: &[Element],
+    renames: &HashMap<&str, &str>,
+) -> (Vec<(String, FieldVal, UpdateAction)>, Vec<String>) {
+    let dest_by_name: HashMap<&str, &Element> = dest.iter().map(|f| (f.name.as_str(), f)).collect();
+    let mut mapped = Vec::new();
+    let mut unmapped = Vec::new();
+    for (uuid, value, action) in values.into_iter() {
+        let src_field = match source.iter().find(|f| f.uuid == uuid) {
+            Some(f) => f,
+            None => continue,
+        };
+        let dest_name = renames
+            .get(src_field.name.as_str())
+            .copied()
+            .unwrap_or(&src_field.name);
+        let dest_field = match dest_by_name.get(dest_name) {
+            Some(f) => f,
+            None => {
+                unmapped.push(format!("{}: no field '{}'", src_field.name, dest_name));
+                continue;
+            }
+        };
+        if dest_field.element_category != src_field.element_category {
+            unmapped.push(format!(
+                "{}: field '{}' has a different type",
+                src_field.name, dest_field.name
+            ));
+            continue;
+        }
+        let value = match (src_field.element_category, value) {
+            (ElementCategoryId::Categories, FieldVal::ArrID(ids)) => {
+                let (ids, missing) = map_choices(&ids, src_field, dest_field);
+                if !missing.is_empty() {
+                    unmapped.push(format!(
+                        "{}: no choice {} in field '{}'",
+                        src_field.name,
+                        missing.join(", "),
+                        dest_field.name
+                    ));
+                }
+                FieldVal::ArrID(ids)
+            }
+            (_, value) => value,
+        };
+        mapped.push((dest_field.uuid.clone(), value, action));
+    }
+    (mapped, unmapped)
+}
+
+/// Translate choice ids of the source field to ids of the same-named choices
+/// in the destination field. Returns the ids, and the names of choices that
+/// aren't in the destination field.
+fn map_choices(ids: &[ID], source: &Element, dest: &Element) -> (Vec<ID>, Vec<String>) {
+    let (src_choices, dest_choices) = (field_choices(source), field_choices(dest));
+    let mut mapped = Vec::new();
+    let mut missing = Vec::new();
+    for id in ids.iter() {
+        let name = match src_choices.iter().find(|c| c.id == *id) {
+            Some(choice) => &choice.name,
+            None => continue,
+        };
+        match dest_choices.iter().find(|c| &c.name == name) {
+            Some(choice) => mapped.push(choice.id),
+            None => missing.push(format!("'{}'", name)),
+        }
+    }
+    (mapped, missing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{choice_field, field};
+
+    #[test]
+    fn element_data_without_choice_ids() {
+        let data = json!({
+            "allowMultiple": true,
+            "predefinedCategories": [
+                {"id": 5, "uuid": "choice-5", "name": "red", "colorHex": "#ff0000"},
+            ],
+        });
+        assert_eq!(
+            Value::Object(copy_element_data(Some(&data))),
+            json!({
+                "allowMultiple": true,
+                "predefinedCategories": [{"name": "red", "colorHex": "#ff0000"}],
+            })
+        );
+        assert!(copy_element_data(None).is_empty());
+    }
+
+    #[test]
+    fn values_mapped_by_field_name() {
+        let source = vec![
+            field(1, "Title", 1),
+            choice_field(3, "Tags", &[(1, "red"), (2, "blue")]),
+        ];
+        let dest = vec![
+            choice_field(13, "Tags", &[(5, "blue"), (6, "red")]),
+            field(11, "Title", 1),
+        ];
+        let values = vec![
+            (
+                "field-1".to_string(),
+                FieldVal::Str("Write tests".into()),
+                UpdateAction::Null,
+            ),
+            (
+                "field-3".to_string(),
+                FieldVal::ArrID(vec![1, 2]),
+                UpdateAction::Null,
+            ),
+        ];
+        let (mapped, _) = map_values(values, &source, &dest, &HashMap::new());
+        assert_eq!(mapped.len(), 2);
+        assert!(matches!(
+            &mapped[0],
+            (uuid, FieldVal::Str(s), UpdateAction::Null) if uuid == "field-11" && s == "Write tests"
+        ));
+        assert!(matches!(
+            &mapped[1],
+            (uuid, FieldVal::ArrID(ids), UpdateAction::Null) if uuid == "field-13" && *ids == [6, 5]
+        ));
+    }
+
+    #[test]
+    fn schema_file() {
+        let schema: ListSchema = serde_json::from_value(json!({ "fields": [
+            { "name": "Notes", "type": "text" },
+            { "name": "Priority", "type": "categories", "choices": ["High", "Low"] },
+            { "name": "Owner", "type": "persons", "multiple": true },
+            { "name": "Project", "type": "references", "target_list": "Projects" },
+        ] }))
+        .unwrap();
+        let fields = &schema.fields;
+        assert_eq!(fields.len(), 4);
+        assert_eq!(fields[0].field_type, FieldType::Text);
+        assert!(fields[0].choices.is_empty() && !fields[0].multiple);
+        assert_eq!(fields[1].choices, vec!["High", "Low"]);
+        assert!(fields[1].field_type.category() == ElementCategoryId::Categories);
+        assert!(fields[2].multiple);
+        assert_eq!(fields[3].target_list.as_deref(), Some("Projects"));
+        assert!(serde_json::from_value::<ListSchema>(json!({ "fields": [
+            { "name": "Due", "type": "time" },
+        ] }))
+        .is_err());
+    }
+
+    #[test]
+    fn exported_schema() {
+        for field_type in [FieldType::Text, FieldType::Url, FieldType::References].iter() {
+            assert_eq!(
+                FieldType::from_category(field_type.category()),
+                Some(*field_type)
+            );
+        }
+        assert_eq!(FieldType::from_category(ElementCategoryId::Formula), None);
+        let schema = ListSchema {
+            fields: vec![
+                FieldSpec {
+                    name: "Notes".into(),
+                    field_type: FieldType::Text,
+                    choices: Vec::new(),
+                    multiple: false,
+                    target_list: None,
+                },
+                FieldSpec {
+                    name: "Priority".into(),
+                    field_type: FieldType::Categories,
+                    choices: vec!["High".into(), "Low".into()],
+                    multiple: true,
+                    target_list: None,
+                },
+            ],
+        };
+        assert_eq!(
+            serde_json::to_value(&schema).unwrap(),
+            json!({ "fields": [
+                { "name": "Notes", "type": "text" },
+                {
+                    "name": "Priority",
+                    "type": "categories",
+                    "choices": ["High", "Low"],
+                    "multiple": true,
+                },
+            ] })
+        );
+    }
+
+    #[test]
+    fn values_mapped_by_renames() {
+        let source = vec![
+            field(1, "Title", 1),
+            field(2, "Estimate", 2),
+            choice_field(3, "Tags", &[(1, "red"), (2, "green")]),
+            field(4, "Notes", 1),
+        ];
+        let dest = vec![
+            field(11, "Name", 1),
+            field(12, "Estimate", 1),
+            choice_field(13, "Labels", &[(5, "red")]),
+        ];
+        let values = vec![
+            (
+                "field-1".to_string(),
+                FieldVal::Str("Write tests".into()),
+                UpdateAction::Null,
+            ),
+            (
+                "field-2".to_string(),
+                FieldVal::Float(3.0),
+                UpdateAction::Null,
+            ),
+            (
+                "field-3".to_string(),
+                FieldVal::ArrID(vec![1, 2]),
+                UpdateAction::Null,
+            ),
+            (
+                "field-4".to_string(),
+                FieldVal::Str("later".into()),
+                UpdateAction::Null,
+            ),
+        ];
+        let renames: HashMap<&str, &str> = vec![("Title", "Name"), ("Tags", "Labels")]
+            .into_iter()
+            .collect();
+        let (mapped, unmapped) = map_values(values, &source, &dest, &renames);
+        assert_eq!(mapped.len(), 2);
+        assert!(matches!(
+            &mapped[0],
+            (uuid, FieldVal::Str(s), _) if uuid == "field-11" && s == "Write tests"
+        ));
+        assert!(matches!(
+            &mapped[1],
+            (uuid, FieldVal::ArrID(ids), _) if uuid == "field-13" && *ids == [5]
+        ));
+        assert_eq!(
+            unmapped,
+            vec![
+                "Estimate: field 'Estimate' has a different type",
+                "Tags: no choice 'green' in field 'Labels'",
+                "Notes: no field 'Notes'",
+            ]
+        );
+    }
+}
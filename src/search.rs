@@ -0,0 +1,114 @@
+//! Search items for text
+use crate::{backup::get_all_items, cache, values::ValueFormatter, Error, SearchOpt};
+use std::result::Result;
+use zenkit::types::{List, ID};
+
+/// Characters of context shown on each side of a match
+const SNIPPET_CONTEXT: usize = 30;
+
+/// Find items in the list (or all active lists of the workspace, `ws_lists`) with a
+/// field value or title containing the query, ignoring case. Items are fetched and
+/// searched locally. Prints one line per matching item: list name, item id, field, and
+/// a snippet of the matching text. Returns the number of matching items.
+pub(crate) async fn search(ws_id: ID, ws_lists: &[List], opt: &SearchOpt) -> Result<usize, Error> {
+    let lists: Vec<List> = match opt.list {
+        Some(ref name) => vec![cache::get_list_info(ws_id, name).await?.list().clone()],
+        None => ws_lists
+            .iter()
+            .filter(|l| l.deprecated_at.is_none())
+            .cloned()
+            .collect(),
+    };
+    let formatter = ValueFormatter::new(ws_id).await?;
+    let query = lowercase(&opt.query);
+    let mut found = 0;
+    for list in lists.iter() {
+        let list_info = cache::get_list_info(ws_id, &list.uuid).await?;
+        for item in get_all_items(&list.uuid, false).await?.iter() {
+            let title = std::iter::once(("Title", item.display_string.clone()));
+            let values = list_info.fields().iter().flat_map(|field| {
+                formatter
+                    .display_values(item, field)
+                    .into_iter()
+                    .map(move |v| (field.name.as_str(), v))
+            });
+            if let Some((field, snip)) = title
+                .chain(values)
+                .find_map(|(field, value)| snippet(&value, &query).map(|s| (field, s)))
+            {
+                println!("{}\t{}\t{}\t{}", list.name, item.id, field, snip);
+                found += 1;
+                if opt.limit.map(|limit| found >= limit).unwrap_or(false) {
+                    return Ok(found);
+                }
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// Lowercase characters, one per character of `s`, so positions match those of `s`
+fn lowercase(s: &str) -> Vec<char> {
+    s.chars()
+        .map(|c| c.to_lowercase().next().unwrap_or(c))
+        .collect()
+}
+
+/// The part of `text` around the first occurrence of `query` (from `lowercase`),
+/// on one line, or None if `text` doesn't contain the query.
+fn snippet(text: &str, query: &[char]) -> Option<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let lower = lowercase(text);
+    if query.is_empty() || query.len() > lower.len() {
+        return None;
+    }
+    let pos = lower.windows(query.len()).position(|w| w == query)?;
+    let start = pos.saturating_sub(SNIPPET_CONTEXT);
+    let end = (pos + query.len() + SNIPPET_CONTEXT).min(chars.len());
+    let mut snip = String::new();
+    if start > 0 {
+        snip.push_str("...");
+    }
+    snip.push_str(&one_line(chars[start..end].iter().copied()));
+    if end < chars.len() {
+        snip.push_str("...");
+    }
+    Some(snip)
+}
+
+/// Text with line breaks and tabs replaced by spaces
+fn one_line<I: Iterator<Item = char>>(chars: I) -> String {
+    chars
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snippets() {
+        let query = lowercase("RELEASE");
+        assert_eq!(
+            snippet("Plan the release\nnext week", &query).as_deref(),
+            Some("Plan the release next week")
+        );
+        let long = format!("{}release notes{}", "a".repeat(40), "b".repeat(40));
+        assert_eq!(
+            snippet(&long, &query),
+            Some(format!(
+                "...{}release notes{}...",
+                "a".repeat(30),
+                "b".repeat(24)
+            ))
+        );
+        assert_eq!(
+            snippet("Ünïcode release", &lowercase("ÜNÏ")).as_deref(),
+            Some("Ünïcode release")
+        );
+        assert_eq!(snippet("Plan", &query), None);
+        assert_eq!(snippet("Plan the launch", &query), None);
+        assert_eq!(snippet("anything", &[]), None);
+    }
+}
@@ -0,0 +1,448 @@
+//! Interactive shell
+use crate::{cache, rest::RestClient, run_cmd, Error, Sub};
+use clap::{AppSettings, Clap};
+use rustyline::{
+    completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter,
+    validate::Validator, Context, Editor, Helper,
+};
+use std::{path::PathBuf, result::Result};
+use zenkit::types::ID;
+
+/// Subcommands that default to the current list, if `-l` is not given
+const LIST_COMMANDS: &[&str] = &[
+    "items",
+    "list",
+    "fields",
+    "field",
+    "item",
+    "get",
+    "url",
+    "open",
+    "choices",
+    "add-choice",
+    "rename-choice",
+    "delete-choice",
+    "create-field",
+    "apply-schema",
+    "export-schema",
+    "set",
+    "bulk-set",
+    "archive",
+    "create",
+    "delete-item",
+    "copy-item",
+    "restore-item",
+    "board",
+    "agenda",
+    "due",
+    "query",
+    "sync",
+    "checklist",
+    "attach",
+    "files",
+    "comment",
+    "comments",
+    "history",
+    "undo",
+    "watch",
+];
+
+/// Command names offered for completion of the first word
+const COMMANDS: &[&str] = &[
+    "use",
+    "help",
+    "exit",
+    "quit",
+    "workspaces",
+    "users",
+    "invite-user",
+    "remove-user",
+    "whoami",
+    "refresh-cache",
+    "lists",
+    "items",
+    "fields",
+    "field",
+    "item",
+    "get",
+    "url",
+    "open",
+    "search",
+    "stats",
+    "board",
+    "agenda",
+    "my-items",
+    "due",
+    "export",
+    "mirror",
+    "sync",
+    "query",
+    "choices",
+    "add-choice",
+    "rename-choice",
+    "delete-choice",
+    "create-field",
+    "apply-schema",
+    "export-schema",
+    "set",
+    "bulk-set",
+    "archive",
+    "create",
+    "delete-item",
+    "copy-item",
+    "move-item",
+    "sync-lists",
+    "create-list",
+    "clone-list",
+    "deprecate-list",
+    "delete-list",
+    "restore-item",
+    "import",
+    "checklist",
+    "attach",
+    "files",
+    "comment",
+    "comments",
+    "activities",
+    "history",
+    "undo",
+    "notifications",
+    "mark-read",
+    "edit-comment",
+    "delete-comment",
+    "webhook",
+    "update-webhook",
+    "list-webhooks",
+    "test-webhook",
+    "delete-webhook",
+    "watch",
+    "replay",
+    "notify",
+    "backup",
+    "diff",
+];
+
+/// A command line entered in the shell
+#[derive(Clap, Debug)]
+#[clap(setting = AppSettings::NoBinaryName)]
+struct ShellCmd {
+    #[clap(subcommand)]
+    cmd: Sub,
+}
+
+/// Run commands interactively until "exit", "quit", or end of input.
+/// Errors from commands are printed, and don't end the session.
+pub(crate) async fn shell(ws_name: &str, rest: &RestClient) -> Result<(), Error> {
+    let ws = cache::get_workspace(ws_name).await?;
+    let mut rl = Editor::<ShellHelper>::new();
+    rl.set_helper(Some(ShellHelper {
+        lists: ws.lists.iter().map(|l| l.name.clone()).collect(),
+        fields: Vec::new(),
+    }));
+    let history = history_file();
+    if let Some(ref path) = history {
+        // no history yet on first use
+        let _ = rl.load_history(path);
+    }
+
+    let mut current: Option<String> = None;
+    loop {
+        let prompt = match current {
+            Some(ref list) => format!("zk:{}/{}> ", ws.name, list),
+            None => format!("zk:{}> ", ws.name),
+        };
+        let line = match rl.readline(&prompt) {
+            Ok(line) => line,
+            // ctrl-c discards the line
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(Error::Io(e.to_string())),
+        };
+        let words = match split_words(&line) {
+            Ok(words) => words,
+            Err(e) => {
+                eprintln!("Error: {}", e.summary());
+                continue;
+            }
+        };
+        if words.is_empty() {
+            continue;
+        }
+        rl.add_history_entry(line.as_str());
+
+        match words[0].as_str() {
+            "exit" | "quit" => break,
+            "use" => {
+                match words.get(1) {
+                    Some(list) => match set_current(ws.get_id(), list, &mut rl).await {
+                        Ok(name) => current = Some(name),
+                        Err(e) => eprintln!("Error: {}", e.summary()),
+                    },
+                    None => current = None,
+                }
+                continue;
+            }
+            _ => {}
+        }
+        let args = expand(words, current.as_deref());
+        let cmd = match parse_command(&args) {
+            Ok(cmd) => cmd,
+            Err(e) => {
+                // includes help and usage messages
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        if let Err(e) = run_cmd(cmd, ws_name, rest).await {
+            eprintln!("Error: {}", e.summary());
+            continue;
+        }
+        // the last list used becomes the current list
+        if let Some(list) = list_arg(&args) {
+            if current.as_deref() != Some(list) {
+                if let Ok(name) = set_current(ws.get_id(), list, &mut rl).await {
+                    current = Some(name);
+                }
+            }
+        }
+    }
+    if let Some(ref path) = history {
+        rl.save_history(path)
+            .map_err(|e| Error::Io(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// Parse the arguments of a command entered without the `zk` prefix
+pub(crate) fn parse_command(args: &[String]) -> Result<Sub, clap::Error> {
+    ShellCmd::try_parse_from(args.iter()).map(|shell_cmd| shell_cmd.cmd)
+}
+
+/// Make the list current, and load its field names for completion.
+/// Returns the list name.
+async fn set_current(ws_id: ID, list: &str, rl: &mut Editor<ShellHelper>) -> Result<String, Error> {
+    let list_info = cache::get_list_info(ws_id, list).await?;
+    if let Some(helper) = rl.helper_mut() {
+        helper.fields = list_info.fields().iter().map(|f| f.name.clone()).collect();
+    }
+    Ok(list_info.list().name.clone())
+}
+
+/// Expand shorthand forms into full command arguments:
+///   items|fields LIST         -> items|fields -l LIST
+///   item ITEM                 -> item -i ITEM
+///   get ITEM FIELD            -> get -i ITEM -f FIELD
+///   set ITEM FIELD VALUE...   -> set -i ITEM -f FIELD -v "VALUE..."
+/// then add `-l` with the current list, if the command needs a list and doesn't have one.
+pub(crate) fn expand(words: Vec<String>, current: Option<&str>) -> Vec<String> {
+    let positional = words.iter().skip(1).all(|w| !w.starts_with('-'));
+    let mut args = if !positional {
+        words
+    } else {
+        match (words[0].as_str(), words.len()) {
+            ("items", 2) | ("list", 2) | ("fields", 2) => {
+                vec![words[0].clone(), "-l".into(), words[1].clone()]
+            }
+            ("item", 2) => vec![words[0].clone(), "-i".into(), words[1].clone()],
+            ("get", 3) => vec![
+                words[0].clone(),
+                "-i".into(),
+                words[1].clone(),
+                "-f".into(),
+                words[2].clone(),
+            ],
+            ("set", n) if n >= 4 => vec![
+                words[0].clone(),
+                "-i".into(),
+                words[1].clone(),
+                "-f".into(),
+                words[2].clone(),
+                "-v".into(),
+                words[3..].join(" "),
+            ],
+            _ => words,
+        }
+    };
+    if let Some(list) = current {
+        if LIST_COMMANDS.contains(&args[0].as_str()) && list_arg(&args).is_none() {
+            args.insert(1, "-l".into());
+            args.insert(2, list.into());
+        }
+    }
+    args
+}
+
+/// Value of the `-l` or `--list` argument
+pub(crate) fn list_arg(args: &[String]) -> Option<&str> {
+    args.iter()
+        .position(|a| a == "-l" || a == "--list")
+        .and_then(|pos| args.get(pos + 1))
+        .map(|s| s.as_str())
+        .or_else(|| args.iter().find_map(|a| a.strip_prefix("--list=")))
+}
+
+/// Split a command line into words. Words may be quoted with single or double quotes
+/// to include spaces.
+pub(crate) fn split_words(line: &str) -> Result<Vec<String>, Error> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    for c in line.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.push(c),
+            None if c == '"' || c == '\'' => {
+                quote = Some(c);
+                in_word = true;
+            }
+            None if c.is_whitespace() => {
+                if in_word {
+                    words.push(std::mem::take(&mut word));
+                    in_word = false;
+                }
+            }
+            None => {
+                word.push(c);
+                in_word = true;
+            }
+        }
+    }
+    if quote.is_some() {
+        return Err(Error::Invalid("Unterminated quote".into()));
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Shell history is saved in ~/.zk_history
+fn history_file() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".zk_history"))
+}
+
+/// Completion of command names, and of list and field names
+struct ShellHelper {
+    /// names of lists in the workspace
+    lists: Vec<String>,
+    /// names of fields in the current list
+    fields: Vec<String>,
+}
+
+impl Completer for ShellHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        // find the start of the word at the cursor
+        let mut start = 0;
+        let mut quote: Option<char> = None;
+        for (i, c) in line[..pos].char_indices() {
+            match quote {
+                Some(q) if c == q => quote = None,
+                Some(_) => {}
+                None if c == '"' || c == '\'' => quote = Some(c),
+                None if c.is_whitespace() => start = i + c.len_utf8(),
+                None => {}
+            }
+        }
+        let prefix = line[start..pos]
+            .trim_start_matches(['"', '\''])
+            .to_lowercase();
+        let names: Vec<&str> = if line[..start].trim().is_empty() {
+            COMMANDS.to_vec()
+        } else {
+            self.lists
+                .iter()
+                .chain(self.fields.iter())
+                .map(|s| s.as_str())
+                .collect()
+        };
+        let mut candidates: Vec<String> = names
+            .into_iter()
+            .filter(|name| name.to_lowercase().starts_with(&prefix))
+            .map(|name| {
+                if name.contains(char::is_whitespace) {
+                    format!("\"{}\"", name)
+                } else {
+                    name.to_string()
+                }
+            })
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ShellHelper {}
+
+impl Validator for ShellHelper {}
+
+impl Helper for ShellHelper {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn split_command_line() {
+        assert_eq!(
+            split_words(r#"items  'My List' -l "a b""#).unwrap(),
+            words(&["items", "My List", "-l", "a b"])
+        );
+        assert_eq!(
+            split_words(r#"set 1 Notes """#).unwrap(),
+            words(&["set", "1", "Notes", ""])
+        );
+        assert!(split_words("").unwrap().is_empty());
+        assert!(matches!(
+            split_words("items 'Tasks"),
+            Err(Error::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn expand_shorthand() {
+        assert_eq!(
+            expand(words(&["items", "Tasks"]), None),
+            words(&["items", "-l", "Tasks"])
+        );
+        assert_eq!(
+            expand(words(&["set", "1", "Notes", "two", "words"]), Some("Tasks")),
+            words(&[
+                "set",
+                "-l",
+                "Tasks",
+                "-i",
+                "1",
+                "-f",
+                "Notes",
+                "-v",
+                "two words"
+            ])
+        );
+        assert_eq!(
+            expand(words(&["item", "--list=Other", "-i", "1"]), Some("Tasks")),
+            words(&["item", "--list=Other", "-i", "1"])
+        );
+        assert_eq!(expand(words(&["lists"]), Some("Tasks")), words(&["lists"]));
+        assert!(matches!(
+            parse_command(&expand(words(&["item", "1"]), Some("Tasks"))),
+            Ok(Sub::Item(_))
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+//! Item counts of lists and workspaces
+use crate::{
+    backup::get_all_items, cache, items::count_by_field, values::ValueFormatter, Error, ListInfo,
+    OutputFormat, StatsOpt,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::result::Result;
+use zenkit::types::{ElementCategoryId, Entry, List, ID};
+
+#[derive(Debug, Serialize)]
+struct WorkspaceStats {
+    workspace: String,
+    /// period, in days, of the created and updated counts
+    days: u32,
+    items: usize,
+    created: usize,
+    updated: usize,
+    lists: Vec<ListStats>,
+}
+
+#[derive(Debug, Serialize)]
+struct ListStats {
+    list: String,
+    uuid: String,
+    items: usize,
+    /// items created in the period
+    created: usize,
+    /// items updated (but not created) in the period
+    updated: usize,
+    /// item counts per value of categories and persons fields
+    fields: Vec<FieldStats>,
+}
+
+#[derive(Debug, Serialize)]
+struct FieldStats {
+    field: String,
+    counts: Vec<ValueCount>,
+}
+
+#[derive(Debug, Serialize)]
+struct ValueCount {
+    value: String,
+    count: usize,
+}
+
+/// Print the number of items in the list (or each active list of the workspace,
+/// `ws_lists`), the number created and updated in the last `--days` days, and the number
+/// with each choice of categories fields, and each person of persons fields.
+/// Archived items are not counted.
+pub(crate) async fn print_stats(
+    ws_id: ID,
+    ws_name: &str,
+    ws_lists: &[List],
+    opt: &StatsOpt,
+) -> Result<(), Error> {
+    let lists: Vec<List> = match opt.list {
+        Some(ref name) => vec![cache::get_list_info(ws_id, name).await?.list().clone()],
+        None => ws_lists
+            .iter()
+            .filter(|l| l.deprecated_at.is_none())
+            .cloned()
+            .collect(),
+    };
+    let formatter = ValueFormatter::new(ws_id).await?;
+    let since = Utc::now() - Duration::days(opt.days as i64);
+    let mut stats = WorkspaceStats {
+        workspace: ws_name.to_string(),
+        days: opt.days,
+        items: 0,
+        created: 0,
+        updated: 0,
+        lists: Vec::new(),
+    };
+    for list in lists.iter() {
+        let list_info = cache::get_list_info(ws_id, &list.uuid).await?;
+        let items = get_all_items(&list.uuid, false).await?;
+        let list_stats = list_stats(&list_info, &items, &formatter, since);
+        stats.items += list_stats.items;
+        stats.created += list_stats.created;
+        stats.updated += list_stats.updated;
+        stats.lists.push(list_stats);
+    }
+
+    match opt.output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&stats)?),
+        OutputFormat::Text => print_text(&stats),
+    }
+    Ok(())
+}
+
+/// Counts of the list's items: created and updated since `since`, and per value of
+/// categories and persons fields
+fn list_stats(
+    list_info: &ListInfo,
+    items: &[Entry],
+    formatter: &ValueFormatter,
+    since: DateTime<Utc>,
+) -> ListStats {
+    let created = items.iter().filter(|i| *i.created_at >= since).count();
+    let updated = items
+        .iter()
+        .filter(|i| *i.created_at < since && *i.updated_at >= since)
+        .count();
+    let fields = list_info
+        .fields()
+        .iter()
+        .filter(|f| {
+            f.element_category == ElementCategoryId::Categories
+                || f.element_category == ElementCategoryId::Persons
+        })
+        .map(|field| FieldStats {
+            field: field.name.clone(),
+            counts: count_by_field(field, items, formatter)
+                .into_iter()
+                .map(|(value, count)| ValueCount { value, count })
+                .collect(),
+        })
+        .collect();
+    ListStats {
+        list: list_info.list().name.clone(),
+        uuid: list_info.list().uuid.clone(),
+        items: items.len(),
+        created,
+        updated,
+        fields,
+    }
+}
+
+fn print_text(stats: &WorkspaceStats) {
+    if stats.lists.len() > 1 {
+        println!(
+            "{}: {} items in {} lists, {} created and {} updated in the last {} days",
+            stats.workspace,
+            stats.items,
+            stats.lists.len(),
+            stats.created,
+            stats.updated,
+            stats.days
+        );
+    }
+    for list in stats.lists.iter() {
+        println!(
+            "{}: {} items, {} created and {} updated in the last {} days",
+            list.list, list.items, list.created, list.updated, stats.days
+        );
+        for field in list.fields.iter() {
+            println!("  {}", field.field);
+            for count in field.counts.iter() {
+                println!("    {}\t{}", count.value, count.count);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{choice_field, field, item, list_info};
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    #[test]
+    fn item_counts() {
+        let list_info = list_info(vec![
+            field(1, "Title", 1),
+            choice_field(2, "Stage", &[(1, "Todo"), (2, "Done")]),
+            field(3, "Owner", ElementCategoryId::Persons as u8),
+            field(4, "Points", 2),
+        ]);
+        let items = vec![
+            item(
+                1,
+                "old",
+                json!({"field-2_categories": [2], "field-3_persons": [7]}),
+            ),
+            item(
+                2,
+                "updated",
+                json!({"field-2_categories": [1], "updated_at": "2021-03-02T00:00:00Z"}),
+            ),
+            item(
+                3,
+                "new",
+                json!({
+                    "field-2_categories": [2],
+                    "field-3_persons": [7],
+                    "created_at": "2021-03-02T00:00:00Z",
+                    "updated_at": "2021-03-02T00:00:00Z",
+                }),
+            ),
+        ];
+        // without the workspace's users, persons are shown by id
+        let formatter = ValueFormatter::default();
+        let since = Utc.ymd(2021, 3, 1).and_hms(0, 0, 0);
+        let stats = list_stats(&list_info, &items, &formatter, since);
+        assert_eq!(
+            (stats.list.as_str(), stats.uuid.as_str()),
+            ("Tasks", "list-1")
+        );
+        assert_eq!((stats.items, stats.created, stats.updated), (3, 1, 1));
+        // counts of categories and persons fields only
+        let fields: Vec<(&str, Vec<(&str, usize)>)> = stats
+            .fields
+            .iter()
+            .map(|f| {
+                let counts = f.counts.iter().map(|c| (c.value.as_str(), c.count));
+                (f.field.as_str(), counts.collect())
+            })
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("Stage", vec![("Todo", 1), ("Done", 2)]),
+                ("Owner", vec![("7", 2), ("(none)", 1)]),
+            ]
+        );
+    }
+}
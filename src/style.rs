@@ -0,0 +1,321 @@
+//! Terminal output: aligned tables, and colors (`--color auto|always|never`).
+//! When stdout isn't a terminal, tables are printed as tab-separated rows without
+//! headings, as in earlier versions, so scripts that read the output keep working.
+use crate::ColorChoice;
+use std::{
+    io::IsTerminal,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+static ALIGNED: AtomicBool = AtomicBool::new(false);
+
+/// Enable colors if `--color always`, or with `--color auto`, if stdout is a terminal
+/// and NO_COLOR isn't set (https://no-color.org). Tables are aligned if stdout is
+/// a terminal.
+pub(crate) fn configure(choice: ColorChoice) {
+    let terminal = std::io::stdout().is_terminal();
+    let no_color = std::env::var_os("NO_COLOR")
+        .map(|v| !v.is_empty())
+        .unwrap_or(false);
+    let dumb = std::env::var("TERM").map(|t| t == "dumb").unwrap_or(false);
+    COLOR.store(
+        use_color(choice, terminal, no_color, dumb),
+        Ordering::Relaxed,
+    );
+    ALIGNED.store(terminal, Ordering::Relaxed);
+}
+
+/// Whether to use colors, for the choice, if stdout is a terminal, NO_COLOR is set,
+/// or the terminal is "dumb"
+fn use_color(choice: ColorChoice, terminal: bool, no_color: bool, dumb: bool) -> bool {
+    match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => terminal && !no_color && !dumb,
+    }
+}
+
+/// Returns true if output is colored
+pub(crate) fn color_enabled() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+/// Returns true if tables are printed in aligned columns, with headings
+pub(crate) fn aligned() -> bool {
+    ALIGNED.load(Ordering::Relaxed)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Style {
+    Bold,
+    /// Less important values, such as uuids
+    Dim,
+    /// Overdue dates
+    Red,
+    /// A choice's color
+    Rgb(u8, u8, u8),
+}
+
+impl Style {
+    /// Style of a hex color (#rrggbb), such as a choice's color
+    pub(crate) fn from_hex(hex: &str) -> Option<Style> {
+        let hex = hex.trim_start_matches('#');
+        if hex.len() != 6 {
+            return None;
+        }
+        let byte = |i: usize| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok();
+        Some(Style::Rgb(byte(0)?, byte(2)?, byte(4)?))
+    }
+
+    fn code(&self) -> String {
+        match self {
+            Style::Bold => "1".to_string(),
+            Style::Dim => "2".to_string(),
+            Style::Red => "31".to_string(),
+            Style::Rgb(r, g, b) => format!("38;2;{};{};{}", r, g, b),
+        }
+    }
+}
+
+/// The text in the style, if colors are enabled
+pub(crate) fn paint(text: &str, style: Style) -> String {
+    if color_enabled() && !text.is_empty() {
+        format!("\x1b[{}m{}\x1b[0m", style.code(), text)
+    } else {
+        text.to_string()
+    }
+}
+
+/// A table cell: text in one or more parts, each with an optional style
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Cell {
+    parts: Vec<(String, Option<Style>)>,
+}
+
+impl Cell {
+    pub(crate) fn styled<S: Into<String>>(text: S, style: Option<Style>) -> Self {
+        Cell {
+            parts: vec![(text.into(), style)],
+        }
+    }
+
+    /// Add text to the end of the cell
+    pub(crate) fn push<S: Into<String>>(&mut self, text: S, style: Option<Style>) {
+        self.parts.push((text.into(), style));
+    }
+
+    /// Width of the text, in characters
+    fn width(&self) -> usize {
+        self.parts
+            .iter()
+            .map(|(text, _)| text.chars().count())
+            .sum()
+    }
+
+    /// The text, with the styles if colors are enabled
+    fn render(&self) -> String {
+        self.parts
+            .iter()
+            .map(|(text, style)| match style {
+                Some(style) => paint(text, *style),
+                None => text.clone(),
+            })
+            .collect()
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Cell::styled(text, None)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Cell::styled(text, None)
+    }
+}
+
+enum Row {
+    Cells(Vec<Cell>),
+    /// A group heading, printed on its own line. Rows after a heading are indented.
+    Heading(Cell),
+}
+
+/// Rows of cells, printed in aligned columns with headings if stdout is a terminal,
+/// and otherwise as tab-separated values
+pub(crate) struct Table {
+    headings: Vec<String>,
+    rows: Vec<Row>,
+}
+
+impl Table {
+    pub(crate) fn new(headings: &[&str]) -> Self {
+        Table {
+            headings: headings.iter().map(|h| h.to_string()).collect(),
+            rows: Vec::new(),
+        }
+    }
+
+    pub(crate) fn push(&mut self, row: Vec<Cell>) {
+        self.rows.push(Row::Cells(row));
+    }
+
+    /// Add a group heading
+    pub(crate) fn push_heading(&mut self, heading: Cell) {
+        self.rows.push(Row::Heading(heading));
+    }
+
+    pub(crate) fn print(&self) {
+        let lines = if aligned() {
+            self.aligned_lines()
+        } else {
+            self.tab_lines()
+        };
+        for line in lines.iter() {
+            println!("{}", line);
+        }
+    }
+
+    /// Print the rows in aligned columns, with headings
+    pub(crate) fn print_aligned(&self) {
+        for line in self.aligned_lines().iter() {
+            println!("{}", line);
+        }
+    }
+
+    /// The rows as tab-separated values, without headings
+    fn tab_lines(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut indent = "";
+        for row in self.rows.iter() {
+            match row {
+                Row::Heading(heading) => {
+                    lines.push(heading.render());
+                    indent = "  ";
+                }
+                Row::Cells(cells) => {
+                    let cells: Vec<String> = cells.iter().map(|c| c.render()).collect();
+                    lines.push(format!("{}{}", indent, cells.join("\t")));
+                }
+            }
+        }
+        lines
+    }
+
+    /// The rows in aligned columns, with headings
+    fn aligned_lines(&self) -> Vec<String> {
+        let mut widths: Vec<usize> = self.headings.iter().map(|h| h.chars().count()).collect();
+        for row in self.rows.iter() {
+            if let Row::Cells(cells) = row {
+                for (i, cell) in cells.iter().enumerate() {
+                    match widths.get_mut(i) {
+                        Some(width) => *width = (*width).max(cell.width()),
+                        None => widths.push(cell.width()),
+                    }
+                }
+            }
+        }
+        let grouped = self.rows.iter().any(|r| matches!(r, Row::Heading(_)));
+        let indent = if grouped { "  " } else { "" };
+        let line = |cells: &[Cell]| {
+            let padded: Vec<String> = cells
+                .iter()
+                .zip(widths.iter())
+                .map(|(cell, width)| {
+                    let pad = width.saturating_sub(cell.width());
+                    format!("{}{}", cell.render(), " ".repeat(pad))
+                })
+                .collect();
+            format!("{}{}", indent, padded.join("  ").trim_end())
+        };
+        let mut lines = Vec::new();
+        if !self.headings.is_empty() {
+            let headings: Vec<Cell> = self
+                .headings
+                .iter()
+                .map(|h| Cell::styled(h.as_str(), Some(Style::Bold)))
+                .collect();
+            lines.push(line(&headings));
+            let rule: Vec<Cell> = widths.iter().map(|w| Cell::from("-".repeat(*w))).collect();
+            lines.push(line(&rule));
+        }
+        for row in self.rows.iter() {
+            match row {
+                Row::Heading(heading) => lines.push(heading.render()),
+                Row::Cells(cells) => lines.push(line(cells)),
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_choice() {
+        assert!(use_color(ColorChoice::Always, false, true, true));
+        assert!(!use_color(ColorChoice::Never, true, false, false));
+        assert!(use_color(ColorChoice::Auto, true, false, false));
+        assert!(!use_color(ColorChoice::Auto, false, false, false));
+        assert!(!use_color(ColorChoice::Auto, true, true, false));
+        assert!(!use_color(ColorChoice::Auto, true, false, true));
+    }
+
+    #[test]
+    fn hex_colors() {
+        assert_eq!(Style::from_hex("#ff8000"), Some(Style::Rgb(255, 128, 0)));
+        assert_eq!(Style::from_hex("00FF00"), Some(Style::Rgb(0, 255, 0)));
+        assert_eq!(Style::from_hex("#fff"), None);
+        assert_eq!(Style::from_hex("#gg0000"), None);
+        assert_eq!(Style::Rgb(1, 2, 3).code(), "38;2;1;2;3");
+    }
+
+    fn table() -> Table {
+        let mut table = Table::new(&["id", "Name"]);
+        table.push(vec![Cell::from("1"), Cell::from("Write tests")]);
+        let mut name = Cell::styled("Plan", None);
+        name.push(" (late)", Some(Style::Red));
+        table.push(vec![Cell::from("12"), name]);
+        table
+    }
+
+    #[test]
+    fn table_lines() {
+        // colors aren't enabled in tests
+        assert_eq!(
+            table().tab_lines(),
+            vec!["1\tWrite tests", "12\tPlan (late)"]
+        );
+        assert_eq!(
+            table().aligned_lines(),
+            vec![
+                "id  Name",
+                "--  -----------",
+                "1   Write tests",
+                "12  Plan (late)",
+            ]
+        );
+    }
+
+    #[test]
+    fn grouped_table_lines() {
+        let mut table = Table::new(&[]);
+        table.push_heading(Cell::from("Done"));
+        table.push(vec![Cell::from("1"), Cell::from("Write tests")]);
+        table.push_heading(Cell::from("Open"));
+        table.push(vec![Cell::from("12"), Cell::from("Plan")]);
+        assert_eq!(
+            table.tab_lines(),
+            vec!["Done", "  1\tWrite tests", "Open", "  12\tPlan"]
+        );
+        assert_eq!(
+            table.aligned_lines(),
+            vec!["Done", "  1   Write tests", "Open", "  12  Plan"]
+        );
+    }
+}
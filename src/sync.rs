@@ -0,0 +1,875 @@
+//! Two-way sync of a list with a local file (json, toml, or Markdown)
+use crate::{
+    backup::get_all_items,
+    bulk::deprecate_item,
+    cache, clear_value, dryrun,
+    rest::RestClient,
+    values::{resolve_values, ValueFormatter},
+    Error, ListInfo, SyncOpt, SyncPrefer,
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::Path,
+    result::Result,
+};
+use zenkit::types::{Element, ElementCategoryId, Entry, FieldVal, UpdateAction, ZKObjectID, ID};
+
+/// Separator of multiple values (choices, persons, references) in a field value
+const SEPARATOR: &str = ", ";
+
+/// Suffix of the file with the state of the last sync
+const STATE_SUFFIX: &str = ".sync.json";
+
+/// An item, as written to the file and the sync state
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct Task {
+    /// None for tasks added to the file, until they are created in the list
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    uuid: Option<String>,
+    title: String,
+    /// field name -> value. Empty fields are omitted.
+    #[serde(default)]
+    fields: BTreeMap<String, String>,
+}
+
+/// The list's items at the last sync
+#[derive(Debug, Serialize, Deserialize)]
+struct SyncState {
+    /// list uuid
+    list: String,
+    synced_at: String,
+    tasks: Vec<Task>,
+}
+
+/// Toml file layout: an array of `[[task]]` tables
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct TomlFile {
+    #[serde(default)]
+    task: Vec<Task>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Json,
+    Toml,
+    Markdown,
+}
+
+/// How a value changed since the last sync
+#[derive(Debug, PartialEq)]
+enum Change {
+    Same,
+    Local,
+    Remote,
+    Conflict,
+}
+
+/// A change to the list, applied after all changes are planned and their values
+/// validated
+enum Planned {
+    /// Create an item for the task at the index in the file (`local`), and in the
+    /// tasks to write (`file`) and to save in the state (`state`)
+    Create {
+        local: usize,
+        file: usize,
+        state: usize,
+        values: Vec<(String, FieldVal, UpdateAction)>,
+    },
+    Update {
+        item_id: ID,
+        values: Vec<(String, FieldVal, UpdateAction)>,
+    },
+    Archive {
+        item_id: ID,
+    },
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct SyncSummary {
+    /// items updated in the list
+    pub pushed: usize,
+    /// items created in the list
+    pub created: usize,
+    /// items archived in the list, after they were removed from the file
+    pub archived: usize,
+    /// tasks updated in the file
+    pub pulled: usize,
+    /// tasks added to the file
+    pub added: usize,
+    /// tasks removed from the file, after their items were archived or deleted
+    pub removed: usize,
+    /// changes that weren't synced
+    pub conflicts: Vec<String>,
+}
+
+/// Reconcile the file with the list's active items. Changes since the last sync (saved
+/// in `<file>.sync.json`) are compared field by field: values changed in the file are
+/// pushed to the list, values changed in the list are pulled into the file, and values
+/// changed differently in both are conflicts, resolved with `--prefer`, or else
+/// reported and left unchanged until the next sync. Tasks added to the file (without a
+/// uuid) are created in the list, and tasks removed from the file are archived. On the
+/// first sync, the file is created with the list's items; if the file is missing after
+/// it was synced, nothing is synced. Formula and files fields are not synced.
+/// The list is only changed after all changes are planned and their values resolved.
+/// If a change fails, the uuids of the items already created are saved in the file and
+/// the state, so they aren't created again by the next sync. With `--dry-run`, the
+/// changes to the list are printed, and the files aren't written.
+pub(crate) async fn sync(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &SyncOpt,
+) -> Result<SyncSummary, Error> {
+    let format = file_format(&opt.file)?;
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let list_uuid = list_info.list().uuid.clone();
+    let fields: Vec<&Element> = list_info
+        .fields()
+        .iter()
+        .filter(|f| {
+            !matches!(
+                f.element_category,
+                ElementCategoryId::Formula | ElementCategoryId::Files
+            )
+        })
+        .collect();
+    let title_field = fields
+        .iter()
+        .copied()
+        .find(|f| f.element_category == ElementCategoryId::Text);
+
+    let formatter = ValueFormatter::new(ws_id).await?;
+    let items = get_all_items(&list_uuid, false).await?;
+    let remote: HashMap<&str, (Task, &Entry)> = items
+        .iter()
+        .map(|item| {
+            let task = item_task(item, &fields, title_field, &formatter);
+            (item.uuid.as_str(), (task, item))
+        })
+        .collect();
+    let state_path = format!("{}{}", opt.file, STATE_SUFFIX);
+    let state = read_state(&state_path)?;
+    if let Some(ref state) = state {
+        if state.list != list_uuid {
+            return Err(Error::Message(format!(
+                "{} was synced with another list. Remove {} to sync it with this list",
+                opt.file, state_path
+            )));
+        }
+    }
+    let local = if Path::new(&opt.file).exists() {
+        read_tasks(&opt.file, format)?
+    } else if state.is_some() {
+        // otherwise every item would be archived as removed from the file
+        return Err(Error::Message(format!(
+            "{} is missing, but was synced before. Restore it, or remove {} to create it \
+             from the list",
+            opt.file, state_path
+        )));
+    } else {
+        Vec::new()
+    };
+    let base: HashMap<&str, &Task> = state
+        .iter()
+        .flat_map(|s| s.tasks.iter())
+        .filter_map(|t| Some((t.uuid.as_deref()?, t)))
+        .collect();
+
+    let mut summary = SyncSummary::default();
+    // tasks for the file, and for the sync state
+    let mut file_tasks = Vec::new();
+    let mut synced = Vec::new();
+    let mut seen = HashSet::new();
+    let mut planned = Vec::new();
+    for (index, task) in local.iter().enumerate() {
+        let uuid = match task.uuid {
+            Some(ref uuid) => uuid.as_str(),
+            None => {
+                planned.push(Planned::Create {
+                    local: index,
+                    file: file_tasks.len(),
+                    state: synced.len(),
+                    values: create_values(&list_info, rest, ws_id, title_field, task).await?,
+                });
+                file_tasks.push(task.clone());
+                synced.push(task.clone());
+                continue;
+            }
+        };
+        seen.insert(uuid);
+        let base_task = base.get(uuid).copied();
+        let (remote_task, item) = match remote.get(uuid) {
+            Some(remote) => remote,
+            None => {
+                // archived or deleted in the list
+                let changed = base_task.map(|b| b != task).unwrap_or(true);
+                match (changed, opt.prefer) {
+                    (false, _) | (true, Some(SyncPrefer::Remote)) => summary.removed += 1,
+                    (true, Some(SyncPrefer::Local)) => {
+                        let mut new_task = task.clone();
+                        new_task.uuid = None;
+                        let values =
+                            create_values(&list_info, rest, ws_id, title_field, &new_task).await?;
+                        planned.push(Planned::Create {
+                            local: index,
+                            file: file_tasks.len(),
+                            state: synced.len(),
+                            values,
+                        });
+                        file_tasks.push(new_task.clone());
+                        synced.push(new_task);
+                    }
+                    (true, None) => {
+                        summary.conflicts.push(format!(
+                            "'{}': changed in the file, but archived or deleted in the list",
+                            task.title
+                        ));
+                        file_tasks.push(task.clone());
+                        synced.extend(base_task.cloned());
+                    }
+                }
+                continue;
+            }
+        };
+
+        let mut file_task = task.clone();
+        let mut state_task = remote_task.clone();
+        let mut pushes: Vec<(&Element, Option<&str>)> = Vec::new();
+        let mut pulled = false;
+        let mut resolve = |name: &str,
+                           local: Option<&String>,
+                           remote: Option<&String>,
+                           base: Option<&String>|
+         -> (Option<String>, Option<String>, bool) {
+            // (file value, state value, push)
+            let base = base_task.map(|_| base);
+            match value_change(local, remote, base, opt.prefer) {
+                Change::Same => (local.cloned(), local.cloned(), false),
+                Change::Local => (local.cloned(), local.cloned(), true),
+                Change::Remote => {
+                    pulled = true;
+                    (remote.cloned(), remote.cloned(), false)
+                }
+                Change::Conflict => {
+                    summary.conflicts.push(format!(
+                        "'{}' {}: '{}' in the file, '{}' in the list",
+                        task.title,
+                        name,
+                        local.map(|s| s.as_str()).unwrap_or_default(),
+                        remote.map(|s| s.as_str()).unwrap_or_default()
+                    ));
+                    (local.cloned(), base.flatten().cloned(), false)
+                }
+            }
+        };
+
+        if let Some(title_field) = title_field {
+            let (file_value, state_value, push) = resolve(
+                "title",
+                Some(&task.title),
+                Some(&remote_task.title),
+                base_task.map(|b| &b.title),
+            );
+            file_task.title = file_value.unwrap_or_default();
+            state_task.title = state_value.unwrap_or_default();
+            if push {
+                pushes.push((title_field, Some(task.title.as_str())));
+            }
+        }
+        for field in fields.iter().copied().filter(|f| !is_title(f, title_field)) {
+            let name = &field.name;
+            let local_value = task.fields.get(name);
+            let (file_value, state_value, push) = resolve(
+                name,
+                local_value,
+                remote_task.fields.get(name),
+                base_task.and_then(|b| b.fields.get(name)),
+            );
+            set_value(&mut file_task.fields, name, file_value);
+            set_value(&mut state_task.fields, name, state_value);
+            if push {
+                pushes.push((field, local_value.map(|s| s.as_str())));
+            }
+        }
+        if pulled {
+            summary.pulled += 1;
+        }
+        if !pushes.is_empty() {
+            let mut values = field_values(&pushes, false);
+            resolve_values(rest, ws_id, &list_info, &mut values).await?;
+            planned.push(Planned::Update {
+                item_id: item.get_id(),
+                values,
+            });
+        }
+        file_tasks.push(file_task);
+        synced.push(state_task);
+    }
+
+    // items that aren't in the file: new in the list, or removed from the file
+    for item in items.iter().filter(|i| !seen.contains(i.uuid.as_str())) {
+        let (remote_task, _) = &remote[item.uuid.as_str()];
+        let base_task = match base.get(item.uuid.as_str()) {
+            Some(base_task) => base_task,
+            None => {
+                summary.added += 1;
+                file_tasks.push(remote_task.clone());
+                synced.push(remote_task.clone());
+                continue;
+            }
+        };
+        let changed = *base_task != remote_task;
+        match (changed, opt.prefer) {
+            (false, _) | (true, Some(SyncPrefer::Local)) => {
+                planned.push(Planned::Archive {
+                    item_id: item.get_id(),
+                });
+            }
+            (true, Some(SyncPrefer::Remote)) => {
+                summary.added += 1;
+                file_tasks.push(remote_task.clone());
+                synced.push(remote_task.clone());
+            }
+            (true, None) => {
+                summary.conflicts.push(format!(
+                    "'{}': removed from the file, but changed in the list",
+                    remote_task.title
+                ));
+                synced.push((*base_task).clone());
+            }
+        }
+    }
+
+    // the list is only changed after all changes are planned
+    let mut created: Vec<(usize, Task)> = Vec::new();
+    for change in planned.into_iter() {
+        let result = match change {
+            Planned::Create {
+                local: index,
+                file,
+                state,
+                values,
+            } => create(&list_info, &values).await.map(|uuid| {
+                file_tasks[file].uuid = uuid.clone();
+                synced[state].uuid = uuid;
+                created.push((index, file_tasks[file].clone()));
+                summary.created += 1;
+            }),
+            Planned::Update { item_id, values } => {
+                update(&list_info, item_id, &values).await.map(|_| {
+                    summary.pushed += 1;
+                })
+            }
+            Planned::Archive { item_id } => deprecate_item(rest, list_info.get_id(), item_id)
+                .await
+                .map(|_| {
+                    summary.archived += 1;
+                }),
+        };
+        if let Err(e) = result {
+            if !dryrun::enabled() && !created.is_empty() {
+                // save the uuids of the created items, so they aren't created again
+                let mut tasks = local;
+                for (index, task) in created.iter() {
+                    tasks[*index].uuid = task.uuid.clone();
+                }
+                write_tasks(&opt.file, format, &list_info.list().name, &tasks)?;
+                let mut state_tasks = state.map(|s| s.tasks).unwrap_or_default();
+                state_tasks.extend(created.into_iter().map(|(_, task)| task));
+                write_state(&state_path, &list_uuid, state_tasks)?;
+            }
+            return Err(e);
+        }
+    }
+
+    if !dryrun::enabled() {
+        write_tasks(&opt.file, format, &list_info.list().name, &file_tasks)?;
+        write_state(&state_path, &list_uuid, synced)?;
+    }
+    Ok(summary)
+}
+
+fn write_state(path: &str, list_uuid: &str, tasks: Vec<Task>) -> Result<(), Error> {
+    let state = SyncState {
+        list: list_uuid.to_string(),
+        synced_at: Utc::now().to_rfc3339(),
+        tasks,
+    };
+    std::fs::write(path, serde_json::to_string_pretty(&state)?)
+        .map_err(|e| Error::Message(format!("Writing {}: {}", path, e)))
+}
+
+/// Values to create an item for the task
+async fn create_values(
+    list_info: &ListInfo,
+    rest: &RestClient,
+    ws_id: ID,
+    title_field: Option<&Element>,
+    task: &Task,
+) -> Result<Vec<(String, FieldVal, UpdateAction)>, Error> {
+    let mut values: Vec<(&Element, Option<&str>)> = Vec::new();
+    if let Some(title_field) = title_field {
+        values.push((title_field, Some(task.title.as_str())));
+    }
+    for (name, value) in task.fields.iter() {
+        if let Some(field) = list_info
+            .fields()
+            .iter()
+            .find(|f| f.name == *name && !is_title(f, title_field))
+        {
+            values.push((field, Some(value.as_str())));
+        }
+    }
+    let mut values = field_values(&values, true);
+    resolve_values(rest, ws_id, list_info, &mut values).await?;
+    Ok(values)
+}
+
+/// Create an item, returning its uuid (None in dry-run mode)
+async fn create(
+    list_info: &ListInfo,
+    values: &[(String, FieldVal, UpdateAction)],
+) -> Result<Option<String>, Error> {
+    if dryrun::enabled() {
+        dryrun::print_item_request(list_info, None, values).await?;
+        Ok(None)
+    } else {
+        Ok(Some(list_info.create_item(values).await?.uuid))
+    }
+}
+
+async fn update(
+    list_info: &ListInfo,
+    item_id: ID,
+    values: &[(String, FieldVal, UpdateAction)],
+) -> Result<(), Error> {
+    if dryrun::enabled() {
+        dryrun::print_item_request(list_info, Some(item_id), values).await
+    } else {
+        list_info.update_item(item_id, values).await.map(|_| ())
+    }
+}
+
+/// Update values for the fields: multiple values are split, and empty values clear
+/// the field
+fn field_values(
+    values: &[(&Element, Option<&str>)],
+    create: bool,
+) -> Vec<(String, FieldVal, UpdateAction)> {
+    values
+        .iter()
+        .map(|(field, value)| match value.filter(|v| !v.is_empty()) {
+            None => clear_value(field.name.clone(), field),
+            Some(value) => match field.element_category {
+                ElementCategoryId::Categories
+                | ElementCategoryId::Persons
+                | ElementCategoryId::References => (
+                    field.name.clone(),
+                    FieldVal::ArrStr(value.split(SEPARATOR).map(String::from).collect()),
+                    if create {
+                        UpdateAction::Null
+                    } else {
+                        UpdateAction::Replace
+                    },
+                ),
+                _ => (
+                    field.name.clone(),
+                    FieldVal::Str(value.to_string()),
+                    UpdateAction::Null,
+                ),
+            },
+        })
+        .collect()
+}
+
+/// How a value changed since the last sync, from its value in the file (`local`),
+/// in the list (`remote`), and in the sync state (`base`, which is None if the task
+/// isn't in the state). Conflicts are resolved with `prefer`, if set.
+fn value_change(
+    local: Option<&String>,
+    remote: Option<&String>,
+    base: Option<Option<&String>>,
+    prefer: Option<SyncPrefer>,
+) -> Change {
+    let change = match base {
+        _ if local == remote => Change::Same,
+        Some(base) if base == local => Change::Remote,
+        Some(base) if base == remote => Change::Local,
+        None if local.is_none() => Change::Remote,
+        None if remote.is_none() => Change::Local,
+        _ => Change::Conflict,
+    };
+    match (change, prefer) {
+        (Change::Conflict, Some(SyncPrefer::Local)) => Change::Local,
+        (Change::Conflict, Some(SyncPrefer::Remote)) => Change::Remote,
+        (change, _) => change,
+    }
+}
+
+fn is_title(field: &Element, title_field: Option<&Element>) -> bool {
+    title_field.map(|t| t.uuid == field.uuid).unwrap_or(false)
+}
+
+fn set_value(fields: &mut BTreeMap<String, String>, name: &str, value: Option<String>) {
+    match value.filter(|v| !v.is_empty()) {
+        Some(value) => {
+            fields.insert(name.to_string(), value);
+        }
+        None => {
+            fields.remove(name);
+        }
+    }
+}
+
+/// The item's title and field values (display strings)
+fn item_task(
+    item: &Entry,
+    fields: &[&Element],
+    title_field: Option<&Element>,
+    formatter: &ValueFormatter,
+) -> Task {
+    let title = title_field
+        .and_then(|f| formatter.display(item, f))
+        .unwrap_or_else(|| item.display_string.clone());
+    let values = fields
+        .iter()
+        .filter(|f| !is_title(f, title_field))
+        .filter_map(|f| Some((f.name.clone(), formatter.display(item, f)?)))
+        .filter(|(_, v)| !v.is_empty())
+        .collect();
+    Task {
+        uuid: Some(item.uuid.clone()),
+        title,
+        fields: values,
+    }
+}
+
+fn file_format(path: &str) -> Result<Format, Error> {
+    let ext = Path::new(path)
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    match ext.as_str() {
+        "json" => Ok(Format::Json),
+        "toml" => Ok(Format::Toml),
+        "md" | "markdown" => Ok(Format::Markdown),
+        _ => Err(Error::Invalid(format!(
+            "Unsupported file type {}: use a .json, .toml, or .md file",
+            path
+        ))),
+    }
+}
+
+fn read_state(path: &str) -> Result<Option<SyncState>, Error> {
+    if !Path::new(path).exists() {
+        return Ok(None);
+    }
+    let state = serde_json::from_slice(&std::fs::read(path)?)
+        .map_err(|e| Error::Invalid(format!("Invalid sync state {}: {}", path, e)))?;
+    Ok(Some(state))
+}
+
+fn read_tasks(path: &str, format: Format) -> Result<Vec<Task>, Error> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| Error::Message(format!("Reading {}: {}", path, e)))?;
+    let invalid = |e: &dyn std::fmt::Display| Error::Invalid(format!("Invalid {}: {}", path, e));
+    let mut tasks = match format {
+        Format::Json => serde_json::from_str(&text).map_err(|e| invalid(&e))?,
+        Format::Toml => {
+            let file: TomlFile = toml::from_str(&text).map_err(|e| invalid(&e))?;
+            file.task
+        }
+        Format::Markdown => parse_markdown(&text),
+    };
+    for task in tasks.iter_mut() {
+        task.uuid = task.uuid.take().filter(|u| !u.trim().is_empty());
+        task.fields.retain(|_, v| !v.is_empty());
+    }
+    Ok(tasks)
+}
+
+fn write_tasks(path: &str, format: Format, list_name: &str, tasks: &[Task]) -> Result<(), Error> {
+    let text = match format {
+        Format::Json => serde_json::to_string_pretty(tasks)?,
+        Format::Toml => toml::to_string(&TomlFile {
+            task: tasks.to_vec(),
+        })
+        .map_err(|e| Error::Message(format!("Writing {}: {}", path, e)))?,
+        Format::Markdown => markdown(list_name, tasks),
+    };
+    std::fs::write(path, text).map_err(|e| Error::Message(format!("Writing {}: {}", path, e)))
+}
+
+/// Markdown with a `## title` heading for each task, followed by a uuid comment and
+/// a `- Field: value` line for each field. Lines after the first line of a value
+/// are indented.
+fn markdown(list_name: &str, tasks: &[Task]) -> String {
+    let mut md = format!("# {}\n", list_name);
+    for task in tasks.iter() {
+        md.push_str(&format!("\n## {}\n", task.title));
+        if let Some(ref uuid) = task.uuid {
+            md.push_str(&format!("<!-- uuid: {} -->\n", uuid));
+        }
+        for (name, value) in task.fields.iter() {
+            md.push_str(&format!("- {}: {}\n", name, value.replace('\n', "\n  ")));
+        }
+    }
+    md
+}
+
+/// Tasks from Markdown written by `markdown`. Other lines are ignored.
+fn parse_markdown(text: &str) -> Vec<Task> {
+    let mut tasks: Vec<Task> = Vec::new();
+    let mut last_field: Option<String> = None;
+    for line in text.lines() {
+        if let Some(title) = line.strip_prefix("## ") {
+            tasks.push(Task {
+                title: title.trim().to_string(),
+                ..Task::default()
+            });
+            last_field = None;
+            continue;
+        }
+        let task = match tasks.last_mut() {
+            Some(task) => task,
+            None => continue,
+        };
+        if let Some(uuid) = line
+            .trim()
+            .strip_prefix("<!-- uuid:")
+            .and_then(|s| s.strip_suffix("-->"))
+        {
+            task.uuid = Some(uuid.trim().to_string());
+        } else if let Some(field) = line.strip_prefix("- ") {
+            last_field = field.find(':').map(|end| {
+                let name = field[..end].trim().to_string();
+                let value = field[end + 1..].trim().to_string();
+                task.fields.insert(name.clone(), value);
+                name
+            });
+        } else if let (Some(continued), Some(name)) = (line.strip_prefix("  "), &last_field) {
+            if let Some(value) = task.fields.get_mut(name) {
+                value.push('\n');
+                value.push_str(continued);
+            }
+        } else {
+            last_field = None;
+        }
+    }
+    tasks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{choice_field, field, item};
+    use serde_json::json;
+
+    fn change(
+        local: Option<&str>,
+        remote: Option<&str>,
+        base: Option<Option<&str>>,
+        prefer: Option<SyncPrefer>,
+    ) -> Change {
+        let (local, remote) = (local.map(String::from), remote.map(String::from));
+        let base = base.map(|b| b.map(String::from));
+        value_change(
+            local.as_ref(),
+            remote.as_ref(),
+            base.as_ref().map(|b| b.as_ref()),
+            prefer,
+        )
+    }
+
+    #[test]
+    fn reconcile_values() {
+        // unchanged, or changed the same way in both
+        assert_eq!(
+            change(Some("a"), Some("a"), Some(Some("a")), None),
+            Change::Same
+        );
+        assert_eq!(
+            change(Some("b"), Some("b"), Some(Some("a")), None),
+            Change::Same
+        );
+        // changed in one place
+        assert_eq!(
+            change(Some("b"), Some("a"), Some(Some("a")), None),
+            Change::Local
+        );
+        assert_eq!(
+            change(Some("a"), Some("b"), Some(Some("a")), None),
+            Change::Remote
+        );
+        // cleared in one place
+        assert_eq!(
+            change(None, Some("a"), Some(Some("a")), None),
+            Change::Local
+        );
+        assert_eq!(
+            change(Some("a"), None, Some(Some("a")), None),
+            Change::Remote
+        );
+        // set in one place, where it was empty
+        assert_eq!(change(Some("a"), None, Some(None), None), Change::Local);
+        assert_eq!(change(None, Some("a"), Some(None), None), Change::Remote);
+        // changed differently in both
+        assert_eq!(
+            change(Some("b"), Some("c"), Some(Some("a")), None),
+            Change::Conflict
+        );
+        assert_eq!(
+            change(
+                Some("b"),
+                Some("c"),
+                Some(Some("a")),
+                Some(SyncPrefer::Local)
+            ),
+            Change::Local
+        );
+        assert_eq!(
+            change(
+                Some("b"),
+                Some("c"),
+                Some(Some("a")),
+                Some(SyncPrefer::Remote)
+            ),
+            Change::Remote
+        );
+    }
+
+    #[test]
+    fn reconcile_without_state() {
+        // a value on only one side is kept
+        assert_eq!(change(None, Some("a"), None, None), Change::Remote);
+        assert_eq!(change(Some("a"), None, None, None), Change::Local);
+        // different values on both sides can't be resolved without the state
+        assert_eq!(change(Some("a"), Some("b"), None, None), Change::Conflict);
+        assert_eq!(
+            change(Some("a"), Some("b"), None, Some(SyncPrefer::Remote)),
+            Change::Remote
+        );
+    }
+
+    #[test]
+    fn markdown_round_trip() {
+        let mut fields = BTreeMap::new();
+        fields.insert("Status".to_string(), "Done".to_string());
+        fields.insert("Notes".to_string(), "first line\nsecond line".to_string());
+        let tasks = vec![
+            Task {
+                uuid: Some("8c0e7e4a-0000-4000-8000-000000000001".to_string()),
+                title: "Write tests".to_string(),
+                fields,
+            },
+            Task {
+                uuid: None,
+                title: "New task".to_string(),
+                fields: BTreeMap::new(),
+            },
+        ];
+        let md = markdown("Tasks", &tasks);
+        assert!(md.starts_with("# Tasks\n"));
+        assert_eq!(parse_markdown(&md), tasks);
+    }
+
+    #[test]
+    fn file_formats() {
+        assert_eq!(file_format("tasks.json").unwrap(), Format::Json);
+        assert_eq!(file_format("dir/tasks.TOML").unwrap(), Format::Toml);
+        assert_eq!(file_format("tasks.md").unwrap(), Format::Markdown);
+        assert_eq!(file_format("tasks.markdown").unwrap(), Format::Markdown);
+        assert!(matches!(file_format("tasks.csv"), Err(Error::Invalid(_))));
+        assert!(matches!(file_format("tasks"), Err(Error::Invalid(_))));
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let mut fields = BTreeMap::new();
+        fields.insert("Status".to_string(), "Done".to_string());
+        let tasks = vec![Task {
+            uuid: Some("item-1".to_string()),
+            title: "Write tests".to_string(),
+            fields,
+        }];
+        for (format, ext) in &[(Format::Json, "json"), (Format::Toml, "toml")] {
+            let path = std::env::temp_dir().join(format!("zk_sync_{}.{}", std::process::id(), ext));
+            let path = path.to_string_lossy().to_string();
+            write_tasks(&path, *format, "Tasks", &tasks).unwrap();
+            assert_eq!(read_tasks(&path, *format).unwrap(), tasks);
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+
+    #[test]
+    fn blank_values_dropped() {
+        let path = std::env::temp_dir().join(format!("zk_sync_blank_{}.json", std::process::id()));
+        let path = path.to_string_lossy().to_string();
+        let text = json!([{ "uuid": " ", "title": "New", "fields": { "Status": "" } }]);
+        std::fs::write(&path, text.to_string()).unwrap();
+        let tasks = read_tasks(&path, Format::Json).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].uuid, None);
+        assert!(tasks[0].fields.is_empty());
+    }
+
+    #[test]
+    fn values_to_update() {
+        let tags = choice_field(3, "Tags", &[(1, "red"), (2, "blue")]);
+        let notes = field(4, "Notes", 1);
+        let values = [(&tags, Some("red, blue")), (&notes, Some(""))];
+        let update = field_values(&values, false);
+        assert!(matches!(
+            &update[0],
+            (name, FieldVal::ArrStr(names), UpdateAction::Replace)
+                if name == "Tags" && names == &["red", "blue"]
+        ));
+        assert!(matches!(
+            &update[1],
+            (name, FieldVal::Str(s), UpdateAction::Null) if name == "Notes" && s.is_empty()
+        ));
+        let values = [(&tags, Some("red")), (&notes, None)];
+        let create = field_values(&values, true);
+        assert!(matches!(
+            &create[0],
+            (_, FieldVal::ArrStr(_), UpdateAction::Null)
+        ));
+    }
+
+    #[test]
+    fn task_of_item() {
+        let name = field(3, "Name", 1);
+        let status = choice_field(4, "Status", &[(1, "Done")]);
+        let notes = field(5, "Notes", 1);
+        let entry = item(
+            1,
+            "display",
+            json!({
+                "field-3_text": "Write tests",
+                "field-4_categories": [1],
+                "field-5_text": "",
+            }),
+        );
+        let formatter = ValueFormatter::default();
+        let task = item_task(&entry, &[&name, &status, &notes], Some(&name), &formatter);
+        assert_eq!(task.uuid.as_deref(), Some("item-1"));
+        assert_eq!(task.title, "Write tests");
+        assert_eq!(task.fields.len(), 1);
+        assert_eq!(task.fields["Status"], "Done");
+        // without a title field, the item's display string is the title
+        let task = item_task(&entry, &[&status], None, &formatter);
+        assert_eq!(task.title, "display");
+    }
+
+    #[test]
+    fn set_values() {
+        let mut fields = BTreeMap::new();
+        set_value(&mut fields, "Status", Some("Done".to_string()));
+        assert_eq!(fields["Status"], "Done");
+        set_value(&mut fields, "Status", Some(String::new()));
+        assert!(fields.is_empty());
+    }
+}
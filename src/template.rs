@@ -0,0 +1,174 @@
+//! Rendering of items with Handlebars templates
+use crate::{values::ValueFormatter, Error, ListInfo};
+use chrono::Local;
+use handlebars::Handlebars;
+use serde_json::{json, Map, Value};
+use std::result::Result;
+use zenkit::types::Entry;
+
+/// Name of the template in the registry
+const TEMPLATE_NAME: &str = "template";
+
+/// A Handlebars template, loaded from a file.
+/// Values are html-escaped only if the file name ends in .html, .htm, .html.hbs, or .htm.hbs.
+pub(crate) struct Template {
+    registry: Handlebars<'static>,
+}
+
+impl Template {
+    pub(crate) fn load(path: &str) -> Result<Self, Error> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| Error::Message(format!("Reading template {}: {}", path, e)))?;
+        let mut registry = Handlebars::new();
+        let name = path.to_lowercase();
+        let name = name.strip_suffix(".hbs").unwrap_or(&name);
+        if !(name.ends_with(".html") || name.ends_with(".htm")) {
+            registry.register_escape_fn(handlebars::no_escape);
+        }
+        registry
+            .register_template_string(TEMPLATE_NAME, text)
+            .map_err(|e| Error::Invalid(format!("Invalid template {}: {}", path, e)))?;
+        Ok(Template { registry })
+    }
+
+    pub(crate) fn render(&self, data: &Value) -> Result<String, Error> {
+        self.registry
+            .render(TEMPLATE_NAME, data)
+            .map_err(|e| Error::Message(format!("Rendering template: {}", e)))
+    }
+
+    /// Render the list's items. The template data has `list`, `items`, and `today`
+    /// (YYYY-MM-DD): see `list_data` and `item_data`.
+    pub(crate) fn render_items(
+        &self,
+        list_info: &ListInfo,
+        items: &[Entry],
+        formatter: &ValueFormatter,
+    ) -> Result<String, Error> {
+        self.render(&json!({
+            "list": list_data(list_info),
+            "items": items
+                .iter()
+                .map(|item| item_data(list_info, item, formatter))
+                .collect::<Vec<_>>(),
+            "today": today(),
+        }))
+    }
+
+    /// Render one item. The template data has `list`, `item`, and `today`.
+    pub(crate) fn render_item(
+        &self,
+        list_info: &ListInfo,
+        item: &Entry,
+        formatter: &ValueFormatter,
+    ) -> Result<String, Error> {
+        self.render(&json!({
+            "list": list_data(list_info),
+            "item": item_data(list_info, item, formatter),
+            "today": today(),
+        }))
+    }
+}
+
+/// The list's id, uuid, name, and field names
+fn list_data(list_info: &ListInfo) -> Value {
+    let list = list_info.list();
+    json!({
+        "id": list.id,
+        "uuid": list.uuid,
+        "name": list.name,
+        "fields": list_info.fields().iter().map(|f| f.name.as_str()).collect::<Vec<_>>(),
+    })
+}
+
+/// The item's id, uuid, title, dates, and comment count, with `fields`
+/// (field name -> display string, empty if the field has no value) and `values`
+/// (field name -> array of display values, for iterating over multi-value fields)
+fn item_data(list_info: &ListInfo, item: &Entry, formatter: &ValueFormatter) -> Value {
+    let mut fields = Map::new();
+    let mut values = Map::new();
+    for field in list_info.fields().iter() {
+        fields.insert(
+            field.name.clone(),
+            formatter.display(item, field).unwrap_or_default().into(),
+        );
+        values.insert(
+            field.name.clone(),
+            formatter.display_values(item, field).into(),
+        );
+    }
+    json!({
+        "id": item.id,
+        "uuid": item.uuid,
+        "title": item.display_string,
+        "created_at": item.created_at.to_rfc3339(),
+        "updated_at": item.updated_at.to_rfc3339(),
+        "archived_at": item.deprecated_at.as_ref().map(|d| d.to_rfc3339()),
+        "comment_count": item.comment_count,
+        "fields": fields,
+        "values": values,
+    })
+}
+
+fn today() -> String {
+    Local::today().format("%Y-%m-%d").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{choice_field, field, item, list_info};
+
+    /// Load the template from a temporary file with the extension
+    fn template(text: &str, extension: &str) -> Template {
+        let path =
+            std::env::temp_dir().join(format!("zk_template_{}.{}", std::process::id(), extension));
+        std::fs::write(&path, text).unwrap();
+        let template = Template::load(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        template.unwrap()
+    }
+
+    #[test]
+    fn render_items() {
+        let list_info = list_info(vec![
+            field(1, "Title", 1),
+            field(2, "Points", 2),
+            choice_field(3, "Tags", &[(1, "red"), (2, "blue")]),
+        ]);
+        let items = vec![
+            item(
+                1,
+                "Fix <b>",
+                json!({"field-2_number": 3, "field-3_categories": [2, 1]}),
+            ),
+            item(2, "Write tests", json!({})),
+        ];
+        let formatter = ValueFormatter::default();
+        let text = "# {{list.name}}\n{{#each items}}- {{id}} {{title}} ({{fields.Points}})\
+                    {{#each values.Tags}} #{{this}}{{/each}}\n{{/each}}";
+        assert_eq!(
+            template(text, "md.hbs")
+                .render_items(&list_info, &items, &formatter)
+                .unwrap(),
+            "# Tasks\n- 1 Fix <b> (3) #blue #red\n- 2 Write tests ()\n"
+        );
+        // values are escaped in html
+        assert_eq!(
+            template("<p>{{item.title}}</p>", "html.hbs")
+                .render_item(&list_info, &items[0], &formatter)
+                .unwrap(),
+            "<p>Fix &lt;b&gt;</p>"
+        );
+    }
+
+    #[test]
+    fn invalid_template() {
+        let path = std::env::temp_dir().join(format!("zk_template_{}.txt", std::process::id()));
+        std::fs::write(&path, "{{#each items}}").unwrap();
+        let template = Template::load(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(template, Err(Error::Invalid(_))));
+    }
+}
@@ -0,0 +1,123 @@
+//! Fields, lists, and items for tests, built from json as the api returns them
+use crate::ListInfo;
+use serde_json::{json, Value};
+use zenkit::types::{Element, Entry};
+
+/// A field of the list with id 1. `category` is the field type's ElementCategoryId
+/// (1 = text, 2 = number, 4 = date, 5 = checkbox, 6 = choices, 14 = persons, ...).
+pub(crate) fn field(id: u64, name: &str, category: u8) -> Element {
+    field_with_data(id, name, category, json!({}))
+}
+
+/// A field with the field type's settings, such as choices or number format
+pub(crate) fn field_with_data(id: u64, name: &str, category: u8, element_data: Value) -> Element {
+    serde_json::from_value(json!({
+        "id": id,
+        "shortId": format!("f{}", id),
+        "uuid": format!("field-{}", id),
+        "name": name,
+        "description": null,
+        "businessData": {},
+        "elementData": element_data,
+        "isPrimary": id == 1,
+        "isAutoCreated": false,
+        "sortOrder": id,
+        "visible": true,
+        "created_at": "2021-01-01T00:00:00Z",
+        "updated_at": "2021-01-01T00:00:00Z",
+        "deprecated_at": null,
+        "elementcategory": category,
+        "listId": 1,
+        "visibleInPublicList": null,
+    }))
+    .unwrap()
+}
+
+/// A choices field with the choices (id, name)
+pub(crate) fn choice_field(id: u64, name: &str, choices: &[(u64, &str)]) -> Element {
+    let choices: Vec<Value> = choices
+        .iter()
+        .map(|(choice_id, name)| {
+            json!({
+                "id": choice_id,
+                "shortId": format!("c{}", choice_id),
+                "uuid": format!("choice-{}", choice_id),
+                "name": name,
+                "colorHex": "#ffffff",
+                "created_at": "2021-01-01T00:00:00Z",
+                "updated_at": "2021-01-01T00:00:00Z",
+                "deprecated_at": null,
+                "elementId": id,
+                "listId": 1,
+                "resourceTags": [],
+                "sortOrder": choice_id,
+            })
+        })
+        .collect();
+    field_with_data(
+        id,
+        name,
+        6,
+        json!({ "multiple": true, "predefinedCategories": choices }),
+    )
+}
+
+/// The list "Tasks", with id 1 and uuid "list-1", in workspace 1
+pub(crate) fn list_info(fields: Vec<Element>) -> ListInfo {
+    let list = json!({
+        "id": 1,
+        "shortId": "l1",
+        "uuid": "list-1",
+        "name": "Tasks",
+        "itemName": null,
+        "itemNamePlural": null,
+        "isBuilding": false,
+        "isMigrating": false,
+        "sortOrder": 0,
+        "description": "",
+        "formulaTSortOrder": null,
+        "listFilePolicy": null,
+        "originProvider": null,
+        "originData": null,
+        "defaultViewModus": 0,
+        "created_at": "2021-01-01T00:00:00Z",
+        "updated_at": "2021-01-01T00:00:00Z",
+        "deprecated_at": null,
+        "origin_created_at": null,
+        "origin_updated_at": null,
+        "origin_deprecated_at": null,
+        "workspaceId": 1,
+        "backgroundId": null,
+        "visibility": 0,
+        "iconColor": null,
+        "iconBackgroundColor": null,
+        "created_by": 1,
+    });
+    ListInfo::new(serde_json::from_value(list).unwrap(), fields)
+}
+
+/// An item of the list with id 1, with the title, and the values in `fields`,
+/// keyed as in the api ("field-2_text")
+pub(crate) fn item(id: u64, title: &str, fields: Value) -> Entry {
+    let mut item = json!({
+        "id": id,
+        "shortId": format!("i{}", id),
+        "uuid": format!("item-{}", id),
+        "listId": 1,
+        "created_at": "2021-01-01T00:00:00Z",
+        "updated_at": "2021-01-01T00:00:00Z",
+        "deprecated_at": null,
+        "created_by": 1,
+        "updated_by": 1,
+        "deprecated_by": null,
+        "displayString": title,
+        "sortOrder": id,
+        "comment_count": 0,
+        "checklists": [],
+        "field-1_text": title,
+    });
+    if let (Some(item), Value::Object(fields)) = (item.as_object_mut(), fields) {
+        item.extend(fields);
+    }
+    serde_json::from_value(item).unwrap()
+}
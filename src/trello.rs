@@ -0,0 +1,595 @@
+//! Import of a Trello board, from its json export
+use crate::{
+    cache, progress,
+    rest::RestClient,
+    retry::retry,
+    schema::{create_field, create_list},
+    values::user_emails,
+    Error, ListInfo, TrelloOpt,
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::{collections::HashMap, result::Result};
+use zenkit::types::{Element, ElementCategoryId, FieldVal, UpdateAction, ZKObjectID, ID};
+
+const DESCRIPTION_FIELD: &str = "Description";
+const LABELS_FIELD: &str = "Labels";
+const MEMBERS_FIELD: &str = "Members";
+const DUE_FIELD: &str = "Due";
+const CARD_URL_FIELD: &str = "Trello card";
+/// Name of the title field, if a new list doesn't have a text field
+const TITLE_FIELD: &str = "Name";
+
+/// Trello label colors
+const LABEL_COLORS: &[(&str, &str)] = &[
+    ("green", "#61bd4f"),
+    ("yellow", "#f2d600"),
+    ("orange", "#ff9f1a"),
+    ("red", "#eb5a46"),
+    ("purple", "#c377e0"),
+    ("blue", "#0079bf"),
+    ("sky", "#00c2e0"),
+    ("lime", "#51e898"),
+    ("pink", "#ff78cb"),
+    ("black", "#344563"),
+];
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Board {
+    name: String,
+    #[serde(default)]
+    desc: String,
+    #[serde(default)]
+    lists: Vec<TrelloList>,
+    #[serde(default)]
+    cards: Vec<Card>,
+    #[serde(default)]
+    labels: Vec<Label>,
+    #[serde(default)]
+    members: Vec<Member>,
+    #[serde(default)]
+    checklists: Vec<Checklist>,
+    #[serde(default)]
+    actions: Vec<Action>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TrelloList {
+    id: String,
+    name: String,
+    #[serde(default)]
+    closed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Card {
+    id: String,
+    name: String,
+    #[serde(default)]
+    desc: String,
+    #[serde(default)]
+    id_list: String,
+    #[serde(default)]
+    id_labels: Vec<String>,
+    #[serde(default)]
+    id_members: Vec<String>,
+    due: Option<String>,
+    #[serde(default)]
+    closed: bool,
+    #[serde(default)]
+    short_url: String,
+    #[serde(default)]
+    attachments: Vec<Value>,
+    #[serde(default)]
+    custom_field_items: Vec<Value>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Label {
+    id: String,
+    #[serde(default)]
+    name: String,
+    color: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Member {
+    id: String,
+    #[serde(default)]
+    full_name: String,
+    #[serde(default)]
+    username: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Checklist {
+    id_card: String,
+    name: String,
+    #[serde(default)]
+    check_items: Vec<CheckItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckItem {
+    name: String,
+    #[serde(default)]
+    state: String,
+    #[serde(default)]
+    pos: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Action {
+    #[serde(rename = "type")]
+    action_type: String,
+    #[serde(default)]
+    date: String,
+    #[serde(default)]
+    data: Value,
+    member_creator: Option<Member>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct TrelloSummary {
+    pub lists: usize,
+    pub items: usize,
+    pub checklists: usize,
+    pub comments: usize,
+    /// what couldn't be imported
+    pub not_imported: Vec<String>,
+}
+
+/// Import a Trello board into a new list (named after the board, or `--list`), with
+/// a categories field for the Trello lists (`--stage-field`), or with `--split-lists`,
+/// into a new list for each Trello list. Cards become items, with fields for the
+/// description, labels (a categories field), members (a persons field), due date, and
+/// the card's url. Checklists are added to the items, and comments are added, oldest
+/// first, with the original author and date. Members are matched to workspace users by
+/// email (from `--member username=email`) or by name. Archived cards and lists are
+/// skipped unless `--include-archived` is used.
+pub(crate) async fn import_trello(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &TrelloOpt,
+) -> Result<TrelloSummary, Error> {
+    let api = zenkit::get_api()?;
+    let board: Board = serde_json::from_slice(&std::fs::read(&opt.file)?)
+        .map_err(|e| Error::Invalid(format!("Invalid Trello export {}: {}", opt.file, e)))?;
+    let mut summary = TrelloSummary::default();
+
+    let lists: Vec<&TrelloList> = board
+        .lists
+        .iter()
+        .filter(|l| opt.include_archived || !l.closed)
+        .collect();
+    let cards: Vec<&Card> = board
+        .cards
+        .iter()
+        .filter(|c| (opt.include_archived || !c.closed) && lists.iter().any(|l| l.id == c.id_list))
+        .collect();
+    let skipped = board.cards.len() - cards.len();
+    if skipped > 0 {
+        summary.not_imported.push(format!(
+            "{} archived card(s), or cards in archived lists",
+            skipped
+        ));
+    }
+    let members = match_members(rest, ws_id, &board.members, opt, &mut summary).await?;
+    let labels = label_choices(&board.labels);
+
+    // destination list name, and its cards
+    let targets: Vec<(String, Vec<&Card>)> = if opt.split_lists {
+        lists
+            .iter()
+            .map(|l| {
+                let list_cards = cards.iter().filter(|c| c.id_list == l.id).copied();
+                (l.name.clone(), list_cards.collect())
+            })
+            .collect()
+    } else {
+        vec![(
+            opt.list.clone().unwrap_or_else(|| board.name.clone()),
+            cards.clone(),
+        )]
+    };
+    let description = Some(board.desc.as_str()).filter(|d| !d.is_empty());
+
+    for (name, list_cards) in targets.iter() {
+        let new_list = match create_list(rest, ws_id, name, description).await? {
+            Some(list) => list,
+            None => {
+                eprintln!(
+                    "(dry run) {} card(s) would be imported into list '{}'",
+                    list_cards.len(),
+                    name
+                );
+                continue;
+            }
+        };
+        summary.lists += 1;
+        // new lists may start with default fields: the first text field is used for titles
+        let existing = retry("get_list_elements", || api.get_list_elements(new_list.id)).await?;
+        let mut specs: Vec<(&str, ElementCategoryId, Map<String, Value>)> = Vec::new();
+        if !existing
+            .iter()
+            .any(|f| f.element_category == ElementCategoryId::Text)
+        {
+            specs.push((TITLE_FIELD, ElementCategoryId::Text, Map::new()));
+        }
+        specs.push((DESCRIPTION_FIELD, ElementCategoryId::Text, Map::new()));
+        if !opt.split_lists {
+            let choices: Vec<Value> = lists.iter().map(|l| json!({ "name": l.name })).collect();
+            specs.push((
+                &opt.stage_field,
+                ElementCategoryId::Categories,
+                categories_data(choices, false),
+            ));
+        }
+        if !labels.is_empty() {
+            let choices: Vec<Value> = labels
+                .iter()
+                .map(|(name, color)| json!({ "name": name, "colorHex": color }))
+                .collect();
+            specs.push((
+                LABELS_FIELD,
+                ElementCategoryId::Categories,
+                categories_data(choices, true),
+            ));
+        }
+        if !members.is_empty() {
+            let mut data = Map::new();
+            data.insert("allowMultiple".into(), true.into());
+            specs.push((MEMBERS_FIELD, ElementCategoryId::Persons, data));
+        }
+        specs.push((DUE_FIELD, ElementCategoryId::Date, Map::new()));
+        specs.push((CARD_URL_FIELD, ElementCategoryId::URL, Map::new()));
+        for (field_name, category, data) in specs.into_iter() {
+            if !existing.iter().any(|f| f.name == field_name) {
+                create_field(rest, new_list.id, field_name, category, data).await?;
+            }
+        }
+
+        let list_info = cache::get_list_info(ws_id, &new_list.uuid).await?;
+        let cards_done = progress::bar(list_cards.len(), "Importing");
+        for card in list_cards.iter() {
+            let values = card_values(&list_info, &board, card, &members, opt);
+            let item = list_info.create_item(&values).await?;
+            summary.items += 1;
+
+            let checklists: Vec<Value> = board
+                .checklists
+                .iter()
+                .filter(|c| c.id_card == card.id)
+                .map(|c| {
+                    let mut items: Vec<&CheckItem> = c.check_items.iter().collect();
+                    items.sort_by(|a, b| {
+                        a.pos
+                            .partial_cmp(&b.pos)
+                            .unwrap_or(std::cmp::Ordering::Equal)
+                    });
+                    let items: Vec<Value> = items
+                        .iter()
+                        .map(|i| json!({ "text": i.name, "checked": i.state == "complete" }))
+                        .collect();
+                    json!({ "name": c.name, "items": items })
+                })
+                .collect();
+            if !checklists.is_empty() {
+                summary.checklists += checklists.len();
+                let path = format!("lists/{}/entries/{}", list_info.get_id(), item.get_id());
+                rest.put(&path, &json!({ "checklists": checklists }))
+                    .await?;
+            }
+
+            // actions are newest first
+            let item_id = item.get_id().to_string();
+            for action in board.actions.iter().rev().filter(|a| {
+                a.action_type == "commentCard"
+                    && a.data.pointer("/card/id").and_then(|v| v.as_str()) == Some(&card.id)
+            }) {
+                let text = action
+                    .data
+                    .get("text")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default();
+                let author = action
+                    .member_creator
+                    .as_ref()
+                    .map(|m| m.full_name.as_str())
+                    .unwrap_or("Trello user");
+                let comment = format!(
+                    "{} ({}): {}",
+                    author,
+                    action.date.get(..10).unwrap_or(&action.date),
+                    text
+                );
+                list_info.add_item_comment(&item_id, &comment).await?;
+                summary.comments += 1;
+            }
+
+            if !card.attachments.is_empty() {
+                summary.not_imported.push(format!(
+                    "card '{}': {} attachment(s)",
+                    card.name,
+                    card.attachments.len()
+                ));
+            }
+            if !card.custom_field_items.is_empty() {
+                summary.not_imported.push(format!(
+                    "card '{}': {} custom field value(s)",
+                    card.name,
+                    card.custom_field_items.len()
+                ));
+            }
+            cards_done.inc(1);
+        }
+        cards_done.finish_and_clear();
+    }
+    Ok(summary)
+}
+
+/// Field values for the card
+fn card_values(
+    list_info: &ListInfo,
+    board: &Board,
+    card: &Card,
+    members: &HashMap<&str, String>,
+    opt: &TrelloOpt,
+) -> Vec<(String, FieldVal, UpdateAction)> {
+    let field = |name: &str| list_info.fields().iter().find(|f| f.name == name);
+    let mut values = Vec::new();
+    let mut push = |field: Option<&Element>, value: FieldVal| {
+        if let Some(field) = field {
+            values.push((field.uuid.clone(), value, UpdateAction::Null));
+        }
+    };
+    let title = list_info
+        .fields()
+        .iter()
+        .find(|f| f.element_category == ElementCategoryId::Text);
+    push(title, FieldVal::Str(card.name.clone()));
+    if !card.desc.is_empty() {
+        push(field(DESCRIPTION_FIELD), FieldVal::Str(card.desc.clone()));
+    }
+    if !opt.split_lists {
+        if let Some(list) = board.lists.iter().find(|l| l.id == card.id_list) {
+            let stage = field(&opt.stage_field);
+            if let Some(id) = stage.and_then(|f| choice_id(f, &list.name)) {
+                push(stage, FieldVal::ArrID(vec![id]));
+            }
+        }
+    }
+    let labels_field = field(LABELS_FIELD);
+    let label_ids: Vec<ID> = card
+        .id_labels
+        .iter()
+        .filter_map(|id| board.labels.iter().find(|l| &l.id == id))
+        .filter_map(|l| labels_field.and_then(|f| choice_id(f, &label_name(l))))
+        .collect();
+    if !label_ids.is_empty() {
+        push(labels_field, FieldVal::ArrID(label_ids));
+    }
+    let persons: Vec<String> = card
+        .id_members
+        .iter()
+        .filter_map(|id| members.get(id.as_str()).cloned())
+        .collect();
+    if !persons.is_empty() {
+        push(field(MEMBERS_FIELD), FieldVal::ArrStr(persons));
+    }
+    if let Some(ref due) = card.due {
+        push(field(DUE_FIELD), FieldVal::Str(due.clone()));
+    }
+    if !card.short_url.is_empty() {
+        push(field(CARD_URL_FIELD), FieldVal::Str(card.short_url.clone()));
+    }
+    values
+}
+
+/// Workspace user uuids of the board's members (Trello member id -> uuid). Members are
+/// matched by the email given with `--member` (for the username or member id), or else
+/// by display name. Members without a match are added to the summary.
+async fn match_members<'b>(
+    rest: &RestClient,
+    ws_id: ID,
+    members: &'b [Member],
+    opt: &TrelloOpt,
+    summary: &mut TrelloSummary,
+) -> Result<HashMap<&'b str, String>, Error> {
+    let mut matched = HashMap::new();
+    if members.is_empty() {
+        return Ok(matched);
+    }
+    let users = rest.get(&format!("workspaces/{}/users", ws_id)).await?;
+    let users = users.as_array().map(|v| v.as_slice()).unwrap_or_default();
+    let emails: HashMap<&str, &str> = opt
+        .member
+        .iter()
+        .map(|(member, email)| (member.trim_start_matches('@'), email.as_str()))
+        .collect();
+    for member in members.iter() {
+        let email = emails
+            .get(member.username.as_str())
+            .or_else(|| emails.get(member.id.as_str()))
+            .copied();
+        let user = users.iter().find(|user| match email {
+            Some(email) => user_emails(user)
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(email)),
+            None => user
+                .get("displayname")
+                .and_then(|v| v.as_str())
+                .map(|name| name.eq_ignore_ascii_case(&member.full_name))
+                .unwrap_or(false),
+        });
+        match user.and_then(|u| u.get("uuid")).and_then(|v| v.as_str()) {
+            Some(uuid) => {
+                matched.insert(member.id.as_str(), uuid.to_string());
+            }
+            None => summary.not_imported.push(format!(
+                "member {} (@{}): no workspace user with {}",
+                member.full_name,
+                member.username,
+                match email {
+                    Some(email) => format!("email {}", email),
+                    None => "that name (use --member username=email)".to_string(),
+                }
+            )),
+        }
+    }
+    Ok(matched)
+}
+
+/// Names and colors of the labels, for the labels field. Labels with the same name
+/// are one choice.
+fn label_choices(labels: &[Label]) -> Vec<(String, Option<&'static str>)> {
+    let mut choices: Vec<(String, Option<&'static str>)> = Vec::new();
+    for label in labels.iter() {
+        let name = label_name(label);
+        if !choices.iter().any(|(n, _)| *n == name) {
+            let color = label.color.as_deref().and_then(|color| {
+                // e.g., "green_dark"
+                let base = color.split('_').next().unwrap_or(color);
+                LABEL_COLORS
+                    .iter()
+                    .find(|(name, _)| *name == base)
+                    .map(|(_, hex)| *hex)
+            });
+            choices.push((name, color));
+        }
+    }
+    choices
+}
+
+/// The label's name, or if it has none, its color
+fn label_name(label: &Label) -> String {
+    if !label.name.is_empty() {
+        label.name.clone()
+    } else {
+        label
+            .color
+            .clone()
+            .unwrap_or_else(|| "(no name)".to_string())
+    }
+}
+
+fn categories_data(choices: Vec<Value>, multiple: bool) -> Map<String, Value> {
+    let mut data = Map::new();
+    data.insert("predefinedCategories".into(), choices.into());
+    data.insert("allowMultiple".into(), multiple.into());
+    data
+}
+
+/// Id of the field's choice with the name
+fn choice_id(field: &Element, name: &str) -> Option<ID> {
+    field
+        .element_data
+        .predefined_categories
+        .as_deref()
+        .unwrap_or_default()
+        .iter()
+        .find(|c| c.name == name)
+        .map(|c| c.id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{choice_field, field, list_info};
+
+    #[test]
+    fn values_of_card() {
+        let list_info = list_info(vec![
+            field(1, TITLE_FIELD, 1),
+            field(2, DESCRIPTION_FIELD, 1),
+            choice_field(3, "Stage", &[(1, "Todo"), (2, "Done")]),
+            choice_field(4, LABELS_FIELD, &[(5, "urgent"), (6, "green_dark")]),
+            field(5, MEMBERS_FIELD, ElementCategoryId::Persons as u8),
+            field(6, DUE_FIELD, 4),
+            field(7, CARD_URL_FIELD, ElementCategoryId::URL as u8),
+        ]);
+        let board: Board = serde_json::from_value(json!({
+            "name": "Sprint",
+            "lists": [{ "id": "l1", "name": "Done" }],
+            "labels": [
+                { "id": "b1", "name": "urgent", "color": "red" },
+                { "id": "b2", "name": "", "color": "green_dark" },
+            ],
+            "cards": [{
+                "id": "c1",
+                "name": "Ship",
+                "desc": "Soon",
+                "idList": "l1",
+                "idLabels": ["b2", "b1"],
+                "idMembers": ["m1", "m2"],
+                "due": "2021-03-05T12:00:00.000Z",
+                "shortUrl": "https://trello.com/c/abc",
+            }],
+        }))
+        .unwrap();
+        let members: HashMap<&str, String> =
+            vec![("m1", "user-1".to_string())].into_iter().collect();
+        let opt = TrelloOpt {
+            file: "board.json".into(),
+            list: None,
+            split_lists: false,
+            stage_field: "Stage".into(),
+            member: Vec::new(),
+            include_archived: false,
+        };
+        let values: Vec<(String, String)> =
+            card_values(&list_info, &board, &board.cards[0], &members, &opt)
+                .into_iter()
+                .map(|(uuid, value, _)| {
+                    let value = match value {
+                        FieldVal::Str(s) => s,
+                        FieldVal::ArrID(ids) => format!("{:?}", ids),
+                        FieldVal::ArrStr(values) => format!("{:?}", values),
+                        _ => "?".to_string(),
+                    };
+                    (uuid, value)
+                })
+                .collect();
+        let expected = vec![
+            ("field-1", "Ship"),
+            ("field-2", "Soon"),
+            ("field-3", "[2]"),
+            ("field-4", "[6, 5]"),
+            ("field-5", "[\"user-1\"]"),
+            ("field-6", "2021-03-05T12:00:00.000Z"),
+            ("field-7", "https://trello.com/c/abc"),
+        ];
+        let expected: Vec<(String, String)> = expected
+            .into_iter()
+            .map(|(uuid, value)| (uuid.to_string(), value.to_string()))
+            .collect();
+        assert_eq!(values, expected);
+    }
+
+    #[test]
+    fn labels() {
+        let labels: Vec<Label> = serde_json::from_value(json!([
+            { "id": "b1", "name": "urgent", "color": "red" },
+            { "id": "b2", "name": "urgent", "color": "blue" },
+            { "id": "b3", "name": "", "color": "green_dark" },
+            { "id": "b4", "name": "later", "color": null },
+            { "id": "b5", "color": null },
+        ]))
+        .unwrap();
+        assert_eq!(
+            label_choices(&labels),
+            vec![
+                ("urgent".to_string(), Some("#eb5a46")),
+                ("green_dark".to_string(), Some("#61bd4f")),
+                ("later".to_string(), None),
+                ("(no name)".to_string(), None),
+            ]
+        );
+    }
+}
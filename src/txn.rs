@@ -0,0 +1,312 @@
+//! Transactions (`--transactional`): the values of items are recorded before they're
+//! changed, and if a later change fails, the changes already applied are reverted.
+//! Changes made by `set`, `create`, and `bulk-set` are recorded.
+use crate::{
+    cache, clear_value, rest::RestClient, restore::entry_values, values::copy_entry, Error,
+    ListInfo, Sub,
+};
+use std::{collections::HashSet, result::Result, sync::Mutex};
+use zenkit::types::{Element, Entry, FieldVal, UpdateAction, ID};
+
+/// Changes of the current transaction, oldest first. None if no transaction is active.
+static JOURNAL: Mutex<Option<Vec<Change>>> = Mutex::new(None);
+
+enum Change {
+    /// An item was updated
+    Updated {
+        ws_id: ID,
+        list_uuid: String,
+        /// the item before its first change in the transaction
+        before: Box<Entry>,
+        /// uuids of the fields changed
+        fields: HashSet<String>,
+    },
+    /// An item was created
+    Created { list_id: ID, item_id: ID },
+}
+
+/// Start recording changes
+pub(crate) fn begin() {
+    if let Ok(mut journal) = JOURNAL.lock() {
+        *journal = Some(Vec::new());
+    }
+}
+
+/// Returns true if the command's changes can be reverted: its changes are recorded
+/// (`set`, `create`, and `bulk-set`), or it doesn't change anything in Zenkit
+pub(crate) fn revertible(cmd: &Sub) -> bool {
+    matches!(
+        cmd,
+        Sub::Set(_)
+            | Sub::Create(_)
+            | Sub::BulkSet(_)
+            | Sub::Workspaces
+            | Sub::Users
+            | Sub::Whoami
+            | Sub::RefreshCache
+            | Sub::Lists
+            | Sub::Items(_)
+            | Sub::Fields(_)
+            | Sub::Field(_)
+            | Sub::Item(_)
+            | Sub::Get(_)
+            | Sub::Url(_)
+            | Sub::Choices(_)
+            | Sub::ExportSchema(_)
+            | Sub::Search(_)
+            | Sub::Stats(_)
+            | Sub::Board(_)
+            | Sub::Agenda(_)
+            | Sub::MyItems(_)
+            | Sub::Due(_)
+            | Sub::Export(_)
+            | Sub::Query(_)
+            | Sub::Files(_)
+            | Sub::Comments(_)
+            | Sub::Activities(_)
+            | Sub::History(_)
+            | Sub::ListWebhooks(_)
+            | Sub::Diff(_)
+    )
+}
+
+/// Returns true if a transaction is active
+pub(crate) fn active() -> bool {
+    JOURNAL.lock().map(|j| j.is_some()).unwrap_or(false)
+}
+
+/// End the transaction, keeping the changes
+pub(crate) fn commit() {
+    if let Ok(mut journal) = JOURNAL.lock() {
+        *journal = None;
+    }
+}
+
+/// Record the values of an item before it's updated with the values.
+/// Does nothing if no transaction is active.
+pub(crate) fn record_update(
+    ws_id: ID,
+    list_info: &ListInfo,
+    before: &Entry,
+    values: &[(String, FieldVal, UpdateAction)],
+) -> Result<(), Error> {
+    let changed: HashSet<String> = values
+        .iter()
+        .filter_map(|(name, _, _)| list_info.get_field(name).ok())
+        .map(|field| field.uuid.clone())
+        .collect();
+    if let Ok(mut journal) = JOURNAL.lock() {
+        let journal = match journal.as_mut() {
+            Some(journal) => journal,
+            None => return Ok(()),
+        };
+        // the values before the item's first change are the ones to restore
+        for change in journal.iter_mut() {
+            if let Change::Updated {
+                before: prev,
+                fields,
+                ..
+            } = change
+            {
+                if prev.uuid == before.uuid {
+                    fields.extend(changed);
+                    return Ok(());
+                }
+            }
+        }
+        journal.push(Change::Updated {
+            ws_id,
+            list_uuid: list_info.list().uuid.clone(),
+            before: Box::new(copy_entry(before)?),
+            fields: changed,
+        });
+    }
+    Ok(())
+}
+
+/// Record a created item. Does nothing if no transaction is active.
+pub(crate) fn record_created(list_id: ID, item_id: ID) {
+    if let Ok(mut journal) = JOURNAL.lock() {
+        if let Some(journal) = journal.as_mut() {
+            journal.push(Change::Created { list_id, item_id });
+        }
+    }
+}
+
+/// End the transaction, reverting its changes, newest first: updated fields are set
+/// back to their previous values, and created items are deleted. Changes that can't
+/// be reverted are reported, and the rest are still reverted.
+pub(crate) async fn rollback(rest: &RestClient) -> Result<(), Error> {
+    let changes = match JOURNAL.lock() {
+        Ok(mut journal) => journal.take().unwrap_or_default(),
+        Err(_) => return Ok(()),
+    };
+    let mut reverted = 0;
+    let mut failed = Vec::new();
+    for change in changes.iter().rev() {
+        let result = match change {
+            Change::Updated {
+                ws_id,
+                list_uuid,
+                before,
+                fields,
+            } => revert_update(*ws_id, list_uuid, before, fields).await,
+            Change::Created { list_id, item_id } => rest
+                .delete(&format!("lists/{}/entries/{}", list_id, item_id))
+                .await
+                .map(|_| ()),
+        };
+        match result {
+            Ok(()) => reverted += 1,
+            Err(e) => {
+                let item_id = match change {
+                    Change::Updated { before, .. } => before.id,
+                    Change::Created { item_id, .. } => *item_id,
+                };
+                eprintln!("Error reverting item {}: {}", item_id, e.summary());
+                failed.push(item_id);
+            }
+        }
+    }
+    eprintln!("Reverted changes to {} item(s)", reverted);
+    if !failed.is_empty() {
+        return Err(Error::Message(format!(
+            "Failed to revert changes to items: {:?}",
+            failed
+        )));
+    }
+    Ok(())
+}
+
+/// Set the changed fields of the item back to their values in `before`
+async fn revert_update(
+    ws_id: ID,
+    list_uuid: &str,
+    before: &Entry,
+    fields: &HashSet<String>,
+) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, list_uuid).await?;
+    let values = revert_values(before, list_info.fields(), fields);
+    if !values.is_empty() {
+        list_info.update_item(before.id, &values).await?;
+    }
+    Ok(())
+}
+
+/// Values that set the changed fields back to their values in `before`, clearing the
+/// fields that were empty
+fn revert_values(
+    before: &Entry,
+    list_fields: &[Element],
+    fields: &HashSet<String>,
+) -> Vec<(String, FieldVal, UpdateAction)> {
+    let mut values = entry_values(before, list_fields, UpdateAction::Replace);
+    values.retain(|(uuid, _, _)| fields.contains(uuid));
+    // fields that were empty
+    for field in list_fields.iter().filter(|f| fields.contains(&f.uuid)) {
+        if !values.iter().any(|(uuid, _, _)| uuid == &field.uuid) {
+            values.push(clear_value(field.uuid.clone(), field));
+        }
+    }
+    values
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{
+        shell::parse_command,
+        testutil::{choice_field, field, item, list_info},
+    };
+    use serde_json::json;
+
+    fn words(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn revertible_commands() {
+        let revertible = |args: &[&str]| revertible(&parse_command(&words(args)).unwrap());
+        assert!(revertible(&[
+            "set", "-l", "Tasks", "-i", "1", "-f", "Notes", "-v", "x"
+        ]));
+        assert!(revertible(&["items", "-l", "Tasks"]));
+        assert!(!revertible(&["delete-item", "-l", "Tasks", "-i", "1"]));
+        assert!(!revertible(&["archive", "-l", "Tasks"]));
+    }
+
+    #[test]
+    fn journal() {
+        let info = list_info(vec![field(2, "Notes", 1), field(3, "Points", 2)]);
+        let entry = item(1, "Write tests", json!({ "field-2_text": "old" }));
+        let notes = vec![(
+            "Notes".to_string(),
+            FieldVal::Str("new".into()),
+            UpdateAction::Null,
+        )];
+        let points = vec![(
+            "Points".to_string(),
+            FieldVal::Float(3.0),
+            UpdateAction::Null,
+        )];
+
+        begin();
+        assert!(active());
+        record_update(1, &info, &entry, &notes).unwrap();
+        // a second change to the item adds to its changed fields
+        record_update(1, &info, &entry, &points).unwrap();
+        record_created(1, 5);
+        {
+            let journal = JOURNAL.lock().unwrap();
+            let changes = journal.as_ref().unwrap();
+            assert_eq!(changes.len(), 2);
+            assert!(matches!(
+                &changes[0],
+                Change::Updated { before, fields, .. }
+                    if before.id == 1 && fields.len() == 2 && fields.contains("field-3")
+            ));
+            assert!(matches!(
+                changes[1],
+                Change::Created {
+                    list_id: 1,
+                    item_id: 5
+                }
+            ));
+        }
+        commit();
+        assert!(!active());
+        // not recorded without a transaction
+        record_created(1, 6);
+        assert!(JOURNAL.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn reverted_values() {
+        let fields = vec![
+            field(2, "Notes", 1),
+            choice_field(3, "Tags", &[(1, "red")]),
+            field(4, "Points", 2),
+        ];
+        let before = item(
+            1,
+            "Write tests",
+            json!({ "field-2_text": "old", "field-3_categories": [1] }),
+        );
+        let changed: HashSet<String> = ["field-2", "field-4"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let values = revert_values(&before, &fields, &changed);
+        assert_eq!(values.len(), 2);
+        assert!(matches!(
+            &values[0],
+            (uuid, FieldVal::Str(s), UpdateAction::Replace) if uuid == "field-2" && s == "old"
+        ));
+        // the field was empty
+        assert!(matches!(
+            &values[1],
+            (uuid, FieldVal::Str(s), UpdateAction::Null) if uuid == "field-4" && s.is_empty()
+        ));
+    }
+}
@@ -0,0 +1,224 @@
+//! Undo recent field changes to an item, using its activity history
+use crate::{
+    activity::{get_activities, FieldChange},
+    cache, clear_value, dryrun,
+    rest::RestClient,
+    retry::retry,
+    Error, UndoOpt,
+};
+use serde_json::Value;
+use std::{collections::HashSet, result::Result};
+use zenkit::types::{Element, ElementCategoryId, FieldVal, UpdateAction, ZKObjectID, ID};
+
+/// Restore the values that the item's fields had before the last `opt.last` field
+/// changes made by the current user. A field that was changed by someone else
+/// after the user's change is left as is, and reported on stderr.
+/// Prints each restored field. Returns the number of fields restored.
+pub(crate) async fn undo_changes(
+    rest: &RestClient,
+    ws_id: ID,
+    opt: &UndoOpt,
+) -> Result<usize, Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let item = retry("get_item", || list_info.get_item(&opt.item)).await?;
+    let me = rest
+        .get("users/me")
+        .await?
+        .get("id")
+        .and_then(|v| v.as_u64());
+    let path = format!(
+        "lists/{}/entries/{}/activities",
+        list_info.get_id(),
+        item.get_id()
+    );
+    // newest first
+    let changes: Vec<FieldChange> = get_activities(rest, &path, None, None, None)
+        .await?
+        .iter()
+        .filter_map(FieldChange::from_activity)
+        .collect();
+
+    let (restore, undone) = changes_to_undo(&changes, list_info.fields(), me, opt.last);
+    if undone == 0 {
+        return Err(Error::Message(format!(
+            "No recent field changes to item {} by the current user",
+            item.get_id()
+        )));
+    }
+
+    let mut values = Vec::new();
+    for (field, change) in restore.iter() {
+        match restore_value(field, &change.old_raw) {
+            Some(value) => {
+                println!(
+                    "{}\t{} -> {}",
+                    field.name, change.new_value, change.old_value
+                );
+                values.push(value);
+            }
+            None => eprintln!("Skipped {}: can't restore this field type", field.name),
+        }
+    }
+    if values.is_empty() {
+        return Ok(0);
+    }
+    if dryrun::enabled() {
+        dryrun::print_item_request(&list_info, Some(item.get_id()), &values).await?;
+    } else {
+        list_info.update_item(item.get_id(), &values).await?;
+    }
+    Ok(values.len())
+}
+
+/// The fields to restore for the user's (`me`) last `last` changes in `changes` (newest
+/// first), each with the oldest of those changes to the field, and the number of the
+/// user's changes found. Fields changed by someone else since, or no longer in the list,
+/// are skipped, and reported on stderr.
+fn changes_to_undo<'c, 'f>(
+    changes: &'c [FieldChange],
+    fields: &'f [Element],
+    me: Option<u64>,
+    last: usize,
+) -> (Vec<(&'f Element, &'c FieldChange)>, usize) {
+    // fields changed by others since the user's changes
+    let mut changed_by_others = HashSet::new();
+    // fields to restore, with the oldest of the user's changes to each
+    let mut restore: Vec<(&'f Element, &'c FieldChange)> = Vec::new();
+    let mut undone = 0;
+    for change in changes.iter() {
+        if undone >= last {
+            break;
+        }
+        if change.user_id.is_none() || change.user_id != me {
+            changed_by_others.insert(change.field.as_str());
+            continue;
+        }
+        undone += 1;
+        if changed_by_others.contains(change.field.as_str()) {
+            eprintln!(
+                "Skipped {}: changed by someone else since {}",
+                change.field, change.created_at
+            );
+            continue;
+        }
+        let field = match fields.iter().find(|f| f.name == change.field) {
+            Some(field) => field,
+            None => {
+                eprintln!("Skipped {}: field no longer exists", change.field);
+                continue;
+            }
+        };
+        // an earlier change to the same field has the older value
+        restore.retain(|(f, _)| f.uuid != field.uuid);
+        restore.push((field, change));
+    }
+    (restore, undone)
+}
+
+/// Field update that sets the field to the value recorded in a change.
+/// Values of multi-value fields are recorded as arrays of objects (choices, persons,
+/// or items), which are converted to ids (uuids, for references). Empty values
+/// clear the field.
+fn restore_value(field: &Element, old: &Value) -> Option<(String, FieldVal, UpdateAction)> {
+    let value = match (field.element_category, old) {
+        (ElementCategoryId::Formula, _) | (ElementCategoryId::Files, _) => return None,
+        (_, Value::Null) => return Some(clear_value(field.uuid.clone(), field)),
+        (ElementCategoryId::References, Value::Array(values)) => FieldVal::ArrStr(
+            values
+                .iter()
+                .filter_map(|v| match v {
+                    Value::Object(obj) => obj.get("uuid").and_then(|uuid| uuid.as_str()),
+                    v => v.as_str(),
+                })
+                .map(String::from)
+                .collect(),
+        ),
+        (_, Value::Array(values)) => FieldVal::ArrID(
+            values
+                .iter()
+                .filter_map(|v| match v {
+                    Value::Object(obj) => obj.get("id").and_then(|id| id.as_u64()),
+                    v => v.as_u64(),
+                })
+                .collect(),
+        ),
+        (_, Value::String(s)) => FieldVal::Str(s.clone()),
+        (_, v) => FieldVal::Str(v.to_string()),
+    };
+    Some((field.uuid.clone(), value, UpdateAction::Replace))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{choice_field, field};
+    use serde_json::json;
+
+    fn change(activity_id: u64, user_id: u64, field: &str, old: Value) -> FieldChange {
+        FieldChange {
+            activity_id,
+            created_at: "2021-03-01T10:00:00.000Z".into(),
+            author: String::new(),
+            field: field.into(),
+            old_value: old.to_string(),
+            new_value: String::new(),
+            user_id: Some(user_id),
+            old_raw: old,
+        }
+    }
+
+    #[test]
+    fn changes_of_user() {
+        let fields = vec![field(2, "Points", 2), field(3, "Notes", 1)];
+        // newest first
+        let changes = vec![
+            change(5, 7, "Points", json!(3)),
+            change(4, 8, "Notes", json!("b")),
+            change(3, 7, "Notes", json!("a")),
+            change(2, 7, "Points", json!(2)),
+            change(1, 7, "Gone", json!("x")),
+        ];
+        let restored = |last| {
+            let (restore, undone) = changes_to_undo(&changes, &fields, Some(7), last);
+            let restore: Vec<(&str, u64)> = restore
+                .iter()
+                .map(|(field, change)| (field.name.as_str(), change.activity_id))
+                .collect();
+            (restore, undone)
+        };
+        assert_eq!(restored(1), (vec![("Points", 5)], 1));
+        // Notes was changed by someone else since, and the older change to Points
+        // has the value to restore
+        assert_eq!(restored(10), (vec![("Points", 2)], 4));
+        assert_eq!(changes_to_undo(&changes, &fields, Some(9), 10).1, 0);
+    }
+
+    #[test]
+    fn restored_values() {
+        let project = field(6, "Project", ElementCategoryId::References as u8);
+        let refs = json!([{ "uuid": "item-9", "displayString": "Launch" }]);
+        assert!(matches!(
+            restore_value(&project, &refs),
+            Some((uuid, FieldVal::ArrStr(uuids), UpdateAction::Replace))
+                if uuid == "field-6" && uuids == ["item-9"]
+        ));
+        let tags = choice_field(3, "Tags", &[(1, "red"), (2, "blue")]);
+        let choices = json!([{ "id": 2, "name": "blue" }, { "id": 1, "name": "red" }]);
+        assert!(matches!(
+            restore_value(&tags, &choices),
+            Some((_, FieldVal::ArrID(ids), UpdateAction::Replace)) if ids == [2, 1]
+        ));
+        assert!(matches!(
+            restore_value(&tags, &Value::Null),
+            Some((_, FieldVal::ArrID(ids), UpdateAction::Replace)) if ids.is_empty()
+        ));
+        let points = field(2, "Points", 2);
+        assert!(matches!(
+            restore_value(&points, &json!(3)),
+            Some((_, FieldVal::Str(s), UpdateAction::Replace)) if s == "3"
+        ));
+        let total = field(7, "Total", ElementCategoryId::Formula as u8);
+        assert!(restore_value(&total, &json!(3)).is_none());
+    }
+}
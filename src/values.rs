@@ -0,0 +1,489 @@
+//! Values of item fields, and their display strings
+use crate::{
+    backup::get_all_items, dates::resolve_dates, fuzzy, offline, rest::RestClient, retry::retry,
+    Error, ListInfo,
+};
+use serde_json::Value;
+use std::{collections::HashMap, result::Result};
+use zenkit::types::{Element, ElementCategoryId, Entry, FieldVal, UpdateAction, ID};
+
+/// Key of the field's value in the item's fields: "<field-uuid>_<suffix>",
+/// or None for field types whose values can't be set directly (formulas and files)
+pub(crate) fn value_key(field: &Element) -> Option<String> {
+    let suffix = match field.element_category {
+        ElementCategoryId::Text => "text",
+        ElementCategoryId::Number => "number",
+        ElementCategoryId::URL => "link",
+        ElementCategoryId::Date => "date",
+        ElementCategoryId::Checkbox => "checked",
+        ElementCategoryId::Categories => "categories",
+        ElementCategoryId::Persons => "persons",
+        ElementCategoryId::References => "references",
+        _ => return None,
+    };
+    Some(format!("{}_{}", field.uuid, suffix))
+}
+
+/// The field's value in the item, as stored by Zenkit.
+/// For file fields, this is the list of file descriptions.
+pub(crate) fn raw_value<'e>(item: &'e Entry, field: &Element) -> Option<&'e Value> {
+    match field.element_category {
+        ElementCategoryId::Files => item.fields.get(&format!("{}_files_sort", field.uuid)),
+        _ => item.fields.get(&value_key(field)?),
+    }
+    .filter(|v| !v.is_null())
+}
+
+/// The json value of a field value that isn't converted like other values of its
+/// field type (see `ListInfo::item_body`): checkboxes ("true" or "false"; empty
+/// is false), and empty values of number, date, and link fields, which clear them
+/// (null). None for other values.
+pub(crate) fn direct_value(field: &Element, value: &FieldVal) -> Option<Value> {
+    match (field.element_category, value) {
+        (ElementCategoryId::Checkbox, FieldVal::Str(s)) => match s.to_lowercase().as_str() {
+            "true" => Some(Value::Bool(true)),
+            "false" | "" => Some(Value::Bool(false)),
+            _ => None,
+        },
+        (
+            ElementCategoryId::Number | ElementCategoryId::Date | ElementCategoryId::URL,
+            FieldVal::Str(s),
+        ) if s.is_empty() => Some(Value::Null),
+        _ => None,
+    }
+}
+
+/// A copy of the item (Entry isn't Clone)
+pub(crate) fn copy_entry(item: &Entry) -> Result<Entry, Error> {
+    Ok(serde_json::from_value(serde_json::to_value(item)?)?)
+}
+
+/// Renders field values for display: choice ids, person ids, and references
+/// are replaced with their names.
+#[derive(Default)]
+pub(crate) struct ValueFormatter {
+    /// person id or uuid -> display name
+    users: HashMap<String, String>,
+}
+
+impl ValueFormatter {
+    /// Load workspace users, for display of person fields.
+    /// Backups don't include users, so in offline mode, persons are shown by id.
+    pub(crate) async fn new(ws_id: ID) -> Result<Self, Error> {
+        let mut users = HashMap::new();
+        if offline::enabled() {
+            return Ok(ValueFormatter { users });
+        }
+        let api = zenkit::get_api()?;
+        for user in retry("get_users_raw", || api.get_users_raw(ws_id))
+            .await?
+            .iter()
+        {
+            users.insert(user.id.to_string(), user.display_name.clone());
+            users.insert(user.uuid.clone(), user.display_name.clone());
+        }
+        Ok(ValueFormatter { users })
+    }
+
+    /// Display string of the field's value in the item, or None if the field is empty.
+    /// Multiple values are separated by ", ".
+    pub(crate) fn display(&self, item: &Entry, field: &Element) -> Option<String> {
+        let values = self.display_values(item, field);
+        if values.is_empty() {
+            None
+        } else {
+            Some(values.join(", "))
+        }
+    }
+
+    /// Returns true if the field has the value `expected`: its display value, or (for
+    /// multi-value fields) one of its values. Choices, persons, and references also
+    /// match by id or uuid. An empty `expected` value matches an empty field.
+    pub(crate) fn has_value(&self, item: &Entry, field: &Element, expected: &str) -> bool {
+        let values = self.display_values(item, field);
+        if expected.is_empty() {
+            return values.is_empty();
+        }
+        values.iter().any(|v| v == expected)
+            || raw_value(item, field)
+                .map(|raw| names(raw, |_| None).iter().any(|v| v == expected))
+                .unwrap_or(false)
+    }
+
+    /// Display strings of the field's values in the item. Single-value fields
+    /// return at most one value.
+    pub(crate) fn display_values(&self, item: &Entry, field: &Element) -> Vec<String> {
+        let value = match raw_value(item, field) {
+            Some(value) => value,
+            None => return Vec::new(),
+        };
+        match field.element_category {
+            ElementCategoryId::Categories => {
+                let choices = field
+                    .element_data
+                    .predefined_categories
+                    .as_deref()
+                    .unwrap_or_default();
+                names(value, |id| {
+                    choices
+                        .iter()
+                        .find(|c| c.id.to_string() == id)
+                        .map(|c| c.name.clone())
+                })
+            }
+            ElementCategoryId::Persons => names(value, |id| self.users.get(id).cloned()),
+            ElementCategoryId::References => {
+                // the sort key holds the referenced items' display strings
+                let refs = item
+                    .fields
+                    .get(&format!("{}_references_sort", field.uuid))
+                    .and_then(|v| v.as_array());
+                names(value, |uuid| {
+                    refs?
+                        .iter()
+                        .find(|r| r.get("uuid").and_then(|v| v.as_str()) == Some(uuid))?
+                        .get("displayString")
+                        .and_then(|v| v.as_str())
+                        .map(|s| s.to_string())
+                })
+            }
+            ElementCategoryId::Files => names(value, |_| None),
+            _ => vec![scalar(value)],
+        }
+    }
+}
+
+/// Json scalar as a string, without quotes
+fn scalar(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        // file descriptions
+        Value::Object(obj) => obj
+            .get("fileName")
+            .map(scalar)
+            .unwrap_or_else(|| value.to_string()),
+        v => v.to_string(),
+    }
+}
+
+/// Names of array values. Values without a name are shown as-is.
+fn names<F: Fn(&str) -> Option<String>>(value: &Value, name: F) -> Vec<String> {
+    let values = match value {
+        Value::Array(values) => values.iter().collect(),
+        v => vec![v],
+    };
+    values
+        .into_iter()
+        .map(|v| {
+            let s = scalar(v);
+            name(&s).unwrap_or(s)
+        })
+        .collect()
+}
+
+/// Resolve values given on the command line, or read from a file, for an update:
+/// field names (or ids or aliases) are replaced with the fields' names, and
+/// references (titles), persons (emails), and dates with the values stored.
+/// Used by every command that sets values, so they accept the same values.
+pub(crate) async fn resolve_values(
+    rest: &RestClient,
+    ws_id: ID,
+    list_info: &ListInfo,
+    values: &mut [(String, FieldVal, UpdateAction)],
+) -> Result<(), Error> {
+    fuzzy::resolve_field_names(list_info, values)?;
+    resolve_references(rest, list_info, values).await?;
+    resolve_person_emails(rest, ws_id, list_info, values).await?;
+    resolve_dates(list_info, values)
+}
+
+/// Convert values of references fields given as item ids or titles to the uuids
+/// of the referenced items, which are looked up in the field's target list.
+/// Values that are already uuids are unchanged. A title matching more than one
+/// item is an error, which lists the matching items.
+pub(crate) async fn resolve_references(
+    rest: &RestClient,
+    list_info: &ListInfo,
+    values: &mut [(String, FieldVal, UpdateAction)],
+) -> Result<(), Error> {
+    let mut raw_fields: Option<Value> = None;
+    // target list uuid -> items
+    let mut targets: HashMap<String, Vec<Entry>> = HashMap::new();
+    for (name, value, _) in values.iter_mut() {
+        let field = fuzzy::get_field(list_info, name)?;
+        if field.element_category != ElementCategoryId::References {
+            continue;
+        }
+        let refs = match value {
+            FieldVal::Str(s) => vec![s.clone()],
+            FieldVal::ArrStr(refs) => refs.clone(),
+            // ids, or not a reference
+            _ => continue,
+        };
+        if refs.iter().all(|r| r.is_empty() || is_uuid(r)) {
+            continue;
+        }
+        if raw_fields.is_none() {
+            // the target list is only in the raw field definition
+            raw_fields = Some(
+                rest.get(&format!("lists/{}/elements", list_info.get_id()))
+                    .await?,
+            );
+        }
+        let target = raw_fields
+            .as_ref()
+            .and_then(|v| v.as_array())
+            .and_then(|fields| {
+                fields
+                    .iter()
+                    .find(|f| f.get("uuid").and_then(|v| v.as_str()) == Some(&field.uuid))
+            })
+            .and_then(|f| f.pointer("/elementData/childListUUID"))
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| {
+                Error::NotFound(format!("Target list of field '{}' not found", field.name))
+            })?
+            .to_string();
+        if !targets.contains_key(&target) {
+            let items = get_all_items(&target, false).await?;
+            targets.insert(target.clone(), items);
+        }
+        let items = &targets[&target];
+        let resolved = refs
+            .iter()
+            .map(|r| resolve_reference(items, r, &field.name))
+            .collect::<Result<Vec<String>, Error>>()?;
+        *value = match value {
+            FieldVal::Str(_) => FieldVal::Str(resolved.into_iter().next().unwrap_or_default()),
+            _ => FieldVal::ArrStr(resolved),
+        };
+    }
+    Ok(())
+}
+
+/// Uuid of the item with the uuid, id, or title (display string). Titles are matched
+/// exactly, or if there is no exact match, ignoring case.
+fn resolve_reference(items: &[Entry], value: &str, field_name: &str) -> Result<String, Error> {
+    if value.is_empty() || is_uuid(value) {
+        return Ok(value.to_string());
+    }
+    if let Ok(id) = value.parse::<ID>() {
+        if let Some(item) = items.iter().find(|i| i.id == id) {
+            return Ok(item.uuid.clone());
+        }
+    }
+    let mut matches: Vec<&Entry> = items.iter().filter(|i| i.display_string == value).collect();
+    if matches.is_empty() {
+        let lower = value.to_lowercase();
+        matches = items
+            .iter()
+            .filter(|i| i.display_string.to_lowercase() == lower)
+            .collect();
+    }
+    match matches.as_slice() {
+        [item] => Ok(item.uuid.clone()),
+        [] => Err(Error::NotFound(format!(
+            "Field '{}': no item '{}' in the referenced list",
+            field_name, value
+        ))),
+        _ => Err(Error::Invalid(format!(
+            "Field '{}': '{}' matches more than one item. Use the item id instead:\n{}",
+            field_name,
+            value,
+            matches
+                .iter()
+                .map(|i| format!("{}\t{}", i.id, i.display_string))
+                .collect::<Vec<_>>()
+                .join("\n")
+        ))),
+    }
+}
+
+/// Convert values of persons fields given as email addresses to the uuids of
+/// the workspace users with those addresses (ignoring case). Other values
+/// (uuids and display names) are unchanged.
+pub(crate) async fn resolve_person_emails(
+    rest: &RestClient,
+    ws_id: ID,
+    list_info: &ListInfo,
+    values: &mut [(String, FieldVal, UpdateAction)],
+) -> Result<(), Error> {
+    // email -> user uuid, loaded when first needed
+    let mut emails: Option<HashMap<String, String>> = None;
+    for (name, value, _) in values.iter_mut() {
+        let field = fuzzy::get_field(list_info, name)?;
+        if field.element_category != ElementCategoryId::Persons {
+            continue;
+        }
+        let persons = match value {
+            FieldVal::Str(s) => std::slice::from_mut(s),
+            FieldVal::ArrStr(persons) => persons.as_mut_slice(),
+            // user ids, or not a person
+            _ => continue,
+        };
+        for person in persons.iter_mut().filter(|p| p.contains('@')) {
+            if emails.is_none() {
+                let users = rest.get(&format!("workspaces/{}/users", ws_id)).await?;
+                let mut map = HashMap::new();
+                for user in users.as_array().map(|v| v.as_slice()).unwrap_or_default() {
+                    if let Some(uuid) = user.get("uuid").and_then(|v| v.as_str()) {
+                        for email in user_emails(user) {
+                            map.insert(email.to_lowercase(), uuid.to_string());
+                        }
+                    }
+                }
+                emails = Some(map);
+            }
+            match emails.as_ref().and_then(|m| m.get(&person.to_lowercase())) {
+                Some(uuid) => *person = uuid.clone(),
+                None => {
+                    return Err(Error::NotFound(format!(
+                        "Field '{}': no user with email '{}' in the workspace",
+                        field.name, person
+                    )))
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Email addresses of a user, from the user's json description
+pub(crate) fn user_emails(user: &Value) -> Vec<&str> {
+    let mut emails: Vec<&str> = user
+        .get("email")
+        .and_then(|v| v.as_str())
+        .into_iter()
+        .collect();
+    for email in user
+        .get("emails")
+        .and_then(|v| v.as_array())
+        .map(|v| v.as_slice())
+        .unwrap_or_default()
+    {
+        if let Some(email) = email.get("email").and_then(|v| v.as_str()) {
+            if !emails.contains(&email) {
+                emails.push(email);
+            }
+        }
+    }
+    emails
+}
+
+/// Returns true if the value has the form of a uuid (8-4-4-4-12 hex digits)
+pub(crate) fn is_uuid(s: &str) -> bool {
+    s.len() == 36
+        && s.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::testutil::{choice_field, field, item};
+    use serde_json::json;
+
+    #[test]
+    fn display_values() {
+        let mut formatter = ValueFormatter::default();
+        formatter.users.insert("7".into(), "Ann".into());
+        let fields = vec![
+            field(1, "Title", 1),
+            field(2, "Points", 2),
+            choice_field(3, "Tags", &[(1, "red"), (2, "blue")]),
+            field(4, "Due", 4),
+            field(5, "Owner", ElementCategoryId::Persons as u8),
+            field(6, "Project", ElementCategoryId::References as u8),
+        ];
+        let item = item(
+            1,
+            "Write tests",
+            json!({
+                "field-2_number": 3,
+                "field-3_categories": [2, 1],
+                "field-4_date": null,
+                "field-5_persons": [7, 8],
+                "field-6_references": ["item-9"],
+                "field-6_references_sort": [{"uuid": "item-9", "displayString": "Launch"}],
+            }),
+        );
+        let display: Vec<Option<String>> =
+            fields.iter().map(|f| formatter.display(&item, f)).collect();
+        assert_eq!(
+            display,
+            vec![
+                Some("Write tests".to_string()),
+                Some("3".to_string()),
+                Some("blue, red".to_string()),
+                None,
+                Some("Ann, 8".to_string()),
+                Some("Launch".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn filter_values() {
+        let formatter = ValueFormatter::default();
+        let tags = choice_field(3, "Tags", &[(1, "red"), (2, "blue")]);
+        let notes = field(4, "Notes", 1);
+        let item = item(1, "Write tests", json!({"field-3_categories": [2, 1]}));
+        // by name or id, matching any of several values
+        assert!(formatter.has_value(&item, &tags, "red"));
+        assert!(formatter.has_value(&item, &tags, "2"));
+        assert!(!formatter.has_value(&item, &tags, "green"));
+        assert!(!formatter.has_value(&item, &tags, "red, blue"));
+        // empty value matches an empty field
+        assert!(formatter.has_value(&item, &notes, ""));
+        assert!(!formatter.has_value(&item, &tags, ""));
+    }
+
+    #[test]
+    fn reference_by_id_or_title() {
+        let items = vec![
+            item(1, "Launch", json!({})),
+            item(2, "Design", json!({})),
+            item(3, "Review", json!({})),
+            item(4, "Review", json!({})),
+        ];
+        let uuid = "0b7d7a3e-9e8c-4a39-8d6a-3c2f1e5b9a10";
+        assert!(is_uuid(uuid));
+        assert!(!is_uuid("item-1"));
+        assert_eq!(resolve_reference(&items, uuid, "Project").unwrap(), uuid);
+        assert_eq!(resolve_reference(&items, "2", "Project").unwrap(), "item-2");
+        assert_eq!(
+            resolve_reference(&items, "Launch", "Project").unwrap(),
+            "item-1"
+        );
+        assert_eq!(
+            resolve_reference(&items, "design", "Project").unwrap(),
+            "item-2"
+        );
+        assert!(matches!(
+            resolve_reference(&items, "Review", "Project"),
+            Err(Error::Invalid(_))
+        ));
+        assert!(matches!(
+            resolve_reference(&items, "Deploy", "Project"),
+            Err(Error::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn emails_of_user() {
+        let user = json!({
+            "email": "ann@example.com",
+            "emails": [{ "email": "ann@example.com" }, { "email": "ann@work.example" }],
+        });
+        assert_eq!(
+            user_emails(&user),
+            vec!["ann@example.com", "ann@work.example"]
+        );
+        let user = json!({ "emails": [{ "email": "bob@example.com" }, { "verified": true }] });
+        assert_eq!(user_emails(&user), vec!["bob@example.com"]);
+        assert!(user_emails(&json!({ "displayname": "Carol" })).is_empty());
+    }
+}
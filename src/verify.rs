@@ -0,0 +1,400 @@
+//! Check a backup folder for missing or corrupt files
+use crate::{backup::sha256_hex, BackupVerifyOpt, Error};
+use serde_json::Value;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    path::{Path, PathBuf},
+    result::Result,
+};
+
+/// Check the backups in the folder (and its workspace subfolders):
+///  - every json file is valid json
+///  - the list, fields, and items files of each list in each summary are present,
+///    and the list file is for that list
+///  - the items file has the number of items recorded in the summary that wrote it
+///  - files in each files manifest are present, with the recorded size and checksum
+///  - there is no unfinished backup (checkpoint file)
+///
+/// Each problem is printed on a line. Returns an error if any problems were found.
+pub(crate) fn verify_backup(opt: &BackupVerifyOpt) -> Result<(), Error> {
+    let dir = Path::new(&opt.dir);
+    let verifier = verify(dir)?;
+    println!(
+        "{} files checked, {} problems",
+        verifier.checked,
+        verifier.problems.len()
+    );
+    if !verifier.problems.is_empty() {
+        return Err(Error::Message(format!(
+            "Backup in {} has {} problems",
+            dir.display(),
+            verifier.problems.len()
+        )));
+    }
+    Ok(())
+}
+
+/// Check the backups in the folder, printing each problem
+fn verify(dir: &Path) -> Result<Verifier, Error> {
+    let mut verifier = Verifier::default();
+    verifier.parse_all(dir)?;
+    let folders: Vec<PathBuf> = verifier
+        .summaries
+        .keys()
+        .filter_map(|p| p.parent())
+        .map(|p| p.to_path_buf())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    if folders.is_empty() {
+        return Err(Error::NotFound(format!(
+            "No backup summary found in {}",
+            dir.display()
+        )));
+    }
+    for folder in folders.iter() {
+        verifier.check_folder(folder)?;
+    }
+    Ok(verifier)
+}
+
+#[derive(Default)]
+struct Verifier {
+    /// number of files checked
+    checked: usize,
+    /// problems found
+    problems: Vec<String>,
+    /// valid json files: path -> array length, for arrays
+    json: BTreeMap<PathBuf, Option<usize>>,
+    /// summary files (summary_<tstamp>.json): path -> (tstamp, contents)
+    summaries: BTreeMap<PathBuf, (u64, Value)>,
+}
+
+impl Verifier {
+    fn problem<T: Display>(&mut self, path: &Path, message: T) {
+        let problem = format!("{}: {}", path.display(), message);
+        println!("{}", problem);
+        self.problems.push(problem);
+    }
+
+    /// Parse the json files in the folder and its subfolders, except attachments
+    /// (files/) and staging folders of archives (.backup_<tstamp>)
+    fn parse_all(&mut self, dir: &Path) -> Result<(), Error> {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.file_type()?.is_dir() {
+                if name != "files" && !name.starts_with('.') {
+                    self.parse_all(&path)?;
+                }
+                continue;
+            }
+            if !name.ends_with(".json") {
+                continue;
+            }
+            self.checked += 1;
+            let value: Value = match std::fs::read(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|data| serde_json::from_slice(&data).map_err(|e| e.to_string()))
+            {
+                Ok(value) => value,
+                Err(e) => {
+                    self.problem(&path, format!("invalid json: {}", e));
+                    continue;
+                }
+            };
+            self.json
+                .insert(path.clone(), value.as_array().map(|a| a.len()));
+            if let Some(tstamp) = name
+                .strip_prefix("summary_")
+                .and_then(|s| s.strip_suffix(".json"))
+                .and_then(|ts| ts.parse::<u64>().ok())
+            {
+                self.summaries.insert(path, (tstamp, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the summaries, list files, and manifests in one backup folder
+    fn check_folder(&mut self, folder: &Path) -> Result<(), Error> {
+        let checkpoint = folder.join(crate::backup::CHECKPOINT_FILE);
+        if checkpoint.exists() {
+            self.problem(
+                &checkpoint,
+                "unfinished backup: run backup with --resume to complete it",
+            );
+        }
+        let mut summaries: Vec<(u64, Value)> = self
+            .summaries
+            .iter()
+            .filter(|(p, _)| p.parent() == Some(folder))
+            .map(|(_, s)| s.clone())
+            .collect();
+        summaries.sort_by_key(|(tstamp, _)| *tstamp);
+
+        // items file -> item count in the most recent summary that wrote it
+        let mut counts: BTreeMap<PathBuf, Option<u64>> = BTreeMap::new();
+        for (tstamp, summary) in summaries.iter() {
+            let summary_path = folder.join(format!("summary_{}.json", tstamp));
+            for ws in array(summary, "workspaces") {
+                let uuid = ws.get("uuid").and_then(|v| v.as_str()).unwrap_or_default();
+                if !self
+                    .summaries
+                    .contains_key(&folder.join(uuid).join(format!("summary_{}.json", tstamp)))
+                {
+                    self.problem(
+                        &summary_path,
+                        format!("missing summary for workspace {}", uuid),
+                    );
+                }
+            }
+            let since = summary.get("since").and_then(|v| v.as_u64());
+            for list in array(summary, "lists") {
+                let uuid = list
+                    .get("uuid")
+                    .and_then(|v| v.as_str())
+                    .unwrap_or_default();
+                self.check_list_file(folder, uuid);
+                self.require_array(&folder.join(format!("{}_fields.json", uuid)));
+                let items_path = match since {
+                    Some(since) => folder.join(format!("{}_items_since_{}.json", uuid, since)),
+                    None => folder.join(format!("{}_items.json", uuid)),
+                };
+                self.require_array(&items_path);
+                counts.insert(items_path, list.get("items").and_then(|v| v.as_u64()));
+            }
+        }
+        for (path, expected) in counts.into_iter() {
+            // summaries written before item counts were recorded have none
+            if let (Some(expected), Some(Some(found))) = (expected, self.json.get(&path)) {
+                if *found as u64 != expected {
+                    let found = *found;
+                    self.problem(
+                        &path,
+                        format!("{} items, but the summary recorded {}", found, expected),
+                    );
+                }
+            }
+        }
+
+        let manifests: Vec<PathBuf> = self
+            .json
+            .keys()
+            .filter(|p| p.parent() == Some(folder) && p.to_string_lossy().ends_with("_files.json"))
+            .cloned()
+            .collect();
+        for manifest in manifests.iter() {
+            self.check_manifest(folder, manifest)?;
+        }
+        Ok(())
+    }
+
+    /// The list file must be present, and be the list with the uuid
+    fn check_list_file(&mut self, folder: &Path, uuid: &str) {
+        let path = folder.join(format!("{}_list.json", uuid));
+        if !self.require(&path) {
+            return;
+        }
+        let list_uuid = std::fs::read(&path)
+            .ok()
+            .and_then(|data| serde_json::from_slice::<Value>(&data).ok())
+            .and_then(|list| list.get("uuid").and_then(|v| v.as_str()).map(String::from));
+        if list_uuid.as_deref() != Some(uuid) {
+            self.problem(&path, format!("not the list file of list {}", uuid));
+        }
+    }
+
+    /// The file must be present and valid json. Returns false if it isn't
+    fn require(&mut self, path: &Path) -> bool {
+        if self.json.contains_key(path) {
+            true
+        } else {
+            // invalid json was already reported
+            if !path.exists() {
+                self.problem(path, "missing");
+            }
+            false
+        }
+    }
+
+    /// The file must be present, and contain a json array
+    fn require_array(&mut self, path: &Path) {
+        if self.require(path) && self.json.get(path) == Some(&None) {
+            self.problem(path, "expected a json array");
+        }
+    }
+
+    /// Files in the manifest must be present, with matching size and checksum
+    fn check_manifest(&mut self, folder: &Path, manifest: &Path) -> Result<(), Error> {
+        let entries: Value = serde_json::from_slice(&std::fs::read(manifest)?).map_err(|e| {
+            Error::Invalid(format!(
+                "Invalid files manifest {}: {}",
+                manifest.display(),
+                e
+            ))
+        })?;
+        let entries = match entries.as_object() {
+            Some(entries) => entries,
+            None => {
+                self.problem(manifest, "expected a json object");
+                return Ok(());
+            }
+        };
+        for (rel_path, entry) in entries.iter() {
+            let path = folder.join(rel_path);
+            self.checked += 1;
+            let data = match std::fs::read(&path) {
+                Ok(data) => data,
+                Err(_) => {
+                    self.problem(&path, "missing (listed in files manifest)");
+                    continue;
+                }
+            };
+            let size = entry.get("size").and_then(|v| v.as_u64());
+            if size != Some(data.len() as u64) {
+                self.problem(
+                    &path,
+                    format!(
+                        "size {} doesn't match files manifest ({})",
+                        data.len(),
+                        size.map(|s| s.to_string()).unwrap_or_default()
+                    ),
+                );
+            } else if entry.get("sha256").and_then(|v| v.as_str())
+                != Some(sha256_hex(&data).as_str())
+            {
+                self.problem(&path, "checksum doesn't match files manifest");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Elements of the array under `key`, or none
+fn array<'v>(value: &'v Value, key: &str) -> &'v [Value] {
+    value
+        .get(key)
+        .and_then(|v| v.as_array())
+        .map(|a| a.as_slice())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    /// A backup folder with one list of two items, and one attachment
+    fn backup_folder(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("zk_verify_{}_{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("files/item-1")).unwrap();
+        let files = json!({
+            "files/item-1/a.txt": { "size": 5, "sha256": sha256_hex(b"hello") },
+        });
+        for (name, value) in [
+            (
+                "summary_1000.json",
+                json!({ "tstamp": 1000, "lists": [{ "uuid": "list-1", "items": 2 }] }),
+            ),
+            ("list-1_list.json", json!({ "uuid": "list-1" })),
+            ("list-1_fields.json", json!([])),
+            ("list-1_items.json", json!([{ "id": 1 }, { "id": 2 }])),
+            ("list-1_files.json", files),
+        ]
+        .iter()
+        {
+            std::fs::write(dir.join(name), value.to_string()).unwrap();
+        }
+        std::fs::write(dir.join("files/item-1/a.txt"), "hello").unwrap();
+        dir
+    }
+
+    /// Problems found in the folder, without the folder path. Removes the folder.
+    fn problems(dir: &Path) -> Vec<String> {
+        let verifier = verify(dir).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        let prefix = format!("{}/", dir.display());
+        verifier
+            .problems
+            .iter()
+            .map(|p| p.trim_start_matches(&prefix).to_string())
+            .collect()
+    }
+
+    #[test]
+    fn valid_backup() {
+        let dir = backup_folder("valid");
+        let verifier = verify(&dir).unwrap();
+        // 5 json files and the attachment
+        assert_eq!(verifier.checked, 6);
+        assert!(verifier.problems.is_empty());
+        assert!(verify_backup(&BackupVerifyOpt {
+            dir: dir.to_string_lossy().to_string()
+        })
+        .is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn missing_list_file() {
+        let dir = backup_folder("missing");
+        std::fs::remove_file(dir.join("list-1_list.json")).unwrap();
+        assert!(verify_backup(&BackupVerifyOpt {
+            dir: dir.to_string_lossy().to_string()
+        })
+        .is_err());
+        assert_eq!(problems(&dir), vec!["list-1_list.json: missing"]);
+    }
+
+    #[test]
+    fn item_count() {
+        let dir = backup_folder("count");
+        std::fs::write(dir.join("list-1_items.json"), "[{}]").unwrap();
+        assert_eq!(
+            problems(&dir),
+            vec!["list-1_items.json: 1 items, but the summary recorded 2"]
+        );
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let dir = backup_folder("checksum");
+        std::fs::write(dir.join("files/item-1/a.txt"), "HELLO").unwrap();
+        assert_eq!(
+            problems(&dir),
+            vec!["files/item-1/a.txt: checksum doesn't match files manifest"]
+        );
+    }
+
+    #[test]
+    fn invalid_files() {
+        let dir = backup_folder("invalid");
+        std::fs::write(dir.join("list-1_fields.json"), "{").unwrap();
+        std::fs::write(dir.join("files/item-1/a.txt"), "hello!").unwrap();
+        std::fs::write(dir.join(crate::backup::CHECKPOINT_FILE), "{}").unwrap();
+        let found = problems(&dir);
+        assert_eq!(found.len(), 3);
+        assert!(found[0].starts_with("list-1_fields.json: invalid json"));
+        assert_eq!(
+            found[1],
+            "backup_checkpoint.json: unfinished backup: run backup with --resume to complete it"
+        );
+        assert_eq!(
+            found[2],
+            "files/item-1/a.txt: size 6 doesn't match files manifest (5)"
+        );
+    }
+
+    #[test]
+    fn no_summary() {
+        let dir = std::env::temp_dir().join(format!("zk_verify_empty_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(matches!(verify(&dir), Err(Error::NotFound(_))));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,234 @@
+//! Poll a list for changes
+use crate::{backup::get_all_items, cache, listen::run_command, Error, JsonFormat, WatchOpt};
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    result::Result,
+};
+use zenkit::types::{Entry, ZKObjectID, ID};
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl std::fmt::Display for ChangeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Deleted => "deleted",
+        })
+    }
+}
+
+/// A change to a list item, detected by comparing snapshots
+#[derive(Debug, Serialize)]
+pub(crate) struct ChangeEvent {
+    pub event: ChangeKind,
+    pub list: String,
+    pub item_id: ID,
+    pub item_uuid: String,
+    pub display_string: String,
+    /// names of changed fields, for updates
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub changed_fields: Vec<String>,
+}
+
+/// Poll the list every `opt.interval`, and emit an event for each created, updated,
+/// or deleted item. The first poll takes the initial snapshot and emits no events.
+pub(crate) async fn watch_list(ws_id: ID, opt: &WatchOpt) -> Result<(), Error> {
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    let list_name = list_info.list().name.clone();
+    let list_uuid = list_info.list().uuid.clone();
+    let field_names: HashMap<&str, &str> = list_info
+        .fields()
+        .iter()
+        .map(|f| (f.uuid.as_str(), f.name.as_str()))
+        .collect();
+
+    let mut snapshot = to_snapshot(get_all_items(&list_uuid, false).await?);
+    loop {
+        tokio::time::sleep(opt.interval).await;
+        let current = match get_all_items(&list_uuid, false).await {
+            Ok(items) => to_snapshot(items),
+            Err(e) => {
+                // keep polling after transient errors
+                eprintln!("Error polling list {}: {}", &list_name, e.summary());
+                continue;
+            }
+        };
+        for event in diff_snapshots(&snapshot, &current, &list_name, &field_names) {
+            emit(&event, opt.exec.as_deref(), opt.output)?;
+        }
+        snapshot = current;
+    }
+}
+
+fn to_snapshot(items: Vec<Entry>) -> HashMap<String, Entry> {
+    items
+        .into_iter()
+        .map(|item| (item.uuid.clone(), item))
+        .collect()
+}
+
+/// Compare snapshots (maps of item uuid to item), returning change events
+/// in order of item id
+pub(crate) fn diff_snapshots(
+    prev: &HashMap<String, Entry>,
+    current: &HashMap<String, Entry>,
+    list_name: &str,
+    field_names: &HashMap<&str, &str>,
+) -> Vec<ChangeEvent> {
+    let mut events = Vec::new();
+    for (uuid, item) in current.iter() {
+        let (kind, changed_fields) = match prev.get(uuid) {
+            None => (ChangeKind::Created, Vec::new()),
+            Some(old) => {
+                let changed = changed_fields(old, item, field_names);
+                if changed.is_empty() && old.display_string == item.display_string {
+                    continue;
+                }
+                (ChangeKind::Updated, changed)
+            }
+        };
+        events.push(change_event(kind, list_name, item, changed_fields));
+    }
+    for (uuid, item) in prev.iter() {
+        if !current.contains_key(uuid) {
+            events.push(change_event(
+                ChangeKind::Deleted,
+                list_name,
+                item,
+                Vec::new(),
+            ));
+        }
+    }
+    events.sort_by_key(|e| e.item_id);
+    events
+}
+
+pub(crate) fn change_event(
+    event: ChangeKind,
+    list_name: &str,
+    item: &Entry,
+    changed_fields: Vec<String>,
+) -> ChangeEvent {
+    ChangeEvent {
+        event,
+        list: list_name.to_string(),
+        item_id: item.get_id(),
+        item_uuid: item.uuid.clone(),
+        display_string: item.display_string.clone(),
+        changed_fields,
+    }
+}
+
+/// Names of business fields whose values differ between the two versions of the item.
+/// Item values are keyed "<field-uuid>_<suffix>"; keys that don't belong to
+/// a known field are ignored.
+pub(crate) fn changed_fields(
+    old: &Entry,
+    new: &Entry,
+    field_names: &HashMap<&str, &str>,
+) -> Vec<String> {
+    let mut changed = BTreeSet::new();
+    for key in old.fields.keys().chain(new.fields.keys()) {
+        if old.fields.get(key) != new.fields.get(key) {
+            let field_uuid = key.split('_').next().unwrap_or_default();
+            if let Some(name) = field_names.get(field_uuid) {
+                changed.insert(name.to_string());
+            }
+        }
+    }
+    changed.into_iter().collect()
+}
+
+/// Print event as json (by default, one line per event), or if `exec` is set,
+/// run the command with the json event on stdin. A failed command is logged,
+/// and the watch continues.
+fn emit(event: &ChangeEvent, exec: Option<&str>, format: JsonFormat) -> Result<(), Error> {
+    let json = format.to_json(event)?;
+    match exec {
+        None => println!("{}", json),
+        Some(cmd) => {
+            let label = format!("{} item {}", event.event, event.item_id);
+            run_command(cmd, &json, &[], &label);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::item;
+    use serde_json::json;
+
+    #[test]
+    fn diff_snapshots_sorted_by_item_id() {
+        let prev = to_snapshot(vec![
+            item(3, "c", json!({})),
+            item(1, "a", json!({})),
+            item(2, "b", json!({ "field-2_text": "old" })),
+        ]);
+        let current = to_snapshot(vec![
+            item(4, "d", json!({})),
+            item(3, "c", json!({})),
+            item(2, "b", json!({ "field-2_text": "new" })),
+        ]);
+        let field_names: HashMap<&str, &str> = vec![("field-2", "Notes")].into_iter().collect();
+        let events: Vec<(String, ID, Vec<String>)> =
+            diff_snapshots(&prev, &current, "Tasks", &field_names)
+                .into_iter()
+                .map(|e| (e.event.to_string(), e.item_id, e.changed_fields))
+                .collect();
+        assert_eq!(
+            events,
+            vec![
+                ("deleted".to_string(), 1, vec![]),
+                ("updated".to_string(), 2, vec!["Notes".to_string()]),
+                ("created".to_string(), 4, vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn fields_changed() {
+        let field_names: HashMap<&str, &str> = vec![("field-2", "Notes"), ("field-3", "Due")]
+            .into_iter()
+            .collect();
+        let old = item(
+            1,
+            "a",
+            json!({ "field-2_text": "old", "field-3_date": "2021-01-01", "other": 1 }),
+        );
+        let new = item(
+            1,
+            "a",
+            json!({ "field-2_text": "new", "field-2_searchText": "new", "other": 2 }),
+        );
+        // a removed value is a change, and keys of unknown fields are ignored
+        assert_eq!(
+            changed_fields(&old, &new, &field_names),
+            vec!["Due", "Notes"]
+        );
+        assert!(changed_fields(&old, &old, &field_names).is_empty());
+
+        // a changed title is an update without changed fields
+        let prev = to_snapshot(vec![old]);
+        let current = to_snapshot(vec![item(
+            1,
+            "b",
+            json!({ "field-2_text": "old", "field-3_date": "2021-01-01", "other": 1 }),
+        )]);
+        let events = diff_snapshots(&prev, &current, "Tasks", &field_names);
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0].event, ChangeKind::Updated));
+        assert!(events[0].changed_fields.is_empty());
+        assert_eq!(events[0].display_string, "b");
+    }
+}
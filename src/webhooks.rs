@@ -0,0 +1,414 @@
+//! Show registered webhooks
+use crate::{
+    cache, dryrun, http,
+    listen::{load_secret, signature, SIGNATURE_HEADER},
+    rest::RestClient,
+    retry::retry,
+    style::{Cell, Style, Table},
+    webhook_trigger_type, Error, ListWebhooksOpt, OutputFormat, TestWebhookOpt,
+};
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{collections::HashMap, result::Result, sync::Arc, time::Instant};
+use zenkit::types::{List, Webhook, WebhookTriggerType, Workspace, ID};
+
+/// A webhook, with the workspace, list, item, and field it's restricted to
+/// shown by name
+#[derive(Debug, Serialize)]
+pub(crate) struct WebhookInfo {
+    pub id: ID,
+    pub uuid: String,
+    #[serde(rename = "type")]
+    pub trigger_type: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub list: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub item: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    pub url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<String>,
+}
+
+impl WebhookInfo {
+    /// What the webhook is restricted to: "item Name (list List)", "list List",
+    /// "workspace Name", or "-"
+    fn scope(&self) -> String {
+        let list = self.list.as_deref().unwrap_or_default();
+        match (&self.workspace, &self.item, &self.field) {
+            (_, Some(item), _) => format!("item {} (list {})", item, list),
+            (_, None, Some(field)) => format!("field {} (list {})", field, list),
+            _ if self.list.is_some() => format!("list {}", list),
+            (Some(ws), _, _) => format!("workspace {}", ws),
+            _ => "-".to_string(),
+        }
+    }
+}
+
+/// Print the webhooks of the user, optionally only those for the list (name, id, or uuid),
+/// of the trigger type, or with a url containing the text. Ids of workspaces, lists,
+/// items, and fields are shown as names, where they can be found.
+/// Text output has one tab-separated line per webhook: id, type, scope, url, and created date.
+pub(crate) async fn list_webhooks(rest: &RestClient, opt: &ListWebhooksOpt) -> Result<(), Error> {
+    let api = zenkit::get_api()?;
+    let workspaces: Vec<Arc<Workspace>> = retry("get_all_workspaces_and_lists", || {
+        api.get_all_workspaces_and_lists()
+    })
+    .await?;
+    let list_filter: Option<ID> = match opt.list {
+        Some(ref name) => Some(find_list(&workspaces, name)?.1.id),
+        None => None,
+    };
+    let trigger_filter = opt.trigger_type.as_ref().map(webhook_trigger_type);
+    let hooks: Vec<Webhook> = retry("get_webhooks", || api.get_webhooks())
+        .await?
+        .into_iter()
+        .filter(|w| list_filter.is_none() || w.list_id == list_filter)
+        .filter(|w| trigger_filter.is_none() || trigger_filter.as_ref() == Some(&w.trigger_type))
+        .filter(|w| match opt.url_contains {
+            Some(ref text) => w.url.contains(text.as_str()),
+            None => true,
+        })
+        .collect();
+
+    // created date isn't included in the zenkit crate's Webhook
+    let created: HashMap<u64, String> = match rest.get("users/me/webhooks").await? {
+        serde_json::Value::Array(values) => values
+            .iter()
+            .filter_map(|v| {
+                Some((
+                    v.get("id")?.as_u64()?,
+                    v.get("created_at")?.as_str()?.to_string(),
+                ))
+            })
+            .collect(),
+        _ => HashMap::new(),
+    };
+    let mut infos = Vec::new();
+    for hook in hooks.iter() {
+        infos.push(webhook_info(hook, &workspaces, created.get(&hook.id).cloned()).await?);
+    }
+    match opt.output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&infos)?),
+        OutputFormat::Text => {
+            let mut table = Table::new(&["id", "type", "scope", "url", "created"]);
+            for info in infos.iter() {
+                table.push(vec![
+                    Cell::from(info.id.to_string()),
+                    Cell::from(info.trigger_type),
+                    Cell::from(info.scope()),
+                    Cell::from(info.url.as_str()),
+                    Cell::from(
+                        info.created_at
+                            .as_deref()
+                            .and_then(|d| d.get(..10))
+                            .unwrap_or("-"),
+                    ),
+                ]);
+            }
+            table.print();
+        }
+    }
+    Ok(())
+}
+
+/// Print the webhook: id, uuid, type, what it's restricted to (by name), and url
+pub(crate) async fn print_webhook(hook: &Webhook) -> Result<(), Error> {
+    let api = zenkit::get_api()?;
+    let workspaces: Vec<Arc<Workspace>> = retry("get_all_workspaces_and_lists", || {
+        api.get_all_workspaces_and_lists()
+    })
+    .await?;
+    let info = webhook_info(hook, &workspaces, None).await?;
+    let mut table = Table::new(&[]);
+    table.push(vec![Cell::from("id"), Cell::from(info.id.to_string())]);
+    table.push(vec![
+        Cell::from("uuid"),
+        Cell::styled(info.uuid.as_str(), Some(Style::Dim)),
+    ]);
+    table.push(vec![Cell::from("type"), Cell::from(info.trigger_type)]);
+    table.push(vec![Cell::from("scope"), Cell::from(info.scope())]);
+    table.push(vec![Cell::from("url"), Cell::from(info.url.as_str())]);
+    table.print();
+    Ok(())
+}
+
+/// Resolve the webhook's restrictions to names. Ids that can't be found
+/// (e.g., deleted items) are shown as ids.
+async fn webhook_info(
+    hook: &Webhook,
+    workspaces: &[Arc<Workspace>],
+    created_at: Option<String>,
+) -> Result<WebhookInfo, Error> {
+    let workspace = hook.workspace_id.map(|id| {
+        workspaces
+            .iter()
+            .find(|ws| ws.id == id)
+            .map(|ws| ws.name.clone())
+            .unwrap_or_else(|| id.to_string())
+    });
+    let list = hook.list_id.and_then(|id| {
+        workspaces.iter().find_map(|ws| {
+            ws.lists
+                .iter()
+                .find(|l| l.id == id)
+                .map(|l| (ws.get_id(), l.uuid.clone(), l.name.clone()))
+        })
+    });
+    let (mut item, mut field) = (
+        hook.list_entry_id.map(|id| id.to_string()),
+        hook.element_id.map(|id| id.to_string()),
+    );
+    if let (Some((ws_id, list_uuid, _)), true) = (&list, item.is_some() || field.is_some()) {
+        let list_info = cache::get_list_info(*ws_id, list_uuid).await?;
+        if let Some(id) = hook.list_entry_id {
+            let id = id.to_string();
+            if let Ok(entry) = retry("get_item", || list_info.get_item(&id)).await {
+                item = Some(entry.display_string);
+            }
+        }
+        if let Some(id) = hook.element_id {
+            if let Some(f) = list_info.fields().iter().find(|f| f.id == id) {
+                field = Some(f.name.clone());
+            }
+        }
+    }
+    Ok(WebhookInfo {
+        id: hook.id,
+        uuid: hook.uuid.clone(),
+        trigger_type: trigger_type_name(&hook.trigger_type),
+        workspace,
+        list: match list {
+            Some((_, _, name)) => Some(name),
+            None => hook.list_id.map(|id| id.to_string()),
+        },
+        item,
+        field,
+        url: hook.url.clone(),
+        created_at,
+    })
+}
+
+/// Name of the trigger type, as used in the `--type` option
+fn trigger_type_name(trigger_type: &WebhookTriggerType) -> &'static str {
+    match trigger_type {
+        WebhookTriggerType::Entry => "item",
+        WebhookTriggerType::Activity => "activity",
+        WebhookTriggerType::Notification => "notification",
+        WebhookTriggerType::SystemMessage => "system",
+        WebhookTriggerType::Comment => "comment",
+        WebhookTriggerType::Element => "field",
+    }
+}
+
+/// Workspace id and list with the name, id, or uuid, in any workspace
+fn find_list<'w>(workspaces: &'w [Arc<Workspace>], name: &str) -> Result<(ID, &'w List), Error> {
+    workspaces
+        .iter()
+        .find_map(|ws| {
+            ws.lists
+                .iter()
+                .find(|l| l.name == name || l.uuid == name || l.id.to_string() == name)
+                .map(|l| (ws.get_id(), l))
+        })
+        .ok_or_else(|| Error::NotFound(format!("List '{}' not found", name)))
+}
+
+/// Test a webhook. With `--simulate`, a sample event is posted to the webhook's url
+/// (or `--url`, which need not be registered), and an error is returned if the response
+/// status isn't success. Otherwise, a change that triggers the webhook is made in Zenkit,
+/// and undone: for item and activity webhooks, an item is created and deleted, and for
+/// comment webhooks, a comment is added (to the webhook's item, or a new item) and deleted.
+/// Zenkit doesn't report whether the event was delivered, so the endpoint must be checked.
+pub(crate) async fn test_webhook(rest: &RestClient, opt: &TestWebhookOpt) -> Result<(), Error> {
+    let api = zenkit::get_api()?;
+    let hooks = retry("get_webhooks", || api.get_webhooks()).await?;
+    let hook = match (opt.webhook, &opt.url) {
+        (Some(id), _) => Some(
+            hooks
+                .iter()
+                .find(|w| w.id == id)
+                .ok_or_else(|| Error::NotFound(format!("Webhook {} not found", id)))?,
+        ),
+        (None, Some(url)) => hooks.iter().find(|w| w.url == *url),
+        (None, None) => {
+            return Err(Error::Invalid(
+                "Either --webhook or --url is required".into(),
+            ))
+        }
+    };
+    let requested = webhook_trigger_type(&opt.trigger_type);
+    let trigger_type = hook.map(|hook| &hook.trigger_type).unwrap_or(&requested);
+
+    if opt.simulate {
+        let url = match hook {
+            Some(hook) => hook.url.clone(),
+            // checked above
+            None => opt.url.clone().unwrap_or_default(),
+        };
+        let secret = load_secret(opt.secret.clone(), opt.secret_file.as_deref())?;
+        return simulate_event(&url, trigger_type, hook, secret.as_deref()).await;
+    }
+    let hook = hook.ok_or_else(|| {
+        Error::NotFound(
+            "No webhook is registered with this url. Use --simulate to post a sample event".into(),
+        )
+    })?;
+    let workspaces: Vec<Arc<Workspace>> = retry("get_all_workspaces_and_lists", || {
+        api.get_all_workspaces_and_lists()
+    })
+    .await?;
+    let (ws_id, list) = match (hook.list_id, &opt.list) {
+        (Some(id), _) => find_list(&workspaces, &id.to_string())?,
+        (None, Some(name)) => find_list(&workspaces, name)?,
+        (None, None) => {
+            return Err(Error::Invalid(format!(
+                "Webhook {} isn't restricted to a list. Use --list to select a list for the test",
+                hook.id
+            )))
+        }
+    };
+    let list_info = cache::get_list_info(ws_id, &list.uuid).await?;
+    let list_id = list_info.get_id();
+    match trigger_type {
+        WebhookTriggerType::Entry | WebhookTriggerType::Activity => {
+            if let Some(item_id) = create_test_item(rest, list_id).await? {
+                delete_test_item(rest, list_id, item_id).await?;
+                println!(
+                    "Created and deleted item {} in list '{}'",
+                    item_id, list.name
+                );
+            }
+        }
+        WebhookTriggerType::Comment => {
+            let (item_id, created) = match hook.list_entry_id {
+                Some(id) => (Some(id), false),
+                None => (create_test_item(rest, list_id).await?, true),
+            };
+            if let Some(item_id) = item_id {
+                let result = add_test_comment(rest, list_id, item_id).await;
+                // also if the comment failed, so the test item isn't left in the list
+                let deleted = if created {
+                    delete_test_item(rest, list_id, item_id).await
+                } else {
+                    Ok(())
+                };
+                result.and(deleted)?;
+                println!(
+                    "Added and deleted a comment on item {} in list '{}'",
+                    item_id, list.name
+                );
+            }
+        }
+        _ => {
+            return Err(Error::Invalid(format!(
+            "Webhooks of type {} can't be triggered by zk. Use --simulate to post a sample event",
+            trigger_type_name(trigger_type)
+        )))
+        }
+    }
+    if !dryrun::enabled() {
+        eprintln!(
+            "Zenkit doesn't report webhook delivery: check {} for the event",
+            hook.url
+        );
+    }
+    Ok(())
+}
+
+/// Create an empty item in the list, returning its id (None in dry-run mode)
+async fn create_test_item(rest: &RestClient, list_id: ID) -> Result<Option<ID>, Error> {
+    let item = rest
+        .post(&format!("lists/{}/entries", list_id), &json!({}))
+        .await?;
+    Ok(item.get("id").and_then(|v| v.as_u64()))
+}
+
+/// Add a comment to the item, and delete it
+async fn add_test_comment(rest: &RestClient, list_id: ID, item_id: ID) -> Result<(), Error> {
+    let comment = rest
+        .post(
+            &format!("users/me/lists/{}/entries/{}/activities", list_id, item_id),
+            &json!({ "message": "zk test-webhook" }),
+        )
+        .await?;
+    if let Some(comment_id) = comment.get("id").and_then(|v| v.as_u64()) {
+        rest.delete(&format!("activities/{}", comment_id)).await?;
+    }
+    Ok(())
+}
+
+async fn delete_test_item(rest: &RestClient, list_id: ID, item_id: ID) -> Result<(), Error> {
+    rest.delete(&format!("lists/{}/entries/{}", list_id, item_id))
+        .await?;
+    Ok(())
+}
+
+/// Post a sample event of the trigger type to the url, and print the response status
+/// and time. Returns an error if the status isn't success.
+async fn simulate_event(
+    url: &str,
+    trigger_type: &WebhookTriggerType,
+    hook: Option<&Webhook>,
+    secret: Option<&str>,
+) -> Result<(), Error> {
+    let now = Utc::now().to_rfc3339();
+    let list_id = hook.and_then(|h| h.list_id);
+    let item_id = hook.and_then(|h| h.list_entry_id);
+    // events are sent as an array, as by Zenkit
+    let event = match trigger_type {
+        WebhookTriggerType::Entry => json!({
+            "id": item_id.unwrap_or(0),
+            "uuid": "00000000-0000-0000-0000-000000000000",
+            "listId": list_id,
+            "displayString": "zk test-webhook",
+            "created_at": now,
+            "updated_at": now,
+        }),
+        WebhookTriggerType::Activity | WebhookTriggerType::Comment => json!({
+            "id": 0,
+            "uuid": "00000000-0000-0000-0000-000000000000",
+            "type": 0,
+            "message": "zk test-webhook",
+            "listId": list_id,
+            "listEntryId": item_id,
+            "created_at": now,
+        }),
+        _ => json!({
+            "id": 0,
+            "message": "zk test-webhook",
+            "created_at": now,
+        }),
+    };
+    let body = Value::Array(vec![event]);
+    if dryrun::enabled() {
+        dryrun::print_request("POST", url, Some(&body));
+        return Ok(());
+    }
+    let body = serde_json::to_vec(&body)?;
+    let mut req = http::client()?
+        .post(url)
+        .header("content-type", "application/json");
+    if let Some(secret) = secret {
+        req = req.header(SIGNATURE_HEADER, signature(secret, &body)?);
+    }
+    let start = Instant::now();
+    let resp = req
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| Error::Message(format!("{}: {}", url, e)))?;
+    let status = resp.status();
+    println!("{}\t{}\t{}ms", url, status, start.elapsed().as_millis());
+    if !status.is_success() {
+        return Err(Error::Message(format!(
+            "Webhook endpoint {} responded {}",
+            url, status
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+//! Links to lists and items in the Zenkit web app (`url` and `open`)
+use crate::{cache, rest::RestClient, Error, UrlOpt};
+use serde_json::Value;
+use std::{process::Command, result::Result, sync::Mutex};
+use zenkit::types::ID;
+
+/// Default link to a list. Placeholders are replaced with short ids.
+const LIST_URL: &str = "{base}/i/{workspace}/{list}";
+
+/// Default link to an item
+const ITEM_URL: &str = "{base}/i/{workspace}/{list}/{item}";
+
+/// Web app url, and the forms of links, from the config file
+#[derive(Clone, Debug, Default)]
+pub(crate) struct WebSettings {
+    /// Web app url, e.g., https://base.zenkit.com
+    pub base: String,
+    /// Form of list links, with placeholders {base}, {workspace}, and {list}
+    pub list_url: Option<String>,
+    /// Form of item links, with the placeholders of list links, and {item},
+    /// {item_id}, and {item_uuid}
+    pub item_url: Option<String>,
+}
+
+static SETTINGS: Mutex<Option<WebSettings>> = Mutex::new(None);
+
+pub(crate) fn configure(settings: WebSettings) {
+    if let Ok(mut s) = SETTINGS.lock() {
+        *s = Some(settings);
+    }
+}
+
+/// Web app url for the api endpoint: the endpoint without its path
+/// (e.g., https://base.zenkit.com/api/v1 -> https://base.zenkit.com)
+pub(crate) fn web_base(endpoint: &str) -> String {
+    let endpoint = endpoint.trim_end_matches('/');
+    let path_start = endpoint
+        .find("://")
+        .map(|pos| pos + 3)
+        .and_then(|host| endpoint[host..].find('/').map(|pos| host + pos));
+    match path_start {
+        Some(pos) => endpoint[..pos].to_string(),
+        None => endpoint.to_string(),
+    }
+}
+
+/// Link to the list, or to the item if `opt.item` is set
+pub(crate) async fn url(rest: &RestClient, ws_id: ID, opt: &UrlOpt) -> Result<String, Error> {
+    let settings = SETTINGS
+        .lock()
+        .ok()
+        .and_then(|s| s.clone())
+        .unwrap_or_default();
+    let list_info = cache::get_list_info(ws_id, &opt.list).await?;
+    // short ids aren't included in the zenkit crate's types
+    let workspaces = rest.get("users/me/workspacesWithLists").await?;
+    let ws = workspaces
+        .as_array()
+        .and_then(|all| all.iter().find(|ws| id_of(ws) == Some(ws_id)))
+        .ok_or_else(|| Error::NotFound(format!("Workspace {} not found", ws_id)))?;
+    let list = ws
+        .get("lists")
+        .and_then(|lists| lists.as_array())
+        .and_then(|lists| lists.iter().find(|l| id_of(l) == Some(list_info.get_id())))
+        .ok_or_else(|| Error::NotFound(format!("List {} not found", opt.list)))?;
+    let entry = match opt.item {
+        Some(ref item) => {
+            let path = format!("lists/{}/entries/{}", list_info.get_id(), item);
+            Some(rest.get(&path).await?)
+        }
+        None => None,
+    };
+    Ok(link(&settings, ws, list, entry.as_ref()))
+}
+
+/// Link to the list in the workspace, or to the item if there is one
+fn link(settings: &WebSettings, ws: &Value, list: &Value, item: Option<&Value>) -> String {
+    let url = match item {
+        Some(entry) => settings
+            .item_url
+            .as_deref()
+            .unwrap_or(ITEM_URL)
+            .replace("{item_id}", &id_of(entry).unwrap_or_default().to_string())
+            .replace("{item_uuid}", str_of(entry, "uuid"))
+            .replace("{item}", short_id(entry)),
+        None => settings.list_url.as_deref().unwrap_or(LIST_URL).to_string(),
+    };
+    url.replace("{base}", settings.base.trim_end_matches('/'))
+        .replace("{workspace}", short_id(ws))
+        .replace("{list}", short_id(list))
+}
+
+/// Open the url in the default browser, or the one in $BROWSER
+pub(crate) fn open(url: &str) -> Result<(), Error> {
+    let mut command = match std::env::var("BROWSER") {
+        Ok(browser) if !browser.is_empty() => Command::new(browser),
+        _ if cfg!(target_os = "macos") => Command::new("open"),
+        _ if cfg!(target_os = "windows") => {
+            let mut command = Command::new("cmd");
+            command.args(["/C", "start", ""]);
+            command
+        }
+        _ => Command::new("xdg-open"),
+    };
+    let status = command
+        .arg(url)
+        .status()
+        .map_err(|e| Error::Message(format!("Opening browser: {}", e)))?;
+    if !status.success() {
+        return Err(Error::Message(format!(
+            "Opening browser failed ({}). Url: {}",
+            status, url
+        )));
+    }
+    Ok(())
+}
+
+fn id_of(v: &Value) -> Option<ID> {
+    v.get("id").and_then(|id| id.as_u64())
+}
+
+fn str_of<'v>(v: &'v Value, key: &str) -> &'v str {
+    v.get(key).and_then(|s| s.as_str()).unwrap_or_default()
+}
+
+/// Short id, or the uuid if there's none
+fn short_id(v: &Value) -> &str {
+    match str_of(v, "shortId") {
+        "" => str_of(v, "uuid"),
+        short => short,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use serde_json::json;
+
+    #[test]
+    fn web_app_url() {
+        assert_eq!(
+            web_base("https://base.zenkit.com/api/v1/"),
+            "https://base.zenkit.com"
+        );
+        assert_eq!(web_base("http://localhost:8080"), "http://localhost:8080");
+    }
+
+    #[test]
+    fn links() {
+        let ws = json!({ "id": 1, "shortId": "w1", "uuid": "ws-1" });
+        let list = json!({ "id": 2, "uuid": "list-2" });
+        let item = json!({ "id": 3, "shortId": "i3", "uuid": "item-3" });
+        let settings = WebSettings {
+            base: "https://base.zenkit.com/".into(),
+            ..Default::default()
+        };
+        // the uuid is used if there's no short id
+        assert_eq!(
+            link(&settings, &ws, &list, None),
+            "https://base.zenkit.com/i/w1/list-2"
+        );
+        assert_eq!(
+            link(&settings, &ws, &list, Some(&item)),
+            "https://base.zenkit.com/i/w1/list-2/i3"
+        );
+        let settings = WebSettings {
+            item_url: Some("{base}/items/{item_id}?uuid={item_uuid}".into()),
+            ..settings
+        };
+        assert_eq!(
+            link(&settings, &ws, &list, Some(&item)),
+            "https://base.zenkit.com/items/3?uuid=item-3"
+        );
+    }
+}
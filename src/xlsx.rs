@@ -0,0 +1,330 @@
+//! Minimal writer of Excel (.xlsx) workbooks: sheets with a header row and typed cells
+use crate::Error;
+use chrono::{NaiveDate, NaiveDateTime};
+use std::{io::Write, result::Result};
+
+/// Maximum length of a sheet name
+const SHEET_NAME_LENGTH: usize = 31;
+
+/// Cell style indexes, in the order of cellXfs in STYLES
+const STYLE_DATE: usize = 1;
+const STYLE_DATETIME: usize = 2;
+const STYLE_HEADER: usize = 3;
+
+const CONTENT_TYPES_START: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/>"#;
+
+const ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+
+const STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><numFmts count="2"><numFmt numFmtId="164" formatCode="yyyy-mm-dd"/><numFmt numFmtId="165" formatCode="yyyy-mm-dd hh:mm"/></numFmts><fonts count="2"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font></fonts><fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills><borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders><cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs><cellXfs count="4"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/><xf numFmtId="164" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/><xf numFmtId="165" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/><xf numFmtId="0" fontId="1" fillId="0" borderId="0" xfId="0" applyFont="1"/></cellXfs><cellStyles count="1"><cellStyle name="Normal" xfId="0" builtinId="0"/></cellStyles></styleSheet>"#;
+
+/// A cell value
+pub(crate) enum Cell {
+    Empty,
+    Text(String),
+    Number(f64),
+    Bool(bool),
+    Date(NaiveDate),
+    DateTime(NaiveDateTime),
+}
+
+/// A worksheet: a header row, then the rows
+pub(crate) struct Sheet {
+    pub name: String,
+    pub header: Vec<String>,
+    pub rows: Vec<Vec<Cell>>,
+}
+
+/// Write the sheets to an xlsx file. Sheet names are shortened to 31 characters,
+/// without the characters Excel doesn't allow, and made unique.
+pub(crate) fn write_workbook(path: &str, sheets: &[Sheet]) -> Result<(), Error> {
+    let file = std::fs::File::create(path)
+        .map_err(|e| Error::Message(format!("Creating {}: {}", path, e)))?;
+    let mut zip = zip::ZipWriter::new(file);
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+
+    let mut names: Vec<String> = Vec::new();
+    for sheet in sheets.iter() {
+        let name = sheet_name(&sheet.name, &names);
+        names.push(name);
+    }
+
+    let mut content_types = CONTENT_TYPES_START.to_string();
+    let mut workbook = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets>"#,
+    );
+    let mut rels = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">"#,
+    );
+    for (n, name) in names.iter().enumerate() {
+        let n = n + 1;
+        content_types.push_str(&format!(
+            r#"<Override PartName="/xl/worksheets/sheet{}.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>"#,
+            n
+        ));
+        workbook.push_str(&format!(
+            r#"<sheet name="{}" sheetId="{}" r:id="rId{}"/>"#,
+            escape(name),
+            n,
+            n
+        ));
+        rels.push_str(&format!(
+            r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet{}.xml"/>"#,
+            n, n
+        ));
+    }
+    content_types.push_str("</Types>");
+    workbook.push_str("</sheets></workbook>");
+    rels.push_str(&format!(
+        r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/></Relationships>"#,
+        names.len() + 1
+    ));
+
+    let parts = [
+        ("[Content_Types].xml", content_types),
+        ("_rels/.rels", ROOT_RELS.to_string()),
+        ("xl/workbook.xml", workbook),
+        ("xl/_rels/workbook.xml.rels", rels),
+        ("xl/styles.xml", STYLES.to_string()),
+    ];
+    for (name, xml) in parts.iter() {
+        zip.start_file(*name, options)?;
+        zip.write_all(xml.as_bytes())?;
+    }
+    for (n, sheet) in sheets.iter().enumerate() {
+        zip.start_file(format!("xl/worksheets/sheet{}.xml", n + 1), options)?;
+        zip.write_all(sheet_xml(sheet).as_bytes())?;
+    }
+    zip.finish()?;
+    Ok(())
+}
+
+fn sheet_xml(sheet: &Sheet) -> String {
+    let columns = sheet.header.len();
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetViews><sheetView workbookViewId="0"><pane ySplit="1" topLeftCell="A2" activePane="bottomLeft" state="frozen"/></sheetView></sheetViews><sheetData>"#,
+    );
+    xml.push_str(r#"<row r="1">"#);
+    for (col, name) in sheet.header.iter().enumerate() {
+        xml.push_str(&format!(
+            r#"<c r="{}1" s="{}" t="inlineStr"><is><t>{}</t></is></c>"#,
+            column_name(col),
+            STYLE_HEADER,
+            escape(name)
+        ));
+    }
+    xml.push_str("</row>");
+    for (r, row) in sheet.rows.iter().enumerate() {
+        let r = r + 2;
+        xml.push_str(&format!(r#"<row r="{}">"#, r));
+        for (col, cell) in row.iter().enumerate() {
+            let pos = format!("{}{}", column_name(col), r);
+            match cell {
+                Cell::Empty => {}
+                Cell::Text(s) => xml.push_str(&format!(
+                    r#"<c r="{}" t="inlineStr"><is><t xml:space="preserve">{}</t></is></c>"#,
+                    pos,
+                    escape(s)
+                )),
+                Cell::Number(n) if n.is_finite() => {
+                    xml.push_str(&format!(r#"<c r="{}"><v>{}</v></c>"#, pos, n))
+                }
+                Cell::Number(_) => {}
+                Cell::Bool(b) => {
+                    xml.push_str(&format!(r#"<c r="{}" t="b"><v>{}</v></c>"#, pos, *b as u8))
+                }
+                Cell::Date(d) => xml.push_str(&format!(
+                    r#"<c r="{}" s="{}"><v>{}</v></c>"#,
+                    pos,
+                    STYLE_DATE,
+                    serial_date(d.and_hms(0, 0, 0))
+                )),
+                Cell::DateTime(dt) => xml.push_str(&format!(
+                    r#"<c r="{}" s="{}"><v>{}</v></c>"#,
+                    pos,
+                    STYLE_DATETIME,
+                    serial_date(*dt)
+                )),
+            }
+        }
+        xml.push_str("</row>");
+    }
+    xml.push_str("</sheetData>");
+    if columns > 0 {
+        xml.push_str(&format!(
+            r#"<autoFilter ref="A1:{}{}"/>"#,
+            column_name(columns - 1),
+            sheet.rows.len() + 1
+        ));
+    }
+    xml.push_str("</worksheet>");
+    xml
+}
+
+/// Excel's serial date: days (and fraction of a day) since 1899-12-30
+fn serial_date(dt: NaiveDateTime) -> f64 {
+    let epoch = NaiveDate::from_ymd(1899, 12, 30).and_hms(0, 0, 0);
+    (dt - epoch).num_seconds() as f64 / 86400.0
+}
+
+/// Column letters for a zero-based column index: A, B, ..., Z, AA, AB, ...
+fn column_name(mut col: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (col % 26) as u8);
+        if col < 26 {
+            break;
+        }
+        col = col / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap_or_default()
+}
+
+/// A valid sheet name, different from the names already used
+fn sheet_name(name: &str, used: &[String]) -> String {
+    let clean: String = name
+        .chars()
+        .map(|c| match c {
+            '[' | ']' | ':' | '*' | '?' | '/' | '\\' => '_',
+            c => c,
+        })
+        .collect();
+    let clean = clean.trim_matches('\'');
+    let base: String = if clean.is_empty() {
+        "Sheet".to_string()
+    } else {
+        clean.chars().take(SHEET_NAME_LENGTH).collect()
+    };
+    let taken = |name: &str| used.iter().any(|u| u.eq_ignore_ascii_case(name));
+    let mut candidate = base.clone();
+    let mut n = 1;
+    while taken(&candidate) {
+        n += 1;
+        let suffix = format!(" ({})", n);
+        let prefix: String = base
+            .chars()
+            .take(SHEET_NAME_LENGTH - suffix.len())
+            .collect();
+        candidate = format!("{}{}", prefix, suffix);
+    }
+    candidate
+}
+
+/// Escape text for xml, dropping control characters that xml doesn't allow
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if (c as u32) < 0x20 => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn column_names() {
+        let names: Vec<String> = [0, 1, 25, 26, 27, 51, 52, 701, 702]
+            .iter()
+            .map(|col| column_name(*col))
+            .collect();
+        assert_eq!(
+            names,
+            vec!["A", "B", "Z", "AA", "AB", "AZ", "BA", "ZZ", "AAA"]
+        );
+    }
+
+    #[test]
+    fn sheet_names() {
+        let used = vec!["Tasks".to_string()];
+        assert_eq!(sheet_name("Q1/Q2: [plan]", &used), "Q1_Q2_ _plan_");
+        assert_eq!(sheet_name("tasks", &used), "tasks (2)");
+        assert_eq!(sheet_name("''", &used), "Sheet");
+        let long = "a".repeat(40);
+        assert_eq!(sheet_name(&long, &[]), "a".repeat(31));
+        assert_eq!(
+            sheet_name(&long, &["a".repeat(31)]),
+            format!("{} (2)", "a".repeat(27))
+        );
+    }
+
+    #[test]
+    fn cell_values() {
+        assert_eq!(
+            serial_date(NaiveDate::from_ymd(1900, 1, 1).and_hms(0, 0, 0)),
+            2.0
+        );
+        assert_eq!(
+            serial_date(NaiveDate::from_ymd(2021, 3, 1).and_hms(12, 0, 0)),
+            44256.5
+        );
+        assert_eq!(
+            escape("a < b & \"c\"\u{1}\n"),
+            "a &lt; b &amp; &quot;c&quot;\n"
+        );
+        let sheet = Sheet {
+            name: "Tasks".into(),
+            header: vec!["id".into(), "title".into(), "done".into()],
+            rows: vec![vec![
+                Cell::Number(7.0),
+                Cell::Text("Fix <b>".into()),
+                Cell::Bool(true),
+                Cell::Empty,
+                Cell::Date(NaiveDate::from_ymd(2021, 3, 1)),
+            ]],
+        };
+        let xml = sheet_xml(&sheet);
+        assert!(xml.contains(r#"<c r="A1" s="3" t="inlineStr"><is><t>id</t></is></c>"#));
+        assert!(xml.contains(r#"<row r="2"><c r="A2"><v>7</v></c>"#));
+        assert!(xml.contains(r#"<t xml:space="preserve">Fix &lt;b&gt;</t>"#));
+        assert!(xml.contains(r#"<c r="C2" t="b"><v>1</v></c><c r="E2" s="1"><v>44256</v></c>"#));
+        assert!(xml.contains(r#"<autoFilter ref="A1:C2"/>"#));
+    }
+
+    #[test]
+    fn workbook_parts() {
+        let path = std::env::temp_dir().join(format!("zk_xlsx_{}.xlsx", std::process::id()));
+        let sheet = |name: &str| Sheet {
+            name: name.into(),
+            header: vec!["id".into()],
+            rows: Vec::new(),
+        };
+        write_workbook(path.to_str().unwrap(), &[sheet("Tasks"), sheet("Tasks")]).unwrap();
+        let mut zip = zip::ZipArchive::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let mut names: Vec<String> = zip.file_names().map(String::from).collect();
+        names.sort();
+        let mut workbook = String::new();
+        std::io::Read::read_to_string(&mut zip.by_name("xl/workbook.xml").unwrap(), &mut workbook)
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(
+            names,
+            vec![
+                "[Content_Types].xml",
+                "_rels/.rels",
+                "xl/_rels/workbook.xml.rels",
+                "xl/styles.xml",
+                "xl/workbook.xml",
+                "xl/worksheets/sheet1.xml",
+                "xl/worksheets/sheet2.xml",
+            ]
+        );
+        assert!(workbook.contains(r#"<sheet name="Tasks (2)" sheetId="2" r:id="rId2"/>"#));
+    }
+}